@@ -0,0 +1,59 @@
+//! Compares single-threaded and parallel walks of a wide directory tree:
+//! one thread, the available parallelism and a fixed four threads.
+//!
+//! Run with `cargo bench --bench scan_threads`. The tree is built in a
+//! temporary directory; pass a path as the first argument to walk an
+//! existing tree instead, such as a network share.
+
+use std::fs;
+use std::time::{Duration, Instant};
+
+use win_disk_info::{scan_files, ScanOptions};
+
+/// Number of top-level directories of the generated tree
+const DIRECTORIES: usize = 200;
+/// Subdirectories of each top-level directory
+const SUBDIRECTORIES: usize = 10;
+/// Files in each subdirectory
+const FILES: usize = 10;
+/// Walks timed per thread count, of which the median is reported
+const RUNS: usize = 5;
+
+fn median_walk(root: &str, threads: usize) -> (Duration, usize) {
+    let options = ScanOptions::new().with_threads(threads);
+    let mut times = Vec::with_capacity(RUNS);
+    let mut count = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        count = scan_files(root, &options).expect("walk failed").len();
+        times.push(start.elapsed());
+    }
+    times.sort();
+    (times[RUNS / 2], count)
+}
+
+fn main() {
+    let temp_dir = tempfile::tempdir().expect("cannot create the temporary directory");
+    let root = match std::env::args().nth(1).filter(|arg| !arg.starts_with('-')) {
+        Some(root) => root,
+        None => {
+            for directory in 0..DIRECTORIES {
+                for subdirectory in 0..SUBDIRECTORIES {
+                    let path = temp_dir.path().join(format!("d{:03}", directory)).join(format!("s{:02}", subdirectory));
+                    fs::create_dir_all(&path).expect("cannot create the tree");
+                    for file in 0..FILES {
+                        fs::write(path.join(format!("f{:02}.txt", file)), b"x").expect("cannot create the tree");
+                    }
+                }
+            }
+            temp_dir.path().to_string_lossy().to_string()
+        }
+    };
+
+    let parallelism = std::thread::available_parallelism().map_or(1, usize::from);
+    println!("walking {} with up to {} threads", root, parallelism);
+    for threads in [1, 0, 4] {
+        let (time, count) = median_walk(&root, threads);
+        println!("with_threads({}): {} entries in {:.1} ms", threads, count, time.as_secs_f64() * 1000.0);
+    }
+}
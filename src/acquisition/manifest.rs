@@ -0,0 +1,189 @@
+//! Acquisition manifests.
+//!
+//! The manifest is a CSV file written next to the container. Comment lines
+//! starting with `#` record the container name, format, creation time and
+//! digests; they are followed by one row per acquired file with its path in
+//! the container, original path, size, timestamps, attributes, source image
+//! and digests.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, Utc};
+
+use super::{AcquiredFile, AcquisitionError, ContainerFormat};
+use crate::hash_sets::split_csv;
+use crate::report::csv::{push_row, unguard};
+use crate::{FileAttributes, FileHashes, FileTimes, HashAlgorithm};
+
+const COLUMNS: [&str; 12] = [
+    "Archive path",
+    "Original path",
+    "Size",
+    "Modified",
+    "Accessed",
+    "Changed",
+    "Born",
+    "Attributes",
+    "Source",
+    "MD5",
+    "SHA-1",
+    "SHA-256",
+];
+/// Index of the first digest column
+const DIGESTS: usize = 9;
+
+/// The content of a manifest.
+#[derive(Debug, Clone)]
+pub(crate) struct Manifest {
+    pub(crate) container: String,
+    pub(crate) format: ContainerFormat,
+    pub(crate) created: DateTime<Local>,
+    pub(crate) container_hashes: FileHashes,
+    pub(crate) files: Vec<AcquiredFile>,
+}
+
+/// Returns the path of the manifest written for `container`.
+pub(crate) fn manifest_path(container: &Path) -> PathBuf {
+    let mut path = container.as_os_str().to_owned();
+    path.push(".manifest.csv");
+    PathBuf::from(path)
+}
+
+/// Renders a manifest as CSV.
+pub(crate) fn render(manifest: &Manifest) -> String {
+    let mut csv = String::from("# Acquisition manifest\r\n");
+    csv.push_str(&format!("# Container: {}\r\n", manifest.container));
+    csv.push_str(&format!("# Format: {}\r\n", manifest.format));
+    csv.push_str(&format!("# Created: {}\r\n", manifest.created.to_rfc3339()));
+    for (algorithm, digest) in manifest.container_hashes.iter() {
+        csv.push_str(&format!("# {}: {}\r\n", algorithm, digest));
+    }
+
+    push_row(&mut csv, COLUMNS);
+    for file in &manifest.files {
+        let original = file.original_path.to_string_lossy();
+        let size = file.size.to_string();
+        let [modified, accessed, changed, born] = [
+            file.times.modified(),
+            file.times.accessed(),
+            file.times.changed(),
+            file.times.born(),
+        ]
+        .map(|time| time.map(|time| time.to_rfc3339()).unwrap_or_default());
+        let attributes = file.attributes.to_string();
+        let source = file.image.as_ref().map(|image| image.to_string_lossy()).unwrap_or_default();
+        let digests = HashAlgorithm::EVIDENCE.map(|algorithm| file.hashes.get(algorithm).unwrap_or_default());
+        push_row(
+            &mut csv,
+            [
+                &file.archive_path,
+                &*original,
+                &size,
+                &modified,
+                &accessed,
+                &changed,
+                &born,
+                &attributes,
+                &*source,
+                digests[0],
+                digests[1],
+                digests[2],
+            ],
+        );
+    }
+    csv
+}
+
+fn invalid(line: usize, message: &str) -> AcquisitionError {
+    AcquisitionError::InvalidManifest(format!("line {}: {}", line, message))
+}
+
+/// Parses an optional RFC 3339 time field.
+fn parse_time(field: &str, line: usize, name: &str) -> Result<Option<DateTime<Utc>>, AcquisitionError> {
+    match field {
+        "" => Ok(None),
+        time => DateTime::parse_from_rfc3339(time)
+            .map(|time| Some(time.with_timezone(&Utc)))
+            .map_err(|_| invalid(line, &format!("invalid {} time", name))),
+    }
+}
+
+/// Reads a manifest written by `render`.
+pub(crate) fn read(path: &Path) -> Result<Manifest, AcquisitionError> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut container = None;
+    let mut format = None;
+    let mut created = None;
+    let mut container_hashes = FileHashes::default();
+    let mut files = Vec::new();
+    let mut header_seen = false;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let number = index + 1;
+        if let Some(comment) = line.strip_prefix('#') {
+            let Some((key, value)) = comment.trim().split_once(": ") else {
+                continue;
+            };
+            match key {
+                "Container" => container = Some(value.to_string()),
+                "Format" => format = Some(value.parse().map_err(|_| invalid(number, "unknown container format"))?),
+                "Created" => {
+                    let time = DateTime::parse_from_rfc3339(value).map_err(|_| invalid(number, "invalid creation time"))?;
+                    created = Some(time.with_timezone(&Local));
+                }
+                key => {
+                    if let Some(algorithm) = HashAlgorithm::ALL.into_iter().find(|algorithm| algorithm.to_string() == key) {
+                        container_hashes.insert(algorithm, value.to_ascii_lowercase());
+                    }
+                }
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        let fields: Vec<String> = split_csv(&line).iter().map(|field| unguard(field).to_string()).collect();
+        if !header_seen {
+            if fields != COLUMNS {
+                return Err(invalid(number, "unexpected columns"));
+            }
+            header_seen = true;
+            continue;
+        }
+        if fields.len() != COLUMNS.len() {
+            return Err(invalid(number, "wrong number of fields"));
+        }
+
+        let mut hashes = FileHashes::default();
+        for (algorithm, digest) in HashAlgorithm::EVIDENCE.into_iter().zip(&fields[DIGESTS..]) {
+            if !digest.is_empty() {
+                hashes.insert(algorithm, digest.to_ascii_lowercase());
+            }
+        }
+        files.push(AcquiredFile {
+            archive_path: fields[0].clone(),
+            original_path: PathBuf::from(&fields[1]),
+            size: fields[2].parse().map_err(|_| invalid(number, "invalid size"))?,
+            times: FileTimes::new()
+                .with_modified(parse_time(&fields[3], number, "modification")?)
+                .with_accessed(parse_time(&fields[4], number, "access")?)
+                .with_changed(parse_time(&fields[5], number, "change")?)
+                .with_born(parse_time(&fields[6], number, "creation")?),
+            attributes: FileAttributes::from_names(&fields[7]).ok_or_else(|| invalid(number, "invalid attributes"))?,
+            image: (!fields[8].is_empty()).then(|| PathBuf::from(&fields[8])),
+            hashes,
+        });
+    }
+
+    Ok(Manifest {
+        container: container.ok_or_else(|| invalid(0, "missing container name"))?,
+        format: format.ok_or_else(|| invalid(0, "missing container format"))?,
+        created: created.ok_or_else(|| invalid(0, "missing creation time"))?,
+        container_hashes,
+        files,
+    })
+}
@@ -0,0 +1,711 @@
+//! Logical acquisition of files into evidence containers.
+//!
+//! Selected files are copied into a ZIP or tar container under paths
+//! relative to their common parent directory, keeping their timestamps.
+//! Each file is hashed while it is copied, the container is hashed once
+//! written, and both sets of digests are recorded in a manifest written next
+//! to the container, so that the container can be verified later.
+
+mod manifest;
+mod tar;
+mod zip;
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Read};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::{DateTime, Local, Utc};
+
+use crate::file_hashing::{hash_reader, MultiHasher};
+use crate::{FileAttributes, FileEntry, FileHashes, FileSource, FileTimes, HashAlgorithm};
+use manifest::{manifest_path, Manifest};
+
+/// Error type for acquisition operations
+#[derive(Debug)]
+pub enum AcquisitionError {
+    /// IO errors from reading or writing the container or manifest
+    Io(io::Error),
+    /// The container is damaged or not in the expected format
+    InvalidContainer(String),
+    /// The manifest is damaged or not in the expected format
+    InvalidManifest(String),
+}
+
+impl fmt::Display for AcquisitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO error: {}", e),
+            Self::InvalidContainer(s) => write!(f, "Invalid container: {}", s),
+            Self::InvalidManifest(s) => write!(f, "Invalid manifest: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for AcquisitionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for AcquisitionError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Container formats files can be acquired into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerFormat {
+    /// ZIP archive with deflate compression, using ZIP64 for large files
+    Zip,
+    /// POSIX (PAX) tar archive, without compression
+    Tar,
+}
+
+impl fmt::Display for ContainerFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContainerFormat::Zip => write!(f, "ZIP"),
+            ContainerFormat::Tar => write!(f, "tar"),
+        }
+    }
+}
+
+impl FromStr for ContainerFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "zip" => Ok(ContainerFormat::Zip),
+            "tar" => Ok(ContainerFormat::Tar),
+            _ => Err(format!("unknown container format: {}", s)),
+        }
+    }
+}
+
+/// Writes members into a container.
+pub(crate) trait ContainerWriter {
+    /// Adds a member made of exactly `size` bytes read from `content`.
+    ///
+    /// Each format keeps the times and attributes it has fields for.
+    fn add(
+        &mut self,
+        name: &str,
+        times: &FileTimes,
+        attributes: FileAttributes,
+        size: u64,
+        content: &mut dyn Read,
+    ) -> io::Result<()>;
+
+    /// Writes the end of the container.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// A regular file read back from a container.
+#[derive(Debug, Clone)]
+pub(crate) struct ContainerMember {
+    pub(crate) name: String,
+    pub(crate) size: u64,
+    pub(crate) hashes: FileHashes,
+    /// False if the member failed its format's integrity check
+    pub(crate) intact: bool,
+}
+
+/// A file copied into a container.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcquiredFile {
+    /// Path of the member inside the container, with `/` separators
+    archive_path: String,
+    /// Path of the file when it was acquired
+    original_path: PathBuf,
+    /// Number of bytes stored in the container
+    size: u64,
+    /// Timestamps of the original file
+    times: FileTimes,
+    /// Attributes of the original file
+    attributes: FileAttributes,
+    /// Image the file was read from, if any
+    image: Option<PathBuf>,
+    /// MD5, SHA-1 and SHA-256 of the stored content
+    hashes: FileHashes,
+}
+
+impl AcquiredFile {
+    /// Returns the path of the member inside the container
+    pub fn archive_path(&self) -> &str {
+        &self.archive_path
+    }
+
+    /// Returns the path of the file when it was acquired
+    pub fn original_path(&self) -> &Path {
+        &self.original_path
+    }
+
+    /// Returns the number of bytes stored in the container
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the last modification time of the original file, if known
+    pub fn modified(&self) -> Option<DateTime<Utc>> {
+        self.times.modified()
+    }
+
+    /// Returns the timestamps of the original file
+    pub fn times(&self) -> &FileTimes {
+        &self.times
+    }
+
+    /// Returns the attributes of the original file
+    pub fn attributes(&self) -> FileAttributes {
+        self.attributes
+    }
+
+    /// Returns the image the file was read from, or `None` for files on a mounted file system
+    pub fn image(&self) -> Option<&Path> {
+        self.image.as_deref()
+    }
+
+    /// Returns the MD5, SHA-1 and SHA-256 of the stored content
+    pub fn hashes(&self) -> &FileHashes {
+        &self.hashes
+    }
+}
+
+/// Result of acquiring files into a container.
+#[derive(Debug)]
+pub struct Acquisition {
+    container: PathBuf,
+    manifest: PathBuf,
+    format: ContainerFormat,
+    created: DateTime<Local>,
+    container_hashes: FileHashes,
+    files: Vec<AcquiredFile>,
+    errors: Vec<(PathBuf, io::Error)>,
+}
+
+impl Acquisition {
+    /// Returns the path of the container
+    pub fn container(&self) -> &Path {
+        &self.container
+    }
+
+    /// Returns the path of the manifest written next to the container
+    pub fn manifest(&self) -> &Path {
+        &self.manifest
+    }
+
+    /// Returns the format of the container
+    pub fn format(&self) -> ContainerFormat {
+        self.format
+    }
+
+    /// Returns when the acquisition started
+    pub fn created(&self) -> DateTime<Local> {
+        self.created
+    }
+
+    /// Returns the MD5, SHA-1 and SHA-256 of the container
+    pub fn container_hashes(&self) -> &FileHashes {
+        &self.container_hashes
+    }
+
+    /// Returns the files copied into the container
+    pub fn files(&self) -> &[AcquiredFile] {
+        &self.files
+    }
+
+    /// Returns the files that could not be read completely
+    ///
+    /// Files that could not be opened are left out of the container. Files
+    /// whose reading failed midway are stored with the missing bytes
+    /// replaced by zeros, and files that grew after they were listed are
+    /// stored truncated to their listed size; the digests describe the
+    /// stored content.
+    pub fn errors(&self) -> &[(PathBuf, io::Error)] {
+        &self.errors
+    }
+}
+
+/// Result of checking a container against its manifest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContainerVerification {
+    stored_hashes: FileHashes,
+    computed_hashes: FileHashes,
+    verified: Vec<String>,
+    mismatched: Vec<String>,
+    missing: Vec<String>,
+    unexpected: Vec<String>,
+}
+
+impl ContainerVerification {
+    /// Returns the container digests recorded in the manifest
+    pub fn stored_hashes(&self) -> &FileHashes {
+        &self.stored_hashes
+    }
+
+    /// Returns the container digests computed now
+    pub fn computed_hashes(&self) -> &FileHashes {
+        &self.computed_hashes
+    }
+
+    /// Returns true if the manifest records container digests and they all match
+    pub fn container_matches(&self) -> bool {
+        !self.stored_hashes.is_empty()
+            && self
+                .stored_hashes
+                .iter()
+                .all(|(algorithm, digest)| self.computed_hashes.get(algorithm) == Some(digest))
+    }
+
+    /// Returns the members whose content matches the manifest
+    pub fn verified(&self) -> &[String] {
+        &self.verified
+    }
+
+    /// Returns the members whose content differs from the manifest or is damaged
+    pub fn mismatched(&self) -> &[String] {
+        &self.mismatched
+    }
+
+    /// Returns the files of the manifest absent from the container
+    pub fn missing(&self) -> &[String] {
+        &self.missing
+    }
+
+    /// Returns the members of the container absent from the manifest
+    pub fn unexpected(&self) -> &[String] {
+        &self.unexpected
+    }
+
+    /// Returns true if the container and every member match the manifest
+    pub fn is_verified(&self) -> bool {
+        self.container_matches() && self.mismatched.is_empty() && self.missing.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+/// Reader that stops at the first error instead of returning it.
+///
+/// The error and the number of bytes read are kept so that a file failing
+/// midway can still be stored, padded to its announced size.
+struct TolerantReader<R> {
+    inner: R,
+    read: u64,
+    error: Option<io::Error>,
+}
+
+impl<R: Read> Read for TolerantReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.error.is_some() {
+            return Ok(0);
+        }
+        loop {
+            match self.inner.read(buf) {
+                Ok(read) => {
+                    self.read += read as u64;
+                    return Ok(read);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.error = Some(e);
+                    return Ok(0);
+                }
+            }
+        }
+    }
+}
+
+/// Reader that hashes the data passing through it.
+struct HashingReader<R> {
+    inner: R,
+    hasher: MultiHasher,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// Returns the deepest directory containing every file, if they share one.
+fn common_base(files: &[FileEntry]) -> Option<PathBuf> {
+    let mut parents = files.iter().filter_map(|file| file.path().parent());
+    let mut base: Vec<Component> = parents.next()?.components().collect();
+    for parent in parents {
+        let shared = base.iter().zip(parent.components()).take_while(|(a, b)| *a == b).count();
+        base.truncate(shared);
+    }
+    (!base.is_empty()).then(|| base.iter().collect())
+}
+
+/// Turns a path into a relative `/`-separated member name.
+///
+/// Drive letters and UNC prefixes become plain directories, and `..`
+/// components are neutralised so that members cannot escape the extraction
+/// directory.
+fn member_name(path: &Path) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Prefix(prefix) => parts.extend(
+                prefix
+                    .as_os_str()
+                    .to_string_lossy()
+                    .split(['\\', '/'])
+                    .filter(|part| !part.is_empty() && *part != "?" && *part != ".")
+                    .map(|part| part.trim_end_matches(':').to_string()),
+            ),
+            Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+            Component::ParentDir => parts.push("_".to_string()),
+            Component::RootDir | Component::CurDir => {}
+        }
+    }
+    parts.join("/")
+}
+
+/// Computes a distinct member name for each file, relative to their common base.
+fn member_names(files: &[FileEntry]) -> Vec<String> {
+    let base = common_base(files);
+    let mut used = HashSet::new();
+    files
+        .iter()
+        .map(|file| {
+            let relative = base
+                .as_ref()
+                .and_then(|base| file.path().strip_prefix(base).ok())
+                .unwrap_or(file.path());
+            let mut name = member_name(relative);
+            if name.is_empty() {
+                name = file.name().to_string();
+            }
+
+            // Entries with the same path, such as carved or recovered files, get a numbered name
+            let (stem, extension) = match name.rfind('.').filter(|&dot| dot > name.rfind('/').map_or(0, |slash| slash + 1)) {
+                Some(dot) => (name[..dot].to_string(), name[dot..].to_string()),
+                None => (name.clone(), String::new()),
+            };
+            let mut counter = 1;
+            while !used.insert(name.clone()) {
+                counter += 1;
+                name = format!("{} ({}){}", stem, counter, extension);
+            }
+            name
+        })
+        .collect()
+}
+
+/// Copies files into a ZIP or tar container and writes its manifest
+///
+/// Files are stored under their path relative to the deepest directory
+/// containing all of them, with the timestamps and attributes the container
+/// format can hold; the manifest records all of them. The MD5, SHA-1 and
+/// SHA-256 of each file are computed while it is copied, and those of the
+/// container once it is complete. The manifest is written next to the
+/// container, as `<container>.manifest.csv`.
+///
+/// # Arguments
+/// * `files` - The files to acquire, on a mounted file system or inside images
+/// * `container` - A string path to the container to create
+/// * `format` - The container format
+///
+/// # Returns
+/// * `Ok(Acquisition)` - The acquired files, the container digests and the files that could not be read
+/// * `Err(AcquisitionError)` - If the container or manifest cannot be written
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::{acquire_files, find_mismatched_extensions, get_files, ContainerFormat};
+///
+/// let files = get_files("C:\\Users\\suspect").unwrap();
+/// let flagged: Vec<_> = find_mismatched_extensions(&files).into_iter().map(|(file, _)| file).collect();
+///
+/// let acquisition = acquire_files(&flagged, "E:\\case-17\\flagged.zip", ContainerFormat::Zip).unwrap();
+/// println!("{}", acquisition.container_hashes());
+/// for (path, error) in acquisition.errors() {
+///     eprintln!("{}: {}", path.display(), error);
+/// }
+/// ```
+pub fn acquire_files(
+    files: &[FileEntry],
+    container: &str,
+    format: ContainerFormat,
+) -> Result<Acquisition, AcquisitionError> {
+    let created = Local::now();
+    let container_path = PathBuf::from(container);
+    let output = BufWriter::new(File::create(&container_path)?);
+    let mut writer: Box<dyn ContainerWriter> = match format {
+        ContainerFormat::Zip => Box::new(zip::ZipWriter::new(output)),
+        ContainerFormat::Tar => Box::new(tar::TarWriter::new(output)),
+    };
+
+    let mut acquired = Vec::new();
+    let mut errors = Vec::new();
+    for (file, name) in files.iter().zip(member_names(files)) {
+        let content = match file.open() {
+            Ok(content) => content,
+            Err(error) => {
+                errors.push((file.path().to_path_buf(), error));
+                continue;
+            }
+        };
+
+        let size = file.size();
+        let mut source = TolerantReader {
+            inner: content,
+            read: 0,
+            error: None,
+        };
+        let mut reader = HashingReader {
+            inner: (&mut source).take(size).chain(io::repeat(0)).take(size),
+            hasher: MultiHasher::new(&HashAlgorithm::EVIDENCE),
+        };
+        writer.add(&name, file.times(), file.attributes(), size, &mut reader)?;
+        let hashes = reader.hasher.finish();
+
+        // Containers announce sizes up front, so content added since the listing is left out
+        let grew = source.error.is_none() && source.read == size && source.read(&mut [0u8; 1])? > 0;
+        if let Some(error) = source.error {
+            errors.push((file.path().to_path_buf(), error));
+        } else if source.read < size {
+            let message = format!("read {} of {} bytes, the rest was stored as zeros", source.read, size);
+            errors.push((file.path().to_path_buf(), io::Error::new(io::ErrorKind::UnexpectedEof, message)));
+        } else if grew {
+            let message = format!("the file grew after it was listed, only its first {} bytes were stored", size);
+            errors.push((file.path().to_path_buf(), io::Error::new(io::ErrorKind::InvalidData, message)));
+        }
+
+        acquired.push(AcquiredFile {
+            archive_path: name,
+            original_path: file.path().to_path_buf(),
+            size,
+            times: *file.times(),
+            attributes: file.attributes(),
+            image: match file.source() {
+                FileSource::Image { image, .. } => Some(image.clone()),
+                FileSource::FileSystem => None,
+            },
+            hashes,
+        });
+    }
+    writer.finish()?;
+    drop(writer);
+
+    let container_hashes = hash_reader(File::open(&container_path)?, &HashAlgorithm::EVIDENCE)?;
+    let manifest = Manifest {
+        container: container_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        format,
+        created,
+        container_hashes: container_hashes.clone(),
+        files: acquired,
+    };
+    let manifest_path = manifest_path(&container_path);
+    std::fs::write(&manifest_path, manifest::render(&manifest))?;
+
+    Ok(Acquisition {
+        container: container_path,
+        manifest: manifest_path,
+        format,
+        created,
+        container_hashes,
+        files: manifest.files,
+        errors,
+    })
+}
+
+/// Verifies a container against the manifest written when it was created
+///
+/// The container digests are recomputed, and every member is read back and
+/// hashed to be compared with the manifest. ZIP members are also checked
+/// against their CRC-32.
+///
+/// # Arguments
+/// * `container` - A string path to the container; its manifest is read from `<container>.manifest.csv`
+///
+/// # Returns
+/// * `Ok(ContainerVerification)` - The result of every check
+/// * `Err(AcquisitionError)` - If the container or manifest cannot be read or parsed
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::verify_container;
+///
+/// let verification = verify_container("E:\\case-17\\flagged.zip").unwrap();
+/// if !verification.is_verified() {
+///     println!("Altered members: {:?}", verification.mismatched());
+/// }
+/// ```
+pub fn verify_container(container: &str) -> Result<ContainerVerification, AcquisitionError> {
+    let container_path = Path::new(container);
+    let manifest = manifest::read(&manifest_path(container_path))?;
+    let computed_hashes = hash_reader(File::open(container_path)?, &HashAlgorithm::EVIDENCE)?;
+
+    let file = File::open(container_path)?;
+    let mut members = match manifest.format {
+        ContainerFormat::Zip => zip::read_members(file)?,
+        ContainerFormat::Tar => tar::read_members(file)?,
+    };
+
+    let mut verification = ContainerVerification {
+        stored_hashes: manifest.container_hashes,
+        computed_hashes,
+        verified: Vec::new(),
+        mismatched: Vec::new(),
+        missing: Vec::new(),
+        unexpected: Vec::new(),
+    };
+    for expected in manifest.files {
+        let Some(position) = members.iter().position(|member| member.name == expected.archive_path) else {
+            verification.missing.push(expected.archive_path);
+            continue;
+        };
+        let member = members.swap_remove(position);
+        let matches = member.intact
+            && member.size == expected.size
+            && !expected.hashes.is_empty()
+            && expected
+                .hashes
+                .iter()
+                .all(|(algorithm, digest)| member.hashes.get(algorithm) == Some(digest));
+        if matches {
+            verification.verified.push(expected.archive_path);
+        } else {
+            verification.mismatched.push(expected.archive_path);
+        }
+    }
+    verification.unexpected = members.into_iter().map(|member| member.name).collect();
+    Ok(verification)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+    use crate::get_files;
+
+    fn acquisition_sources(dir: &Path) -> Vec<FileEntry> {
+        let source = dir.join("source");
+        fs::create_dir_all(source.join("docs")).unwrap();
+        fs::write(source.join("docs").join("report.pdf"), b"%PDF-1.7 not really").unwrap();
+        fs::write(source.join("photo.jpg"), vec![0x5A; 20_000]).unwrap();
+        let mut files = get_files(source.to_str().unwrap()).unwrap();
+        files.sort_by(|a, b| a.path().cmp(b.path()));
+        files
+    }
+
+    #[test]
+    fn test_member_names() {
+        assert_eq!(member_name(Path::new("/home/user/../a.txt")), "home/user/_/a.txt");
+        assert_eq!(member_name(Path::new("docs/a.txt")), "docs/a.txt");
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut files = acquisition_sources(dir.path());
+        assert_eq!(member_names(&files), vec!["docs/report.pdf", "photo.jpg"]);
+
+        files.push(files[1].clone());
+        assert_eq!(member_names(&files)[2], "photo (2).jpg");
+    }
+
+    #[test]
+    fn test_acquire_and_verify() {
+        for format in [ContainerFormat::Zip, ContainerFormat::Tar] {
+            let dir = tempfile::tempdir().unwrap();
+            let files = acquisition_sources(dir.path());
+            let container = dir.path().join(format!("evidence.{}", format.to_string().to_lowercase()));
+
+            let acquisition = acquire_files(&files, container.to_str().unwrap(), format).unwrap();
+            assert!(acquisition.errors().is_empty());
+            assert_eq!(acquisition.files().len(), 2);
+            assert_eq!(acquisition.files()[1].archive_path(), "photo.jpg");
+            assert_eq!(acquisition.files()[1].times(), files[1].times());
+            assert!(acquisition.files()[1].times().accessed().is_some());
+            let sha256 = crate::file_hashing::to_hex(&Sha256::digest(vec![0x5A; 20_000]));
+            assert_eq!(acquisition.files()[1].hashes().sha256(), Some(sha256.as_str()));
+            assert_eq!(acquisition.manifest(), dir.path().join(format!("evidence.{}.manifest.csv", format.to_string().to_lowercase())));
+
+            let verification = verify_container(container.to_str().unwrap()).unwrap();
+            assert!(verification.is_verified(), "{:?}", verification);
+            assert_eq!(verification.verified().len(), 2);
+
+            let manifest = manifest::read(acquisition.manifest()).unwrap();
+            assert_eq!(manifest.format, format);
+            assert_eq!(manifest.files, acquisition.files());
+        }
+    }
+
+    #[test]
+    fn test_verify_detects_tampering() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = acquisition_sources(dir.path());
+        let container = dir.path().join("evidence.tar");
+        acquire_files(&files, container.to_str().unwrap(), ContainerFormat::Tar).unwrap();
+
+        // Flip a byte of the photo content, which follows a PAX header, its records and a ustar header per member
+        let mut data = fs::read(&container).unwrap();
+        data[512 * 7 + 100] ^= 0xFF;
+        fs::write(&container, data).unwrap();
+
+        let verification = verify_container(container.to_str().unwrap()).unwrap();
+        assert!(!verification.container_matches());
+        assert_eq!(verification.mismatched(), ["photo.jpg"]);
+        assert_eq!(verification.verified(), ["docs/report.pdf"]);
+        assert!(!verification.is_verified());
+    }
+
+    #[test]
+    fn test_acquire_unreadable_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = acquisition_sources(dir.path());
+        fs::remove_file(files[0].path()).unwrap();
+        let container = dir.path().join("evidence.zip");
+
+        let acquisition = acquire_files(&files, container.to_str().unwrap(), ContainerFormat::Zip).unwrap();
+        assert_eq!(acquisition.errors().len(), 1);
+        assert_eq!(acquisition.files().len(), 1);
+        assert!(verify_container(container.to_str().unwrap()).unwrap().is_verified());
+    }
+
+    #[test]
+    fn test_manifest_guards_formulas() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        fs::create_dir(&source).unwrap();
+        fs::write(source.join("=cmd.txt"), b"a").unwrap();
+        fs::write(source.join("'quoted.txt"), b"b").unwrap();
+        let files = get_files(source.to_str().unwrap()).unwrap();
+        let container = dir.path().join("evidence.zip");
+
+        let acquisition = acquire_files(&files, container.to_str().unwrap(), ContainerFormat::Zip).unwrap();
+        let manifest = fs::read_to_string(acquisition.manifest()).unwrap();
+        assert!(manifest.contains("\r\n'=cmd.txt,") && manifest.contains("\r\n''quoted.txt,"));
+        assert_eq!(manifest::read(acquisition.manifest()).unwrap().files, acquisition.files());
+        assert!(verify_container(container.to_str().unwrap()).unwrap().is_verified());
+    }
+
+    #[test]
+    fn test_acquire_grown_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = acquisition_sources(dir.path());
+        fs::write(files[0].path(), b"%PDF-1.7 not really, and then some").unwrap();
+        let container = dir.path().join("evidence.tar");
+
+        let acquisition = acquire_files(&files, container.to_str().unwrap(), ContainerFormat::Tar).unwrap();
+        assert_eq!(acquisition.errors().len(), 1);
+        assert_eq!(acquisition.errors()[0].0, files[0].path());
+        assert_eq!(acquisition.files()[0].size(), files[0].size());
+        assert!(verify_container(container.to_str().unwrap()).unwrap().is_verified());
+    }
+}
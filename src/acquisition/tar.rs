@@ -0,0 +1,312 @@
+//! POSIX tar container writer and reader.
+//!
+//! Members are written as ustar entries. Names that do not fit the ustar
+//! fields or are not ASCII, sizes of 8 GiB or more, and times other than
+//! whole seconds of modification since 1970 are carried by a PAX extended
+//! header preceding the entry, as GNU tar and bsdtar expect. The creation
+//! time uses the `LIBARCHIVE.creationtime` key, which bsdtar restores.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+
+use chrono::{DateTime, Utc};
+
+use super::{AcquisitionError, ContainerMember, ContainerWriter};
+use crate::file_hashing::MultiHasher;
+use crate::{FileAttributes, FileTimes, HashAlgorithm};
+
+const BLOCK_SIZE: usize = 512;
+/// Largest size that fits the 11 octal digits of the ustar size field
+const USTAR_MAX_SIZE: u64 = 0o77777777777;
+
+/// Writes a tar archive.
+pub(crate) struct TarWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> TarWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        TarWriter { inner }
+    }
+
+    fn write_header(&mut self, name: &str, size: u64, mtime: u64, kind: u8) -> io::Result<()> {
+        let mut header = [0u8; BLOCK_SIZE];
+        let name_bytes = name.as_bytes();
+        header[..name_bytes.len().min(100)].copy_from_slice(&name_bytes[..name_bytes.len().min(100)]);
+        header[100..108].copy_from_slice(b"0000644\0");
+        header[108..116].copy_from_slice(b"0000000\0");
+        header[116..124].copy_from_slice(b"0000000\0");
+        header[124..136].copy_from_slice(format!("{:011o}\0", size.min(USTAR_MAX_SIZE)).as_bytes());
+        header[136..148].copy_from_slice(format!("{:011o}\0", mtime.min(USTAR_MAX_SIZE)).as_bytes());
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+
+        // The checksum is computed with its own field filled with spaces
+        header[148..156].fill(b' ');
+        let checksum: u32 = header.iter().map(|&byte| byte as u32).sum();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+        self.inner.write_all(&header)
+    }
+
+    fn write_padding(&mut self, size: u64) -> io::Result<()> {
+        let padding = (BLOCK_SIZE - (size % BLOCK_SIZE as u64) as usize) % BLOCK_SIZE;
+        self.inner.write_all(&[0u8; BLOCK_SIZE][..padding])
+    }
+}
+
+/// Formats a PAX record, whose length prefix counts its own digits.
+fn pax_record(key: &str, value: &str) -> String {
+    let body = format!(" {}={}\n", key, value);
+    let mut length = body.len() + 1;
+    while length.to_string().len() + body.len() != length {
+        length = length.to_string().len() + body.len();
+    }
+    format!("{}{}", length, body)
+}
+
+/// Formats a time as PAX decimal seconds since 1970, with the nanoseconds if any.
+fn pax_time(time: DateTime<Utc>) -> String {
+    let (seconds, nanoseconds) = (time.timestamp(), time.timestamp_subsec_nanos());
+    if nanoseconds == 0 {
+        return seconds.to_string();
+    }
+    // The fraction extends the seconds away from zero, so times before 1970 count down from the next second
+    let (sign, seconds, nanoseconds) = if seconds < 0 {
+        ("-", -(seconds + 1), 1_000_000_000 - nanoseconds)
+    } else {
+        ("", seconds, nanoseconds)
+    };
+    let fraction = format!("{:09}", nanoseconds);
+    format!("{}{}.{}", sign, seconds, fraction.trim_end_matches('0'))
+}
+
+impl<W: Write> ContainerWriter for TarWriter<W> {
+    fn add(
+        &mut self,
+        name: &str,
+        times: &FileTimes,
+        _attributes: FileAttributes,
+        size: u64,
+        content: &mut dyn Read,
+    ) -> io::Result<()> {
+        let mtime = times.modified().map_or(0, |modified| modified.timestamp().max(0) as u64);
+
+        let mut pax = String::new();
+        if name.len() > 100 || !name.is_ascii() {
+            pax.push_str(&pax_record("path", name));
+        }
+        if size > USTAR_MAX_SIZE {
+            pax.push_str(&pax_record("size", &size.to_string()));
+        }
+        // The ustar field only holds whole seconds since 1970
+        let modified = times.modified().filter(|modified| modified.timestamp() < 0 || modified.timestamp_subsec_nanos() != 0);
+        let records = [
+            ("mtime", modified),
+            ("atime", times.accessed()),
+            ("ctime", times.changed()),
+            ("LIBARCHIVE.creationtime", times.born()),
+        ];
+        for (key, time) in records {
+            if let Some(time) = time {
+                pax.push_str(&pax_record(key, &pax_time(time)));
+            }
+        }
+        if !pax.is_empty() {
+            let pax_name: String = format!("PaxHeaders/{}", name).chars().filter(char::is_ascii).take(100).collect();
+            self.write_header(&pax_name, pax.len() as u64, mtime, b'x')?;
+            self.inner.write_all(pax.as_bytes())?;
+            self.write_padding(pax.len() as u64)?;
+        }
+
+        let ustar_name: String = name.chars().filter(char::is_ascii).collect();
+        self.write_header(&ustar_name, size, mtime, b'0')?;
+        // The header announces `size` bytes, so the content must provide exactly that many
+        let copied = io::copy(&mut content.take(size), &mut self.inner)?;
+        if copied != size {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("{} is shorter than announced", name)));
+        }
+        self.write_padding(size)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.inner.write_all(&[0u8; BLOCK_SIZE * 2])?;
+        self.inner.flush()
+    }
+}
+
+/// Parses a NUL or space terminated octal field, or a base-256 one.
+fn parse_number(field: &[u8]) -> Option<u64> {
+    if field.first().is_some_and(|byte| byte & 0x80 != 0) {
+        return Some(field[1..].iter().fold(0u64, |value, &byte| (value << 8) | byte as u64));
+    }
+    let text = std::str::from_utf8(field).ok()?;
+    let text = text.trim_matches(|c: char| c == '\0' || c == ' ');
+    if text.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(text, 8).ok()
+}
+
+/// Reads a NUL-terminated string field.
+fn parse_string(field: &[u8]) -> String {
+    let end = field.iter().position(|&byte| byte == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).to_string()
+}
+
+/// Applies the `path` and `size` records of a PAX extended header.
+fn parse_pax(data: &[u8], path: &mut Option<String>, size: &mut Option<u64>) {
+    let text = String::from_utf8_lossy(data);
+    let mut rest: &str = &text;
+    while let Some((length, _)) = rest.split_once(' ') {
+        let Ok(length) = length.parse::<usize>() else {
+            break;
+        };
+        if length == 0 || length > rest.len() {
+            break;
+        }
+        let record = &rest[..length];
+        if let Some((key, value)) = record.split_once(' ').and_then(|(_, record)| record.split_once('=')) {
+            let value = value.strip_suffix('\n').unwrap_or(value);
+            match key {
+                "path" => *path = Some(value.to_string()),
+                "size" => *size = value.parse().ok(),
+                _ => {}
+            }
+        }
+        rest = &rest[length..];
+    }
+}
+
+/// Reads every regular file of a tar archive, hashing its content.
+pub(crate) fn read_members(file: File) -> Result<Vec<ContainerMember>, AcquisitionError> {
+    let mut reader = BufReader::new(file);
+    let mut members = Vec::new();
+    let mut pax_path = None;
+    let mut pax_size = None;
+
+    loop {
+        let mut header = [0u8; BLOCK_SIZE];
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(AcquisitionError::InvalidContainer("missing end of archive".to_string()))
+            }
+            Err(e) => return Err(e.into()),
+        }
+        if header.iter().all(|&byte| byte == 0) {
+            break;
+        }
+
+        let stored: u32 = header[..148].iter().chain(&[b' '; 8]).chain(&header[156..]).map(|&byte| byte as u32).sum();
+        if parse_number(&header[148..156]) != Some(stored as u64) {
+            return Err(AcquisitionError::InvalidContainer("invalid header checksum".to_string()));
+        }
+        let size = pax_size
+            .take()
+            .or_else(|| parse_number(&header[124..136]))
+            .ok_or_else(|| AcquisitionError::InvalidContainer("invalid member size".to_string()))?;
+        let padding = (BLOCK_SIZE as u64 - size % BLOCK_SIZE as u64) % BLOCK_SIZE as u64;
+
+        match header[156] {
+            b'x' => {
+                let mut data = Vec::new();
+                (&mut reader).take(size).read_to_end(&mut data)?;
+                parse_pax(&data, &mut pax_path, &mut pax_size);
+            }
+            b'0' | 0 => {
+                let name = pax_path.take().unwrap_or_else(|| {
+                    let name = parse_string(&header[..100]);
+                    let prefix = parse_string(&header[345..500]);
+                    if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) }
+                });
+                let mut hasher = MultiHasher::new(&HashAlgorithm::EVIDENCE);
+                let mut content = (&mut reader).take(size);
+                let mut buffer = vec![0u8; 64 * 1024];
+                let mut read_size = 0u64;
+                loop {
+                    let read = content.read(&mut buffer)?;
+                    if read == 0 {
+                        break;
+                    }
+                    hasher.update(&buffer[..read]);
+                    read_size += read as u64;
+                }
+                members.push(ContainerMember {
+                    name,
+                    size: read_size,
+                    hashes: hasher.finish(),
+                    intact: read_size == size,
+                });
+            }
+            _ => {
+                pax_path = None;
+                io::copy(&mut (&mut reader).take(size), &mut io::sink())?;
+            }
+        }
+        io::copy(&mut (&mut reader).take(padding), &mut io::sink())?;
+    }
+    Ok(members)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_pax_record() {
+        assert_eq!(pax_record("path", "a"), "9 path=a\n");
+        // The length prefix itself pushes the record past 99 bytes
+        let record = pax_record("path", &"x".repeat(91));
+        assert_eq!(record.len(), 101);
+        assert!(record.starts_with("101 path="));
+    }
+
+    #[test]
+    fn test_pax_time() {
+        let time = Utc.with_ymd_and_hms(2024, 3, 15, 13, 45, 30).unwrap();
+        assert_eq!(pax_time(time), "1710510330");
+        assert_eq!(pax_time(time + chrono::Duration::milliseconds(250)), "1710510330.25");
+        let before_epoch = Utc.timestamp_opt(-2, 500_000_000).unwrap();
+        assert_eq!(pax_time(before_epoch), "-1.5");
+    }
+
+    #[test]
+    fn test_write_and_read_long_name() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.tar");
+        let modified = Utc.with_ymd_and_hms(2024, 3, 15, 13, 45, 30).unwrap();
+        let times = FileTimes::new().with_modified(Some(modified));
+        let attributes = FileAttributes::default();
+        let long_name = format!("{}/résumé.txt", "deep/".repeat(30));
+
+        let mut writer = TarWriter::new(Vec::new());
+        assert!(writer.add("short.txt", &times, attributes, 10, &mut Cursor::new(b"abc")).is_err());
+
+        let mut writer = TarWriter::new(File::create(&path).unwrap());
+        writer.add(&long_name, &times, attributes, 5, &mut Cursor::new(b"hello")).unwrap();
+        let times = times.with_accessed(Some(modified)).with_changed(Some(modified)).with_born(Some(modified));
+        writer.add("short.bin", &times, attributes, 1000, &mut Cursor::new(vec![1u8; 1000])).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len() % BLOCK_SIZE as u64, 0);
+
+        // The times that do not fit the ustar header are in the PAX header of the second member
+        let data = String::from_utf8_lossy(&std::fs::read(&path).unwrap()).to_string();
+        for record in ["atime=1710510330\n", "ctime=1710510330\n", "LIBARCHIVE.creationtime=1710510330\n"] {
+            assert!(data.contains(record), "{}", record);
+        }
+        assert!(!data.contains("mtime="));
+
+        let members = read_members(File::open(&path).unwrap()).unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].name, long_name);
+        assert_eq!(members[0].hashes.md5(), Some("5d41402abc4b2a76b9719d911017c592"));
+        assert_eq!(members[1].name, "short.bin");
+        assert_eq!(members[1].size, 1000);
+        assert!(members[1].intact);
+    }
+}
@@ -0,0 +1,525 @@
+//! ZIP container writer and reader.
+//!
+//! Members are compressed with deflate. Their CRC-32 and compressed size are
+//! only known once the data is written, so the local header is written with
+//! placeholders and patched afterwards, which keeps the archive readable by
+//! tools that ignore data descriptors. ZIP64 extra fields are added for
+//! members and archives that exceed the 32-bit limits. The modification
+//! time is stored as MS-DOS local time, and the modification, access and
+//! creation times as a UTC extended timestamp; the MS-DOS attributes of the
+//! files are kept as their external attributes.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+
+use chrono::{DateTime, Datelike, Local, Timelike, Utc};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+
+use super::{AcquisitionError, ContainerMember, ContainerWriter};
+use crate::file_hashing::MultiHasher;
+use crate::images::{le_u16, le_u32, le_u64};
+use crate::{FileAttributes, FileTimes, HashAlgorithm};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_END_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+/// Flag marking file names encoded in UTF-8
+const UTF8_FLAG: u16 = 0x0800;
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+/// Uncompressed size from which a ZIP64 field is reserved in the local
+/// header, leaving room for deflate to expand incompressible data
+const ZIP64_THRESHOLD: u64 = 0xF000_0000;
+const EXTENDED_TIMESTAMP_ID: u16 = 0x5455;
+/// Extended timestamp flags of the modification, access and creation times
+const EXTENDED_MTIME: u8 = 1;
+const EXTENDED_ATIME: u8 = 2;
+const EXTENDED_CRTIME: u8 = 4;
+/// Attribute bits with the same meaning in the MS-DOS attribute byte
+const DOS_ATTRIBUTES_MASK: u32 = 0x37;
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+
+/// A member already written, described again in the central directory.
+struct CentralEntry {
+    name: String,
+    time: u16,
+    date: u16,
+    /// Flags of the extended timestamp, if any, and the modification time
+    timestamp_flags: u8,
+    mtime: Option<u32>,
+    external_attributes: u32,
+    crc: u32,
+    compressed_size: u64,
+    size: u64,
+    offset: u64,
+}
+
+/// Writes a ZIP archive to a seekable output.
+pub(crate) struct ZipWriter<W: Write + Seek> {
+    inner: W,
+    entries: Vec<CentralEntry>,
+}
+
+impl<W: Write + Seek> ZipWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        ZipWriter {
+            inner,
+            entries: Vec::new(),
+        }
+    }
+}
+
+/// MS-DOS (time, date) fields of 1980-01-01 00:00, the earliest representable time
+const DOS_EPOCH: (u16, u16) = (0, (1 << 5) | 1);
+
+/// Converts a local time to MS-DOS (time, date) fields, clamped to 1980-2107.
+fn dos_date_time(time: DateTime<Local>) -> (u16, u16) {
+    if time.year() < 1980 {
+        return DOS_EPOCH;
+    }
+    let year = (time.year() - 1980).min(127) as u16;
+    let date = (year << 9) | ((time.month() as u16) << 5) | time.day() as u16;
+    let time = ((time.hour() as u16) << 11) | ((time.minute() as u16) << 5) | (time.second() as u16 / 2);
+    (time, date)
+}
+
+/// Converts a time to the 32-bit Unix time of an extended timestamp.
+fn unix_time(time: DateTime<Utc>) -> u32 {
+    time.timestamp().clamp(0, u32::MAX as i64) as u32
+}
+
+/// Appends an extended timestamp field holding `flags` and `times`.
+///
+/// The local header carries every time flagged; the central directory only
+/// the modification time, with the same flags.
+fn push_extended_timestamp(extra: &mut Vec<u8>, flags: u8, times: &[u32]) {
+    extra.extend_from_slice(&EXTENDED_TIMESTAMP_ID.to_le_bytes());
+    extra.extend_from_slice(&(1 + 4 * times.len() as u16).to_le_bytes());
+    extra.push(flags);
+    for time in times {
+        extra.extend_from_slice(&time.to_le_bytes());
+    }
+}
+
+/// Counts the bytes written through it.
+struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write + Seek> ContainerWriter for ZipWriter<W> {
+    fn add(
+        &mut self,
+        name: &str,
+        times: &FileTimes,
+        attributes: FileAttributes,
+        size: u64,
+        content: &mut dyn Read,
+    ) -> io::Result<()> {
+        let offset = self.inner.stream_position()?;
+        // DOS times are local; members without a known time get the DOS epoch and no extended timestamp
+        let (time, date) = times.modified().map_or(DOS_EPOCH, |modified| dos_date_time(modified.with_timezone(&Local)));
+        let mtime = times.modified().map(unix_time);
+        let zip64 = size >= ZIP64_THRESHOLD;
+
+        let mut extra = Vec::new();
+        if zip64 {
+            extra.extend_from_slice(&ZIP64_EXTRA_ID.to_le_bytes());
+            extra.extend_from_slice(&16u16.to_le_bytes());
+            extra.extend_from_slice(&[0u8; 16]);
+        }
+        let mut timestamp_flags = 0;
+        let mut timestamps = Vec::new();
+        // ZIP has no field for the change time of the metadata
+        let flagged = [
+            (EXTENDED_MTIME, times.modified()),
+            (EXTENDED_ATIME, times.accessed()),
+            (EXTENDED_CRTIME, times.born()),
+        ];
+        for (flag, time) in flagged {
+            if let Some(time) = time {
+                timestamp_flags |= flag;
+                timestamps.push(unix_time(time));
+            }
+        }
+        if timestamp_flags != 0 {
+            push_extended_timestamp(&mut extra, timestamp_flags, &timestamps);
+        }
+
+        let mut header = Vec::with_capacity(30 + name.len() + extra.len());
+        header.extend_from_slice(&LOCAL_HEADER_SIGNATURE.to_le_bytes());
+        header.extend_from_slice(&(if zip64 { 45u16 } else { 20u16 }).to_le_bytes());
+        header.extend_from_slice(&UTF8_FLAG.to_le_bytes());
+        header.extend_from_slice(&METHOD_DEFLATE.to_le_bytes());
+        header.extend_from_slice(&time.to_le_bytes());
+        header.extend_from_slice(&date.to_le_bytes());
+        // CRC and sizes are patched once the data is written
+        header.extend_from_slice(&[0u8; 12]);
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        header.extend_from_slice(&extra);
+        self.inner.write_all(&header)?;
+
+        let mut crc = Crc::new();
+        let mut written = 0u64;
+        let mut encoder = DeflateEncoder::new(
+            CountingWriter {
+                inner: &mut self.inner,
+                count: 0,
+            },
+            Compression::default(),
+        );
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let read = content.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            crc.update(&buffer[..read]);
+            encoder.write_all(&buffer[..read])?;
+            written += read as u64;
+        }
+        let compressed_size = encoder.finish()?.count;
+        let end = self.inner.stream_position()?;
+
+        if !zip64 && (written > u32::MAX as u64 || compressed_size > u32::MAX as u64) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} grew past the ZIP member size limit", name)));
+        }
+        self.inner.seek(SeekFrom::Start(offset + 14))?;
+        self.inner.write_all(&crc.sum().to_le_bytes())?;
+        if zip64 {
+            self.inner.write_all(&[0xFF; 8])?;
+            self.inner.seek(SeekFrom::Start(offset + 30 + name.len() as u64 + 4))?;
+            self.inner.write_all(&written.to_le_bytes())?;
+            self.inner.write_all(&compressed_size.to_le_bytes())?;
+        } else {
+            self.inner.write_all(&(compressed_size as u32).to_le_bytes())?;
+            self.inner.write_all(&(written as u32).to_le_bytes())?;
+        }
+        self.inner.seek(SeekFrom::Start(end))?;
+
+        self.entries.push(CentralEntry {
+            name: name.to_string(),
+            time,
+            date,
+            timestamp_flags,
+            mtime,
+            external_attributes: attributes.bits() & DOS_ATTRIBUTES_MASK,
+            crc: crc.sum(),
+            compressed_size,
+            size: written,
+            offset,
+        });
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let directory_offset = self.inner.stream_position()?;
+        for entry in &self.entries {
+            let mut zip64 = Vec::new();
+            let mut field = |value: u64| {
+                if value >= u32::MAX as u64 {
+                    zip64.extend_from_slice(&value.to_le_bytes());
+                    u32::MAX
+                } else {
+                    value as u32
+                }
+            };
+            let size = field(entry.size);
+            let compressed_size = field(entry.compressed_size);
+            let offset = field(entry.offset);
+
+            let mut extra = Vec::new();
+            if !zip64.is_empty() {
+                extra.extend_from_slice(&ZIP64_EXTRA_ID.to_le_bytes());
+                extra.extend_from_slice(&(zip64.len() as u16).to_le_bytes());
+                extra.extend_from_slice(&zip64);
+            }
+            if entry.timestamp_flags != 0 {
+                push_extended_timestamp(&mut extra, entry.timestamp_flags, entry.mtime.as_slice());
+            }
+
+            let version: u16 = if zip64.is_empty() { 20 } else { 45 };
+            let mut header = Vec::with_capacity(46 + entry.name.len() + extra.len());
+            header.extend_from_slice(&CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            header.extend_from_slice(&version.to_le_bytes());
+            header.extend_from_slice(&version.to_le_bytes());
+            header.extend_from_slice(&UTF8_FLAG.to_le_bytes());
+            header.extend_from_slice(&METHOD_DEFLATE.to_le_bytes());
+            header.extend_from_slice(&entry.time.to_le_bytes());
+            header.extend_from_slice(&entry.date.to_le_bytes());
+            header.extend_from_slice(&entry.crc.to_le_bytes());
+            header.extend_from_slice(&compressed_size.to_le_bytes());
+            header.extend_from_slice(&size.to_le_bytes());
+            header.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            header.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+            // Comment length, disk number and internal attributes
+            header.extend_from_slice(&[0u8; 6]);
+            // The version made by records MS-DOS as the host, whose attributes are in the low byte
+            header.extend_from_slice(&entry.external_attributes.to_le_bytes());
+            header.extend_from_slice(&offset.to_le_bytes());
+            header.extend_from_slice(entry.name.as_bytes());
+            header.extend_from_slice(&extra);
+            self.inner.write_all(&header)?;
+        }
+
+        let directory_end = self.inner.stream_position()?;
+        let directory_size = directory_end - directory_offset;
+        let count = self.entries.len() as u64;
+        let needs_zip64 = count >= 0xFFFF || directory_offset >= u32::MAX as u64 || directory_size >= u32::MAX as u64;
+
+        if needs_zip64 {
+            let mut record = Vec::with_capacity(76);
+            record.extend_from_slice(&ZIP64_END_SIGNATURE.to_le_bytes());
+            record.extend_from_slice(&44u64.to_le_bytes());
+            record.extend_from_slice(&45u16.to_le_bytes());
+            record.extend_from_slice(&45u16.to_le_bytes());
+            record.extend_from_slice(&[0u8; 8]);
+            record.extend_from_slice(&count.to_le_bytes());
+            record.extend_from_slice(&count.to_le_bytes());
+            record.extend_from_slice(&directory_size.to_le_bytes());
+            record.extend_from_slice(&directory_offset.to_le_bytes());
+            record.extend_from_slice(&ZIP64_LOCATOR_SIGNATURE.to_le_bytes());
+            record.extend_from_slice(&0u32.to_le_bytes());
+            record.extend_from_slice(&directory_end.to_le_bytes());
+            record.extend_from_slice(&1u32.to_le_bytes());
+            self.inner.write_all(&record)?;
+        }
+
+        let mut end = Vec::with_capacity(22);
+        end.extend_from_slice(&END_SIGNATURE.to_le_bytes());
+        end.extend_from_slice(&[0u8; 4]);
+        let count16 = count.min(0xFFFF) as u16;
+        end.extend_from_slice(&count16.to_le_bytes());
+        end.extend_from_slice(&count16.to_le_bytes());
+        end.extend_from_slice(&(directory_size.min(u32::MAX as u64) as u32).to_le_bytes());
+        end.extend_from_slice(&(directory_offset.min(u32::MAX as u64) as u32).to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        self.inner.write_all(&end)?;
+        self.inner.flush()
+    }
+}
+
+/// A member listed in the central directory.
+struct DirectoryEntry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: u64,
+    offset: u64,
+}
+
+fn invalid(message: &str) -> AcquisitionError {
+    AcquisitionError::InvalidContainer(message.to_string())
+}
+
+/// Reads the central directory of a ZIP archive.
+fn read_directory(file: &mut File) -> Result<Vec<DirectoryEntry>, AcquisitionError> {
+    let length = file.seek(SeekFrom::End(0))?;
+    let tail_length = length.min(22 + 0xFFFF);
+    let mut tail = vec![0u8; tail_length as usize];
+    file.seek(SeekFrom::Start(length - tail_length))?;
+    file.read_exact(&mut tail)?;
+
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&position| le_u32(&tail, position) == END_SIGNATURE)
+        .ok_or_else(|| invalid("end of central directory not found"))?;
+    let mut count = le_u16(&tail, end + 10) as u64;
+    let mut directory_size = le_u32(&tail, end + 12) as u64;
+    let mut directory_offset = le_u32(&tail, end + 16) as u64;
+
+    if end >= 20 && le_u32(&tail, end - 20) == ZIP64_LOCATOR_SIGNATURE {
+        let mut record = [0u8; 56];
+        file.seek(SeekFrom::Start(le_u64(&tail, end - 12)))?;
+        file.read_exact(&mut record)?;
+        if le_u32(&record, 0) != ZIP64_END_SIGNATURE {
+            return Err(invalid("invalid ZIP64 end of central directory"));
+        }
+        count = le_u64(&record, 32);
+        directory_size = le_u64(&record, 40);
+        directory_offset = le_u64(&record, 48);
+    }
+
+    let mut directory = Vec::new();
+    file.seek(SeekFrom::Start(directory_offset))?;
+    file.take(directory_size).read_to_end(&mut directory)?;
+
+    let mut entries = Vec::new();
+    let mut position = 0;
+    for _ in 0..count {
+        if position + 46 > directory.len() || le_u32(&directory, position) != CENTRAL_HEADER_SIGNATURE {
+            return Err(invalid("invalid central directory entry"));
+        }
+        let name_length = le_u16(&directory, position + 28) as usize;
+        let extra_length = le_u16(&directory, position + 30) as usize;
+        let comment_length = le_u16(&directory, position + 32) as usize;
+        let name_start = position + 46;
+        let extra_start = name_start + name_length;
+        let next = extra_start + extra_length + comment_length;
+        if next > directory.len() {
+            return Err(invalid("truncated central directory entry"));
+        }
+
+        let mut size = le_u32(&directory, position + 24) as u64;
+        let mut compressed_size = le_u32(&directory, position + 20) as u64;
+        let mut offset = le_u32(&directory, position + 42) as u64;
+
+        // ZIP64 values appear in the order of the fields they replace
+        let extra = &directory[extra_start..extra_start + extra_length];
+        let mut field = 0;
+        while field + 4 <= extra.len() {
+            let id = le_u16(extra, field);
+            let data_length = le_u16(extra, field + 2) as usize;
+            let data = &extra[field + 4..(field + 4 + data_length).min(extra.len())];
+            if id == ZIP64_EXTRA_ID {
+                let mut values = data.chunks_exact(8).map(|value| le_u64(value, 0));
+                for slot in [&mut size, &mut compressed_size, &mut offset] {
+                    if *slot == u32::MAX as u64 {
+                        *slot = values.next().ok_or_else(|| invalid("truncated ZIP64 field"))?;
+                    }
+                }
+            }
+            field += 4 + data_length;
+        }
+
+        entries.push(DirectoryEntry {
+            name: String::from_utf8_lossy(&directory[name_start..extra_start]).to_string(),
+            method: le_u16(&directory, position + 10),
+            crc: le_u32(&directory, position + 16),
+            compressed_size,
+            offset,
+        });
+        position = next;
+    }
+    Ok(entries)
+}
+
+/// Reads every member of a ZIP archive, hashing its content and checking its CRC-32.
+pub(crate) fn read_members(file: File) -> Result<Vec<ContainerMember>, AcquisitionError> {
+    let mut file = file;
+    let entries = read_directory(&mut file)?;
+    let mut members = Vec::with_capacity(entries.len());
+
+    for entry in entries {
+        if entry.name.ends_with('/') {
+            continue;
+        }
+        let mut header = [0u8; 30];
+        file.seek(SeekFrom::Start(entry.offset))?;
+        file.read_exact(&mut header)?;
+        if le_u32(&header, 0) != LOCAL_HEADER_SIGNATURE {
+            return Err(invalid("invalid local header"));
+        }
+        let data_offset = entry.offset + 30 + le_u16(&header, 26) as u64 + le_u16(&header, 28) as u64;
+        file.seek(SeekFrom::Start(data_offset))?;
+
+        let data = BufReader::new((&mut file).take(entry.compressed_size));
+        let mut reader: Box<dyn Read + '_> = match entry.method {
+            METHOD_DEFLATE => Box::new(DeflateDecoder::new(data)),
+            METHOD_STORED => Box::new(data),
+            method => return Err(AcquisitionError::InvalidContainer(format!("unsupported compression method {}", method))),
+        };
+
+        let mut hasher = MultiHasher::new(&HashAlgorithm::EVIDENCE);
+        let mut crc = Crc::new();
+        let mut buffer = vec![0u8; 64 * 1024];
+        let mut size = 0u64;
+        let mut intact = true;
+        loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(read) => read,
+                // Corrupt compressed data makes the member unreadable, not the whole archive
+                Err(e) if e.kind() == io::ErrorKind::InvalidInput || e.kind() == io::ErrorKind::InvalidData => {
+                    intact = false;
+                    break;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            crc.update(&buffer[..read]);
+            size += read as u64;
+        }
+
+        members.push(ContainerMember {
+            name: entry.name,
+            size,
+            hashes: hasher.finish(),
+            intact: intact && crc.sum() == entry.crc,
+        });
+    }
+    Ok(members)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_dos_date_time() {
+        let time = Local.with_ymd_and_hms(2024, 3, 15, 13, 45, 31).unwrap();
+        assert_eq!(dos_date_time(time), ((13 << 11) | (45 << 5) | 15, (44 << 9) | (3 << 5) | 15));
+        let time = Local.with_ymd_and_hms(1970, 1, 1, 12, 0, 0).unwrap();
+        assert_eq!(dos_date_time(time), (0, (1 << 5) | 1));
+    }
+
+    #[test]
+    fn test_write_and_read() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.zip");
+        let modified = Utc.with_ymd_and_hms(2024, 3, 15, 13, 45, 30).unwrap();
+        let times = FileTimes::new().with_modified(Some(modified)).with_born(Some(modified - chrono::Duration::days(1)));
+        let attributes = FileAttributes::HIDDEN;
+
+        let mut writer = ZipWriter::new(File::create(&path).unwrap());
+        let content = vec![7u8; 100_000];
+        writer.add("dir/a.bin", &times, attributes, 100_000, &mut Cursor::new(&content)).unwrap();
+        writer.add("b.txt", &FileTimes::new(), FileAttributes::default(), 5, &mut Cursor::new(b"hello")).unwrap();
+        writer.finish().unwrap();
+
+        // The local header holds the modification and creation times, the central directory only the former
+        let data = std::fs::read(&path).unwrap();
+        let mtime = (modified.timestamp() as u32).to_le_bytes();
+        let crtime = (modified.timestamp() as u32 - 86_400).to_le_bytes();
+        let local = [&[0x55, 0x54, 9, 0, EXTENDED_MTIME | EXTENDED_CRTIME][..], &mtime, &crtime].concat();
+        assert_eq!(&data[30 + 9..30 + 9 + 13], &local[..]);
+        let central = data.windows(4).position(|window| window == CENTRAL_HEADER_SIGNATURE.to_le_bytes()).unwrap();
+        let central_extra = [&[0x55, 0x54, 5, 0, EXTENDED_MTIME | EXTENDED_CRTIME][..], &mtime].concat();
+        assert_eq!(&data[central + 46 + 9..central + 46 + 9 + 9], &central_extra[..]);
+        assert_eq!(le_u32(&data, central + 38), FileAttributes::HIDDEN.bits());
+
+        let members = read_members(File::open(&path).unwrap()).unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].name, "dir/a.bin");
+        assert_eq!(members[0].size, 100_000);
+        assert!(members[0].intact);
+        assert_eq!(members[1].hashes.md5(), Some("5d41402abc4b2a76b9719d911017c592"));
+
+        // Deflate shrinks the repeated bytes well below their size
+        assert!(std::fs::metadata(&path).unwrap().len() < 2_000);
+    }
+}
@@ -0,0 +1,546 @@
+//! This module recovers files from raw byte ranges by their signatures.
+//!
+//! Every sector of the scanned range is matched against the signatures known
+//! to `infer`, the same detection used by `identify_files`. The end of each
+//! recovered file is found by walking its internal structure when the format
+//! allows it (JPEG segments, PNG chunks, ZIP end record, RIFF and MP4 sizes,
+//! ...). Otherwise the file is cut at the next signature, the end of the
+//! range or the configured maximum size, whichever comes first.
+
+use std::io::{Read, Seek};
+use std::path::PathBuf;
+
+use crate::images::{
+    be_u16, be_u32, be_u64, free_clusters, le_u16, le_u32, le_u64, read_bytes, ImageError, SharedImage, VirtualDisk,
+};
+use crate::{get_image_disk, DataRun, ExtentKind, FileEntry, FileTimes};
+
+/// Largest file recovered when its end cannot be determined from its content
+pub const DEFAULT_MAX_CARVE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Files are assumed to start on a sector boundary
+const SECTOR_SIZE: u64 = 512;
+/// Number of bytes scanned between reads of the device
+const SCAN_BLOCK_SIZE: u64 = 1024 * 1024;
+/// Number of leading bytes given to `infer` for each sector
+const HEADER_WINDOW: u64 = 8192;
+/// Name of the virtual directory holding carved files
+const CARVED_DIRECTORY: &str = "$Carved";
+
+/// A file signature found on the device.
+struct Hit {
+    offset: u64,
+    extension: &'static str,
+}
+
+/// Finds the sectors of `start..end` that begin with a known file signature.
+fn find_signatures<R: Read + Seek>(device: &mut R, start: u64, end: u64) -> Result<Vec<Hit>, ImageError> {
+    let mut hits = Vec::new();
+    let mut position = start;
+
+    while position < end {
+        let scan = SCAN_BLOCK_SIZE.min(end - position);
+        // Read past the block so signatures near its end see a full window
+        let block = read_bytes(device, position, (scan + HEADER_WINDOW).min(end - position) as usize)?;
+
+        for sector in (0..scan as usize).step_by(SECTOR_SIZE as usize) {
+            let window = &block[sector..block.len().min(sector + HEADER_WINDOW as usize)];
+            // Blank and filled sectors are by far the most common and never match
+            let lead = &window[..window.len().min(16)];
+            if lead.iter().all(|&byte| byte == lead[0]) {
+                continue;
+            }
+            if let Some(kind) = infer::get(window) {
+                hits.push(Hit {
+                    offset: position + sector as u64,
+                    extension: kind.extension(),
+                });
+            }
+        }
+        position += scan;
+    }
+    Ok(hits)
+}
+
+/// Finds the end of a JPEG by walking its segments up to the EOI marker.
+fn jpeg_length(data: &[u8]) -> Option<usize> {
+    let mut position = 2;
+    loop {
+        while *data.get(position)? == 0xFF && *data.get(position + 1)? == 0xFF {
+            position += 1;
+        }
+        if *data.get(position)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(position + 1)?;
+        position += 2;
+        match marker {
+            0xD9 => return Some(position),
+            0x01 | 0xD0..=0xD7 => continue,
+            0xD8 => return None,
+            _ => {}
+        }
+
+        let length = be_u16(data.get(position..position + 2)?, 0) as usize;
+        if length < 2 {
+            return None;
+        }
+        position += length;
+
+        if marker == 0xDA {
+            // Entropy-coded data runs until a marker other than stuffing or restart
+            loop {
+                if *data.get(position)? == 0xFF {
+                    let next = *data.get(position + 1)?;
+                    if next != 0x00 && !(0xD0..=0xD7).contains(&next) && next != 0xFF {
+                        break;
+                    }
+                    position += if next == 0xFF { 1 } else { 2 };
+                } else {
+                    position += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Finds the end of a PNG by walking its chunks up to IEND.
+fn png_length(data: &[u8]) -> Option<usize> {
+    let mut position = 8;
+    loop {
+        let length = be_u32(data.get(position..position + 4)?, 0) as usize;
+        let kind = data.get(position + 4..position + 8)?;
+        position = position.checked_add(12 + length)?;
+        if kind == b"IEND" {
+            return (position <= data.len()).then_some(position);
+        }
+    }
+}
+
+/// Skips a chain of GIF data sub-blocks.
+fn gif_skip_sub_blocks(data: &[u8], mut position: usize) -> Option<usize> {
+    loop {
+        let size = *data.get(position)? as usize;
+        position += 1 + size;
+        if size == 0 {
+            return Some(position);
+        }
+    }
+}
+
+/// Finds the end of a GIF by walking its blocks up to the trailer.
+fn gif_length(data: &[u8]) -> Option<usize> {
+    let flags = *data.get(10)?;
+    let mut position = 13;
+    if flags & 0x80 != 0 {
+        position += 3 << ((flags & 0x07) + 1);
+    }
+    loop {
+        match *data.get(position)? {
+            0x3B => return Some(position + 1),
+            0x21 => position = gif_skip_sub_blocks(data, position + 2)?,
+            0x2C => {
+                let flags = *data.get(position + 9)?;
+                position += 10;
+                if flags & 0x80 != 0 {
+                    position += 3 << ((flags & 0x07) + 1);
+                }
+                position = gif_skip_sub_blocks(data, position + 1)?;
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// Returns the position of the first occurrence of `needle` in `data` after `from`.
+fn find(data: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    data.get(from..)?.windows(needle.len()).position(|window| window == needle).map(|position| from + position)
+}
+
+/// Finds the end of a PDF, keeping incremental updates appended to it.
+fn pdf_length(data: &[u8]) -> Option<usize> {
+    let limit = find(data, b"%PDF-", 1).unwrap_or(data.len());
+    let data = &data[..limit];
+    let end = data.windows(5).rposition(|window| window == b"%%EOF")? + 5;
+    let eol = data[end..].iter().take(2).take_while(|&&byte| byte == b'\r' || byte == b'\n').count();
+    Some(end + eol)
+}
+
+/// Finds the end of a ZIP archive (and ZIP-based documents) from its end of central directory.
+fn zip_length(data: &[u8]) -> Option<usize> {
+    let record = find(data, b"PK\x05\x06", 4)?;
+    let comment = le_u16(data.get(record + 20..record + 22)?, 0) as usize;
+    Some(record + 22 + comment)
+}
+
+/// Finds the end of an MP4 or QuickTime file by walking its top-level atoms.
+fn mp4_length(data: &[u8]) -> Option<usize> {
+    let mut position = 0;
+    while let Some(header) = data.get(position..).and_then(|rest| rest.get(..8)) {
+        if !header[4..8].iter().all(|byte| byte.is_ascii_alphanumeric() || *byte == b' ') {
+            break;
+        }
+        let size = match be_u32(header, 0) as u64 {
+            1 => be_u64(data.get(position + 8..position + 16)?, 0),
+            0 => return None,
+            size => size,
+        };
+        if size < 8 {
+            break;
+        }
+        position = position.checked_add(usize::try_from(size).ok()?)?;
+    }
+    (position > 0).then_some(position)
+}
+
+/// Finds the end of an Ogg stream by walking its pages up to the end-of-stream page.
+fn ogg_length(data: &[u8]) -> Option<usize> {
+    let mut position = 0;
+    loop {
+        let header = data.get(position..position + 27)?;
+        if &header[0..4] != b"OggS" {
+            return None;
+        }
+        let segments = header[26] as usize;
+        let table = data.get(position + 27..position + 27 + segments)?;
+        position += 27 + segments + table.iter().map(|&size| size as usize).sum::<usize>();
+        if header[5] & 0x04 != 0 {
+            return Some(position);
+        }
+    }
+}
+
+/// Returns the length of a file starting at the beginning of `data`, when its
+/// format records or delimits it.
+fn file_length(data: &[u8]) -> Option<usize> {
+    let length = if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        jpeg_length(data)
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        png_length(data)
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        gif_length(data)
+    } else if data.starts_with(b"%PDF-") {
+        pdf_length(data)
+    } else if data.starts_with(b"PK\x03\x04") {
+        zip_length(data)
+    } else if data.starts_with(b"BM") {
+        data.get(2..6).map(|size| le_u32(size, 0) as usize)
+    } else if data.starts_with(b"RIFF") {
+        data.get(4..8).map(|size| le_u32(size, 0) as usize + 8)
+    } else if data.get(4..8) == Some(b"ftyp") {
+        mp4_length(data)
+    } else if data.starts_with(b"OggS") {
+        ogg_length(data)
+    } else if data.starts_with(b"SQLite format 3\0") {
+        data.get(16..32).and_then(|header| {
+            let page_size: usize = match be_u16(header, 0) {
+                1 => 65536,
+                size => size as usize,
+            };
+            page_size.checked_mul(be_u32(header, 12) as usize)
+        })
+    } else if data.starts_with(b"7z\xBC\xAF\x27\x1C") {
+        data.get(12..28).and_then(|header| {
+            let next_header_offset = usize::try_from(le_u64(header, 0)).ok()?;
+            let next_header_size = usize::try_from(le_u64(header, 8)).ok()?;
+            32usize.checked_add(next_header_offset)?.checked_add(next_header_size)
+        })
+    } else {
+        None
+    };
+    // A length past the data read means the file was cut or the header is bogus
+    length.filter(|&length| length > 0 && length <= data.len())
+}
+
+/// Carves files from a list of `(offset, length)` ranges of an image.
+fn carve_regions(path: &str, regions: &[(u64, u64)], max_size: u64) -> Result<Vec<FileEntry>, ImageError> {
+    let mut device = VirtualDisk::open(path)?;
+    let image = PathBuf::from(path);
+    let shared = SharedImage::new(image.clone());
+    let mut files = Vec::new();
+
+    for &(start, length) in regions {
+        let end = start.saturating_add(length).min(device.size());
+        if start >= end {
+            continue;
+        }
+        let hits = find_signatures(&mut device, start, end)?;
+
+        // Signatures inside a file whose end is known belong to that file
+        let mut covered = start;
+        for (index, hit) in hits.iter().enumerate() {
+            if hit.offset < covered {
+                continue;
+            }
+            let limit = max_size.min(end - hit.offset);
+            let data = read_bytes(&mut device, hit.offset, limit as usize)?;
+            let length = match file_length(&data) {
+                Some(length) => {
+                    covered = hit.offset + length as u64;
+                    length as u64
+                }
+                None => {
+                    let next = hits.get(index + 1).map(|next| next.offset).unwrap_or(end);
+                    limit.min(next - hit.offset)
+                }
+            };
+
+            let name = format!("f{:010}.{}", hit.offset / SECTOR_SIZE, hit.extension);
+            files.push(FileEntry::from_image(
+                image.join(CARVED_DIRECTORY).join(name),
+                length,
+                FileTimes::new(),
+                &shared,
+                vec![DataRun::Extent {
+                    offset: hit.offset,
+                    length,
+                }],
+            ));
+        }
+    }
+    Ok(files)
+}
+
+/// Recovers files from a byte range of an image by their signatures
+///
+/// Each carved file is returned as a `FileEntry` named after the sector it
+/// starts at (`f0000002048.jpg`), located under a virtual `$Carved`
+/// directory of the image. Its content can be read with `FileEntry::open`,
+/// and `FileEntry::image_offset` gives its position in the image.
+///
+/// # Arguments
+/// * `path` - A string path to the image file, in any format supported by `VirtualDisk`
+/// * `offset` - Byte offset of the range to scan
+/// * `length` - Length of the range to scan in bytes
+/// * `max_size` - Largest file recovered when its end cannot be found from its content
+///
+/// # Returns
+/// * `Ok(Vec<FileEntry>)` - The recovered files, ordered by offset
+/// * `Err(ImageError)` - If the image cannot be read
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::{carve_range, DEFAULT_MAX_CARVE_SIZE};
+///
+/// // Scan the first partition of an SD card image
+/// let files = carve_range("sdcard.img", 4_194_304, 31_914_983_424, DEFAULT_MAX_CARVE_SIZE).unwrap();
+/// ```
+pub fn carve_range(path: &str, offset: u64, length: u64, max_size: u64) -> Result<Vec<FileEntry>, ImageError> {
+    carve_regions(path, &[(offset, length)], max_size)
+}
+
+/// Recovers files from a whole image by their signatures
+///
+/// See `carve_range` for the naming and content of the returned entries.
+///
+/// # Arguments
+/// * `path` - A string path to the image file, in any format supported by `VirtualDisk`
+/// * `max_size` - Largest file recovered when its end cannot be found from its content
+///
+/// # Returns
+/// * `Ok(Vec<FileEntry>)` - The recovered files, ordered by offset
+/// * `Err(ImageError)` - If the image cannot be read
+///
+/// # Examples
+/// ```no_run
+/// use std::io::Read;
+/// use win_disk_info::{carve_files, DEFAULT_MAX_CARVE_SIZE};
+///
+/// for file in carve_files("sdcard.img", DEFAULT_MAX_CARVE_SIZE).unwrap() {
+///     let mut content = Vec::new();
+///     file.open().unwrap().read_to_end(&mut content).unwrap();
+///     std::fs::write(format!("recovered/{}", file.name()), content).unwrap();
+/// }
+/// ```
+pub fn carve_files(path: &str, max_size: u64) -> Result<Vec<FileEntry>, ImageError> {
+    carve_regions(path, &[(0, u64::MAX)], max_size)
+}
+
+/// Recovers files from the space of an image that holds no live data
+///
+/// Two kinds of space are scanned. Deleted or overwritten partitions often
+/// leave whole files behind in the gaps between the current partitions,
+/// which are the unallocated extents of the disk returned by
+/// `get_image_disk`; an image without a partition table or file system is
+/// scanned as a whole. Deleted files, such as photos removed from an SD
+/// card, stay in the free clusters of their volume until they are reused,
+/// so the clusters that the allocation table of FAT volumes and the
+/// `$Bitmap` of NTFS volumes mark as free are scanned too. A FAT or NTFS
+/// volume whose allocation metadata is corrupt is scanned as a whole, and
+/// volumes of other file systems, exFAT included, are not scanned. See
+/// `carve_range` for the naming and content of the returned entries.
+///
+/// # Arguments
+/// * `path` - A string path to the image file, in any format supported by `VirtualDisk`
+/// * `max_size` - Largest file recovered when its end cannot be found from its content
+///
+/// # Returns
+/// * `Ok(Vec<FileEntry>)` - The recovered files, ordered by offset
+/// * `Err(ImageError)` - If the image cannot be read
+pub fn carve_unallocated(path: &str, max_size: u64) -> Result<Vec<FileEntry>, ImageError> {
+    let mut regions: Vec<(u64, u64)> = get_image_disk(path)?
+        .extents()
+        .iter()
+        .filter(|extent| *extent.kind() == ExtentKind::Unallocated)
+        .map(|extent| (extent.offset(), extent.length()))
+        .collect();
+    regions.extend(free_clusters(&mut VirtualDisk::open(path)?)?);
+
+    // Files may span free clusters that follow each other, or a volume and the gap after it
+    regions.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::new();
+    for (offset, length) in regions {
+        match merged.last_mut() {
+            Some((start, merged_length)) if offset <= *start + *merged_length => {
+                *merged_length = (*merged_length).max(offset + length - *start);
+            }
+            _ => merged.push((offset, length)),
+        }
+    }
+    carve_regions(path, &merged, max_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    /// Builds a small baseline JPEG with stuffed bytes and a restart marker.
+    fn jpeg() -> Vec<u8> {
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10];
+        jpeg.extend(b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0");
+        jpeg.extend([0xFF, 0xDA, 0x00, 0x08, 1, 1, 0, 0, 0x3F, 0]);
+        jpeg.extend([0x12, 0xFF, 0x00, 0x34, 0xFF, 0xD0, 0x56, 0x78]);
+        jpeg.extend([0xFF, 0xD9]);
+        jpeg
+    }
+
+    fn png() -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend(13u32.to_be_bytes());
+        png.extend(b"IHDR");
+        png.extend([0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0]);
+        png.extend([0; 4]);
+        png.extend(0u32.to_be_bytes());
+        png.extend(b"IEND");
+        png.extend([0xAE, 0x42, 0x60, 0x82]);
+        png
+    }
+
+    fn write_image(content: &[(u64, Vec<u8>)], size: usize) -> (tempfile::TempDir, String) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut image = vec![0u8; size];
+        for (offset, data) in content {
+            image[*offset as usize..*offset as usize + data.len()].copy_from_slice(data);
+        }
+        let path = temp_dir.path().join("card.img");
+        std::fs::write(&path, image).unwrap();
+        let path = path.to_str().unwrap().to_string();
+        (temp_dir, path)
+    }
+
+    #[test]
+    fn test_jpeg_length() {
+        let mut data = jpeg();
+        let length = data.len();
+        data.extend([0xFF, 0xD8, 0xFF, 0xE0]);
+        assert_eq!(jpeg_length(&data), Some(length));
+        assert_eq!(jpeg_length(&data[..length - 1]), None);
+    }
+
+    #[test]
+    fn test_carve_files() {
+        let gzip = [0x1F, 0x8B, 0x08, 0x00, 0x11, 0x22, 0x33, 0x44];
+        let (_temp_dir, path) = write_image(&[(1024, jpeg()), (8192, png()), (16384, gzip.to_vec())], 64 * 1024);
+
+        let files = carve_files(&path, DEFAULT_MAX_CARVE_SIZE).unwrap();
+        assert_eq!(files.len(), 3);
+
+        assert_eq!(files[0].name(), "f0000000002.jpg");
+        assert_eq!(files[0].size(), jpeg().len() as u64);
+        assert_eq!(files[0].image_offset(), Some(1024));
+        let mut content = Vec::new();
+        files[0].open().unwrap().read_to_end(&mut content).unwrap();
+        assert_eq!(content, jpeg());
+
+        assert_eq!(files[1].extension(), Some("png"));
+        assert_eq!(files[1].size(), png().len() as u64);
+
+        // The end of a gzip stream is not recorded, so the file runs to the end of the image
+        assert_eq!(files[2].extension(), Some("gz"));
+        assert_eq!(files[2].size(), 64 * 1024 - 16384);
+    }
+
+    #[test]
+    fn test_bogus_recorded_lengths() {
+        // A 7z next header offset that wraps around to a small length when added up
+        let mut seven_zip = b"7z\xBC\xAF\x27\x1C\x00\x04".to_vec();
+        seven_zip.extend([0; 4]);
+        seven_zip.extend((u64::MAX - 10).to_le_bytes());
+        seven_zip.extend(50u64.to_le_bytes());
+        seven_zip.extend([0; 4]);
+        let mut data = seven_zip.clone();
+        data.resize(4096, 0);
+        assert_eq!(file_length(&data), None);
+
+        let mut sqlite = b"SQLite format 3\0".to_vec();
+        sqlite.extend(1u16.to_be_bytes());
+        sqlite.extend([0; 10]);
+        sqlite.extend(u32::MAX.to_be_bytes());
+        sqlite.resize(4096, 0);
+        assert_eq!(file_length(&sqlite), None);
+
+        // An MP4 atom with a 64-bit size reaching the end of the address space
+        let mut mp4 = 1u32.to_be_bytes().to_vec();
+        mp4.extend(b"ftyp");
+        mp4.extend((u64::MAX - 4).to_be_bytes());
+        mp4.resize(4096, 0);
+        assert_eq!(file_length(&mp4), None);
+
+        let (_temp_dir, path) = write_image(&[(4096, seven_zip)], 64 * 1024);
+        let files = carve_files(&path, DEFAULT_MAX_CARVE_SIZE).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].size(), 64 * 1024 - 4096);
+    }
+
+    #[test]
+    fn test_carve_max_size() {
+        let gzip = [0x1F, 0x8B, 0x08, 0x00, 0x11, 0x22, 0x33, 0x44];
+        let (_temp_dir, path) = write_image(&[(0, gzip.to_vec()), (4096, png())], 64 * 1024);
+
+        let files = carve_files(&path, 1000).unwrap();
+        assert_eq!(files[0].size(), 1000);
+        assert_eq!(files[1].size(), png().len() as u64);
+    }
+
+    #[test]
+    fn test_carve_unallocated() {
+        let mut mbr = vec![0u8; 512];
+        crate::partition_table::tests::mbr_entry(&mut mbr, 0, 0x0C, 8, 64);
+        let inside = 8 * 512 + 1024;
+        let gap = 80 * 512;
+        let (_temp_dir, path) = write_image(&[(0, mbr), (inside, jpeg()), (gap, png())], 128 * 512);
+
+        let files = carve_unallocated(&path, DEFAULT_MAX_CARVE_SIZE).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].image_offset(), Some(gap));
+    }
+
+    #[test]
+    fn test_carve_free_clusters() {
+        // A card with a single FAT32 partition has no space outside it
+        let mut card = vec![0u8; 512];
+        crate::partition_table::tests::mbr_entry(&mut card, 0, 0x0C, 8, 128);
+        let volume = 8 * 512;
+        let fat = crate::images::tests::build_fat32_volume();
+        // Cluster N of the volume is at its sector N; cluster 4 holds a live file, cluster 10 is free
+        let content = [(0, card), (volume, fat), (volume + 4 * 512, png()), (volume + 10 * 512, jpeg())];
+        let (_temp_dir, path) = write_image(&content, 136 * 512);
+
+        let files = carve_unallocated(&path, DEFAULT_MAX_CARVE_SIZE).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].image_offset(), Some(volume + 10 * 512));
+        assert_eq!(files[0].size(), jpeg().len() as u64);
+    }
+}
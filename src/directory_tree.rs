@@ -0,0 +1,444 @@
+//! This module aggregates the sizes of a directory tree, directory by directory.
+//!
+//! Every directory down to a chosen depth becomes a node holding the size of
+//! its own files and the size of everything below it, so that the question
+//! "what is using the space of this drive" can be answered by walking down
+//! the largest branches. The whole tree is always measured; deeper
+//! directories are only folded into the sizes of their ancestors, and can be
+//! expanded into nodes later by walking their subtree again.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "serialize")]
+use serde::Serialize;
+
+use crate::file_extraction::format_file_size;
+use crate::scanner::{walk_sizes, ScanOptions};
+
+/// A directory of a `DirectoryTree` with the sizes of its files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct DirectoryNode {
+    path: PathBuf,
+    /// Depth below the root of the tree, 0 for the root
+    depth: usize,
+    own_size: u64,
+    own_files: u64,
+    size: u64,
+    files: u64,
+    /// Subdirectories, largest first
+    children: Vec<DirectoryNode>,
+    expanded: bool,
+}
+
+impl DirectoryNode {
+    /// Returns the path of the directory
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the name of the directory, or its path for the root of a volume
+    pub fn name(&self) -> String {
+        match self.path.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => self.path.to_string_lossy().to_string(),
+        }
+    }
+
+    /// Returns the depth of the directory below the root of the tree, 0 for the root
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the total size in bytes of the files directly inside the directory
+    pub fn own_size(&self) -> u64 {
+        self.own_size
+    }
+
+    /// Returns the number of files directly inside the directory
+    pub fn own_file_count(&self) -> u64 {
+        self.own_files
+    }
+
+    /// Returns the total size in bytes of the files inside the directory and all its subdirectories
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the number of files inside the directory and all its subdirectories
+    pub fn file_count(&self) -> u64 {
+        self.files
+    }
+
+    /// Returns the subdirectories, largest first, or nothing if the directory was not expanded
+    pub fn children(&self) -> &[DirectoryNode] {
+        &self.children
+    }
+
+    /// Returns the `count` largest subdirectories, largest first
+    pub fn largest_children(&self, count: usize) -> &[DirectoryNode] {
+        &self.children[..count.min(self.children.len())]
+    }
+
+    /// Returns true if the subdirectories are nodes of the tree, false if they are only counted in the sizes
+    pub fn is_expanded(&self) -> bool {
+        self.expanded
+    }
+
+    /// Returns the directory and all the nodes below it, each directory before its subdirectories.
+    pub fn iter(&self) -> impl Iterator<Item = &DirectoryNode> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children.iter().rev());
+            Some(node)
+        })
+    }
+
+    /// Sorts the children largest first, then by name.
+    fn sort_children(&mut self) {
+        self.children
+            .sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    }
+}
+
+impl fmt::Display for DirectoryNode {
+    /// Formats the directory and its expanded subdirectories as an indented list of sizes.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        for node in self.iter() {
+            if !first {
+                writeln!(f)?;
+            }
+            first = false;
+            let indent = "  ".repeat(node.depth - self.depth);
+            write!(f, "{}{}  {}", indent, node.name(), format_file_size(node.size))?;
+        }
+        Ok(())
+    }
+}
+
+/// Sizes of the files of a directory, gathered during a walk.
+#[derive(Debug, Clone, Copy, Default)]
+struct Tally {
+    own_size: u64,
+    own_files: u64,
+    /// Files in subdirectories deeper than the nodes of the tree
+    nested_size: u64,
+    nested_files: u64,
+}
+
+/// The directories of a tree with their sizes, as returned by `directory_tree`.
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::{directory_tree, format_file_size, ScanOptions};
+///
+/// let tree = directory_tree("C:\\", &ScanOptions::volume(), 3).unwrap();
+/// for node in tree.largest_directories(20) {
+///     println!("{:>12}  {}", format_file_size(node.size()), node.path().display());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct DirectoryTree {
+    root: DirectoryNode,
+    /// Path of the root as given, from which the tree is walked again
+    root_path: PathBuf,
+    options: ScanOptions,
+}
+
+impl DirectoryTree {
+    /// Returns the root directory of the tree
+    pub fn root(&self) -> &DirectoryNode {
+        &self.root
+    }
+
+    /// Returns the options the tree was scanned with
+    pub fn options(&self) -> &ScanOptions {
+        &self.options
+    }
+
+    /// Returns every directory of the tree, each directory before its subdirectories
+    pub fn iter(&self) -> impl Iterator<Item = &DirectoryNode> {
+        self.root.iter()
+    }
+
+    /// Returns the node of a directory, if it is part of the tree
+    pub fn find(&self, path: &Path) -> Option<&DirectoryNode> {
+        let mut node = &self.root;
+        while node.path != path {
+            node = node.children.iter().find(|child| path.starts_with(&child.path))?;
+        }
+        Some(node)
+    }
+
+    /// Returns the `count` largest directories below the root, largest first.
+    ///
+    /// Sizes include subdirectories, so a large directory is usually
+    /// followed by its largest subdirectories.
+    pub fn largest_directories(&self, count: usize) -> Vec<&DirectoryNode> {
+        let mut nodes: Vec<&DirectoryNode> = self.iter().skip(1).collect();
+        nodes.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        nodes.truncate(count);
+        nodes
+    }
+
+    /// Returns the directories of at least `min_size` bytes no deeper than `max_depth`, largest first.
+    ///
+    /// The root has a depth of 0 and is included when it is large enough.
+    pub fn directories_at_least(&self, min_size: u64, max_depth: usize) -> Vec<&DirectoryNode> {
+        let mut nodes: Vec<&DirectoryNode> = self
+            .iter()
+            .filter(|node| node.size >= min_size && node.depth <= max_depth)
+            .collect();
+        nodes.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        nodes
+    }
+
+    /// Expands a directory of the tree into `levels` more levels of subdirectories.
+    ///
+    /// Only the subtree of the directory is walked, with the options and as
+    /// if from the root of the tree, so that relative patterns and ignore
+    /// files keep applying. Its sizes are measured again and the sizes of
+    /// its ancestors are updated with the difference.
+    ///
+    /// # Arguments
+    /// * `path` - The directory to expand, a node of the tree
+    /// * `levels` - How many levels of subdirectories below it become nodes
+    ///
+    /// # Returns
+    /// * `Ok(true)` - If the directory was expanded
+    /// * `Ok(false)` - If the directory is not a node of the tree
+    /// * `Err(walkdir::Error)` - If there's an error during directory traversal
+    pub fn expand(&mut self, path: &Path, levels: usize) -> Result<bool, walkdir::Error> {
+        let Some(node) = self.find(path) else {
+            return Ok(false);
+        };
+        let depth = node.depth;
+        let subtree = build(&self.root_path, Some(path), &self.options, depth + levels)?;
+        replace(&mut self.root, subtree);
+        Ok(true)
+    }
+}
+
+impl fmt::Display for DirectoryTree {
+    /// Formats the tree as an indented list of sizes, largest directories first.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.root.fmt(f)
+    }
+}
+
+/// Replaces the node with the path of `subtree` and updates the sizes of its ancestors.
+fn replace(node: &mut DirectoryNode, subtree: DirectoryNode) {
+    if node.path == subtree.path {
+        *node = subtree;
+        return;
+    }
+    let Some(child) = node.children.iter_mut().find(|child| subtree.path.starts_with(&child.path)) else {
+        return;
+    };
+    let (old_size, old_files) = (child.size, child.files);
+    replace(child, subtree);
+    let (new_size, new_files) = (child.size, child.files);
+    node.size = node.size - old_size + new_size;
+    node.files = node.files - old_files + new_files;
+    node.sort_children();
+}
+
+/// Walks the tree of `root`, or its part below `below`, into nodes down to `depth` below the root.
+fn build(root: &Path, below: Option<&Path>, options: &ScanOptions, depth: usize) -> Result<DirectoryNode, walkdir::Error> {
+    let relative = |path: &Path| path.strip_prefix(root).unwrap_or(path).to_path_buf();
+    let tallies = walk_sizes(root, below, options, |tallies: &mut HashMap<PathBuf, Tally>, entry, size| {
+        let path = relative(entry.path());
+        let entry_depth = path.components().count();
+        if entry.file_type().is_dir() {
+            if entry_depth <= depth {
+                tallies.entry(path).or_default();
+            }
+            return;
+        }
+        // A root that is a file counts as its own directory
+        let parent_depth = entry_depth.saturating_sub(1);
+        let parent = path.parent().unwrap_or(&path);
+        if parent_depth <= depth {
+            let tally = tallies.entry(parent.to_path_buf()).or_default();
+            tally.own_size += size;
+            tally.own_files += 1;
+        } else {
+            let ancestor = path.ancestors().nth(entry_depth - depth).unwrap_or(&path);
+            let tally = tallies.entry(ancestor.to_path_buf()).or_default();
+            tally.nested_size += size;
+            tally.nested_files += 1;
+        }
+    })?;
+
+    let mut merged: HashMap<PathBuf, Tally> = HashMap::new();
+    for tallies in tallies {
+        for (path, tally) in tallies {
+            let total = merged.entry(path).or_default();
+            total.own_size += tally.own_size;
+            total.own_files += tally.own_files;
+            total.nested_size += tally.nested_size;
+            total.nested_files += tally.nested_files;
+        }
+    }
+    let top = below.map(relative).unwrap_or_default();
+    merged.entry(top.clone()).or_default();
+
+    let mut children: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    for path in merged.keys().filter(|path| **path != top) {
+        if let Some(parent) = path.parent() {
+            children.entry(parent.to_path_buf()).or_default().push(path.clone());
+        }
+    }
+    Ok(assemble(root, &top, &merged, &mut children, depth))
+}
+
+/// Turns the tallies of a directory and of the directories below it into a node.
+fn assemble(
+    root: &Path,
+    path: &Path,
+    tallies: &HashMap<PathBuf, Tally>,
+    children: &mut HashMap<PathBuf, Vec<PathBuf>>,
+    depth: usize,
+) -> DirectoryNode {
+    let tally = tallies.get(path).copied().unwrap_or_default();
+    let node_depth = path.components().count();
+    let mut node = DirectoryNode {
+        path: if path.as_os_str().is_empty() { root.to_path_buf() } else { root.join(path) },
+        depth: node_depth,
+        own_size: tally.own_size,
+        own_files: tally.own_files,
+        size: tally.own_size + tally.nested_size,
+        files: tally.own_files + tally.nested_files,
+        children: Vec::new(),
+        expanded: node_depth < depth,
+    };
+    for child in children.remove(path).unwrap_or_default() {
+        let child = assemble(root, &child, tallies, children, depth);
+        node.size += child.size;
+        node.files += child.files;
+        node.children.push(child);
+    }
+    node.sort_children();
+    node
+}
+
+/// Measures a directory tree, directory by directory
+///
+/// The whole tree is walked, on several threads unless the options say
+/// otherwise, and the size of every matching file is added to its directory
+/// and to all its ancestors. Directories down to `depth` levels below the
+/// root become nodes of the tree; deeper ones are counted in the sizes of
+/// their ancestors and can be turned into nodes with
+/// `DirectoryTree::expand`. Sort and limit options are ignored. A walk
+/// cancelled through the monitor of the options returns the sizes counted
+/// so far.
+///
+/// # Arguments
+/// * `path` - A string path to the directory to analyze
+/// * `options` - What to walk and which files to count
+/// * `depth` - How many levels of subdirectories become nodes
+///
+/// # Returns
+/// * `Ok(DirectoryTree)` - The directories with their sizes
+/// * `Err(walkdir::Error)` - If there's an error during directory traversal
+///
+/// # Examples
+/// ```no_run
+/// use std::path::Path;
+/// use win_disk_info::{directory_tree, ScanOptions};
+///
+/// let mut tree = directory_tree("C:\\", &ScanOptions::volume(), 1).unwrap();
+/// // Directories of at least 10 GB down to 3 levels, after expanding the largest one
+/// let largest = tree.root().largest_children(1)[0].path().to_path_buf();
+/// tree.expand(&largest, 2).unwrap();
+/// for node in tree.directories_at_least(10 << 30, 3) {
+///     println!("{}", node.path().display());
+/// }
+/// println!("{}", tree.find(Path::new("C:\\Users")).unwrap());
+/// ```
+pub fn directory_tree(path: &str, options: &ScanOptions, depth: usize) -> Result<DirectoryTree, walkdir::Error> {
+    let root_path = PathBuf::from(path);
+    let root = build(&root_path, None, options, depth)?;
+    Ok(DirectoryTree {
+        root,
+        root_path,
+        options: options.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn sized_tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for (path, size) in [
+            ("top.bin", 10),
+            ("a/a.bin", 100),
+            ("a/deep/d.bin", 1000),
+            ("a/deep/deeper/e.bin", 2000),
+            ("b/b.bin", 50),
+            ("b/c/c.bin", 5),
+        ] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![0u8; size]).unwrap();
+        }
+        fs::create_dir(root.join("empty")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_directory_tree_sizes() {
+        let dir = sized_tree();
+        let tree = directory_tree(dir.path().to_str().unwrap(), &ScanOptions::new(), 1).unwrap();
+
+        let root = tree.root();
+        assert_eq!((root.size(), root.file_count()), (3165, 6));
+        assert_eq!((root.own_size(), root.own_file_count()), (10, 1));
+        assert!(root.is_expanded());
+        let names: Vec<String> = root.children().iter().map(DirectoryNode::name).collect();
+        assert_eq!(names, ["a", "b", "empty"]);
+
+        // Deeper directories are folded into the nodes at the limit
+        let a = &root.children()[0];
+        assert_eq!((a.size(), a.file_count(), a.own_size()), (3100, 3, 100));
+        assert!(!a.is_expanded() && a.children().is_empty());
+        assert_eq!(root.largest_children(1), &root.children()[..1]);
+        assert_eq!(tree.iter().count(), 4);
+    }
+
+    #[test]
+    fn test_directory_tree_queries_and_expansion() {
+        let dir = sized_tree();
+        let root = dir.path();
+        let mut tree = directory_tree(root.to_str().unwrap(), &ScanOptions::new().with_threads(2), 1).unwrap();
+
+        let largest: Vec<&Path> = tree.largest_directories(2).iter().map(|node| node.path()).collect();
+        assert_eq!(largest, [root.join("a"), root.join("b")]);
+        assert_eq!(tree.directories_at_least(100, 0).len(), 1);
+
+        // New files are picked up by the walk of the expanded subtree
+        fs::write(root.join("a").join("deep").join("new.bin"), vec![0u8; 400]).unwrap();
+        assert!(tree.expand(&root.join("a"), 2).unwrap());
+        assert!(!tree.expand(&root.join("missing"), 1).unwrap());
+        assert_eq!(tree.root().size(), 3565);
+
+        let deep = tree.find(&root.join("a").join("deep")).unwrap();
+        assert_eq!((deep.depth(), deep.own_size(), deep.size()), (2, 1400, 3400));
+        let deeper = tree.find(&root.join("a").join("deep").join("deeper")).unwrap();
+        assert_eq!((deeper.depth(), deeper.size()), (3, 2000));
+        assert!(!deeper.is_expanded());
+
+        let at_least: Vec<&Path> = tree.directories_at_least(2000, 3).iter().map(|node| node.path()).collect();
+        assert_eq!(at_least, [root.to_path_buf(), root.join("a"), root.join("a").join("deep"), root.join("a").join("deep").join("deeper")]);
+        assert_eq!(tree.to_string().lines().nth(3), Some("      deeper  1.95 KB"));
+    }
+}
@@ -0,0 +1,392 @@
+//! This module finds files with identical content.
+//!
+//! Candidates are narrowed down in stages, each one more expensive than the
+//! previous but applied to fewer files: files are first grouped by size, then
+//! by a digest of their first and last blocks, and only the files still
+//! sharing a group are hashed in full. Hard links to the same file are
+//! counted once, since removing one of them reclaims no space.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+#[cfg(feature = "serialize")]
+use serde::Serialize;
+
+use crate::file_extraction::{file_identity, format_file_size, get_files};
+use crate::file_hashing::hash_reader;
+use crate::{FileEntry, FileSource, HashAlgorithm};
+
+/// Size of the blocks read at the start and end of a file for the partial digest
+const PARTIAL_BLOCK: u64 = 4096;
+
+/// Groups of identical files, and the path and error of every file that could not be read
+type Duplicates = (Vec<DuplicateGroup>, Vec<(PathBuf, io::Error)>);
+
+/// Options controlling which files are compared by `find_duplicates`.
+///
+/// # Examples
+/// ```
+/// use win_disk_info::DuplicateOptions;
+///
+/// let options = DuplicateOptions::new()
+///     .with_min_size(1024 * 1024)
+///     .with_same_partition(true);
+/// assert_eq!(options.min_size(), 1024 * 1024);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DuplicateOptions {
+    /// Files smaller than this size in bytes are ignored
+    min_size: u64,
+    /// Whether empty files are reported as duplicates of each other
+    include_empty: bool,
+    /// Whether files are only compared with files on the same partition
+    same_partition: bool,
+}
+
+impl DuplicateOptions {
+    /// Creates options comparing every non-empty file, whatever its partition.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ignores files smaller than `min_size` bytes.
+    pub fn with_min_size(mut self, min_size: u64) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Reports empty files as duplicates of each other, which are ignored by default.
+    pub fn with_empty_files(mut self, include_empty: bool) -> Self {
+        self.include_empty = include_empty;
+        self
+    }
+
+    /// Only compares files stored on the same partition.
+    ///
+    /// Duplicates on different partitions cannot be replaced by hard links,
+    /// so they are often reported separately.
+    pub fn with_same_partition(mut self, same_partition: bool) -> Self {
+        self.same_partition = same_partition;
+        self
+    }
+
+    /// Returns the minimum size in bytes of the compared files
+    pub fn min_size(&self) -> u64 {
+        self.min_size
+    }
+
+    /// Returns true if empty files are reported
+    pub fn include_empty(&self) -> bool {
+        self.include_empty
+    }
+
+    /// Returns true if only files on the same partition are compared
+    pub fn same_partition(&self) -> bool {
+        self.same_partition
+    }
+}
+
+/// A set of files with identical content.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct DuplicateGroup {
+    /// Size in bytes of each file
+    size: u64,
+    /// BLAKE3 digest of the shared content
+    digest: String,
+    /// The identical files, sorted by path
+    files: Vec<FileEntry>,
+}
+
+impl DuplicateGroup {
+    /// Returns the size in bytes of each file
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the BLAKE3 digest of the shared content
+    pub fn digest(&self) -> &str {
+        &self.digest
+    }
+
+    /// Returns the identical files, sorted by path
+    pub fn files(&self) -> &[FileEntry] {
+        &self.files
+    }
+
+    /// Returns the space freed by keeping a single copy
+    pub fn reclaimable_bytes(&self) -> u64 {
+        self.size * (self.files.len() as u64).saturating_sub(1)
+    }
+}
+
+impl fmt::Display for DuplicateGroup {
+    /// Formats the group as a summary line followed by one path per line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} copies of {} ({} reclaimable)",
+            self.files.len(),
+            format_file_size(self.size),
+            format_file_size(self.reclaimable_bytes())
+        )?;
+        for file in &self.files {
+            write!(f, "\n  {}", file.path().display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Finds the files with identical content in a directory and its subdirectories
+///
+/// # Arguments
+/// * `path` - A string path to the directory to scan
+/// * `options` - Which files to compare
+///
+/// # Returns
+/// * `Ok((Vec<DuplicateGroup>, Vec<(PathBuf, io::Error)>))` - The groups of identical files, largest
+///   reclaimable space first, and the path and error of every file that could not be read
+/// * `Err(walkdir::Error)` - If there's an error during directory traversal
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::{find_duplicates, format_file_size, DuplicateOptions};
+///
+/// let (groups, errors) = find_duplicates("D:/Shares", &DuplicateOptions::new().with_min_size(4096)).unwrap();
+/// for (path, error) in &errors {
+///     eprintln!("{}: {}", path.display(), error);
+/// }
+/// let total: u64 = groups.iter().map(|group| group.reclaimable_bytes()).sum();
+/// println!("{} reclaimable", format_file_size(total));
+/// for group in groups.iter().take(10) {
+///     println!("{}", group);
+/// }
+/// ```
+pub fn find_duplicates(path: &str, options: &DuplicateOptions) -> Result<Duplicates, walkdir::Error> {
+    Ok(find_duplicate_files(get_files(path)?, options))
+}
+
+/// Finds the files with identical content in a list of files
+///
+/// Files that cannot be read are left out of the groups and returned with their error.
+/// The BLAKE3 digest of every file placed in a group is stored on its entry.
+///
+/// # Arguments
+/// * `files` - The files to compare, on a mounted file system or inside images
+/// * `options` - Which files to compare
+///
+/// # Returns
+/// The groups of identical files, largest reclaimable space first, and the
+/// path and error of every file that could not be read
+pub fn find_duplicate_files(files: Vec<FileEntry>, options: &DuplicateOptions) -> Duplicates {
+    let candidates = files
+        .into_iter()
+        .filter(|file| file.size() >= options.min_size && (file.size() > 0 || options.include_empty));
+    let by_size = group_by(candidates, |file| Some(file.size()));
+
+    let mut groups = Vec::new();
+    let mut errors = Vec::new();
+    for files in by_size {
+        // Entries without a recorded identity cost a system call each, so only files sharing a size are looked up
+        let mut seen = HashSet::new();
+        let mut distinct = Vec::new();
+        for file in files {
+            let identity = match file.source() {
+                FileSource::FileSystem => file.identity().or_else(|| file_identity(file.path()).ok()),
+                FileSource::Image { .. } => None,
+            };
+            if identity.is_none_or(|identity| seen.insert(identity)) {
+                let partition = identity.filter(|_| options.same_partition).map(|identity| identity.volume());
+                distinct.push((partition, file));
+            }
+        }
+
+        for files in group_by(distinct, |(partition, _)| Some(*partition)) {
+            let files = files.into_iter().map(|(_, file)| file);
+            let partial = read_each(files, |file| partial_digest(file), &mut errors);
+            for files in group_by(partial, |(digest, _)| Some(digest.clone())) {
+                let files = files.into_iter().map(|(_, file)| file);
+                let hashed = read_each(
+                    files,
+                    |file| {
+                        file.hash(&[HashAlgorithm::Blake3])
+                            .map(|hashes| hashes.blake3().unwrap_or_default().to_string())
+                    },
+                    &mut errors,
+                );
+                for files in group_by(hashed, |(digest, _)| Some(digest.clone())) {
+                    let digest = files[0].0.clone();
+                    let mut files: Vec<FileEntry> = files.into_iter().map(|(_, file)| file).collect();
+                    files.sort_by(|a, b| a.path().cmp(b.path()));
+                    groups.push(DuplicateGroup {
+                        size: files[0].size(),
+                        digest,
+                        files,
+                    });
+                }
+            }
+        }
+    }
+
+    groups.sort_by(|a, b| {
+        b.reclaimable_bytes()
+            .cmp(&a.reclaimable_bytes())
+            .then(b.size.cmp(&a.size))
+            .then_with(|| a.files[0].path().cmp(b.files[0].path()))
+    });
+    (groups, errors)
+}
+
+/// Groups items by key, keeping the groups of at least two items.
+///
+/// Items whose key is `None` are left out.
+fn group_by<T, K: Hash + Eq>(items: impl IntoIterator<Item = T>, key: impl Fn(&T) -> Option<K>) -> Vec<Vec<T>> {
+    let mut groups: HashMap<K, Vec<T>> = HashMap::new();
+    for item in items {
+        if let Some(key) = key(&item) {
+            groups.entry(key).or_default().push(item);
+        }
+    }
+    groups.into_values().filter(|group| group.len() > 1).collect()
+}
+
+/// Computes the BLAKE3 digest of the first and last blocks of a file.
+///
+/// Files no larger than two blocks are hashed in full.
+fn partial_digest(file: &FileEntry) -> io::Result<String> {
+    let size = file.size();
+    let reader: Box<dyn Read> = if size <= 2 * PARTIAL_BLOCK {
+        file.open()?
+    } else {
+        Box::new(file.open()?.take(PARTIAL_BLOCK).chain(file.open_at(size - PARTIAL_BLOCK)?))
+    };
+    let hashes = hash_reader(reader, &[HashAlgorithm::Blake3])?;
+    Ok(hashes.blake3().unwrap_or_default().to_string())
+}
+
+/// Pairs each file with the value read from it.
+///
+/// Files that cannot be read are left out and their path and error added to `errors`.
+fn read_each<T>(
+    files: impl IntoIterator<Item = FileEntry>,
+    mut read: impl FnMut(&mut FileEntry) -> io::Result<T>,
+    errors: &mut Vec<(PathBuf, io::Error)>,
+) -> Vec<(T, FileEntry)> {
+    let mut read_files = Vec::new();
+    for mut file in files {
+        match read(&mut file) {
+            Ok(value) => read_files.push((value, file)),
+            Err(error) => errors.push((file.path().to_path_buf(), error)),
+        }
+    }
+    read_files
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    /// Content of `size` bytes, distinct for each `seed`
+    fn content(size: usize, seed: u8) -> Vec<u8> {
+        (0..size).map(|i| (i % 253) as u8 ^ seed).collect()
+    }
+
+    #[test]
+    fn test_find_duplicates_stages() {
+        let dir = tempdir().unwrap();
+        let large = content(64 * 1024, 1);
+        fs::write(dir.path().join("a.bin"), &large).unwrap();
+        fs::create_dir(dir.path().join("copy")).unwrap();
+        fs::write(dir.path().join("copy").join("a.bin"), &large).unwrap();
+
+        // Same size, first and last blocks as the copies but a different middle
+        let mut altered = large.clone();
+        altered[32 * 1024] ^= 0xFF;
+        fs::write(dir.path().join("altered.bin"), &altered).unwrap();
+
+        // Same size, different first block
+        fs::write(dir.path().join("other.bin"), content(64 * 1024, 2)).unwrap();
+
+        fs::write(dir.path().join("small1.txt"), b"hello").unwrap();
+        fs::write(dir.path().join("small2.txt"), b"hello").unwrap();
+        fs::write(dir.path().join("small3.txt"), b"hello").unwrap();
+
+        let (groups, errors) = find_duplicates(dir.path().to_str().unwrap(), &DuplicateOptions::new()).unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(groups.len(), 2);
+
+        assert_eq!(groups[0].size(), 64 * 1024);
+        assert_eq!(groups[0].reclaimable_bytes(), 64 * 1024);
+        assert_eq!(groups[0].files()[0].path(), dir.path().join("a.bin"));
+        assert_eq!(groups[0].files()[1].path(), dir.path().join("copy").join("a.bin"));
+        assert_eq!(groups[0].files()[0].hashes().blake3(), Some(groups[0].digest()));
+
+        assert_eq!(groups[1].files().len(), 3);
+        assert_eq!(groups[1].reclaimable_bytes(), 10);
+    }
+
+    #[test]
+    fn test_find_duplicates_options() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("empty1"), b"").unwrap();
+        fs::write(dir.path().join("empty2"), b"").unwrap();
+        fs::write(dir.path().join("small1.txt"), b"hello").unwrap();
+        fs::write(dir.path().join("small2.txt"), b"hello").unwrap();
+        let path = dir.path().to_str().unwrap();
+
+        let (groups, _) = find_duplicates(path, &DuplicateOptions::new()).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].size(), 5);
+
+        let (groups, _) = find_duplicates(path, &DuplicateOptions::new().with_empty_files(true)).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[1].size(), 0);
+        assert_eq!(groups[1].reclaimable_bytes(), 0);
+
+        let options = DuplicateOptions::new().with_min_size(6).with_same_partition(true);
+        assert!(find_duplicates(path, &options).unwrap().0.is_empty());
+
+        // Files of a single directory share a partition
+        let options = DuplicateOptions::new().with_same_partition(true);
+        assert_eq!(find_duplicates(path, &options).unwrap().0.len(), 1);
+    }
+
+    #[test]
+    fn test_find_duplicates_hard_links() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("original.txt"), b"linked content").unwrap();
+        fs::hard_link(dir.path().join("original.txt"), dir.path().join("link.txt")).unwrap();
+        let path = dir.path().to_str().unwrap();
+
+        assert!(find_duplicates(path, &DuplicateOptions::new()).unwrap().0.is_empty());
+
+        fs::write(dir.path().join("copy.txt"), b"linked content").unwrap();
+        let (groups, _) = find_duplicates(path, &DuplicateOptions::new()).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].files().len(), 2);
+    }
+
+    #[test]
+    fn test_find_duplicates_unreadable_files() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), b"same content").unwrap();
+        fs::write(dir.path().join("b.txt"), b"same content").unwrap();
+        fs::write(dir.path().join("c.txt"), b"same content").unwrap();
+        let files = get_files(dir.path().to_str().unwrap()).unwrap();
+        fs::remove_file(dir.path().join("b.txt")).unwrap();
+
+        let (groups, errors) = find_duplicate_files(files, &DuplicateOptions::new());
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].files().len(), 2);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, dir.path().join("b.txt"));
+        assert_eq!(errors[0].1.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -1,14 +1,20 @@
 use std::fs;
 use std::io;
+use std::path::Path;
 use std::time::SystemTime;
-use walkdir::WalkDir;
+use chrono::{DateTime, Utc};
 
-use crate::FileEntry;
+use crate::pattern::{escape_glob, has_wildcards};
+use crate::{scan_directory_size, scan_files, FileEntry, FileIdentity, ScanOptions, SizeKind, TimestampKind};
 
 /// Retrieves all files in a directory and its subdirectories recursively
 ///
 /// This function traverses the given path recursively and collects all file entries
 /// (excluding directories). Each file is converted to a FileEntry for further processing.
+/// As with `scan_files`, the first directory that cannot be read stops the
+/// traversal, while files whose metadata cannot be read, such as files deleted
+/// during the scan, are left out. Use `scan_files_with_errors` to list the
+/// rest of the tree and collect both kinds of errors.
 ///
 /// # Arguments
 /// * `path` - A string path to the directory to scan
@@ -27,24 +33,24 @@ use crate::FileEntry;
 /// }
 /// ```
 pub fn get_files(path: &str) -> Result<Vec<FileEntry>, walkdir::Error> {
-    let mut files = Vec::new();
-    for entry in WalkDir::new(path) {
-        let entry = entry?;
-        if entry.file_type().is_file() {
-            files.push(FileEntry::from(entry));
-        }
-    }
-    Ok(files)
+    scan_files(path, &ScanOptions::new())
 }
 
 /// Retrieves files that match a specific pattern in their filename
 ///
 /// This function traverses the given path recursively and collects files
-/// whose names contain the specified pattern.
+/// whose names match the specified pattern. A pattern with wildcards, such
+/// as `*.pst` or `2023-*`, must match the whole name, and one containing a
+/// `/` the path relative to `path`, as in `**/*.{jpg,png}`; any other
+/// pattern is a substring to search for. Case is ignored on Windows, as the
+/// file system does; use `scan_files` to choose.
+///
+/// Errors are handled as in `get_files`: a directory that cannot be read
+/// stops the traversal and files whose metadata cannot be read are left out.
 ///
 /// # Arguments
 /// * `path` - A string path to the directory to scan
-/// * `pattern` - A substring to search for in filenames
+/// * `pattern` - A wildcard pattern, or a substring to search for in filenames
 ///
 /// # Returns
 /// * `Ok(Vec<FileEntry>)` - A vector of matching files
@@ -55,19 +61,15 @@ pub fn get_files(path: &str) -> Result<Vec<FileEntry>, walkdir::Error> {
 /// use win_disk_info::get_files_by_pattern;
 /// 
 /// let image_files = get_files_by_pattern("C:/Users/Pictures", "vacation");
+/// let mailboxes = get_files_by_pattern("C:/Users", "*.pst");
 /// ```
 pub fn get_files_by_pattern(path: &str, pattern: &str) -> Result<Vec<FileEntry>, walkdir::Error> {
-    let mut files = Vec::new();
-    for entry in WalkDir::new(path) {
-        let entry = entry?;
-        if entry.file_type().is_file() {
-            let file_name = entry.file_name().to_string_lossy();
-            if file_name.contains(pattern) {
-                files.push(FileEntry::from(entry));
-            }
-        }
-    }
-    Ok(files)
+    let pattern = if has_wildcards(pattern) || pattern.contains('/') {
+        pattern.to_string()
+    } else {
+        format!("*{}*", escape_glob(pattern))
+    };
+    scan_files(path, &ScanOptions::new().with_include(&pattern))
 }
 
 /// Error type for file extraction operations
@@ -134,26 +136,17 @@ pub fn get_recently_modified_files(
     let cutoff = now.checked_sub(duration)
         .ok_or_else(|| FileExtractionError::TimeError("Failed to calculate cutoff time".to_string()))?;
 
-    let mut files = Vec::new();
-    for entry in WalkDir::new(path) {
-        let entry = entry?;
-        if entry.file_type().is_file() {
-            let metadata = fs::metadata(entry.path())?;
-            if let Ok(modified) = metadata.modified() {
-                if modified >= cutoff {
-                    files.push(FileEntry::from(entry));
-                }
-            }
-        }
-    }
-
-    Ok(files)
+    let options = ScanOptions::new().with_time_range(TimestampKind::Modified, Some(DateTime::<Utc>::from(cutoff)), None);
+    Ok(scan_files(path, &options)?)
 }
 
 /// Calculates the total size of all files in a directory and its subdirectories
 ///
-/// This function traverses the given path recursively and sums up the size
-/// of all files it finds.
+/// This function traverses the given path recursively, on as many threads
+/// as the available parallelism, and sums up the size of all files it finds.
+/// Files with several hard links in the tree are counted once. Use
+/// `scan_directory_size` to choose the threads, filter the files, or follow
+/// the progress and cancel through `ScanOptions::with_monitor`.
 ///
 /// # Arguments
 /// * `path` - A string path to the directory to analyze
@@ -173,16 +166,72 @@ pub fn get_recently_modified_files(
 /// }
 /// ```
 pub fn calculate_directory_size(path: &str) -> Result<u64, walkdir::Error> {
-    let mut total_size: u64 = 0;
-    for entry in WalkDir::new(path) {
-        let entry = entry?;
-        if entry.file_type().is_file() {
-            if let Ok(metadata) = entry.metadata() {
-                total_size += metadata.len();
-            }
-        }
-    }
-    Ok(total_size)
+    scan_directory_size(path, &ScanOptions::new())
+}
+
+/// Calculates the space taken on disk by all files in a directory and its subdirectories
+///
+/// Unlike `calculate_directory_size`, the allocated size of each file is
+/// added up: sizes are rounded up to whole clusters, compressed and sparse
+/// files count for the space they actually use, and hard links count once.
+/// Deleting the directory frees about this much space on the volume.
+///
+/// # Arguments
+/// * `path` - A string path to the directory to analyze
+///
+/// # Returns
+/// * `Ok(u64)` - The allocated size in bytes
+/// * `Err(walkdir::Error)` - If there's an error during directory traversal
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::{calculate_allocated_size, calculate_directory_size, format_file_size};
+///
+/// let apparent = calculate_directory_size("C:\\Windows\\WinSxS").unwrap();
+/// let allocated = calculate_allocated_size("C:\\Windows\\WinSxS").unwrap();
+/// println!("{} on disk for {} of files", format_file_size(allocated), format_file_size(apparent));
+/// ```
+pub fn calculate_allocated_size(path: &str) -> Result<u64, walkdir::Error> {
+    scan_directory_size(path, &ScanOptions::new().with_size_kind(SizeKind::Allocated))
+}
+
+/// Retrieves the identity of the file at `path` from its inode number.
+#[cfg(unix)]
+pub(crate) fn file_identity(path: &Path) -> io::Result<FileIdentity> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::metadata(path)?;
+    Ok(FileIdentity::new(metadata.dev(), metadata.ino()))
+}
+
+/// Retrieves the identity of the file at `path` from its volume serial number and file index.
+#[cfg(windows)]
+pub(crate) fn file_identity(path: &Path) -> io::Result<FileIdentity> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::Storage::FileSystem::{
+        GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION, FILE_FLAG_BACKUP_SEMANTICS,
+    };
+
+    // Opened without data access, and with backup semantics so that directories can be opened too
+    let file = fs::OpenOptions::new()
+        .access_mode(0)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS.0)
+        .open(path)?;
+    let mut info = BY_HANDLE_FILE_INFORMATION::default();
+    // SAFETY: the handle stays open for the duration of the call and `info` is a valid output buffer
+    unsafe { GetFileInformationByHandle(HANDLE(file.as_raw_handle()), &mut info) }.map_err(io::Error::from)?;
+    Ok(FileIdentity::new(
+        info.dwVolumeSerialNumber as u64,
+        ((info.nFileIndexHigh as u64) << 32) | info.nFileIndexLow as u64,
+    ))
+}
+
+/// File identities are not available on this platform.
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn file_identity(_path: &Path) -> io::Result<FileIdentity> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "file identities are not supported on this platform"))
 }
 
 /// Formats a file size in bytes into a human-readable string
@@ -288,6 +337,34 @@ mod tests {
             assert!(file.name().contains("test"));
         }
     }
+
+    #[test]
+    fn test_get_files_by_wildcard_pattern() {
+        let temp_dir = setup_test_directory();
+        let root = temp_dir.path().to_str().unwrap();
+
+        assert_eq!(get_files_by_pattern(root, "*.dat").unwrap().len(), 2);
+        assert_eq!(get_files_by_pattern(root, "s*.txt").unwrap().len(), 2);
+        assert_eq!(get_files_by_pattern(root, "subdir/*").unwrap().len(), 1);
+        assert_eq!(get_files_by_pattern(root, "**/*.{txt,dat}").unwrap().len(), 6);
+    }
+
+    #[test]
+    fn test_get_files_stops_at_walk_errors() {
+        let temp_dir = setup_test_directory();
+        let missing = temp_dir.path().join("missing");
+        let missing = missing.to_str().unwrap();
+
+        // The wrappers fail where `scan_files` does, and `scan_files_with_errors` reports the same error
+        assert!(scan_files(missing, &ScanOptions::new()).is_err());
+        assert!(get_files(missing).is_err());
+        assert!(get_files_by_pattern(missing, "*.txt").is_err());
+        assert!(get_files_by_pattern(missing, "test").is_err());
+        let outcome = crate::scan_files_with_errors(missing, &ScanOptions::new());
+        assert!(outcome.files().is_empty());
+        assert_eq!(outcome.errors().len(), 1);
+        assert_eq!(outcome.errors()[0].kind(), io::ErrorKind::NotFound);
+    }
     
     #[test]
     fn test_recently_modified_files() {
@@ -312,6 +389,34 @@ mod tests {
         // The total size should be the sum of all files (100 + 2000 + 5000 + 1500 + 1500 + 1000)
         assert_eq!(size, 11100);
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_allocated_size_and_hard_links() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = tempdir().unwrap();
+        let data = temp_dir.path().join("data.bin");
+        fs::write(&data, vec![1u8; 10_000]).unwrap();
+        fs::hard_link(&data, temp_dir.path().join("link.bin")).unwrap();
+        // A sparse file has no blocks behind most of its length
+        let sparse = temp_dir.path().join("sparse.img");
+        File::create(&sparse).unwrap().set_len(64 * 1024 * 1024).unwrap();
+        let root = temp_dir.path().to_str().unwrap();
+
+        assert_eq!(calculate_directory_size(root).unwrap(), 10_000 + 64 * 1024 * 1024);
+        let blocks = |path: &Path| fs::metadata(path).unwrap().blocks() * 512;
+        let allocated = calculate_allocated_size(root).unwrap();
+        assert_eq!(allocated, blocks(&data) + blocks(&sparse));
+        assert!(allocated < 64 * 1024 * 1024);
+
+        let files = get_files(root).unwrap();
+        let file = files.iter().find(|file| file.name() == "data.bin").unwrap();
+        assert_eq!(file.allocated_size(), Some(blocks(&data)));
+        let link = files.iter().find(|file| file.name() == "link.bin").unwrap();
+        assert_eq!(file.identity(), link.identity());
+        assert_eq!(file.identity(), file_identity(&data).ok());
+    }
     
     #[test]
     fn test_format_file_size() {
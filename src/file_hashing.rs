@@ -0,0 +1,426 @@
+//! This module computes cryptographic digests of file contents.
+//!
+//! Content is streamed through a fixed-size buffer and fed to every
+//! requested algorithm at once, so MD5, SHA-1 and SHA-256 digests of an
+//! evidence file cost a single read of it. Files are read through
+//! `FileEntry::open`, which covers files inside disk images as well.
+
+use std::io::{self, Read};
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+
+use md5::{Digest, Md5};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+use crate::progress::{Monitor, Tracker};
+use crate::{FileEntry, FileHashes, HashAlgorithm};
+
+/// Size of the buffer used to stream file content to the hashers
+const BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Renders bytes as lowercase hexadecimal.
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Parses a hexadecimal string, in either case, into bytes.
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Running state of one hash algorithm.
+enum Hasher {
+    Md5(Md5),
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+            HashAlgorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(hasher) => hasher.update(data),
+            Hasher::Sha1(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    fn finalize(self) -> String {
+        match self {
+            Hasher::Md5(hasher) => to_hex(&hasher.finalize()),
+            Hasher::Sha1(hasher) => to_hex(&hasher.finalize()),
+            Hasher::Sha256(hasher) => to_hex(&hasher.finalize()),
+            Hasher::Sha512(hasher) => to_hex(&hasher.finalize()),
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+/// Computes several digests of data fed in pieces.
+pub(crate) struct MultiHasher {
+    hashers: Vec<(HashAlgorithm, Hasher)>,
+}
+
+impl MultiHasher {
+    /// Creates hashers for `algorithms`; duplicates are computed once.
+    pub(crate) fn new(algorithms: &[HashAlgorithm]) -> Self {
+        let mut algorithms = algorithms.to_vec();
+        algorithms.sort();
+        algorithms.dedup();
+        MultiHasher {
+            hashers: algorithms.into_iter().map(|algorithm| (algorithm, Hasher::new(algorithm))).collect(),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        for (_, hasher) in &mut self.hashers {
+            hasher.update(data);
+        }
+    }
+
+    pub(crate) fn finish(self) -> FileHashes {
+        let mut hashes = FileHashes::default();
+        for (algorithm, hasher) in self.hashers {
+            hashes.insert(algorithm, hasher.finalize());
+        }
+        hashes
+    }
+}
+
+/// Computes several digests of a stream in a single pass
+///
+/// # Arguments
+/// * `reader` - The content to hash, read until its end
+/// * `algorithms` - The digests to compute; duplicates are computed once
+///
+/// # Returns
+/// * `io::Result<FileHashes>` - The digests, or the error that interrupted the read
+///
+/// # Examples
+/// ```
+/// use win_disk_info::{hash_reader, HashAlgorithm};
+///
+/// let hashes = hash_reader(&b"hello"[..], &HashAlgorithm::EVIDENCE).unwrap();
+/// assert_eq!(hashes.md5(), Some("5d41402abc4b2a76b9719d911017c592"));
+/// ```
+pub fn hash_reader<R: Read>(reader: R, algorithms: &[HashAlgorithm]) -> io::Result<FileHashes> {
+    let hashes = hash_chunks(reader, algorithms, |_| ControlFlow::Continue(()))?;
+    Ok(hashes.unwrap_or_default())
+}
+
+/// Computes several digests of a stream, calling `chunk` with the size of every chunk read.
+///
+/// Returns `None` if `chunk` breaks off the read before the end of the stream.
+fn hash_chunks<R, F>(mut reader: R, algorithms: &[HashAlgorithm], mut chunk: F) -> io::Result<Option<FileHashes>>
+where
+    R: Read,
+    F: FnMut(usize) -> ControlFlow<()>,
+{
+    let mut hasher = MultiHasher::new(algorithms);
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&buffer[..read]);
+        if chunk(read).is_break() {
+            return Ok(None);
+        }
+    }
+    Ok(Some(hasher.finish()))
+}
+
+/// Computes several digests of a file in a single read pass
+///
+/// The digests are returned without being stored on the entry; use
+/// `FileEntry::hash` to keep them.
+///
+/// # Arguments
+/// * `file` - The file to hash, on a mounted file system or inside an image
+/// * `algorithms` - The digests to compute
+///
+/// # Returns
+/// * `io::Result<FileHashes>` - The digests, or the error raised while reading the file
+pub fn hash_file(file: &FileEntry, algorithms: &[HashAlgorithm]) -> io::Result<FileHashes> {
+    hash_reader(file.open()?, algorithms)
+}
+
+/// Hashes a list of files and stores the digests on each entry
+///
+/// # Arguments
+/// * `files` - The files to hash
+/// * `algorithms` - The digests to compute for every file
+///
+/// # Returns
+/// A vector with the path and error of every file that could not be read.
+/// The other entries hold their digests, available through `FileEntry::hashes`.
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::{get_files, hash_files, HashAlgorithm};
+///
+/// let mut files = get_files("E:\\evidence").unwrap();
+/// for (path, error) in hash_files(&mut files, &HashAlgorithm::EVIDENCE) {
+///     eprintln!("{}: {}", path.display(), error);
+/// }
+/// for file in &files {
+///     println!("{}\n{}", file.path().display(), file.hashes());
+/// }
+/// ```
+pub fn hash_files(files: &mut [FileEntry], algorithms: &[HashAlgorithm]) -> Vec<(PathBuf, io::Error)> {
+    files
+        .iter_mut()
+        .filter_map(|file| file.hash(algorithms).err().map(|error| (file.path().to_path_buf(), error)))
+        .collect()
+}
+
+/// Hashes a list of files on several threads and stores the digests on each entry
+///
+/// Each thread streams one file at a time through its own buffer, so memory
+/// use stays bounded by the number of threads whatever the size of the
+/// files, which suits multi-terabyte trees.
+///
+/// # Arguments
+/// * `files` - The files to hash
+/// * `algorithms` - The digests to compute for every file
+/// * `threads` - Number of worker threads, or 0 to use the available parallelism
+///
+/// # Returns
+/// A vector with the path and error of every file that could not be read,
+/// in the order of `files`.
+pub fn hash_files_parallel(
+    files: &mut [FileEntry],
+    algorithms: &[HashAlgorithm],
+    threads: usize,
+) -> Vec<(PathBuf, io::Error)> {
+    hash_files_with_monitor(files, algorithms, threads, &Monitor::new())
+}
+
+/// Hashes a list of files on several threads, reporting progress and stopping when cancelled
+///
+/// Works as `hash_files_parallel`. Progress counts the files hashed and the
+/// bytes read, against the number and total size of `files`. Cancellation
+/// is checked after every chunk read, so that it takes effect even in the
+/// middle of a large file; the files hashed until then keep their digests,
+/// the others are left without new ones and are not reported as errors.
+///
+/// # Arguments
+/// * `files` - The files to hash
+/// * `algorithms` - The digests to compute for every file
+/// * `threads` - Number of worker threads, or 0 to use the available parallelism
+/// * `monitor` - Where progress is reported and cancellation requested
+///
+/// # Returns
+/// A vector with the path and error of every file that could not be read,
+/// in the order of `files`.
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::{get_files, hash_files_with_monitor, HashAlgorithm, Monitor};
+///
+/// let mut files = get_files("E:\\evidence").unwrap();
+/// let monitor = Monitor::new().with_progress(|progress| {
+///     if let Some(fraction) = progress.fraction() {
+///         println!("{:.1}%", fraction * 100.0);
+///     }
+/// });
+/// let errors = hash_files_with_monitor(&mut files, &HashAlgorithm::EVIDENCE, 0, &monitor);
+/// ```
+pub fn hash_files_with_monitor(
+    files: &mut [FileEntry],
+    algorithms: &[HashAlgorithm],
+    threads: usize,
+    monitor: &Monitor,
+) -> Vec<(PathBuf, io::Error)> {
+    let tracker = Tracker::with_totals(monitor, files.len() as u64, files.iter().map(FileEntry::size).sum());
+    let threads = match threads {
+        0 => thread::available_parallelism().map(usize::from).unwrap_or(1),
+        threads => threads,
+    }
+    .min(files.len().max(1));
+
+    let pending = Mutex::new(files.iter_mut().enumerate());
+    let errors = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                // Release the queue before hashing so other workers can proceed
+                let next = pending.lock().unwrap_or_else(|e| e.into_inner()).next();
+                let Some((index, file)) = next else {
+                    break;
+                };
+                if tracker.is_cancelled() {
+                    break;
+                }
+                let hashed = file.open().and_then(|reader| {
+                    hash_chunks(reader, algorithms, |read| {
+                        tracker.advance(file.path(), 0, read as u64);
+                        if tracker.is_cancelled() {
+                            ControlFlow::Break(())
+                        } else {
+                            ControlFlow::Continue(())
+                        }
+                    })
+                });
+                match hashed {
+                    Ok(Some(hashes)) => {
+                        file.merge_hashes(hashes);
+                        tracker.advance(file.path(), 1, 0);
+                    }
+                    // Cancelled in the middle of the file
+                    Ok(None) => break,
+                    Err(error) => {
+                        tracker.advance(file.path(), 1, 0);
+                        let path = file.path().to_path_buf();
+                        errors.lock().unwrap_or_else(|e| e.into_inner()).push((index, path, error));
+                    }
+                }
+            });
+        }
+    });
+    tracker.finish();
+
+    let mut errors = errors.into_inner().unwrap_or_else(|e| e.into_inner());
+    errors.sort_by_key(|(index, _, _)| *index);
+    errors.into_iter().map(|(_, path, error)| (path, error)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{get_files, CancellationToken};
+
+    #[test]
+    fn test_hash_reader_all_algorithms() {
+        let hashes = hash_reader(&b"abc"[..], &HashAlgorithm::ALL).unwrap();
+        assert_eq!(hashes.md5(), Some("900150983cd24fb0d6963f7d28e17f72"));
+        assert_eq!(hashes.sha1(), Some("a9993e364706816aba3e25717850c26c9cd0d89d"));
+        assert_eq!(hashes.sha256(), Some(to_hex(&Sha256::digest(b"abc")).as_str()));
+        assert_eq!(
+            hashes.sha512(),
+            Some(
+                "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+                 2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+            )
+        );
+        assert_eq!(hashes.blake3(), Some("6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"));
+    }
+
+    #[test]
+    fn test_hash_reader_streams_large_content() {
+        // Larger than the buffer, so the digest spans several reads
+        let content: Vec<u8> = (0..BUFFER_SIZE * 2 + 123).map(|i| (i % 251) as u8).collect();
+        let hashes = hash_reader(&content[..], &[HashAlgorithm::Sha256, HashAlgorithm::Sha256]).unwrap();
+        assert_eq!(hashes.iter().count(), 1);
+        assert_eq!(hashes.sha256(), Some(to_hex(&Sha256::digest(&content[..])).as_str()));
+    }
+
+    #[test]
+    fn test_hash_files_stores_digests() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), b"abc").unwrap();
+        fs::write(dir.path().join("b.txt"), b"").unwrap();
+
+        let mut files = get_files(dir.path().to_str().unwrap()).unwrap();
+        files.sort_by(|a, b| a.name().cmp(b.name()));
+        assert!(hash_files(&mut files, &HashAlgorithm::EVIDENCE).is_empty());
+
+        assert_eq!(files[0].hashes().md5(), Some("900150983cd24fb0d6963f7d28e17f72"));
+        assert_eq!(files[1].hashes().sha1(), Some("da39a3ee5e6b4b0d3255bfef95601890afd80709"));
+        assert_eq!(files[1].hashes().sha512(), None);
+
+        // Further digests are added to the stored ones
+        files[0].hash(&[HashAlgorithm::Blake3]).unwrap();
+        assert_eq!(files[0].hashes().iter().count(), 4);
+    }
+
+    #[test]
+    fn test_hash_files_parallel() {
+        let dir = tempdir().unwrap();
+        for index in 0..20 {
+            fs::write(dir.path().join(format!("{:02}.bin", index)), vec![index as u8; index * 1000]).unwrap();
+        }
+        let mut files = get_files(dir.path().to_str().unwrap()).unwrap();
+        files.sort_by(|a, b| a.name().cmp(b.name()));
+        let missing = dir.path().join("05.bin");
+        fs::remove_file(&missing).unwrap();
+
+        let errors = hash_files_parallel(&mut files, &[HashAlgorithm::Sha256], 4);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, missing);
+
+        for (index, file) in files.iter().enumerate().filter(|(index, _)| *index != 5) {
+            let expected = to_hex(&Sha256::digest(&vec![index as u8; index * 1000][..]));
+            assert_eq!(file.hashes().sha256(), Some(expected.as_str()));
+        }
+        assert!(files[5].hashes().is_empty());
+    }
+
+    #[test]
+    fn test_hash_files_with_monitor() {
+        let dir = tempdir().unwrap();
+        for index in 0..3 {
+            fs::write(dir.path().join(format!("{}.bin", index)), vec![index as u8; 1000]).unwrap();
+        }
+        let mut files = get_files(dir.path().to_str().unwrap()).unwrap();
+        files.sort_by(|a, b| a.name().cmp(b.name()));
+
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        let last = Arc::new(Mutex::new(None));
+        let sink = Arc::clone(&last);
+        let monitor = Monitor::new()
+            .with_progress(move |progress| {
+                // Stop once the first file is done
+                if progress.files() == 1 {
+                    canceller.cancel();
+                }
+                *sink.lock().unwrap() = Some(progress.clone());
+            })
+            .with_cancellation(token)
+            .with_interval(Duration::ZERO);
+
+        assert!(hash_files_with_monitor(&mut files, &[HashAlgorithm::Md5], 1, &monitor).is_empty());
+        assert!(files[0].hashes().md5().is_some());
+        assert!(files[1].hashes().is_empty() && files[2].hashes().is_empty());
+
+        let last = last.lock().unwrap().clone().unwrap();
+        assert!(last.is_finished() && last.is_cancelled());
+        assert_eq!((last.files(), last.bytes()), (1, 1000));
+        assert_eq!((last.total_files(), last.total_bytes()), (Some(3), Some(3000)));
+    }
+}
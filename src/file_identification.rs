@@ -1,9 +1,15 @@
 use std::collections::HashMap;
+use std::io::{self, Read};
+use std::path::PathBuf;
 
 use infer::MatcherType;
 
+use crate::progress::{Monitor, Tracker};
 use crate::FileEntry;
 
+/// Files by category, and the path and error of every file that could not be read
+type Identified = (HashMap<String, Vec<FileEntry>>, Vec<(PathBuf, io::Error)>);
+
 /// Convert an infer MatcherType enum to a human-readable string
 ///
 /// # Arguments
@@ -26,6 +32,37 @@ fn matcher_type_to_string(matcher_type: MatcherType) -> String {
     }
 }
 
+/// Number of leading bytes inspected to detect a file type
+const HEADER_SIZE: u64 = 8192;
+
+/// Reads the leading bytes of a file used for signature detection
+///
+/// The content is read through `FileEntry::open`, so entries stored inside
+/// images are identified the same way as files on a mounted file system.
+///
+/// # Arguments
+/// * `file` - A reference to the FileEntry to read
+///
+/// # Returns
+/// * `io::Result<Vec<u8>>` - Up to `HEADER_SIZE` bytes from the start of the file
+fn read_header(file: &FileEntry) -> io::Result<Vec<u8>> {
+    let mut header = Vec::new();
+    file.open()?.take(HEADER_SIZE).read_to_end(&mut header)?;
+    Ok(header)
+}
+
+/// Detects the category and MIME type of a file from its signature
+///
+/// # Arguments
+/// * `file` - A reference to the FileEntry to identify
+///
+/// # Returns
+/// * `io::Result<Option<(String, String)>>` - The category and MIME type, or `None` if no signature matches
+pub(crate) fn detect_content_type(file: &FileEntry) -> io::Result<Option<(String, String)>> {
+    let kind = infer::get(&read_header(file)?);
+    Ok(kind.map(|kind| (matcher_type_to_string(kind.matcher_type()), kind.mime_type().to_string())))
+}
+
 /// Checks if a file extension matches its actual content type
 ///
 /// This function reads the file and attempts to determine its true content type
@@ -40,7 +77,7 @@ fn matcher_type_to_string(matcher_type: MatcherType) -> String {
 /// - Option<String>: the detected MIME type of the file (None if detection failed)
 pub fn validate_file_extension(file: &FileEntry) -> (bool, Option<String>) {
     // Try to detect the file type
-    let kind = match infer::get_from_path(file.path()) {
+    let kind = match read_header(file).map(|header| infer::get(&header)) {
         Ok(Some(k)) => k,
         _ => return (true, None), // Couldn't determine type, assume extension is correct
     };
@@ -109,35 +146,104 @@ pub fn validate_file_extension(file: &FileEntry) -> (bool, Option<String>) {
 /// values are vectors of FileEntry objects belonging to that category
 ///
 /// # Note
-/// Files that cannot be identified will be skipped and not included in the results
+/// Files that cannot be read will be skipped and not included in the results;
+/// `identify_files_with_monitor` returns them with their errors
 pub fn identify_files(file_entries: Vec<FileEntry>) -> HashMap<String, Vec<FileEntry>> {
-    let mut identified_files = HashMap::new();
+    identify_files_with_monitor(file_entries, &Monitor::new()).0
+}
 
-    for file in file_entries {
-        // Attempt to identify file type, skip files that can't be identified
-        match infer::get_from_path(file.path()) {
-            Err(e) => {
-                eprintln!("Error identifying file: {:?}", e);
-                continue;
-            }
-            Ok(Some(kind)) => {
-                let category = matcher_type_to_string(kind.matcher_type());
+/// Sorts files into categories based on their content type, reporting progress and stopping when cancelled
+///
+/// Works as `identify_files`. Progress counts the files identified and
+/// their sizes, against the number and total size of `file_entries`. Once
+/// the monitor is cancelled no further file is read, and the categories of
+/// the files identified until then are returned.
+///
+/// # Arguments
+/// * `file_entries` - A vector of FileEntry objects to categorize
+/// * `monitor` - Where progress is reported and cancellation requested
+///
+/// # Returns
+/// A HashMap where keys are category names (e.g., "Image", "Document") and
+/// values are vectors of FileEntry objects belonging to that category, and
+/// the path of every file that could not be read with the error met
+///
+/// # Examples
+/// ```no_run
+/// use std::time::Duration;
+/// use win_disk_info::{get_files, identify_files_with_monitor, CancellationToken, Monitor};
+///
+/// let token = CancellationToken::new();
+/// let monitor = Monitor::new()
+///     .with_progress(|progress| println!("{} of {:?} files", progress.files(), progress.total_files()))
+///     .with_interval(Duration::from_secs(1))
+///     .with_cancellation(token.clone());
+/// let (identified, errors) = identify_files_with_monitor(get_files("C:\\Documents").unwrap(), &monitor);
+/// for (path, error) in &errors {
+///     eprintln!("{}: {}", path.display(), error);
+/// }
+/// ```
+pub fn identify_files_with_monitor(file_entries: Vec<FileEntry>, monitor: &Monitor) -> Identified {
+    let mut identified_files = HashMap::new();
+    let mut errors = Vec::new();
+    let tracker = Tracker::with_totals(monitor, file_entries.len() as u64, file_entries.iter().map(FileEntry::size).sum());
+
+    // The check runs before each file is read, as the iterator is lazy
+    let pending = file_entries.into_iter().take_while(|_| !tracker.is_cancelled());
+    for (file, category) in identify_iter(pending) {
+        tracker.advance(file.path(), 1, file.size());
+        // Files that can't be read are reported rather than categorized
+        match category {
+            Err(e) => errors.push((file.path().to_path_buf(), e)),
+            Ok(category) => {
                 identified_files
                     .entry(category)
                     .or_insert_with(Vec::new)
                     .push(file);
             }
-            Ok(None) => {
-                eprintln!("Could not identify file: {:?}", file.path());
-                identified_files
-                    .entry("Unknown".to_string())
-                    .or_insert_with(Vec::new)
-                    .push(file);
-            }
         }
     }
+    tracker.finish();
 
-    identified_files
+    (identified_files, errors)
+}
+
+/// Identifies the content category of files as an iterator yields them
+///
+/// Each file is read and categorized only when the returned iterator is
+/// advanced, so any number of files, for instance those streamed by
+/// `scan_files_iter`, can be identified in constant memory.
+///
+/// # Arguments
+/// * `files` - Any iterator over the files to identify
+///
+/// # Returns
+/// An iterator yielding each file with its category, as named by
+/// `identify_files` ("Unknown" when no signature matches), or the error
+/// met reading it
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::{identify_iter, scan_files_iter, ScanOptions};
+///
+/// let files = scan_files_iter("D:\\", &ScanOptions::volume()).filter_map(Result::ok);
+/// for (file, category) in identify_iter(files) {
+///     if let Ok("Video") = category.as_deref() {
+///         println!("{}", file.path().display());
+///     }
+/// }
+/// ```
+pub fn identify_iter<I>(files: I) -> impl Iterator<Item = (FileEntry, io::Result<String>)>
+where
+    I: IntoIterator<Item = FileEntry>,
+{
+    files.into_iter().map(|file| {
+        let category = detect_content_type(&file).map(|kind| match kind {
+            Some((category, _)) => category,
+            None => "Unknown".to_string(),
+        });
+        (file, category)
+    })
 }
 
 /// Identifies files with incorrect or misleading extensions
@@ -169,6 +275,57 @@ pub fn find_mismatched_extensions(file_entries: &[FileEntry]) -> Vec<(FileEntry,
     mismatched
 }
 
+/// Checks the extension of files against their content as an iterator yields them
+///
+/// This is the lazy counterpart of calling `validate_file_extension` on
+/// every file.
+///
+/// # Arguments
+/// * `files` - Any iterator over the files to check
+///
+/// # Returns
+/// An iterator yielding each file with the result of `validate_file_extension`
+pub fn validate_extensions_iter<I>(files: I) -> impl Iterator<Item = (FileEntry, bool, Option<String>)>
+where
+    I: IntoIterator<Item = FileEntry>,
+{
+    files.into_iter().map(|file| {
+        let (valid, mime_type) = validate_file_extension(&file);
+        (file, valid, mime_type)
+    })
+}
+
+/// Finds files with incorrect or misleading extensions as an iterator yields them
+///
+/// This is the lazy counterpart of `find_mismatched_extensions`, which
+/// works on any iterator and takes the files instead of cloning them.
+///
+/// # Arguments
+/// * `files` - Any iterator over the files to check
+///
+/// # Returns
+/// An iterator yielding the files whose extension does not match their
+/// content, with their actual MIME type
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::{mismatched_extensions_iter, scan_files_iter, ScanOptions};
+///
+/// let files = scan_files_iter("C:\\Users", &ScanOptions::user_files()).filter_map(Result::ok);
+/// for (file, mime_type) in mismatched_extensions_iter(files) {
+///     println!("{} is {}", file.path().display(), mime_type);
+/// }
+/// ```
+pub fn mismatched_extensions_iter<I>(files: I) -> impl Iterator<Item = (FileEntry, String)>
+where
+    I: IntoIterator<Item = FileEntry>,
+{
+    validate_extensions_iter(files).filter_map(|(file, valid, mime_type)| match (valid, mime_type) {
+        (false, Some(mime_type)) => Some((file, mime_type)),
+        _ => None,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -179,14 +336,13 @@ mod tests {
 
     /// Helper function to create a test FileEntry
     fn create_test_file_entry(path: &PathBuf) -> FileEntry {
-        // Use From<DirEntry> implementation that's available
         let dir_entry = walkdir::WalkDir::new(path)
             .into_iter()
             .next()
             .unwrap()
             .unwrap();
 
-        FileEntry::from(dir_entry)
+        FileEntry::try_from(dir_entry).unwrap()
     }
 
     #[test]
@@ -329,4 +485,59 @@ mod tests {
         assert_eq!(identified["Image"][0].path(), image_entry.path());
         assert_eq!(identified["Archive"][0].path(), pdf_entry.path());
     }
+
+    #[test]
+    fn test_identify_files_with_monitor() {
+        let temp_dir = tempdir().unwrap();
+        let mut file_entries = Vec::new();
+        for index in 0..3 {
+            let path = temp_dir.path().join(format!("image{}.jpg", index));
+            File::create(&path).unwrap().write_all(&[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0x4A, 0x46, 0x49, 0x46]).unwrap();
+            file_entries.push(create_test_file_entry(&path));
+        }
+
+        let monitor = crate::Monitor::new();
+        let (identified, errors) = identify_files_with_monitor(file_entries.clone(), &monitor);
+        assert_eq!(identified["Image"].len(), 3);
+        assert!(errors.is_empty());
+
+        // Unreadable files are returned with their error
+        std::fs::remove_file(file_entries[0].path()).unwrap();
+        let (identified, errors) = identify_files_with_monitor(file_entries.clone(), &monitor);
+        assert_eq!(identified["Image"].len(), 2);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, file_entries[0].path());
+
+        // A cancelled identification reads no more files
+        monitor.cancellation().cancel();
+        let (identified, errors) = identify_files_with_monitor(file_entries, &monitor);
+        assert!(identified.is_empty() && errors.is_empty());
+    }
+
+    #[test]
+    fn test_iterator_adapters() {
+        let temp_dir = tempdir().unwrap();
+        let jpeg_header = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0x4A, 0x46, 0x49, 0x46];
+        std::fs::write(temp_dir.path().join("photo.jpg"), jpeg_header).unwrap();
+        std::fs::write(temp_dir.path().join("invoice.txt"), jpeg_header).unwrap();
+        std::fs::write(temp_dir.path().join("notes.txt"), b"plain text").unwrap();
+        let files = || {
+            crate::scan_files_iter(temp_dir.path().to_str().unwrap(), &crate::ScanOptions::new())
+                .map(Result::unwrap)
+        };
+
+        let mut categories: Vec<String> = identify_iter(files()).map(|(_, category)| category.unwrap()).collect();
+        categories.sort();
+        assert_eq!(categories, vec!["Image", "Image", "Unknown"]);
+
+        assert_eq!(validate_extensions_iter(files()).filter(|(_, valid, _)| *valid).count(), 2);
+        let mismatched: Vec<(FileEntry, String)> = mismatched_extensions_iter(files()).collect();
+        assert_eq!(mismatched.len(), 1);
+        assert_eq!(mismatched[0].0.name(), "invoice.txt");
+        assert_eq!(mismatched[0].1, "image/jpeg");
+
+        let missing = create_test_file_entry(&temp_dir.path().join("notes.txt"));
+        std::fs::remove_file(missing.path()).unwrap();
+        assert!(identify_iter(vec![missing]).next().unwrap().1.is_err());
+    }
 }
@@ -0,0 +1,299 @@
+//! This module identifies the file system stored in a range of a disk.
+//!
+//! Detection only reads the boot sector or superblock of each supported file
+//! system, which is enough to report its type, label and capacity without
+//! mounting it.
+
+use std::io::{Read, Seek};
+use std::path::PathBuf;
+
+use crate::images::{be_u32, be_u64, le_u16, le_u32, le_u64, read_bytes, DeviceSlice, ImageError, OpticalVolume};
+use crate::FileSystem;
+
+/// Offset of the ext2/3/4 superblock
+const EXT_SUPERBLOCK_OFFSET: u64 = 1024;
+/// Magic number of the ext2/3/4 superblock
+const EXT_MAGIC: u16 = 0xEF53;
+/// Offset of the primary Btrfs superblock
+const BTRFS_SUPERBLOCK_OFFSET: u64 = 0x10000;
+/// Offset of the first ZFS uberblock in the first vdev label
+const ZFS_UBERBLOCK_OFFSET: u64 = 128 * 1024;
+/// Magic number of a ZFS uberblock
+const ZFS_UBERBLOCK_MAGIC: u64 = 0x00BA_B10C;
+
+/// File systems recognized by their on-disk signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DetectedFileSystem {
+    Ntfs,
+    Fat32,
+    /// FAT12 or FAT16, with its name
+    Fat(&'static str),
+    ExFat,
+    Ext4,
+    /// ext2 or ext3, with its name
+    Ext(&'static str),
+    Xfs,
+    Btrfs,
+    Zfs,
+    Iso9660,
+    Udf,
+    Unknown,
+}
+
+/// A file system found on a disk range.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DetectedVolume {
+    /// Type of file system
+    pub(crate) file_system: DetectedFileSystem,
+    /// Volume label, empty if none is stored in the boot sector or superblock
+    pub(crate) label: String,
+    /// Capacity reported by the file system, in bytes
+    pub(crate) total_space: u64,
+    /// Free space reported by the file system, in bytes, or 0 if unknown
+    pub(crate) available_space: u64,
+}
+
+impl DetectedVolume {
+    fn new(file_system: DetectedFileSystem, label: String, total_space: u64, available_space: u64) -> Self {
+        DetectedVolume {
+            file_system,
+            label,
+            total_space,
+            available_space,
+        }
+    }
+
+    /// Returns the `FileSystem` value for this volume mounted at `mount_path`.
+    pub(crate) fn to_file_system(&self, mount_path: PathBuf) -> FileSystem {
+        match self.file_system {
+            DetectedFileSystem::Ntfs => FileSystem::NTFS(mount_path),
+            DetectedFileSystem::Fat32 => FileSystem::FAT32(mount_path),
+            DetectedFileSystem::Fat(name) => FileSystem::NotImplemented(name.to_string(), mount_path),
+            DetectedFileSystem::ExFat => FileSystem::EXFAT(mount_path),
+            DetectedFileSystem::Ext4 => FileSystem::EXT4(mount_path),
+            DetectedFileSystem::Ext(name) => FileSystem::NotImplemented(name.to_string(), mount_path),
+            DetectedFileSystem::Xfs => FileSystem::XFS(mount_path),
+            DetectedFileSystem::Btrfs => FileSystem::BTRFS(vec![mount_path]),
+            DetectedFileSystem::Zfs => FileSystem::ZFS(mount_path),
+            DetectedFileSystem::Iso9660 => FileSystem::ISO9660(mount_path),
+            DetectedFileSystem::Udf => FileSystem::UDF(mount_path),
+            DetectedFileSystem::Unknown => FileSystem::Unknown,
+        }
+    }
+}
+
+/// Trims the padding from a fixed-size text field.
+fn trim_label(field: &[u8]) -> String {
+    String::from_utf8_lossy(field).trim_end_matches([' ', '\0']).to_string()
+}
+
+/// Identifies the file system stored in `length` bytes starting at `offset`.
+///
+/// Unrecognized content is reported as `DetectedFileSystem::Unknown` spanning
+/// the whole range.
+pub(crate) fn detect_file_system<R: Read + Seek>(
+    reader: &mut R,
+    offset: u64,
+    length: u64,
+) -> Result<DetectedVolume, ImageError> {
+    let mut slice = DeviceSlice::new(reader, offset, length);
+    let unknown = DetectedVolume::new(DetectedFileSystem::Unknown, String::new(), length, 0);
+
+    let boot = match read_bytes(&mut slice, 0, 512) {
+        Ok(boot) => boot,
+        Err(_) => return Ok(unknown),
+    };
+
+    if &boot[3..11] == b"NTFS    " {
+        let sector_size = le_u16(&boot, 11) as u64;
+        // A corrupt sector count leaves the size unknown
+        let total = le_u64(&boot, 40).checked_mul(sector_size).unwrap_or(0);
+        return Ok(DetectedVolume::new(DetectedFileSystem::Ntfs, String::new(), total, 0));
+    }
+
+    if &boot[3..11] == b"EXFAT   " {
+        let sector_shift = boot[108] as u32;
+        let cluster_size = 1u64 << (sector_shift + boot[109] as u32).min(63);
+        let total = le_u64(&boot, 72) << sector_shift.min(63);
+        let percent_in_use = boot[112] as u64;
+        let available = if percent_in_use <= 100 {
+            (le_u32(&boot, 92) as u64)
+                .checked_mul(cluster_size)
+                .and_then(|size| size.checked_mul(100 - percent_in_use))
+                .map_or(0, |size| size / 100)
+        } else {
+            0
+        };
+        return Ok(DetectedVolume::new(DetectedFileSystem::ExFat, String::new(), total, available));
+    }
+
+    if &boot[82..90] == b"FAT32   " || boot[54..57] == *b"FAT" {
+        let sector_size = le_u16(&boot, 11) as u64;
+        let total_sectors = match le_u16(&boot, 19) {
+            0 => le_u32(&boot, 32) as u64,
+            sectors => sectors as u64,
+        };
+
+        if &boot[82..90] != b"FAT32   " {
+            let name = if &boot[54..59] == b"FAT12" { "FAT12" } else { "FAT16" };
+            let label = trim_label(&boot[43..54]);
+            let label = if label == "NO NAME" { String::new() } else { label };
+            return Ok(DetectedVolume::new(DetectedFileSystem::Fat(name), label, total_sectors * sector_size, 0));
+        }
+
+        let label = trim_label(&boot[71..82]);
+        let label = if label == "NO NAME" { String::new() } else { label };
+        let cluster_size = sector_size * boot[13] as u64;
+        let info = read_bytes(&mut slice, le_u16(&boot, 48) as u64 * sector_size, 512).ok();
+        let free_clusters = info
+            .filter(|info| &info[0..4] == b"RRaA" && &info[484..488] == b"rrAa")
+            .map(|info| le_u32(&info, 488))
+            .filter(|&free| free != u32::MAX)
+            .unwrap_or(0) as u64;
+        return Ok(DetectedVolume::new(
+            DetectedFileSystem::Fat32,
+            label,
+            total_sectors * sector_size,
+            free_clusters * cluster_size,
+        ));
+    }
+
+    if &boot[0..4] == b"XFSB" {
+        let block_size = be_u32(&boot, 4) as u64;
+        return Ok(DetectedVolume::new(
+            DetectedFileSystem::Xfs,
+            trim_label(&boot[108..120]),
+            be_u64(&boot, 8) * block_size,
+            be_u64(&boot, 144) * block_size,
+        ));
+    }
+
+    if let Ok(superblock) = read_bytes(&mut slice, EXT_SUPERBLOCK_OFFSET, 1024) {
+        if le_u16(&superblock, 56) == EXT_MAGIC {
+            let block_size = 1024u64 << le_u32(&superblock, 24).min(16);
+            let compatible = le_u32(&superblock, 92);
+            let incompatible = le_u32(&superblock, 96);
+            let is_64bit = incompatible & 0x80 != 0;
+            let high = |at: usize| if is_64bit { (le_u32(&superblock, at) as u64) << 32 } else { 0 };
+            let blocks = le_u32(&superblock, 4) as u64 | high(0x150);
+            let free = le_u32(&superblock, 12) as u64 | high(0x158);
+
+            // extents, 64bit or flex_bg only exist on ext4
+            let file_system = if incompatible & (0x40 | 0x80 | 0x200) != 0 {
+                DetectedFileSystem::Ext4
+            } else if compatible & 0x4 != 0 {
+                DetectedFileSystem::Ext("EXT3")
+            } else {
+                DetectedFileSystem::Ext("EXT2")
+            };
+            return Ok(DetectedVolume::new(
+                file_system,
+                trim_label(&superblock[120..136]),
+                blocks * block_size,
+                free * block_size,
+            ));
+        }
+    }
+
+    if let Ok(superblock) = read_bytes(&mut slice, BTRFS_SUPERBLOCK_OFFSET, 0x22B) {
+        if &superblock[0x40..0x48] == b"_BHRfS_M" {
+            let total = le_u64(&superblock, 0x70);
+            let used = le_u64(&superblock, 0x78);
+            return Ok(DetectedVolume::new(
+                DetectedFileSystem::Btrfs,
+                trim_label(&superblock[0x12B..0x22B]),
+                total,
+                total.saturating_sub(used),
+            ));
+        }
+    }
+
+    if let Ok(uberblock) = read_bytes(&mut slice, ZFS_UBERBLOCK_OFFSET, 8) {
+        let magic = le_u64(&uberblock, 0);
+        if magic == ZFS_UBERBLOCK_MAGIC || magic.swap_bytes() == ZFS_UBERBLOCK_MAGIC {
+            return Ok(DetectedVolume::new(DetectedFileSystem::Zfs, String::new(), length, 0));
+        }
+    }
+
+    if let Ok(Some(volume)) = OpticalVolume::open(&mut slice) {
+        let file_system = match volume {
+            OpticalVolume::Iso9660(_) => DetectedFileSystem::Iso9660,
+            OpticalVolume::Udf(_) => DetectedFileSystem::Udf,
+        };
+        return Ok(DetectedVolume::new(file_system, volume.label().to_string(), volume.size(), 0));
+    }
+
+    Ok(unknown)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_detect_ntfs() {
+        let mut disk = vec![0u8; 4096];
+        let boot = &mut disk[1024..1536];
+        boot[3..11].copy_from_slice(b"NTFS    ");
+        boot[11..13].copy_from_slice(&512u16.to_le_bytes());
+        boot[40..48].copy_from_slice(&2048u64.to_le_bytes());
+
+        let volume = detect_file_system(&mut Cursor::new(disk), 1024, 2048).unwrap();
+        assert_eq!(volume.file_system, DetectedFileSystem::Ntfs);
+        assert_eq!(volume.total_space, 2048 * 512);
+
+        let mut disk = vec![0u8; 4096];
+        disk[3..11].copy_from_slice(b"NTFS    ");
+        disk[11..13].copy_from_slice(&4096u16.to_le_bytes());
+        disk[40..48].copy_from_slice(&u64::MAX.to_le_bytes());
+        let volume = detect_file_system(&mut Cursor::new(disk), 0, 4096).unwrap();
+        assert_eq!((volume.file_system, volume.total_space), (DetectedFileSystem::Ntfs, 0));
+    }
+
+    #[test]
+    fn test_detect_fat32() {
+        let mut disk = vec![0u8; 8192];
+        disk[11..13].copy_from_slice(&512u16.to_le_bytes());
+        disk[13] = 8;
+        disk[32..36].copy_from_slice(&1_000_000u32.to_le_bytes());
+        disk[48..50].copy_from_slice(&1u16.to_le_bytes());
+        disk[71..82].copy_from_slice(b"CAMERA     ");
+        disk[82..90].copy_from_slice(b"FAT32   ");
+        disk[512..516].copy_from_slice(b"RRaA");
+        disk[996..1000].copy_from_slice(b"rrAa");
+        disk[1000..1004].copy_from_slice(&100u32.to_le_bytes());
+
+        let volume = detect_file_system(&mut Cursor::new(disk), 0, 8192).unwrap();
+        assert_eq!(volume.file_system, DetectedFileSystem::Fat32);
+        assert_eq!(volume.label, "CAMERA");
+        assert_eq!(volume.total_space, 1_000_000 * 512);
+        assert_eq!(volume.available_space, 100 * 4096);
+    }
+
+    #[test]
+    fn test_detect_ext4() {
+        let mut disk = vec![0u8; 4096];
+        let superblock = &mut disk[1024..2048];
+        superblock[4..8].copy_from_slice(&1000u32.to_le_bytes());
+        superblock[12..16].copy_from_slice(&250u32.to_le_bytes());
+        superblock[24..28].copy_from_slice(&2u32.to_le_bytes());
+        superblock[56..58].copy_from_slice(&EXT_MAGIC.to_le_bytes());
+        superblock[96..100].copy_from_slice(&0x40u32.to_le_bytes());
+        superblock[120..124].copy_from_slice(b"root");
+
+        let volume = detect_file_system(&mut Cursor::new(disk), 0, 4096).unwrap();
+        assert_eq!(volume.file_system, DetectedFileSystem::Ext4);
+        assert_eq!(volume.label, "root");
+        assert_eq!(volume.total_space, 1000 * 4096);
+        assert_eq!(volume.available_space, 250 * 4096);
+    }
+
+    #[test]
+    fn test_detect_unknown() {
+        let volume = detect_file_system(&mut Cursor::new(vec![0u8; 4096]), 0, 4096).unwrap();
+        assert_eq!(volume.file_system, DetectedFileSystem::Unknown);
+        assert_eq!(volume.total_space, 4096);
+    }
+}
@@ -0,0 +1,703 @@
+//! This module matches files against sets of known hashes.
+//!
+//! Hash sets such as the NSRL Reference Data Set list the digests of files
+//! shipped with operating systems and applications, while case-specific sets
+//! list the digests of malware or contraband. Loading them into a
+//! `KnownFileSet` lets a triage filter out the known-good files and highlight
+//! the known-bad ones.
+//!
+//! Digests are kept in memory as sorted binary records, one table per
+//! algorithm, and can be saved to a compact index file that loads without
+//! parsing the original text sets again.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::file_hashing::from_hex;
+use crate::{FileEntry, FileHashes, HashAlgorithm, KnownStatus};
+
+/// Signature at the start of a saved index
+const INDEX_MAGIC: &[u8; 8] = b"WDIHASH\x00";
+/// Version of the saved index layout
+const INDEX_VERSION: u8 = 1;
+
+/// Error type for hash set operations
+#[derive(Debug)]
+pub enum HashSetError {
+    /// IO errors from reading or writing a hash set
+    Io(io::Error),
+    /// The hash set or index is malformed
+    InvalidFormat(String),
+    /// Hash sets can only hold known-good or known-bad files
+    InvalidStatus(KnownStatus),
+}
+
+impl fmt::Display for HashSetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO error: {}", e),
+            Self::InvalidFormat(s) => write!(f, "Invalid hash set: {}", s),
+            Self::InvalidStatus(status) => write!(f, "Invalid hash set status: {}", status),
+        }
+    }
+}
+
+impl std::error::Error for HashSetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for HashSetError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Text formats hash sets can be loaded from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashSetFormat {
+    /// NSRL RDS `NSRLFile.txt`: quoted CSV with `"SHA-1"`, `"MD5"` and other columns
+    NsrlRds,
+    /// One hexadecimal digest per line, optionally followed by a file name as in
+    /// `md5sum` output; the algorithm is deduced from the digest length
+    HashList,
+    /// hashdeep output: a `%%%%` header naming the columns, then one CSV line per file
+    Hashdeep,
+}
+
+/// Sorted digests of one algorithm, each followed by a status byte.
+#[derive(Debug, Clone)]
+struct DigestTable {
+    algorithm: HashAlgorithm,
+    records: Vec<u8>,
+}
+
+impl DigestTable {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        DigestTable {
+            algorithm,
+            records: Vec::new(),
+        }
+    }
+
+    fn digest_len(&self) -> usize {
+        self.algorithm.digest_len()
+    }
+
+    fn len(&self) -> usize {
+        self.records.len() / (self.digest_len() + 1)
+    }
+
+    fn digest(&self, index: usize) -> &[u8] {
+        let start = index * (self.digest_len() + 1);
+        &self.records[start..start + self.digest_len()]
+    }
+
+    /// Returns the position of `digest`, or where it would be inserted.
+    fn search(&self, digest: &[u8]) -> Result<usize, usize> {
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let middle = (low + high) / 2;
+            match self.digest(middle).cmp(digest) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return Ok(middle),
+            }
+        }
+        Err(low)
+    }
+
+    fn find(&self, digest: &[u8]) -> Option<KnownStatus> {
+        let index = self.search(digest).ok()?;
+        decode_status(self.records[index * (self.digest_len() + 1) + self.digest_len()])
+    }
+
+    /// Adds a record, leaving the table to be sorted by `normalize`.
+    fn push(&mut self, digest: &[u8], status: KnownStatus) {
+        self.records.extend_from_slice(digest);
+        self.records.push(encode_status(status));
+    }
+
+    /// Sorts the records and removes duplicate digests, known-bad winning over known-good.
+    fn normalize(&mut self) {
+        let record_len = self.digest_len() + 1;
+        let mut records: Vec<&[u8]> = self.records.chunks_exact(record_len).collect();
+        records.sort_unstable();
+
+        let mut sorted = Vec::with_capacity(self.records.len());
+        for (index, record) in records.iter().enumerate() {
+            // Records of a digest are sorted by status, so the last one holds the strongest
+            let digest = &record[..record_len - 1];
+            if records.get(index + 1).is_none_or(|next| &next[..record_len - 1] != digest) {
+                sorted.extend_from_slice(record);
+            }
+        }
+        self.records = sorted;
+    }
+}
+
+fn encode_status(status: KnownStatus) -> u8 {
+    match status {
+        KnownStatus::Unknown => 0,
+        KnownStatus::KnownGood => 1,
+        KnownStatus::KnownBad => 2,
+    }
+}
+
+fn decode_status(byte: u8) -> Option<KnownStatus> {
+    match byte {
+        1 => Some(KnownStatus::KnownGood),
+        2 => Some(KnownStatus::KnownBad),
+        _ => None,
+    }
+}
+
+/// An index of known-good and known-bad file digests.
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::{get_files, HashSetFormat, KnownFileSet, KnownStatus};
+///
+/// let mut known = KnownFileSet::new();
+/// known.load("D:/nsrl/NSRLFile.txt", HashSetFormat::NsrlRds, KnownStatus::KnownGood).unwrap();
+/// known.load("D:/cases/2024-17/bad.md5", HashSetFormat::HashList, KnownStatus::KnownBad).unwrap();
+/// known.save("D:/cases/2024-17/known.idx").unwrap();
+///
+/// let mut files = get_files("E:\\").unwrap();
+/// known.match_files(&mut files);
+/// for file in files.iter().filter(|file| file.known_status() == KnownStatus::KnownBad) {
+///     println!("{}", file.path().display());
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct KnownFileSet {
+    /// One table per algorithm, ordered by algorithm
+    tables: Vec<DigestTable>,
+}
+
+impl KnownFileSet {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens an index previously written by `save`.
+    ///
+    /// # Arguments
+    /// * `path` - The index file
+    ///
+    /// # Returns
+    /// * `Ok(KnownFileSet)` - The digests of the index
+    /// * `Err(HashSetError)` - If the file cannot be read or is not a valid index
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, HashSetError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut header = [0u8; 10];
+        reader.read_exact(&mut header)?;
+        if &header[..8] != INDEX_MAGIC || header[8] != INDEX_VERSION {
+            return Err(HashSetError::InvalidFormat("not a hash set index".to_string()));
+        }
+
+        let mut set = KnownFileSet::new();
+        for _ in 0..header[9] {
+            let mut table_header = [0u8; 9];
+            reader.read_exact(&mut table_header)?;
+            let algorithm = *HashAlgorithm::ALL
+                .get(table_header[0] as usize)
+                .ok_or_else(|| HashSetError::InvalidFormat(format!("unknown algorithm {}", table_header[0])))?;
+            let count = u64::from_le_bytes(table_header[1..9].try_into().unwrap_or_default());
+
+            let mut table = DigestTable::new(algorithm);
+            let size = count
+                .checked_mul(algorithm.digest_len() as u64 + 1)
+                .ok_or_else(|| HashSetError::InvalidFormat("table too large".to_string()))?;
+            reader.by_ref().take(size).read_to_end(&mut table.records)?;
+            if (table.records.len() as u64) < size {
+                return Err(HashSetError::InvalidFormat("truncated index".to_string()));
+            }
+            let record_len = algorithm.digest_len() + 1;
+            if table.records.chunks_exact(record_len).any(|record| decode_status(record[record_len - 1]).is_none()) {
+                return Err(HashSetError::InvalidFormat("invalid record status".to_string()));
+            }
+            set.tables.push(table);
+        }
+        set.tables.sort_by_key(|table| table.algorithm);
+        Ok(set)
+    }
+
+    /// Writes the set to a compact index file that can be reopened with `open`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), HashSetError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(INDEX_MAGIC)?;
+        writer.write_all(&[INDEX_VERSION, self.tables.len() as u8])?;
+        for table in &self.tables {
+            let id = HashAlgorithm::ALL.iter().position(|algorithm| *algorithm == table.algorithm).unwrap_or_default();
+            writer.write_all(&[id as u8])?;
+            writer.write_all(&(table.len() as u64).to_le_bytes())?;
+            writer.write_all(&table.records)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Loads the digests of a text hash set
+    ///
+    /// Digests already in the set keep their entry; a digest listed as both
+    /// known-good and known-bad is considered known-bad.
+    ///
+    /// # Arguments
+    /// * `path` - The hash set file
+    /// * `format` - The layout of the file
+    /// * `status` - Whether the set lists known-good or known-bad files
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - The number of digests read from the file
+    /// * `Err(HashSetError)` - If the file cannot be read or is malformed
+    pub fn load<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        format: HashSetFormat,
+        status: KnownStatus,
+    ) -> Result<usize, HashSetError> {
+        if status == KnownStatus::Unknown {
+            return Err(HashSetError::InvalidStatus(status));
+        }
+        let reader = BufReader::new(File::open(path)?);
+        let digests = match format {
+            HashSetFormat::NsrlRds => parse_nsrl(reader)?,
+            HashSetFormat::HashList => parse_hash_list(reader)?,
+            HashSetFormat::Hashdeep => parse_hashdeep(reader)?,
+        };
+
+        let count = digests.len();
+        for (algorithm, digest) in digests {
+            self.table_mut(algorithm).push(&digest, status);
+        }
+        for table in &mut self.tables {
+            table.normalize();
+        }
+        Ok(count)
+    }
+
+    /// Adds a single digest to the set
+    ///
+    /// # Arguments
+    /// * `algorithm` - The algorithm the digest was computed with
+    /// * `digest` - The digest in hexadecimal
+    /// * `status` - Whether the digest identifies a known-good or known-bad file
+    pub fn insert(&mut self, algorithm: HashAlgorithm, digest: &str, status: KnownStatus) -> Result<(), HashSetError> {
+        if status == KnownStatus::Unknown {
+            return Err(HashSetError::InvalidStatus(status));
+        }
+        let digest = parse_digest(digest)
+            .filter(|digest| digest.len() == algorithm.digest_len())
+            .ok_or_else(|| HashSetError::InvalidFormat(format!("invalid {} digest: {}", algorithm, digest)))?;
+
+        let table = self.table_mut(algorithm);
+        let record_len = table.digest_len() + 1;
+        match table.search(&digest) {
+            Ok(index) => {
+                let byte = &mut table.records[index * record_len + record_len - 1];
+                *byte = (*byte).max(encode_status(status));
+            }
+            Err(index) => {
+                let position = index * record_len;
+                table.records.splice(position..position, digest.into_iter().chain([encode_status(status)]));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of distinct digests in the set
+    pub fn len(&self) -> usize {
+        self.tables.iter().map(DigestTable::len).sum()
+    }
+
+    /// Returns true if the set holds no digest
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the algorithms of the digests in the set
+    pub fn algorithms(&self) -> Vec<HashAlgorithm> {
+        self.tables.iter().filter(|table| table.len() > 0).map(|table| table.algorithm).collect()
+    }
+
+    /// Looks up the digests of a file
+    ///
+    /// # Returns
+    /// `KnownBad` if any digest is known-bad, `KnownGood` if any is known-good,
+    /// and `Unknown` otherwise
+    pub fn lookup(&self, hashes: &FileHashes) -> KnownStatus {
+        self.tables
+            .iter()
+            .filter_map(|table| {
+                let digest = from_hex(hashes.get(table.algorithm)?)?;
+                table.find(&digest)
+            })
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Labels each file as known-good, known-bad or unknown
+    ///
+    /// The digests needed by the set are computed in a single read of each
+    /// file and stored on the entry; digests already stored are reused. The
+    /// label is then available through `FileEntry::known_status`.
+    ///
+    /// # Arguments
+    /// * `files` - The files to match
+    ///
+    /// # Returns
+    /// A vector with the path and error of every file that could not be read.
+    /// Those files are labelled unknown.
+    pub fn match_files(&self, files: &mut [FileEntry]) -> Vec<(PathBuf, io::Error)> {
+        let algorithms = self.algorithms();
+        let mut errors = Vec::new();
+
+        for file in files.iter_mut() {
+            let missing: Vec<HashAlgorithm> = algorithms
+                .iter()
+                .copied()
+                .filter(|algorithm| file.hashes().get(*algorithm).is_none())
+                .collect();
+            if !missing.is_empty() {
+                if let Err(error) = file.hash(&missing) {
+                    errors.push((file.path().to_path_buf(), error));
+                    file.set_known_status(KnownStatus::Unknown);
+                    continue;
+                }
+            }
+            let status = self.lookup(file.hashes());
+            file.set_known_status(status);
+        }
+        errors
+    }
+
+    fn table_mut(&mut self, algorithm: HashAlgorithm) -> &mut DigestTable {
+        let index = match self.tables.binary_search_by_key(&algorithm, |table| table.algorithm) {
+            Ok(index) => index,
+            Err(index) => {
+                self.tables.insert(index, DigestTable::new(algorithm));
+                index
+            }
+        };
+        &mut self.tables[index]
+    }
+}
+
+/// Removes the known-good files from the output of `identify_files`
+///
+/// Files must have been labelled by `KnownFileSet::match_files` first.
+/// Categories left without files are removed.
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::{exclude_known_good, get_files, identify_files, KnownFileSet};
+///
+/// let known = KnownFileSet::open("D:/nsrl/known.idx").unwrap();
+/// let mut files = get_files("E:\\").unwrap();
+/// known.match_files(&mut files);
+/// for (category, files) in exclude_known_good(identify_files(files)) {
+///     println!("{}: {} files", category, files.len());
+/// }
+/// ```
+pub fn exclude_known_good(identified: HashMap<String, Vec<FileEntry>>) -> HashMap<String, Vec<FileEntry>> {
+    identified
+        .into_iter()
+        .filter_map(|(category, files)| {
+            let files: Vec<FileEntry> = files
+                .into_iter()
+                .filter(|file| file.known_status() != KnownStatus::KnownGood)
+                .collect();
+            (!files.is_empty()).then_some((category, files))
+        })
+        .collect()
+}
+
+/// Parses a hexadecimal digest, returning `None` if it is malformed.
+fn parse_digest(hex: &str) -> Option<Vec<u8>> {
+    from_hex(hex.trim()).filter(|digest| !digest.is_empty())
+}
+
+/// Deduces the algorithm of a digest from its length.
+fn algorithm_for_len(len: usize) -> Option<HashAlgorithm> {
+    match len {
+        16 => Some(HashAlgorithm::Md5),
+        20 => Some(HashAlgorithm::Sha1),
+        32 => Some(HashAlgorithm::Sha256),
+        64 => Some(HashAlgorithm::Sha512),
+        _ => None,
+    }
+}
+
+/// Maps a column name such as `"SHA-1"`, `sha256` or `MD5` to its algorithm.
+fn algorithm_for_column(name: &str) -> Option<HashAlgorithm> {
+    let name: String = name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    match name.as_str() {
+        "md5" => Some(HashAlgorithm::Md5),
+        "sha1" => Some(HashAlgorithm::Sha1),
+        "sha256" => Some(HashAlgorithm::Sha256),
+        "sha512" => Some(HashAlgorithm::Sha512),
+        "blake3" => Some(HashAlgorithm::Blake3),
+        _ => None,
+    }
+}
+
+/// Splits a CSV line, honouring quoted fields and doubled quotes.
+pub(crate) fn split_csv(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Reads the digests of the columns named in `columns` from CSV `fields`.
+fn read_columns(
+    columns: &[(usize, HashAlgorithm)],
+    fields: &[String],
+    line_number: usize,
+    digests: &mut Vec<(HashAlgorithm, Vec<u8>)>,
+) -> Result<(), HashSetError> {
+    for &(index, algorithm) in columns {
+        let field = fields.get(index).map(|field| field.trim()).unwrap_or_default();
+        if field.is_empty() {
+            continue;
+        }
+        let digest = parse_digest(field)
+            .filter(|digest| digest.len() == algorithm.digest_len())
+            .ok_or_else(|| HashSetError::InvalidFormat(format!("line {}: invalid {} digest", line_number, algorithm)))?;
+        digests.push((algorithm, digest));
+    }
+    Ok(())
+}
+
+/// Parses an NSRL RDS file, whose first line names the columns.
+fn parse_nsrl<R: BufRead>(reader: R) -> Result<Vec<(HashAlgorithm, Vec<u8>)>, HashSetError> {
+    let mut lines = reader.lines();
+    let header = lines
+        .next()
+        .transpose()?
+        .ok_or_else(|| HashSetError::InvalidFormat("empty NSRL file".to_string()))?;
+    let columns: Vec<(usize, HashAlgorithm)> = split_csv(header.trim_start_matches('\u{feff}'))
+        .iter()
+        .enumerate()
+        .filter_map(|(index, name)| Some((index, algorithm_for_column(name)?)))
+        .collect();
+    if columns.is_empty() {
+        return Err(HashSetError::InvalidFormat("no hash column in NSRL header".to_string()));
+    }
+
+    let mut digests = Vec::new();
+    for (index, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        read_columns(&columns, &split_csv(&line), index + 2, &mut digests)?;
+    }
+    Ok(digests)
+}
+
+/// Parses a list with one digest per line.
+fn parse_hash_list<R: BufRead>(reader: R) -> Result<Vec<(HashAlgorithm, Vec<u8>)>, HashSetError> {
+    let mut digests = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let token = line.split_whitespace().next().unwrap_or_default();
+        let digest = parse_digest(token)
+            .ok_or_else(|| HashSetError::InvalidFormat(format!("line {}: invalid digest", index + 1)))?;
+        let algorithm = algorithm_for_len(digest.len()).ok_or_else(|| {
+            HashSetError::InvalidFormat(format!("line {}: unsupported digest length", index + 1))
+        })?;
+        digests.push((algorithm, digest));
+    }
+    Ok(digests)
+}
+
+/// Parses hashdeep output, whose `%%%%` header names the columns.
+fn parse_hashdeep<R: BufRead>(reader: R) -> Result<Vec<(HashAlgorithm, Vec<u8>)>, HashSetError> {
+    let mut columns: Option<(usize, Vec<(usize, HashAlgorithm)>)> = None;
+    let mut digests = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with("##") {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix("%%%%") {
+            let names: Vec<&str> = header.trim().split(',').collect();
+            // The version line (`%%%% HASHDEEP-1.0`) has a single column
+            if names.len() > 1 {
+                let hashes = names
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, name)| Some((index, algorithm_for_column(name)?)))
+                    .collect();
+                columns = Some((names.len(), hashes));
+            }
+            continue;
+        }
+        let (count, hashes) = columns
+            .as_ref()
+            .ok_or_else(|| HashSetError::InvalidFormat("missing hashdeep column header".to_string()))?;
+        // The file name comes last and may contain commas
+        let fields: Vec<String> = line.splitn(*count, ',').map(str::to_string).collect();
+        read_columns(hashes, &fields, index + 1, &mut digests)?;
+    }
+    Ok(digests)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::tempdir;
+
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+    use crate::file_hashing::to_hex;
+    use crate::{get_files, hash_reader, identify_files};
+
+    const HELLO_MD5: &str = "5d41402abc4b2a76b9719d911017c592";
+    const HELLO_SHA1: &str = "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d";
+
+    fn sha256_hex(data: &[u8]) -> String {
+        to_hex(&Sha256::digest(data))
+    }
+
+    #[test]
+    fn test_load_formats() {
+        let dir = tempdir().unwrap();
+        let nsrl = dir.path().join("NSRLFile.txt");
+        fs::write(
+            &nsrl,
+            format!(
+                "\"SHA-1\",\"MD5\",\"CRC32\",\"FileName\",\"FileSize\",\"ProductCode\",\"OpSystemCode\",\"SpecialCode\"\n\
+                 \"{}\",\"{}\",\"3610A686\",\"hello, world.txt\",5,1,\"358\",\"\"\n",
+                HELLO_SHA1.to_uppercase(),
+                HELLO_MD5.to_uppercase()
+            ),
+        )
+        .unwrap();
+        let list = dir.path().join("bad.txt");
+        fs::write(&list, format!("# known bad\n{}  evil.exe\n\n", sha256_hex(b"evil"))).unwrap();
+        let hashdeep = dir.path().join("known.hashdeep");
+        fs::write(
+            &hashdeep,
+            format!(
+                "%%%% HASHDEEP-1.0\n%%%% size,md5,sha256,filename\n## Invoked from: /\n\
+                 3,{},{},/data/a,b.txt\n",
+                "900150983cd24fb0d6963f7d28e17f72",
+                sha256_hex(b"abc")
+            ),
+        )
+        .unwrap();
+
+        let mut set = KnownFileSet::new();
+        assert_eq!(set.load(&nsrl, HashSetFormat::NsrlRds, KnownStatus::KnownGood).unwrap(), 2);
+        assert_eq!(set.load(&list, HashSetFormat::HashList, KnownStatus::KnownBad).unwrap(), 1);
+        assert_eq!(set.load(&hashdeep, HashSetFormat::Hashdeep, KnownStatus::KnownGood).unwrap(), 2);
+        assert_eq!(set.len(), 5);
+        assert_eq!(set.algorithms(), vec![HashAlgorithm::Md5, HashAlgorithm::Sha1, HashAlgorithm::Sha256]);
+
+        let hello = hash_reader(&b"hello"[..], &[HashAlgorithm::Sha1]).unwrap();
+        assert_eq!(set.lookup(&hello), KnownStatus::KnownGood);
+        let evil = hash_reader(&b"evil"[..], &HashAlgorithm::EVIDENCE).unwrap();
+        assert_eq!(set.lookup(&evil), KnownStatus::KnownBad);
+        let other = hash_reader(&b"other"[..], &HashAlgorithm::EVIDENCE).unwrap();
+        assert_eq!(set.lookup(&other), KnownStatus::Unknown);
+
+        let result = set.load(&list, HashSetFormat::Hashdeep, KnownStatus::KnownBad);
+        assert!(matches!(result, Err(HashSetError::InvalidFormat(_))));
+        let result = set.load(&list, HashSetFormat::HashList, KnownStatus::Unknown);
+        assert!(matches!(result, Err(HashSetError::InvalidStatus(_))));
+    }
+
+    #[test]
+    fn test_known_bad_wins_and_index_round_trip() {
+        let dir = tempdir().unwrap();
+        let mut set = KnownFileSet::new();
+        set.insert(HashAlgorithm::Md5, HELLO_MD5, KnownStatus::KnownBad).unwrap();
+        set.insert(HashAlgorithm::Md5, &HELLO_MD5.to_uppercase(), KnownStatus::KnownGood).unwrap();
+        set.insert(HashAlgorithm::Md5, "00000000000000000000000000000000", KnownStatus::KnownGood).unwrap();
+        set.insert(HashAlgorithm::Sha1, HELLO_SHA1, KnownStatus::KnownGood).unwrap();
+        assert!(set.insert(HashAlgorithm::Sha1, HELLO_MD5, KnownStatus::KnownGood).is_err());
+        assert_eq!(set.len(), 3);
+
+        let hello = hash_reader(&b"hello"[..], &HashAlgorithm::EVIDENCE).unwrap();
+        assert_eq!(set.lookup(&hello), KnownStatus::KnownBad);
+
+        let index = dir.path().join("known.idx");
+        set.save(&index).unwrap();
+        let reopened = KnownFileSet::open(&index).unwrap();
+        assert_eq!(reopened.len(), 3);
+        assert_eq!(reopened.lookup(&hello), KnownStatus::KnownBad);
+
+        fs::write(&index, b"not an index").unwrap();
+        assert!(matches!(KnownFileSet::open(&index), Err(HashSetError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn test_match_files_and_exclude_known_good() {
+        let dir = tempdir().unwrap();
+        let png = [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A];
+        fs::write(dir.path().join("system.png"), png).unwrap();
+        fs::write(dir.path().join("photo.png"), [&png[..], b"photo"].concat()).unwrap();
+        fs::write(dir.path().join("dropper.bin"), b"evil").unwrap();
+
+        let mut set = KnownFileSet::new();
+        set.insert(HashAlgorithm::Sha256, &sha256_hex(&png), KnownStatus::KnownGood).unwrap();
+        // An unrelated MD5 digest makes matching compute MD5 as well
+        set.insert(HashAlgorithm::Md5, "4034f3ca5b2e3d58e2a1c1a9a1d4a8a8", KnownStatus::KnownBad).unwrap();
+        set.insert(HashAlgorithm::Sha256, &sha256_hex(b"evil"), KnownStatus::KnownBad).unwrap();
+
+        let mut files = get_files(dir.path().to_str().unwrap()).unwrap();
+        assert!(set.match_files(&mut files).is_empty());
+        let status = |name: &str| files.iter().find(|file| file.name() == name).unwrap().known_status();
+        assert_eq!(status("system.png"), KnownStatus::KnownGood);
+        assert_eq!(status("photo.png"), KnownStatus::Unknown);
+        assert_eq!(status("dropper.bin"), KnownStatus::KnownBad);
+        assert!(files[0].hashes().md5().is_some());
+
+        let identified = exclude_known_good(identify_files(files));
+        assert_eq!(identified["Image"].len(), 1);
+        assert_eq!(identified["Image"][0].name(), "photo.png");
+        assert_eq!(identified["Unknown"][0].known_status(), KnownStatus::KnownBad);
+    }
+
+    #[test]
+    fn test_split_csv() {
+        assert_eq!(split_csv("\"a,b\",c,\"say \"\"hi\"\"\""), vec!["a,b", "c", "say \"hi\""]);
+        assert_eq!(split_csv(""), vec![""]);
+    }
+}
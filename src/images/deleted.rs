@@ -0,0 +1,211 @@
+//! Deleted file recovery shared by the NTFS and FAT readers.
+//!
+//! Unlike carving, recovering files from file system metadata keeps their
+//! names, location in the directory tree, timestamps and exact size.
+
+use std::path::PathBuf;
+
+use super::fat::{self, FatKind};
+use super::{ntfs, ImageError, SharedImage, VirtualDisk};
+use crate::file_system_detection::{detect_file_system, DetectedFileSystem};
+use crate::partition_table::read_partition_table;
+use crate::{DataRun, FileAttributes, FileEntry, FileTimes, Recoverability};
+
+/// A deleted file found in the metadata of a volume.
+#[derive(Debug, Clone)]
+pub(crate) struct DeletedFile {
+    /// Path inside the volume, using `/` separators and a leading `/`
+    pub(crate) path: String,
+    /// File size in bytes
+    pub(crate) size: u64,
+    /// Timestamps still recorded in the metadata
+    pub(crate) times: FileTimes,
+    /// Attributes still recorded in the metadata
+    pub(crate) attributes: FileAttributes,
+    /// NTFS file reference number of the freed record
+    pub(crate) file_id: Option<u64>,
+    /// Location of the content in the device
+    pub(crate) runs: Vec<DataRun>,
+    /// Whether the clusters of the file were reused since its deletion
+    pub(crate) recoverability: Recoverability,
+}
+
+/// Retrieves the deleted files still described by the NTFS and FAT volumes of an image
+///
+/// NTFS files are found in MFT records whose in-use flag is cleared, and FAT
+/// files in directory entries marked `0xE5`. Each file keeps its original
+/// name and timestamps. Its path is resolved through its parent directories
+/// when they still exist; NTFS files whose parent was reused are placed in
+/// a virtual `$OrphanFiles` directory. The first letter of deleted FAT 8.3
+/// names is lost and replaced by `_`.
+///
+/// The returned entries have paths made of the image path, the partition
+/// (`Partition 1`, ...) when the image holds a partition table, and the
+/// original location of the file. They are flagged as deleted with an
+/// estimate of their recoverability, and their content can be read with
+/// `FileEntry::open`.
+///
+/// # Arguments
+/// * `path` - A string path to the image file, in any format supported by `VirtualDisk`
+///
+/// # Returns
+/// * `Ok(Vec<FileEntry>)` - The deleted files found on every NTFS and FAT volume
+/// * `Err(ImageError)` - If the image cannot be read or holds no NTFS or FAT volume
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::{get_deleted_files, Recoverability};
+///
+/// for file in get_deleted_files("D:/cases/2024-17/usb.E01").unwrap() {
+///     if file.recoverability() == Some(Recoverability::Recoverable) {
+///         println!("{} ({} bytes)", file.path().display(), file.size());
+///     }
+/// }
+/// ```
+pub fn get_deleted_files(path: &str) -> Result<Vec<FileEntry>, ImageError> {
+    let mut device = VirtualDisk::open(path)?;
+    let volumes = volumes(&mut device)?;
+
+    let image = PathBuf::from(path);
+    let shared = SharedImage::new(image.clone());
+    let mut files = Vec::new();
+    let mut supported = false;
+
+    for (partition, offset, length) in volumes {
+        let volume = detect_file_system(&mut device, offset, length)?;
+        let deleted = match volume.file_system {
+            DetectedFileSystem::Ntfs => ntfs::deleted_files(&mut device, offset, length)?,
+            DetectedFileSystem::Fat32 => fat::deleted_files(&mut device, offset, length, FatKind::Fat32)?,
+            DetectedFileSystem::Fat("FAT12") => fat::deleted_files(&mut device, offset, length, FatKind::Fat12)?,
+            DetectedFileSystem::Fat(_) => fat::deleted_files(&mut device, offset, length, FatKind::Fat16)?,
+            _ => continue,
+        };
+        supported = true;
+
+        let mut root = image.clone();
+        root.extend(partition);
+        for file in deleted {
+            let mut entry_path = root.clone();
+            entry_path.extend(file.path.split('/').filter(|part| !part.is_empty()));
+            files.push(
+                FileEntry::from_image(entry_path, file.size, file.times, &shared, file.runs)
+                    .with_deleted(file.recoverability)
+                    .with_attributes(file.attributes)
+                    .with_file_id(file.file_id),
+            );
+        }
+    }
+
+    if !supported {
+        return Err(ImageError::UnsupportedFormat(format!("no NTFS or FAT file system in {}", path)));
+    }
+    Ok(files)
+}
+
+/// Lists the volumes of a device as `(partition name, offset, length)`.
+///
+/// A device without a partition table is a single unnamed volume.
+fn volumes(device: &mut VirtualDisk) -> Result<Vec<(Option<String>, u64, u64)>, ImageError> {
+    let size = device.size();
+    Ok(match read_partition_table(device, size)? {
+        Some(table) => table
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(id, entry)| (Some(format!("Partition {}", id + 1)), entry.offset, entry.length))
+            .collect(),
+        None => vec![(None, 0, size)],
+    })
+}
+
+/// Groups the units `0..count` for which `is_free` holds into `(first, count)` runs.
+pub(crate) fn free_runs(count: u64, is_free: impl Fn(u64) -> bool) -> Vec<(u64, u64)> {
+    let mut runs: Vec<(u64, u64)> = Vec::new();
+    for unit in (0..count).filter(|&unit| is_free(unit)) {
+        match runs.last_mut() {
+            Some((first, length)) if *first + *length == unit => *length += 1,
+            _ => runs.push((unit, 1)),
+        }
+    }
+    runs
+}
+
+/// Lists the free clusters of the NTFS and FAT volumes of a device, as `(offset, length)` ranges.
+///
+/// A volume whose allocation metadata cannot be parsed is returned whole,
+/// since none of its clusters can be known to be in use. Volumes of other
+/// file systems are left out.
+pub(crate) fn free_clusters(device: &mut VirtualDisk) -> Result<Vec<(u64, u64)>, ImageError> {
+    let mut free = Vec::new();
+    for (_, offset, length) in volumes(device)? {
+        let volume = detect_file_system(device, offset, length)?;
+        let extents = match volume.file_system {
+            DetectedFileSystem::Ntfs => ntfs::free_extents(device, offset, length),
+            DetectedFileSystem::Fat32 => fat::free_extents(device, offset, length, FatKind::Fat32),
+            DetectedFileSystem::Fat("FAT12") => fat::free_extents(device, offset, length, FatKind::Fat12),
+            DetectedFileSystem::Fat(_) => fat::free_extents(device, offset, length, FatKind::Fat16),
+            _ => continue,
+        };
+        match extents {
+            Ok(extents) => free.extend(extents),
+            Err(ImageError::InvalidImage(_)) => free.push((offset, length)),
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(free)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::images::fat::tests::build_fat32_volume;
+    use crate::images::ntfs::tests::build_ntfs_volume;
+
+    #[test]
+    fn test_get_deleted_files_partitions() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let ntfs = build_ntfs_volume();
+        let fat = build_fat32_volume();
+
+        let mut image = vec![0u8; 512 * 512];
+        crate::partition_table::tests::mbr_entry(&mut image[..512], 0, 0x07, 128, 128);
+        crate::partition_table::tests::mbr_entry(&mut image[..512], 1, 0x0C, 256, 128);
+        image[128 * 512..128 * 512 + ntfs.len()].copy_from_slice(&ntfs);
+        image[256 * 512..256 * 512 + fat.len()].copy_from_slice(&fat);
+        let image_path = temp_dir.path().join("usb.img");
+        std::fs::write(&image_path, image).unwrap();
+
+        let files = get_deleted_files(image_path.to_str().unwrap()).unwrap();
+        assert_eq!(files.len(), 7);
+        assert!(files.iter().all(FileEntry::is_deleted));
+
+        let secret = &files[0];
+        assert_eq!(secret.path(), image_path.join("Partition 1").join("secret.docx"));
+        assert_eq!(secret.recoverability(), Some(Recoverability::Recoverable));
+        assert_eq!(secret.image_offset(), Some((128 + 100) * 512));
+        let mut content = Vec::new();
+        secret.open().unwrap().read_to_end(&mut content).unwrap();
+        assert_eq!(content.len(), 700);
+        assert_eq!(&content[..4], b"PK\x03\x04");
+
+        // The entries share the device opened by the first read
+        std::fs::remove_file(&image_path).unwrap();
+        let notes = files.iter().find(|file| file.name() == "_OTES.TXT").unwrap();
+        assert_eq!(notes.path(), image_path.join("Partition 2").join("DOCS").join("_OTES.TXT"));
+        let mut content = String::new();
+        notes.open().unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, "hello");
+    }
+
+    #[test]
+    fn test_get_deleted_files_unsupported() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let image_path = temp_dir.path().join("blank.img");
+        std::fs::write(&image_path, vec![0u8; 64 * 1024]).unwrap();
+
+        let result = get_deleted_files(image_path.to_str().unwrap());
+        assert!(matches!(result, Err(ImageError::UnsupportedFormat(_))));
+    }
+}
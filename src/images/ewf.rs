@@ -0,0 +1,929 @@
+//! Expert Witness Format (EWF) reader for E01 evidence images.
+//!
+//! An EWF image is split into segment files (`.E01`, `.E02`, ... `.E99`,
+//! `.EAA`, ...) made of chained sections. The `volume` section gives the
+//! media geometry, `sectors` sections hold the chunks of media data, each
+//! stored as-is with an Adler-32 checksum or compressed with zlib, and
+//! `table` sections give the location of every chunk. Acquisition details
+//! are stored in the compressed `header` and `header2` sections, and the
+//! hashes computed while acquiring in the `hash` and `digest` sections.
+//!
+//! Only the EnCase 1-6 format (EWF-E01) is supported; EnCase 7 `.Ex01`
+//! images use a different layout (EWF2) and are reported as unsupported.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use flate2::read::ZlibDecoder;
+use md5::{Digest, Md5};
+use sha1::Sha1;
+
+#[cfg(feature = "serialize")]
+use serde::Serialize;
+
+use super::{format_guid, le_u32, le_u64, read_file_at, ImageError, ReadAt};
+use crate::file_hashing::to_hex;
+
+/// Signature of an EWF-E01 segment file
+pub(crate) const EWF_SIGNATURE: &[u8] = b"EVF\x09\x0d\x0a\xff\x00";
+/// Signature of an EWF2 (Ex01) segment file
+pub(crate) const EWF2_SIGNATURE: &[u8] = b"EVF2\x0d\x0a\x81\x00";
+/// Size of the file header of each segment
+const FILE_HEADER_SIZE: u64 = 13;
+/// Size of a section descriptor
+const SECTION_DESCRIPTOR_SIZE: u64 = 76;
+/// Table entry flag of a compressed chunk
+const COMPRESSED_CHUNK: u32 = 1 << 31;
+/// Largest chunk accepted, to bound allocations on corrupt images
+const MAX_CHUNK_SIZE: u64 = 16 * 1024 * 1024;
+/// Largest compressed header section accepted
+const MAX_HEADER_SIZE: u64 = 16 * 1024 * 1024;
+/// Number of segment names available, from E01 to ZZZ
+const MAX_SEGMENTS: usize = 99 + 22 * 26 * 26;
+
+/// Computes the Adler-32 checksum used by EWF structures.
+fn adler32(data: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 is the largest block that cannot overflow before the modulo
+    for block in data.chunks(5552) {
+        for &byte in block {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MODULUS;
+        b %= MODULUS;
+    }
+    (b << 16) | a
+}
+
+/// Returns the path of segment `number` (1-based) of the image whose first
+/// segment is `first`, keeping the case of its extension.
+fn segment_path(first: &Path, number: usize) -> Option<PathBuf> {
+    if number == 0 || number > MAX_SEGMENTS {
+        return None;
+    }
+    let extension = first.extension()?.to_str()?;
+    let lowercase = extension.chars().next()?.is_ascii_lowercase();
+
+    let name = if number <= 99 {
+        format!("E{:02}", number)
+    } else {
+        let index = number - 100;
+        let letter = |offset: usize| (b'A' + offset as u8) as char;
+        format!("{}{}{}", letter(4 + index / (26 * 26)), letter(index / 26 % 26), letter(index % 26))
+    };
+    let name = if lowercase { name.to_ascii_lowercase() } else { name };
+    Some(first.with_extension(name))
+}
+
+/// A section of a segment file.
+#[derive(Debug, Clone)]
+struct Section {
+    kind: String,
+    /// Offset of the section descriptor
+    start: u64,
+    /// Offset of the end of the section
+    end: u64,
+}
+
+impl Section {
+    fn data_offset(&self) -> u64 {
+        self.start + SECTION_DESCRIPTOR_SIZE
+    }
+
+    fn data_size(&self) -> u64 {
+        self.end.saturating_sub(self.data_offset())
+    }
+}
+
+/// Reads the chain of sections of a segment file.
+fn read_sections(file: &mut File, file_size: u64) -> Result<Vec<Section>, ImageError> {
+    let mut sections: Vec<Section> = Vec::new();
+    let mut offset = FILE_HEADER_SIZE;
+
+    while offset + SECTION_DESCRIPTOR_SIZE <= file_size {
+        let descriptor = read_file_at(file, offset, SECTION_DESCRIPTOR_SIZE as usize)?;
+        if adler32(&descriptor[..72]) != le_u32(&descriptor, 72) {
+            return Err(ImageError::InvalidImage(format!("EWF section checksum mismatch at offset {}", offset)));
+        }
+        let kind = String::from_utf8_lossy(&descriptor[..16]).trim_end_matches('\0').to_string();
+        let next = le_u64(&descriptor, 16);
+        let size = le_u64(&descriptor, 24);
+        let end = if size == 0 { offset + SECTION_DESCRIPTOR_SIZE } else { offset + size };
+
+        let last = kind == "next" || kind == "done";
+        sections.push(Section { kind, start: offset, end });
+        // The final section of a segment points to itself
+        if last || next <= offset {
+            break;
+        }
+        offset = next;
+    }
+    Ok(sections)
+}
+
+/// Reads and decompresses a zlib-compressed section.
+fn read_compressed_section(file: &mut File, section: &Section) -> io::Result<Vec<u8>> {
+    let compressed = read_file_at(file, section.data_offset(), section.data_size().min(MAX_HEADER_SIZE) as usize)?;
+    let mut data = Vec::new();
+    ZlibDecoder::new(&compressed[..]).take(MAX_HEADER_SIZE).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Decodes the text of a header (ASCII) or header2 (UTF-16) section.
+fn decode_header_text(data: &[u8]) -> String {
+    if data.starts_with(&[0xFF, 0xFE]) {
+        let units: Vec<u16> = data[2..].chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        String::from_utf8_lossy(data).to_string()
+    }
+}
+
+/// Parses the tab-separated key and value lines following the `main` line
+/// of a header section.
+fn parse_header_values(text: &str) -> HashMap<String, String> {
+    let lines: Vec<&str> = text.lines().map(|line| line.trim_end_matches('\r')).collect();
+    let Some(main) = lines.iter().position(|line| line.trim() == "main") else {
+        return HashMap::new();
+    };
+    let keys = lines.get(main + 1).copied().unwrap_or_default().split('\t');
+    let values = lines.get(main + 2).copied().unwrap_or_default().split('\t');
+    keys.zip(values).map(|(key, value)| (key.trim().to_string(), value.trim().to_string())).collect()
+}
+
+/// Parses an acquisition date, stored as a Unix timestamp in header2
+/// sections and as `year month day hour minute second` in header sections.
+fn parse_header_date(value: &str) -> Option<DateTime<Local>> {
+    if let Ok(seconds) = value.parse::<i64>() {
+        return Local.timestamp_opt(seconds, 0).single();
+    }
+    let fields: Vec<u32> = value.split_whitespace().filter_map(|field| field.parse().ok()).collect();
+    if fields.len() != 6 {
+        return None;
+    }
+    let naive = NaiveDate::from_ymd_opt(fields[0] as i32, fields[1], fields[2])?.and_hms_opt(fields[3], fields[4], fields[5])?;
+    Local.from_local_datetime(&naive).earliest()
+}
+
+/// Acquisition details stored in an EWF image.
+///
+/// Text fields are empty when the acquisition software did not record them.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct EwfMetadata {
+    /// Case number
+    case_number: String,
+    /// Evidence number
+    evidence_number: String,
+    /// Description of the evidence
+    description: String,
+    /// Name of the examiner who acquired the evidence
+    examiner: String,
+    /// Free-form notes
+    notes: String,
+    /// Date of the acquisition
+    acquisition_date: Option<DateTime<Local>>,
+    /// Clock of the acquisition system when the acquisition started
+    system_date: Option<DateTime<Local>>,
+    /// Version of the acquisition software
+    acquisition_software: String,
+    /// Operating system used for the acquisition
+    acquisition_os: String,
+    /// Model of the acquired device
+    model: String,
+    /// Serial number of the acquired device
+    serial_number: String,
+    /// MD5 of the media computed at acquisition time, in lowercase hexadecimal
+    md5: Option<String>,
+    /// SHA-1 of the media computed at acquisition time, in lowercase hexadecimal
+    sha1: Option<String>,
+    /// Size of the acquired media in bytes
+    media_size: u64,
+    /// Size of a sector of the acquired media
+    bytes_per_sector: u32,
+    /// Number of segment files of the image
+    segment_count: usize,
+}
+
+impl EwfMetadata {
+    /// Returns the case number.
+    pub fn case_number(&self) -> &str {
+        &self.case_number
+    }
+
+    /// Returns the evidence number.
+    pub fn evidence_number(&self) -> &str {
+        &self.evidence_number
+    }
+
+    /// Returns the description of the evidence.
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Returns the name of the examiner.
+    pub fn examiner(&self) -> &str {
+        &self.examiner
+    }
+
+    /// Returns the acquisition notes.
+    pub fn notes(&self) -> &str {
+        &self.notes
+    }
+
+    /// Returns the acquisition date, if recorded.
+    pub fn acquisition_date(&self) -> Option<DateTime<Local>> {
+        self.acquisition_date
+    }
+
+    /// Returns the acquisition system date, if recorded.
+    pub fn system_date(&self) -> Option<DateTime<Local>> {
+        self.system_date
+    }
+
+    /// Returns the version of the acquisition software.
+    pub fn acquisition_software(&self) -> &str {
+        &self.acquisition_software
+    }
+
+    /// Returns the operating system used for the acquisition.
+    pub fn acquisition_os(&self) -> &str {
+        &self.acquisition_os
+    }
+
+    /// Returns the model of the acquired device.
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Returns the serial number of the acquired device.
+    pub fn serial_number(&self) -> &str {
+        &self.serial_number
+    }
+
+    /// Returns the MD5 stored in the image, in lowercase hexadecimal.
+    pub fn md5(&self) -> Option<&str> {
+        self.md5.as_deref()
+    }
+
+    /// Returns the SHA-1 stored in the image, in lowercase hexadecimal.
+    pub fn sha1(&self) -> Option<&str> {
+        self.sha1.as_deref()
+    }
+
+    /// Returns the size of the acquired media in bytes.
+    pub fn media_size(&self) -> u64 {
+        self.media_size
+    }
+
+    /// Returns the sector size of the acquired media.
+    pub fn bytes_per_sector(&self) -> u32 {
+        self.bytes_per_sector
+    }
+
+    /// Returns the number of segment files of the image.
+    pub fn segment_count(&self) -> usize {
+        self.segment_count
+    }
+
+    /// Fills the text fields from the values of a header section.
+    fn apply_header(&mut self, values: &HashMap<String, String>) {
+        let get = |key: &str| values.get(key).cloned().unwrap_or_default();
+        self.case_number = get("c");
+        self.evidence_number = get("n");
+        self.description = get("a");
+        self.examiner = get("e");
+        self.notes = get("t");
+        self.acquisition_software = get("av");
+        self.acquisition_os = get("ov");
+        self.model = get("md");
+        self.serial_number = get("sn");
+        self.acquisition_date = values.get("m").and_then(|value| parse_header_date(value));
+        self.system_date = values.get("u").and_then(|value| parse_header_date(value));
+    }
+}
+
+/// Result of checking an EWF image against the hashes stored in it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct EwfVerification {
+    /// MD5 stored in the image
+    stored_md5: Option<String>,
+    /// MD5 computed from the media
+    computed_md5: String,
+    /// SHA-1 stored in the image
+    stored_sha1: Option<String>,
+    /// SHA-1 computed from the media
+    computed_sha1: String,
+}
+
+impl EwfVerification {
+    /// Returns the MD5 stored in the image.
+    pub fn stored_md5(&self) -> Option<&str> {
+        self.stored_md5.as_deref()
+    }
+
+    /// Returns the MD5 computed from the media.
+    pub fn computed_md5(&self) -> &str {
+        &self.computed_md5
+    }
+
+    /// Returns the SHA-1 stored in the image.
+    pub fn stored_sha1(&self) -> Option<&str> {
+        self.stored_sha1.as_deref()
+    }
+
+    /// Returns the SHA-1 computed from the media.
+    pub fn computed_sha1(&self) -> &str {
+        &self.computed_sha1
+    }
+
+    /// Returns whether the MD5 matches, or `None` if no MD5 is stored.
+    pub fn md5_matches(&self) -> Option<bool> {
+        self.stored_md5.as_ref().map(|stored| stored.eq_ignore_ascii_case(&self.computed_md5))
+    }
+
+    /// Returns whether the SHA-1 matches, or `None` if no SHA-1 is stored.
+    pub fn sha1_matches(&self) -> Option<bool> {
+        self.stored_sha1.as_ref().map(|stored| stored.eq_ignore_ascii_case(&self.computed_sha1))
+    }
+
+    /// Returns true if at least one hash is stored and every stored hash matches.
+    pub fn is_verified(&self) -> bool {
+        let checks = [self.md5_matches(), self.sha1_matches()];
+        checks.iter().any(Option::is_some) && checks.iter().flatten().all(|matches| *matches)
+    }
+}
+
+/// Location of a chunk in the segment files.
+#[derive(Debug, Clone, Copy)]
+struct Chunk {
+    segment: usize,
+    offset: u64,
+    /// Stored size, including the checksum of uncompressed chunks
+    size: u64,
+    compressed: bool,
+}
+
+/// An EWF-E01 image made of one or more segment files.
+pub(crate) struct EwfImage {
+    segments: Vec<File>,
+    chunks: Vec<Chunk>,
+    /// Size of a chunk of media data in bytes
+    chunk_size: u64,
+    /// Set identifier shared by the segments
+    set_identifier: Option<String>,
+    metadata: EwfMetadata,
+    /// Most recently read chunk and its index
+    cached_chunk: Option<(usize, Vec<u8>)>,
+}
+
+impl EwfImage {
+    /// Opens an EWF image from its first segment, following the other segments.
+    pub(crate) fn open(path: &Path) -> Result<Self, ImageError> {
+        let mut segments = Vec::new();
+        let mut chunks = Vec::new();
+        let mut metadata = EwfMetadata::default();
+        let mut volume: Option<(u64, u64, u64, Option<String>)> = None;
+        let mut header: Option<HashMap<String, String>> = None;
+        let mut header2: Option<HashMap<String, String>> = None;
+
+        for number in 1.. {
+            let segment = if number == 1 {
+                path.to_path_buf()
+            } else {
+                segment_path(path, number)
+                    .ok_or_else(|| ImageError::InvalidImage("EWF image has too many segments".to_string()))?
+            };
+            let mut file = match File::open(&segment) {
+                Ok(file) => file,
+                Err(err) if number > 1 && err.kind() == ErrorKind::NotFound => {
+                    return Err(ImageError::MissingSegment(segment.display().to_string()));
+                }
+                Err(err) => return Err(err.into()),
+            };
+            let file_size = file.metadata()?.len();
+
+            let file_header = read_file_at(&mut file, 0, FILE_HEADER_SIZE as usize)?;
+            if file_header.starts_with(EWF2_SIGNATURE) {
+                return Err(ImageError::UnsupportedFormat("EWF2 (Ex01) images".to_string()));
+            }
+            if !file_header.starts_with(EWF_SIGNATURE) {
+                return Err(ImageError::InvalidImage(format!("{} is not an EWF segment", segment.display())));
+            }
+            let segment_number = u16::from_le_bytes([file_header[9], file_header[10]]) as usize;
+            if segment_number != number {
+                return Err(ImageError::InvalidImage(format!(
+                    "{} is segment {} of its image, expected segment {}",
+                    segment.display(),
+                    segment_number,
+                    number
+                )));
+            }
+
+            let sections = read_sections(&mut file, file_size)?;
+            for section in &sections {
+                match section.kind.as_str() {
+                    "header2" if header2.is_none() => {
+                        let text = decode_header_text(&read_compressed_section(&mut file, section)?);
+                        header2 = Some(parse_header_values(&text));
+                    }
+                    "header" if header.is_none() => {
+                        let text = decode_header_text(&read_compressed_section(&mut file, section)?);
+                        header = Some(parse_header_values(&text));
+                    }
+                    "volume" | "disk" | "data" if volume.is_none() => {
+                        let data = read_file_at(&mut file, section.data_offset(), section.data_size().min(1052) as usize)?;
+                        if data.len() < 24 {
+                            return Err(ImageError::InvalidImage("EWF volume section is too small".to_string()));
+                        }
+                        let sectors_per_chunk = le_u32(&data, 8) as u64;
+                        let bytes_per_sector = le_u32(&data, 12) as u64;
+                        // The SMART layout (94 bytes) stores a 32-bit sector count
+                        let (sector_count, set_identifier) = if data.len() >= 80 {
+                            let guid = &data[64..80];
+                            let guid = guid.iter().any(|&byte| byte != 0).then(|| format_guid(guid));
+                            (le_u64(&data, 16), guid)
+                        } else {
+                            (le_u32(&data, 16) as u64, None)
+                        };
+                        volume = Some((sectors_per_chunk, bytes_per_sector, sector_count, set_identifier));
+                    }
+                    "table" => {
+                        chunks.extend(Self::read_table(&mut file, section, &sections, segments.len())?);
+                    }
+                    "hash" => {
+                        let data = read_file_at(&mut file, section.data_offset(), 16)?;
+                        metadata.md5.get_or_insert_with(|| to_hex(&data));
+                    }
+                    "digest" => {
+                        let data = read_file_at(&mut file, section.data_offset(), 36)?;
+                        metadata.md5 = Some(to_hex(&data[..16]));
+                        if data[16..36].iter().any(|&byte| byte != 0) {
+                            metadata.sha1 = Some(to_hex(&data[16..36]));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            segments.push(file);
+
+            match sections.last().map(|section| section.kind.as_str()) {
+                Some("done") => break,
+                Some("next") => continue,
+                _ => {
+                    return Err(ImageError::InvalidImage(format!(
+                        "segment {} is truncated",
+                        segment.display()
+                    )))
+                }
+            }
+        }
+
+        let (sectors_per_chunk, bytes_per_sector, sector_count, set_identifier) =
+            volume.ok_or_else(|| ImageError::InvalidImage("EWF volume section not found".to_string()))?;
+        let chunk_size = sectors_per_chunk * bytes_per_sector;
+        if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+            return Err(ImageError::InvalidImage(format!("invalid EWF chunk size {}", chunk_size)));
+        }
+        let media_size = sector_count * bytes_per_sector;
+        if (chunks.len() as u64) < media_size.div_ceil(chunk_size) {
+            return Err(ImageError::InvalidImage(format!(
+                "EWF tables list {} chunks, {} expected",
+                chunks.len(),
+                media_size.div_ceil(chunk_size)
+            )));
+        }
+
+        if let Some(values) = header2.or(header) {
+            metadata.apply_header(&values);
+        }
+        metadata.media_size = media_size;
+        metadata.bytes_per_sector = bytes_per_sector as u32;
+        metadata.segment_count = segments.len();
+
+        Ok(EwfImage {
+            segments,
+            chunks,
+            chunk_size,
+            set_identifier,
+            metadata,
+            cached_chunk: None,
+        })
+    }
+
+    /// Reads the chunk locations listed in a table section.
+    fn read_table(file: &mut File, table: &Section, sections: &[Section], segment: usize) -> Result<Vec<Chunk>, ImageError> {
+        let header = read_file_at(file, table.data_offset(), 24)?;
+        let count = le_u32(&header, 0) as u64;
+        let base = le_u64(&header, 8);
+        if 24 + count * 4 > table.data_size() {
+            return Err(ImageError::InvalidImage("EWF table section is too small".to_string()));
+        }
+        let entries = read_file_at(file, table.data_offset() + 24, (count * 4) as usize)?;
+        let offsets: Vec<(u64, bool)> = entries
+            .chunks_exact(4)
+            .map(|entry| {
+                let entry = le_u32(entry, 0);
+                (base + (entry & !COMPRESSED_CHUNK) as u64, entry & COMPRESSED_CHUNK != 0)
+            })
+            .collect();
+
+        let mut chunks = Vec::with_capacity(offsets.len());
+        for (index, &(offset, compressed)) in offsets.iter().enumerate() {
+            // The last chunk of a table ends with the section holding it
+            let end = match offsets.get(index + 1) {
+                Some(&(next, _)) => next,
+                None => sections
+                    .iter()
+                    .find(|section| section.start <= offset && offset < section.end)
+                    .map(|section| section.end)
+                    .unwrap_or(table.start),
+            };
+            if end <= offset || end - offset > MAX_CHUNK_SIZE + 4 {
+                return Err(ImageError::InvalidImage(format!("invalid EWF chunk at offset {}", offset)));
+            }
+            chunks.push(Chunk {
+                segment,
+                offset,
+                size: end - offset,
+                compressed,
+            });
+        }
+        Ok(chunks)
+    }
+
+    /// Returns the acquisition details of the image.
+    pub(crate) fn metadata(&self) -> &EwfMetadata {
+        &self.metadata
+    }
+
+    /// Reads and unpacks a chunk, checking its integrity.
+    fn read_chunk(&mut self, index: usize) -> io::Result<&[u8]> {
+        if self.cached_chunk.as_ref().map(|(cached, _)| *cached) != Some(index) {
+            let chunk = self.chunks[index];
+            let expected = self.chunk_size.min(self.metadata.media_size - index as u64 * self.chunk_size) as usize;
+            let stored = read_file_at(&mut self.segments[chunk.segment], chunk.offset, chunk.size as usize)?;
+
+            let data = if chunk.compressed {
+                let mut data = Vec::with_capacity(self.chunk_size as usize);
+                ZlibDecoder::new(&stored[..]).take(self.chunk_size).read_to_end(&mut data)?;
+                data
+            } else if stored.len() >= expected + 4 {
+                let data = &stored[..stored.len() - 4];
+                let checksum = le_u32(&stored, stored.len() - 4);
+                if adler32(data) != checksum {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("EWF chunk {} checksum mismatch", index),
+                    ));
+                }
+                data.to_vec()
+            } else {
+                stored
+            };
+
+            if data.len() < expected {
+                return Err(io::Error::new(ErrorKind::InvalidData, format!("EWF chunk {} is truncated", index)));
+            }
+            self.cached_chunk = Some((index, data));
+        }
+        Ok(&self.cached_chunk.as_ref().expect("cached chunk").1)
+    }
+}
+
+impl ReadAt for EwfImage {
+    fn size(&self) -> u64 {
+        self.metadata.media_size
+    }
+
+    fn identifier(&self) -> Option<String> {
+        if self.metadata.serial_number.is_empty() {
+            self.set_identifier.clone()
+        } else {
+            Some(self.metadata.serial_number.clone())
+        }
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if offset >= self.metadata.media_size {
+            return Ok(0);
+        }
+        let index = (offset / self.chunk_size) as usize;
+        let within = (offset % self.chunk_size) as usize;
+        let len = buf.len().min((self.metadata.media_size - offset).min(self.chunk_size - within as u64) as usize);
+
+        let chunk = self.read_chunk(index)?;
+        buf[..len].copy_from_slice(&chunk[within..within + len]);
+        Ok(len)
+    }
+}
+
+/// Returns true if `buf`, read from the start of a file, is an EWF segment header.
+pub(crate) fn is_ewf(buf: &[u8]) -> bool {
+    buf.starts_with(EWF_SIGNATURE) || buf.starts_with(EWF2_SIGNATURE)
+}
+
+/// Reads the acquisition details of an EWF (E01) image
+///
+/// Only the section structures are read, so this is fast even on large
+/// evidence. The remaining segments are located next to the first one.
+///
+/// # Arguments
+/// * `path` - A string path to the first segment (`.E01`) of the image
+///
+/// # Returns
+/// * `Ok(EwfMetadata)` - Case details, stored hashes and media geometry
+/// * `Err(ImageError)` - If a segment is missing or the image is damaged
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::get_ewf_metadata;
+///
+/// let metadata = get_ewf_metadata("D:/cases/2024-17/laptop.E01").unwrap();
+/// println!("Case {} by {}", metadata.case_number(), metadata.examiner());
+/// println!("MD5: {}", metadata.md5().unwrap_or("not recorded"));
+/// ```
+pub fn get_ewf_metadata(path: &str) -> Result<EwfMetadata, ImageError> {
+    Ok(EwfImage::open(Path::new(path))?.metadata().clone())
+}
+
+/// Verifies an EWF (E01) image against the hashes recorded at acquisition
+///
+/// Every chunk is read and checked, and the MD5 and SHA-1 of the media are
+/// computed and compared with the stored values.
+///
+/// # Arguments
+/// * `path` - A string path to the first segment (`.E01`) of the image
+///
+/// # Returns
+/// * `Ok(EwfVerification)` - The stored and computed hashes
+/// * `Err(ImageError)` - If a segment is missing or a chunk is corrupt
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::verify_ewf_image;
+///
+/// let verification = verify_ewf_image("D:/cases/2024-17/laptop.E01").unwrap();
+/// if verification.is_verified() {
+///     println!("Evidence verified: {}", verification.computed_md5());
+/// }
+/// ```
+pub fn verify_ewf_image(path: &str) -> Result<EwfVerification, ImageError> {
+    let mut image = EwfImage::open(Path::new(path))?;
+    let mut md5 = Md5::new();
+    let mut sha1 = Sha1::new();
+
+    let mut buf = vec![0u8; 1024 * 1024];
+    let mut offset = 0;
+    while offset < image.size() {
+        let read = image.read_at(offset, &mut buf)?;
+        md5.update(&buf[..read]);
+        sha1.update(&buf[..read]);
+        offset += read as u64;
+    }
+
+    Ok(EwfVerification {
+        stored_md5: image.metadata.md5.clone(),
+        computed_md5: to_hex(&md5.finalize()),
+        stored_sha1: image.metadata.sha1.clone(),
+        computed_sha1: to_hex(&sha1.finalize()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    use super::*;
+    use crate::images::{ImageFormat, VirtualDisk};
+
+    const SECTORS_PER_CHUNK: u32 = 8;
+    const CHUNK_SIZE: usize = SECTORS_PER_CHUNK as usize * 512;
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// Appends a section to a segment, chaining it to the following one.
+    fn push_section(segment: &mut Vec<u8>, kind: &str, data: &[u8]) {
+        let start = segment.len() as u64;
+        let last = kind == "next" || kind == "done";
+        let size = SECTION_DESCRIPTOR_SIZE + data.len() as u64;
+        let mut descriptor = vec![0u8; SECTION_DESCRIPTOR_SIZE as usize];
+        descriptor[..kind.len()].copy_from_slice(kind.as_bytes());
+        let next = if last { start } else { start + size };
+        descriptor[16..24].copy_from_slice(&next.to_le_bytes());
+        descriptor[24..32].copy_from_slice(&(if last { 0 } else { size }).to_le_bytes());
+        let checksum = adler32(&descriptor[..72]);
+        descriptor[72..76].copy_from_slice(&checksum.to_le_bytes());
+        segment.extend(descriptor);
+        segment.extend(data);
+    }
+
+    /// Builds the segments of an E01 image of `media`, storing
+    /// `chunks_per_segment` chunks in each segment and compressing even chunks.
+    fn build_ewf(media: &[u8], chunks_per_segment: usize, header_text: &str, hashes: bool) -> Vec<Vec<u8>> {
+        let chunks: Vec<&[u8]> = media.chunks(CHUNK_SIZE).collect();
+        let groups: Vec<&[&[u8]]> = chunks.chunks(chunks_per_segment).collect();
+        let mut segments = Vec::new();
+
+        for (number, group) in groups.iter().enumerate() {
+            let mut segment = EWF_SIGNATURE.to_vec();
+            segment.push(1);
+            segment.extend(((number + 1) as u16).to_le_bytes());
+            segment.extend([0, 0]);
+
+            let mut volume = vec![0u8; 1052];
+            volume[4..8].copy_from_slice(&(chunks.len() as u32).to_le_bytes());
+            volume[8..12].copy_from_slice(&SECTORS_PER_CHUNK.to_le_bytes());
+            volume[12..16].copy_from_slice(&512u32.to_le_bytes());
+            volume[16..24].copy_from_slice(&(media.len() as u64 / 512).to_le_bytes());
+            volume[64..80].copy_from_slice(&[0x5A; 16]);
+            if number == 0 {
+                push_section(&mut segment, "header", &compress(header_text.as_bytes()));
+                push_section(&mut segment, "volume", &volume);
+            } else {
+                push_section(&mut segment, "data", &volume);
+            }
+
+            let sectors_start = segment.len() as u64 + SECTION_DESCRIPTOR_SIZE;
+            let mut sectors = Vec::new();
+            let mut entries = Vec::new();
+            for (index, chunk) in group.iter().enumerate() {
+                let offset = (sectors_start + sectors.len() as u64) as u32;
+                if index % 2 == 0 {
+                    entries.push(offset | COMPRESSED_CHUNK);
+                    sectors.extend(compress(chunk));
+                } else {
+                    entries.push(offset);
+                    sectors.extend(*chunk);
+                    sectors.extend(adler32(chunk).to_le_bytes());
+                }
+            }
+            push_section(&mut segment, "sectors", &sectors);
+
+            let mut table = vec![0u8; 24];
+            table[0..4].copy_from_slice(&(entries.len() as u32).to_le_bytes());
+            for entry in &entries {
+                table.extend(entry.to_le_bytes());
+            }
+            push_section(&mut segment, "table", &table);
+            push_section(&mut segment, "table2", &table);
+
+            if number == groups.len() - 1 {
+                if hashes {
+                    let mut digest = vec![0u8; 80];
+                    digest[..16].copy_from_slice(&Md5::digest(media));
+                    digest[16..36].copy_from_slice(&Sha1::digest(media));
+                    push_section(&mut segment, "digest", &digest);
+                }
+                push_section(&mut segment, "done", &[]);
+            } else {
+                push_section(&mut segment, "next", &[]);
+            }
+            segments.push(segment);
+        }
+        segments
+    }
+
+    /// Writes the segments of an image as `name.E01`, `name.E02`... and returns the first path.
+    fn write_segments(directory: &Path, name: &str, segments: &[Vec<u8>]) -> PathBuf {
+        let first = directory.join(format!("{}.E01", name));
+        for (index, segment) in segments.iter().enumerate() {
+            std::fs::write(segment_path(&first, index + 1).unwrap(), segment).unwrap();
+        }
+        first
+    }
+
+    fn sample_media() -> Vec<u8> {
+        // Three and a half chunks, so the last chunk is short
+        (0..CHUNK_SIZE * 7 / 2).map(|i| (i / 512 + i % 7) as u8).collect()
+    }
+
+    const HEADER: &str = "1\nmain\nc\tn\ta\te\tt\tav\tov\tm\tu\tp\tr\n\
+                          2024-17\t3\tSeized laptop\tJ. Doe\tBitLocker off\t6.19\tWindows 10\t2024 3 4 10 19 59\t2024 3 4 10 20 1\t0\tf\n\n";
+
+    #[test]
+    fn test_segment_path() {
+        let first = Path::new("case/disk.E01");
+        assert_eq!(segment_path(first, 2).unwrap(), Path::new("case/disk.E02"));
+        assert_eq!(segment_path(first, 99).unwrap(), Path::new("case/disk.E99"));
+        assert_eq!(segment_path(first, 100).unwrap(), Path::new("case/disk.EAA"));
+        assert_eq!(segment_path(first, 100 + 26).unwrap(), Path::new("case/disk.EBA"));
+        assert_eq!(segment_path(first, 100 + 26 * 26).unwrap(), Path::new("case/disk.FAA"));
+        assert_eq!(segment_path(Path::new("disk.e01"), 3).unwrap(), Path::new("disk.e03"));
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn test_multi_segment_ewf() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let media = sample_media();
+        let first = write_segments(temp_dir.path(), "laptop", &build_ewf(&media, 2, HEADER, true));
+
+        let mut device = VirtualDisk::open(&first).unwrap();
+        assert_eq!(device.format(), ImageFormat::Ewf);
+        assert_eq!(device.size(), media.len() as u64);
+
+        let mut data = Vec::new();
+        device.read_to_end(&mut data).unwrap();
+        assert_eq!(data, media);
+    }
+
+    #[test]
+    fn test_ewf_metadata() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let media = sample_media();
+        let first = write_segments(temp_dir.path(), "laptop", &build_ewf(&media, 2, HEADER, true));
+
+        let metadata = get_ewf_metadata(first.to_str().unwrap()).unwrap();
+        assert_eq!(metadata.case_number(), "2024-17");
+        assert_eq!(metadata.evidence_number(), "3");
+        assert_eq!(metadata.description(), "Seized laptop");
+        assert_eq!(metadata.examiner(), "J. Doe");
+        assert_eq!(metadata.notes(), "BitLocker off");
+        assert_eq!(metadata.acquisition_software(), "6.19");
+        assert_eq!(
+            metadata.acquisition_date().unwrap().naive_local(),
+            NaiveDate::from_ymd_opt(2024, 3, 4).unwrap().and_hms_opt(10, 19, 59).unwrap()
+        );
+        assert_eq!(metadata.md5(), Some(to_hex(&Md5::digest(&media)).as_str()));
+        assert_eq!(metadata.segment_count(), 2);
+        assert_eq!(metadata.media_size(), media.len() as u64);
+    }
+
+    #[test]
+    fn test_verify_ewf() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let media = sample_media();
+        let first = write_segments(temp_dir.path(), "laptop", &build_ewf(&media, 2, HEADER, true));
+
+        let verification = verify_ewf_image(first.to_str().unwrap()).unwrap();
+        assert_eq!(verification.md5_matches(), Some(true));
+        assert_eq!(verification.sha1_matches(), Some(true));
+        assert!(verification.is_verified());
+    }
+
+    #[test]
+    fn test_verify_ewf_without_hashes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let first = write_segments(temp_dir.path(), "usb", &build_ewf(&sample_media(), 4, HEADER, false));
+
+        let verification = verify_ewf_image(first.to_str().unwrap()).unwrap();
+        assert_eq!(verification.md5_matches(), None);
+        assert!(!verification.is_verified());
+    }
+
+    #[test]
+    fn test_corrupt_ewf_chunk() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut segments = build_ewf(&sample_media(), 4, HEADER, true);
+        // Chunk 1 is stored uncompressed right after the compressed chunk 0
+        let sectors = segments[0].windows(7).position(|window| window == b"sectors").unwrap();
+        let chunk0 = compress(&sample_media()[..CHUNK_SIZE]).len();
+        segments[0][sectors + SECTION_DESCRIPTOR_SIZE as usize + chunk0 + 10] ^= 0xFF;
+        let first = write_segments(temp_dir.path(), "usb", &segments);
+
+        let result = verify_ewf_image(first.to_str().unwrap());
+        assert!(matches!(result, Err(ImageError::Io(err)) if err.kind() == ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn test_missing_ewf_segment() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let segments = build_ewf(&sample_media(), 1, HEADER, true);
+        let first = write_segments(temp_dir.path(), "laptop", &segments);
+        std::fs::remove_file(segment_path(&first, 3).unwrap()).unwrap();
+
+        let result = VirtualDisk::open(&first);
+        assert!(matches!(result, Err(ImageError::MissingSegment(segment)) if segment.ends_with("laptop.E03")));
+    }
+
+    #[test]
+    fn test_ewf_disk() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let media = crate::images::tests::build_fat32_disk();
+        let first = write_segments(temp_dir.path(), "usb", &build_ewf(&media, 256, HEADER, true));
+
+        let disk = crate::get_image_disk(first.to_str().unwrap()).unwrap();
+        assert_eq!(disk.model(), "EWF image");
+        assert_eq!(disk.serial(), "5A5A5A5A-5A5A-5A5A-5A5A-5A5A5A5A5A5A");
+        assert_eq!(disk.partitions().len(), 1);
+        assert_eq!(disk.partitions()[0].name(), "EVIDENCE");
+    }
+}
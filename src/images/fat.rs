@@ -0,0 +1,538 @@
+//! FAT12, FAT16 and FAT32 directory reader used to recover deleted files.
+//!
+//! Deleting a file on FAT replaces the first byte of its directory entry
+//! with `0xE5` and frees its cluster chain in the allocation table. The
+//! entry keeps the size, timestamps and first cluster of the file, so its
+//! content can be recovered by assuming it was stored contiguously, which is
+//! the common case on removable media.
+
+use std::collections::HashSet;
+use std::io::{Read, Seek};
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone, Utc};
+
+use super::deleted::{free_runs, DeletedFile};
+use super::{le_u16, le_u32, read_bytes, DeviceSlice, ImageError};
+use crate::{DataRun, FileAttributes, FileTimes, Recoverability};
+
+/// Size of a directory entry
+const ENTRY_SIZE: usize = 32;
+/// First byte of a deleted directory entry
+const DELETED_MARKER: u8 = 0xE5;
+/// First byte of an entry whose name really starts with `0xE5`
+const ESCAPED_MARKER: u8 = 0x05;
+const ATTRIBUTE_VOLUME_LABEL: u8 = 0x08;
+const ATTRIBUTE_DIRECTORY: u8 = 0x10;
+const ATTRIBUTE_LONG_NAME: u8 = 0x0F;
+/// Flags of byte 12 requesting a lowercase base name and extension
+const LOWERCASE_BASE: u8 = 0x08;
+const LOWERCASE_EXTENSION: u8 = 0x10;
+/// Character replacing the lost first letter of deleted 8.3 names
+const LOST_CHARACTER: char = '_';
+
+/// Width of the allocation table entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FatKind {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+/// Location of the root directory.
+enum Root {
+    /// Fixed region of FAT12 and FAT16 volumes, as `(offset, length)`
+    Region(u64, u64),
+    /// First cluster of the FAT32 root directory
+    Cluster(u32),
+}
+
+/// A FAT volume opened for deleted file recovery.
+struct FatVolume {
+    /// Offset of the volume in the device
+    offset: u64,
+    cluster_size: u64,
+    /// Offset of cluster 2 in the volume
+    data_offset: u64,
+    /// Decoded allocation table, indexed by cluster
+    table: Vec<u32>,
+    /// Lowest value marking a bad cluster or the end of a chain
+    end_of_chain: u32,
+    root: Root,
+}
+
+impl FatVolume {
+    /// Reads the boot sector and the first allocation table of the volume.
+    fn open<R: Read + Seek>(volume: &mut DeviceSlice<R>, offset: u64, kind: FatKind) -> Result<Self, ImageError> {
+        let boot = read_bytes(volume, 0, 512)?;
+        let invalid = || ImageError::InvalidImage("invalid FAT boot sector".to_string());
+        let sector_size = le_u16(&boot, 11) as u64;
+        if !(512..=4096).contains(&sector_size) || !sector_size.is_power_of_two() {
+            return Err(invalid());
+        }
+        let cluster_size = sector_size * boot[13] as u64;
+        let reserved = le_u16(&boot, 14) as u64;
+        let table_count = boot[16] as u64;
+        let root_entries = le_u16(&boot, 17) as u64;
+        let total_sectors = match le_u16(&boot, 19) {
+            0 => le_u32(&boot, 32) as u64,
+            sectors => sectors as u64,
+        };
+        let table_sectors = match le_u16(&boot, 22) {
+            0 => le_u32(&boot, 36) as u64,
+            sectors => sectors as u64,
+        };
+        if cluster_size == 0 || table_count == 0 || table_sectors == 0 {
+            return Err(invalid());
+        }
+
+        // The regions the boot sector describes must fit in the volume before anything is allocated for them
+        let table_offset = reserved * sector_size;
+        let table_length = table_sectors.checked_mul(sector_size).ok_or_else(invalid)?;
+        let root_offset = table_count
+            .checked_mul(table_length)
+            .and_then(|tables| tables.checked_add(table_offset))
+            .ok_or_else(invalid)?;
+        let root_length = (root_entries * ENTRY_SIZE as u64).div_ceil(sector_size) * sector_size;
+        let data_offset = root_offset.checked_add(root_length).ok_or_else(invalid)?;
+        if data_offset > volume.length() {
+            return Err(ImageError::InvalidImage("FAT regions extend past the end of the volume".to_string()));
+        }
+        let clusters = (total_sectors * sector_size).min(volume.length()).saturating_sub(data_offset) / cluster_size;
+
+        let raw = read_bytes(volume, table_offset, table_length as usize)?;
+        let entries = (clusters as usize + 2).min(match kind {
+            FatKind::Fat12 => raw.len() * 2 / 3,
+            FatKind::Fat16 => raw.len() / 2,
+            FatKind::Fat32 => raw.len() / 4,
+        });
+        let table = (0..entries)
+            .map(|cluster| match kind {
+                FatKind::Fat12 => {
+                    let value = le_u16(&raw, cluster * 3 / 2) as u32;
+                    if cluster % 2 == 0 { value & 0x0FFF } else { value >> 4 }
+                }
+                FatKind::Fat16 => le_u16(&raw, cluster * 2) as u32,
+                FatKind::Fat32 => le_u32(&raw, cluster * 4) & 0x0FFF_FFFF,
+            })
+            .collect();
+
+        let (end_of_chain, root) = match kind {
+            FatKind::Fat12 => (0x0FF7, Root::Region(root_offset, root_length)),
+            FatKind::Fat16 => (0xFFF7, Root::Region(root_offset, root_length)),
+            FatKind::Fat32 => (0x0FFF_FFF7, Root::Cluster(le_u32(&boot, 44))),
+        };
+
+        Ok(FatVolume {
+            offset,
+            cluster_size,
+            data_offset,
+            table,
+            end_of_chain,
+            root,
+        })
+    }
+
+    /// Returns true if `cluster` is a data cluster of the volume.
+    fn is_valid(&self, cluster: u32) -> bool {
+        cluster >= 2 && (cluster as usize) < self.table.len()
+    }
+
+    /// Returns true if `cluster` is allocated in the table.
+    fn is_allocated(&self, cluster: u32) -> bool {
+        self.table.get(cluster as usize).is_some_and(|&next| next != 0)
+    }
+
+    /// Returns the offset of `cluster` in the volume.
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        self.data_offset + (cluster as u64 - 2) * self.cluster_size
+    }
+
+    /// Follows the chain of clusters starting at `first`.
+    fn chain(&self, first: u32) -> Vec<u32> {
+        let mut chain = Vec::new();
+        let mut cluster = first;
+        while self.is_valid(cluster) && chain.len() < self.table.len() {
+            chain.push(cluster);
+            let next = self.table[cluster as usize];
+            if next >= self.end_of_chain {
+                break;
+            }
+            cluster = next;
+        }
+        chain
+    }
+
+    /// Reads the content of a list of clusters.
+    fn read_clusters<R: Read + Seek>(&self, volume: &mut DeviceSlice<R>, clusters: &[u32]) -> Result<Vec<u8>, ImageError> {
+        let length = (clusters.len() as u64).saturating_mul(self.cluster_size).min(volume.length());
+        let mut data = Vec::with_capacity(length as usize);
+        for &cluster in clusters {
+            data.extend(read_bytes(volume, self.cluster_offset(cluster), self.cluster_size as usize)?);
+        }
+        Ok(data)
+    }
+
+    /// Locates the content of a deleted file assuming its clusters are
+    /// contiguous from its first cluster.
+    fn deleted_content(&self, first: u32, size: u64) -> (Vec<DataRun>, Recoverability) {
+        if size == 0 {
+            return (Vec::new(), Recoverability::Recoverable);
+        }
+        if !self.is_valid(first) {
+            return (Vec::new(), Recoverability::Unknown);
+        }
+        let needed = size.div_ceil(self.cluster_size);
+        let available = self.table.len() as u64 - first as u64;
+        let clusters = needed.min(available);
+        let reallocated = (first..first + clusters as u32).filter(|&cluster| self.is_allocated(cluster)).count() as u64;
+
+        let runs = vec![DataRun::Extent {
+            offset: self.offset + self.cluster_offset(first),
+            length: clusters * self.cluster_size,
+        }];
+        let recoverability = if clusters < needed {
+            // The file would run past the end of the volume
+            Recoverability::Unknown
+        } else {
+            Recoverability::from_clusters(clusters, reallocated)
+        };
+        (runs, recoverability)
+    }
+
+    /// Walks a directory, collecting deleted files and descending into
+    /// subdirectories. Every entry of a deleted directory is deleted too.
+    fn walk<R: Read + Seek>(
+        &self,
+        volume: &mut DeviceSlice<R>,
+        directory: &[u8],
+        path: &str,
+        deleted_directory: bool,
+        visited: &mut HashSet<u32>,
+        files: &mut Vec<DeletedFile>,
+    ) -> Result<(), ImageError> {
+        let mut long_name: Vec<&[u8]> = Vec::new();
+
+        for entry in directory.chunks_exact(ENTRY_SIZE) {
+            if entry[0] == 0 {
+                break;
+            }
+            if entry[11] & 0x3F == ATTRIBUTE_LONG_NAME {
+                long_name.push(entry);
+                continue;
+            }
+            let parts = std::mem::take(&mut long_name);
+            if entry[11] & ATTRIBUTE_VOLUME_LABEL != 0 || entry[0] == b'.' {
+                continue;
+            }
+
+            let deleted = deleted_directory || entry[0] == DELETED_MARKER;
+            let name = decode_long_name(&parts, entry).unwrap_or_else(|| short_name(entry));
+            let first = (le_u16(entry, 20) as u32) << 16 | le_u16(entry, 26) as u32;
+            let entry_path = format!("{}/{}", path, name);
+
+            if entry[11] & ATTRIBUTE_DIRECTORY != 0 {
+                if !self.is_valid(first) || !visited.insert(first) {
+                    continue;
+                }
+                let (clusters, deleted) = if deleted {
+                    // The chain is gone, only the first cluster can be trusted
+                    if self.is_allocated(first) {
+                        continue;
+                    }
+                    (vec![first], true)
+                } else {
+                    (self.chain(first), false)
+                };
+                let content = self.read_clusters(volume, &clusters)?;
+                self.walk(volume, &content, &entry_path, deleted, visited, files)?;
+            } else if deleted {
+                let size = le_u32(entry, 28) as u64;
+                let (runs, recoverability) = self.deleted_content(first, size);
+                files.push(DeletedFile {
+                    path: entry_path,
+                    size,
+                    // FAT records no metadata change time, and only the date of the last access
+                    times: FileTimes::new()
+                        .with_modified(dos_time(le_u16(entry, 24), le_u16(entry, 22), 0))
+                        .with_accessed(dos_time(le_u16(entry, 18), 0, 0))
+                        .with_born(dos_time(le_u16(entry, 16), le_u16(entry, 14), entry[13])),
+                    // The attribute byte uses the same bits as FILE_ATTRIBUTE_*
+                    attributes: FileAttributes::from_bits(entry[11] as u32),
+                    file_id: None,
+                    runs,
+                    recoverability,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Computes the checksum of an 8.3 name stored in long name entries.
+fn short_name_checksum(name: &[u8]) -> u8 {
+    name.iter().fold(0u8, |sum, &byte| sum.rotate_right(1).wrapping_add(byte))
+}
+
+/// Decodes the long name stored in the entries preceding a short entry.
+///
+/// Returns `None` if the entries do not belong to the short entry. Deleted
+/// entries lost the first byte of their short name, so only the agreement
+/// of the long name entries between themselves can be checked.
+fn decode_long_name(parts: &[&[u8]], entry: &[u8]) -> Option<String> {
+    let checksum = parts.first()?[13];
+    if parts.iter().any(|part| part[13] != checksum) {
+        return None;
+    }
+    if entry[0] != DELETED_MARKER && short_name_checksum(&entry[0..11]) != checksum {
+        return None;
+    }
+
+    // Long name entries are stored last part first
+    let units: Vec<u16> = parts
+        .iter()
+        .rev()
+        .flat_map(|part| [&part[1..11], &part[14..26], &part[28..32]])
+        .flat_map(|field| field.chunks_exact(2).map(|unit| le_u16(unit, 0)))
+        .take_while(|&unit| unit != 0)
+        .collect();
+    (!units.is_empty()).then(|| String::from_utf16_lossy(&units))
+}
+
+/// Builds the display form of an 8.3 name.
+fn short_name(entry: &[u8]) -> String {
+    let case = |field: &[u8], lowercase: bool| {
+        let text = String::from_utf8_lossy(field).trim_end().to_string();
+        if lowercase { text.to_lowercase() } else { text }
+    };
+    let mut base = case(&entry[0..8], entry[12] & LOWERCASE_BASE != 0);
+    let extension = case(&entry[8..11], entry[12] & LOWERCASE_EXTENSION != 0);
+
+    match entry[0] {
+        DELETED_MARKER => base.replace_range(..base.chars().next().map_or(0, char::len_utf8), &LOST_CHARACTER.to_string()),
+        ESCAPED_MARKER => base.replace_range(..1, "\u{E5}"),
+        _ => {}
+    }
+    if extension.is_empty() {
+        base
+    } else {
+        format!("{}.{}", base, extension)
+    }
+}
+
+/// Converts a DOS date and time, stored in local time, to UTC.
+///
+/// `hundredths` holds the 10 ms units kept for creation times, up to 199,
+/// that refine the two-second resolution of DOS times.
+fn dos_time(date: u16, time: u16, hundredths: u8) -> Option<DateTime<Utc>> {
+    let day = NaiveDate::from_ymd_opt(1980 + (date >> 9) as i32, ((date >> 5) & 0x0F) as u32, (date & 0x1F) as u32)?;
+    let naive = day.and_hms_opt((time >> 11) as u32, ((time >> 5) & 0x3F) as u32, (time & 0x1F) as u32 * 2)?;
+    let naive = naive + chrono::Duration::milliseconds(hundredths.min(199) as i64 * 10);
+    Local.from_local_datetime(&naive).earliest().map(|time| time.with_timezone(&Utc))
+}
+
+/// Lists the deleted files referenced by the directories of a FAT volume.
+pub(crate) fn deleted_files<R: Read + Seek>(
+    device: &mut R,
+    offset: u64,
+    length: u64,
+    kind: FatKind,
+) -> Result<Vec<DeletedFile>, ImageError> {
+    let mut volume = DeviceSlice::new(device, offset, length);
+    let fat = FatVolume::open(&mut volume, offset, kind)?;
+
+    let mut visited = HashSet::new();
+    let root = match fat.root {
+        Root::Region(offset, length) => read_bytes(&mut volume, offset, length as usize)?,
+        Root::Cluster(first) => {
+            visited.insert(first);
+            fat.read_clusters(&mut volume, &fat.chain(first))?
+        }
+    };
+
+    let mut files = Vec::new();
+    fat.walk(&mut volume, &root, "", false, &mut visited, &mut files)?;
+    Ok(files)
+}
+
+/// Lists the clusters of a FAT volume that its allocation table marks as free.
+///
+/// Ranges are `(offset, length)` in the device, merged when the clusters are contiguous.
+pub(crate) fn free_extents<R: Read + Seek>(
+    device: &mut R,
+    offset: u64,
+    length: u64,
+    kind: FatKind,
+) -> Result<Vec<(u64, u64)>, ImageError> {
+    let mut volume = DeviceSlice::new(device, offset, length);
+    let fat = FatVolume::open(&mut volume, offset, kind)?;
+    let free = free_runs(fat.table.len() as u64, |cluster| cluster >= 2 && !fat.is_allocated(cluster as u32));
+    Ok(free
+        .into_iter()
+        .map(|(first, count)| (offset + fat.cluster_offset(first as u32), count * fat.cluster_size))
+        .collect())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use chrono::{Datelike, Timelike};
+
+    use super::*;
+
+    /// Writes a directory entry at `index` of the directory stored at `offset`.
+    fn write_entry(image: &mut [u8], offset: usize, index: usize, name: &[u8; 11], attributes: u8, cluster: u32, size: u32) {
+        let entry = &mut image[offset + index * ENTRY_SIZE..offset + (index + 1) * ENTRY_SIZE];
+        entry[0..11].copy_from_slice(name);
+        entry[11] = attributes;
+        entry[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+        entry[22..24].copy_from_slice(&(14 << 11 | 30 << 5 | 5u16).to_le_bytes());
+        entry[24..26].copy_from_slice(&(44 << 9 | 3 << 5 | 15u16).to_le_bytes());
+        entry[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+        entry[28..32].copy_from_slice(&size.to_le_bytes());
+    }
+
+    /// Writes a long name entry holding up to 13 characters of `part`.
+    fn write_long_entry(image: &mut [u8], offset: usize, index: usize, marker: u8, part: &str, checksum: u8) {
+        let entry = &mut image[offset + index * ENTRY_SIZE..offset + (index + 1) * ENTRY_SIZE];
+        entry[0] = marker;
+        entry[11] = ATTRIBUTE_LONG_NAME;
+        entry[13] = checksum;
+        let mut units: Vec<u16> = part.encode_utf16().collect();
+        units.push(0);
+        units.resize(13, 0xFFFF);
+        let slots = (1..11).step_by(2).chain((14..26).step_by(2)).chain((28..32).step_by(2));
+        for (slot, unit) in slots.zip(units) {
+            entry[slot..slot + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+    }
+
+    /// Builds a 64 KiB FAT32 volume with 512-byte clusters:
+    ///
+    /// * `/Report final.txt`, deleted with its long name, in free clusters 5-6
+    /// * `/KEEP.TXT`, live in cluster 4
+    /// * `/_ONE.TXT`, deleted, its cluster 4 reused by `KEEP.TXT`
+    /// * `/DOCS/_OTES.TXT`, deleted inside a live directory
+    pub(crate) fn build_fat32_volume() -> Vec<u8> {
+        let mut image = vec![0u8; 128 * 512];
+        image[3..11].copy_from_slice(b"MSDOS5.0");
+        image[11..13].copy_from_slice(&512u16.to_le_bytes());
+        image[13] = 1;
+        image[14..16].copy_from_slice(&1u16.to_le_bytes());
+        image[16] = 1;
+        image[32..36].copy_from_slice(&128u32.to_le_bytes());
+        image[36..40].copy_from_slice(&1u32.to_le_bytes());
+        image[44..48].copy_from_slice(&2u32.to_le_bytes());
+        image[82..90].copy_from_slice(b"FAT32   ");
+
+        for (cluster, value) in [(0, 0x0FFF_FFF8u32), (1, 0x0FFF_FFFF), (2, 0x0FFF_FFFF), (3, 0x0FFF_FFFF), (4, 0x0FFF_FFFF)] {
+            image[512 + cluster * 4..516 + cluster * 4].copy_from_slice(&value.to_le_bytes());
+        }
+
+        // Data starts at sector 2 with cluster 2, so cluster N is at sector N
+        let cluster = |number: usize| number * 512;
+        let root = cluster(2);
+        let checksum = short_name_checksum(b"REPORT~1TXT");
+        write_long_entry(&mut image, root, 0, DELETED_MARKER, "txt", checksum);
+        write_long_entry(&mut image, root, 1, DELETED_MARKER, "Report final.", checksum);
+        write_entry(&mut image, root, 2, b"\xE5EPORT~1TXT", 0x20, 5, 700);
+        write_entry(&mut image, root, 3, b"KEEP    TXT", 0x20, 4, 10);
+        write_entry(&mut image, root, 4, b"\xE5ONE    TXT", 0x20, 4, 10);
+        write_entry(&mut image, root, 5, b"DOCS       ", ATTRIBUTE_DIRECTORY, 3, 0);
+
+        let docs = cluster(3);
+        write_entry(&mut image, docs, 0, b".          ", ATTRIBUTE_DIRECTORY, 3, 0);
+        write_entry(&mut image, docs, 1, b"..         ", ATTRIBUTE_DIRECTORY, 0, 0);
+        write_entry(&mut image, docs, 2, b"\xE5OTES   TXT", 0x20, 8, 5);
+
+        image[cluster(5)..cluster(5) + 4].copy_from_slice(b"Q3\n ");
+        image[cluster(8)..cluster(8) + 5].copy_from_slice(b"hello");
+        image
+    }
+
+    #[test]
+    fn test_short_name() {
+        let mut entry = [0u8; 32];
+        entry[0..11].copy_from_slice(b"\xE5ACKUP  ZIP");
+        assert_eq!(short_name(&entry), "_ACKUP.ZIP");
+
+        entry[0..11].copy_from_slice(b"README     ");
+        entry[12] = LOWERCASE_BASE;
+        assert_eq!(short_name(&entry), "readme");
+    }
+
+    #[test]
+    fn test_dos_time() {
+        // DOS times are local, the result is in UTC
+        let time = dos_time(44 << 9 | 3 << 5 | 15, 14 << 11 | 30 << 5 | 5, 0).unwrap().with_timezone(&Local);
+        assert_eq!((time.year(), time.month(), time.day()), (2024, 3, 15));
+        assert_eq!((time.hour(), time.minute(), time.second()), (14, 30, 10));
+        assert_eq!(dos_time(0, 0, 0), None);
+
+        let created = dos_time(44 << 9 | 3 << 5 | 15, 14 << 11 | 30 << 5 | 5, 150).unwrap();
+        assert_eq!(created.timestamp_subsec_millis(), 500);
+        assert_eq!(created.timestamp() - time.timestamp(), 1);
+    }
+
+    #[test]
+    fn test_fat32_deleted_files() {
+        let image = build_fat32_volume();
+        let length = image.len() as u64;
+        let files = deleted_files(&mut std::io::Cursor::new(image), 0, length, FatKind::Fat32).unwrap();
+        let paths: Vec<&str> = files.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, vec!["/Report final.txt", "/_ONE.TXT", "/DOCS/_OTES.TXT"]);
+
+        assert_eq!(files[0].size, 700);
+        assert_eq!(files[0].runs, vec![DataRun::Extent { offset: 5 * 512, length: 1024 }]);
+        assert_eq!(files[0].recoverability, Recoverability::Recoverable);
+        assert_eq!(files[1].recoverability, Recoverability::Overwritten);
+        assert_eq!(files[2].runs, vec![DataRun::Extent { offset: 8 * 512, length: 512 }]);
+    }
+
+    #[test]
+    fn test_corrupt_boot_sector() {
+        let corrupt = |offset: usize, value: &[u8]| {
+            let mut image = build_fat32_volume();
+            image[offset..offset + value.len()].copy_from_slice(value);
+            let length = image.len() as u64;
+            deleted_files(&mut std::io::Cursor::new(image), 0, length, FatKind::Fat32)
+        };
+        // Table size, table count and sector size far beyond the 64 KiB volume
+        for (offset, value) in [(36, &0x4000_0000u32.to_le_bytes()[..]), (16, &[255][..]), (11, &1000u16.to_le_bytes()[..])] {
+            assert!(matches!(corrupt(offset, value), Err(ImageError::InvalidImage(_))));
+        }
+        assert!(corrupt(36, &u32::MAX.to_le_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_fat12_deleted_files() {
+        // 1.44 MB floppy layout with one deleted file in the fixed root directory
+        let mut image = vec![0u8; 2880 * 512];
+        image[11..13].copy_from_slice(&512u16.to_le_bytes());
+        image[13] = 1;
+        image[14..16].copy_from_slice(&1u16.to_le_bytes());
+        image[16] = 2;
+        image[17..19].copy_from_slice(&224u16.to_le_bytes());
+        image[19..21].copy_from_slice(&2880u16.to_le_bytes());
+        image[22..24].copy_from_slice(&9u16.to_le_bytes());
+        // Clusters 2 and 3 are allocated, packed on 12 bits
+        image[512..518].copy_from_slice(&[0xF0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        let root = 19 * 512;
+        write_entry(&mut image, root, 0, b"\xE5ETTER  DOC", 0x20, 3, 1500);
+
+        let length = image.len() as u64;
+        let files = deleted_files(&mut std::io::Cursor::new(image), 0, length, FatKind::Fat12).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "/_ETTER.DOC");
+        // Data starts after the root directory at sector 33
+        assert_eq!(files[0].runs, vec![DataRun::Extent { offset: (33 + 1) * 512, length: 1536 }]);
+        assert_eq!(files[0].recoverability, Recoverability::PartiallyOverwritten);
+    }
+
+    #[test]
+    fn test_free_extents() {
+        let image = build_fat32_volume();
+        let length = image.len() as u64;
+        let free = free_extents(&mut std::io::Cursor::new(image), 0, length, FatKind::Fat32).unwrap();
+        // Clusters 2 to 4 are in use and cluster N is at sector N
+        assert_eq!(free, vec![(5 * 512, 123 * 512)]);
+    }
+}
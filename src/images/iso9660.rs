@@ -0,0 +1,639 @@
+//! ISO 9660 file system reader with Joliet and Rock Ridge support.
+//!
+//! The primary volume descriptor is always parsed. When the root directory
+//! carries the Rock Ridge SUSP marker, POSIX names from the primary tree are
+//! used; otherwise a Joliet supplementary tree is preferred for its Unicode
+//! names, falling back to plain ISO 9660 names.
+
+use std::collections::HashSet;
+use std::io::{Read, Seek};
+
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone, Utc};
+
+use super::optical::OpticalFile;
+use super::{le_u16, le_u32, read_bytes, ImageError};
+use crate::{DataRun, FileTimes};
+
+/// Size of a volume descriptor and of the sectors holding them
+const SECTOR_SIZE: u64 = 2048;
+/// Sector of the first volume descriptor
+const FIRST_DESCRIPTOR_SECTOR: u64 = 16;
+/// Upper bound on the number of volume descriptors examined
+const MAX_DESCRIPTORS: u64 = 64;
+/// Standard identifier present in every volume descriptor
+const STANDARD_IDENTIFIER: &[u8] = b"CD001";
+
+/// Volume descriptor types
+const DESCRIPTOR_PRIMARY: u8 = 1;
+const DESCRIPTOR_SUPPLEMENTARY: u8 = 2;
+const DESCRIPTOR_TERMINATOR: u8 = 255;
+
+/// Directory record flags
+const FLAG_DIRECTORY: u8 = 0x02;
+const FLAG_MULTI_EXTENT: u8 = 0x80;
+
+/// Naming scheme used for the selected directory tree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Naming {
+    /// Plain ISO 9660 `NAME.EXT;1` identifiers
+    Plain,
+    /// Joliet UCS-2 identifiers from a supplementary descriptor
+    Joliet,
+    /// Rock Ridge `NM` entries, skipping `skip` bytes of each system use area
+    RockRidge { skip: usize },
+}
+
+/// A parsed directory record.
+#[derive(Debug, Clone)]
+struct DirectoryRecord {
+    /// First logical block of the extent
+    extent: u32,
+    /// Length of the extent in bytes
+    length: u32,
+    /// Record flags
+    flags: u8,
+    /// Raw file identifier
+    identifier: Vec<u8>,
+    /// Recording date of the extent
+    recorded: Option<DateTime<Utc>>,
+    /// System use area, holding Rock Ridge entries when present
+    system_use: Vec<u8>,
+}
+
+impl DirectoryRecord {
+    /// Parses the record at the start of `buf`, returning `None` if it is malformed.
+    fn parse(buf: &[u8]) -> Option<Self> {
+        let length = *buf.first()? as usize;
+        if length < 34 || length > buf.len() {
+            return None;
+        }
+        let name_len = buf[32] as usize;
+        if 33 + name_len > length {
+            return None;
+        }
+        let system_use_start = 33 + name_len + (1 - name_len % 2);
+
+        Some(DirectoryRecord {
+            extent: le_u32(buf, 2),
+            length: le_u32(buf, 10),
+            flags: buf[25],
+            identifier: buf[33..33 + name_len].to_vec(),
+            recorded: parse_record_date(&buf[18..25]),
+            system_use: buf.get(system_use_start..length).unwrap_or_default().to_vec(),
+        })
+    }
+
+    fn is_directory(&self) -> bool {
+        self.flags & FLAG_DIRECTORY != 0
+    }
+
+    /// Returns true for the `.` and `..` entries of a directory.
+    fn is_special(&self) -> bool {
+        self.identifier == [0] || self.identifier == [1]
+    }
+}
+
+/// Information extracted from the Rock Ridge entries of a record.
+#[derive(Debug, Default)]
+struct RockRidgeInfo {
+    /// Alternate POSIX name
+    name: Option<String>,
+    /// POSIX timestamps
+    times: FileTimes,
+    /// Block of the real directory for a relocated directory (`CL`)
+    child_link: Option<u32>,
+    /// Whether the record is a relocated directory that must be hidden (`RE`)
+    relocated: bool,
+}
+
+/// An ISO 9660 volume and the directory tree selected for listing.
+#[derive(Debug)]
+pub(crate) struct Iso9660Volume {
+    /// Volume identifier
+    label: String,
+    /// Logical block size in bytes
+    block_size: u64,
+    /// Number of logical blocks in the volume
+    volume_blocks: u64,
+    /// Root directory record of the selected tree
+    root: DirectoryRecord,
+    /// Naming scheme of the selected tree
+    naming: Naming,
+}
+
+impl Iso9660Volume {
+    /// Parses the volume descriptors of an ISO 9660 image.
+    ///
+    /// Returns `Ok(None)` when the media does not contain an ISO 9660 volume.
+    pub(crate) fn open<R: Read + Seek>(reader: &mut R) -> Result<Option<Self>, ImageError> {
+        let mut primary = None;
+        let mut joliet = None;
+
+        for index in 0..MAX_DESCRIPTORS {
+            let offset = (FIRST_DESCRIPTOR_SECTOR + index) * SECTOR_SIZE;
+            let descriptor = match read_bytes(reader, offset, SECTOR_SIZE as usize) {
+                Ok(descriptor) => descriptor,
+                Err(_) if index == 0 => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            if &descriptor[1..6] != STANDARD_IDENTIFIER {
+                break;
+            }
+            match descriptor[0] {
+                DESCRIPTOR_PRIMARY if primary.is_none() => primary = Some(descriptor),
+                DESCRIPTOR_SUPPLEMENTARY if joliet.is_none() && is_joliet(&descriptor) => {
+                    joliet = Some(descriptor)
+                }
+                DESCRIPTOR_TERMINATOR => break,
+                _ => {}
+            }
+        }
+
+        let primary = match primary {
+            Some(primary) => primary,
+            None => return Ok(None),
+        };
+
+        let block_size = match le_u16(&primary, 128) {
+            0 => SECTOR_SIZE,
+            size => size as u64,
+        };
+        let volume_blocks = le_u32(&primary, 80) as u64;
+        let root = DirectoryRecord::parse(&primary[156..190])
+            .ok_or_else(|| ImageError::InvalidImage("malformed ISO 9660 root directory record".to_string()))?;
+
+        let mut volume = Iso9660Volume {
+            label: decode_identifier(&primary[40..72], false),
+            block_size,
+            volume_blocks,
+            root,
+            naming: Naming::Plain,
+        };
+
+        if let Some(skip) = volume.rock_ridge_skip(reader)? {
+            volume.naming = Naming::RockRidge { skip };
+        } else if let Some(joliet) = joliet {
+            if let Some(root) = DirectoryRecord::parse(&joliet[156..190]) {
+                let label = decode_identifier(&joliet[40..72], true);
+                if !label.is_empty() {
+                    volume.label = label;
+                }
+                volume.root = root;
+                volume.naming = Naming::Joliet;
+            }
+        }
+
+        Ok(Some(volume))
+    }
+
+    /// Returns the volume identifier.
+    pub(crate) fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Returns the size of the volume in bytes.
+    pub(crate) fn size(&self) -> u64 {
+        self.volume_blocks * self.block_size
+    }
+
+    /// Returns a short description of the extensions in use.
+    pub(crate) fn description(&self) -> &'static str {
+        match self.naming {
+            Naming::Plain => "ISO 9660",
+            Naming::Joliet => "ISO 9660 + Joliet",
+            Naming::RockRidge { .. } => "ISO 9660 + Rock Ridge",
+        }
+    }
+
+    /// Lists every regular file of the selected directory tree.
+    pub(crate) fn files<R: Read + Seek>(&self, reader: &mut R) -> Result<Vec<OpticalFile>, ImageError> {
+        let mut files = Vec::new();
+        let mut visited = HashSet::new();
+        let mut pending = vec![(String::new(), self.root.extent, self.root.length)];
+
+        while let Some((dir_path, extent, length)) = pending.pop() {
+            if !visited.insert(extent) {
+                continue;
+            }
+
+            // Multi-extent files are split across consecutive records sharing a name
+            let mut partial: Option<(u64, Vec<DataRun>)> = None;
+
+            for record in self.read_directory(reader, extent, length)? {
+                if record.is_special() {
+                    continue;
+                }
+                let rock_ridge = match self.naming {
+                    Naming::RockRidge { skip } => self.read_rock_ridge(reader, &record.system_use, skip)?,
+                    _ => RockRidgeInfo::default(),
+                };
+                if rock_ridge.relocated {
+                    continue;
+                }
+
+                let name = match rock_ridge.name {
+                    Some(name) => name,
+                    None => decode_file_identifier(&record.identifier, self.naming == Naming::Joliet),
+                };
+                let path = format!("{}/{}", dir_path, name);
+
+                if let Some(child) = rock_ridge.child_link {
+                    pending.push((path, child, self.directory_length(reader, child)?));
+                    continue;
+                }
+                if record.is_directory() {
+                    pending.push((path, record.extent, record.length));
+                    continue;
+                }
+
+                let (size, mut runs) = partial.take().unwrap_or_default();
+                runs.push(DataRun::Extent {
+                    offset: record.extent as u64 * self.block_size,
+                    length: record.length as u64,
+                });
+                let size = size + record.length as u64;
+
+                if record.flags & FLAG_MULTI_EXTENT != 0 {
+                    partial = Some((size, runs));
+                    continue;
+                }
+
+                files.push(OpticalFile {
+                    path,
+                    size,
+                    // The recording date stands in for a missing POSIX modification time
+                    times: rock_ridge.times.with_modified(rock_ridge.times.modified().or(record.recorded)),
+                    runs,
+                });
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Reads and parses all records of the directory stored at `extent`.
+    fn read_directory<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        extent: u32,
+        length: u32,
+    ) -> Result<Vec<DirectoryRecord>, ImageError> {
+        if length as u64 > self.size().max(SECTOR_SIZE) {
+            return Err(ImageError::InvalidImage(format!(
+                "ISO 9660 directory at block {} is larger than the volume",
+                extent
+            )));
+        }
+        let data = read_bytes(reader, extent as u64 * self.block_size, length as usize)?;
+
+        let mut records = Vec::new();
+        let mut offset = 0;
+        while offset < data.len() {
+            let record_len = data[offset] as usize;
+            if record_len == 0 {
+                // Records never cross a sector boundary; skip the padding
+                offset = (offset / SECTOR_SIZE as usize + 1) * SECTOR_SIZE as usize;
+                continue;
+            }
+            match DirectoryRecord::parse(&data[offset..]) {
+                Some(record) => records.push(record),
+                None => break,
+            }
+            offset += record_len;
+        }
+        Ok(records)
+    }
+
+    /// Returns the data length of the directory whose `.` record is at `extent`.
+    fn directory_length<R: Read + Seek>(&self, reader: &mut R, extent: u32) -> Result<u32, ImageError> {
+        let sector = read_bytes(reader, extent as u64 * self.block_size, SECTOR_SIZE as usize)?;
+        DirectoryRecord::parse(&sector)
+            .map(|record| record.length)
+            .ok_or_else(|| ImageError::InvalidImage(format!("malformed directory at block {}", extent)))
+    }
+
+    /// Checks the root `.` record for the SUSP `SP` marker announcing Rock Ridge.
+    ///
+    /// Returns the number of bytes to skip in every system use area.
+    fn rock_ridge_skip<R: Read + Seek>(&self, reader: &mut R) -> Result<Option<usize>, ImageError> {
+        let sector = read_bytes(reader, self.root.extent as u64 * self.block_size, SECTOR_SIZE as usize)?;
+        let record = match DirectoryRecord::parse(&sector) {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        let area = &record.system_use;
+        if area.len() >= 7 && &area[0..2] == b"SP" && area[4] == 0xBE && area[5] == 0xEF {
+            return Ok(Some(area[6] as usize));
+        }
+        Ok(None)
+    }
+
+    /// Collects the Rock Ridge entries of a system use area, following `CE` continuations.
+    fn read_rock_ridge<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        system_use: &[u8],
+        skip: usize,
+    ) -> Result<RockRidgeInfo, ImageError> {
+        let mut info = RockRidgeInfo::default();
+        let mut name = String::new();
+        let mut has_name = false;
+        let mut area = system_use.get(skip..).unwrap_or_default().to_vec();
+        let mut continuations = 0;
+
+        loop {
+            let mut continuation = None;
+            for (signature, entry) in susp_entries(&area) {
+                match signature {
+                    // Flags 0x02 and 0x04 denote the current and parent directories
+                    b"NM" if entry.len() >= 5 && entry[4] & 0x06 == 0 => {
+                        name.push_str(&String::from_utf8_lossy(&entry[5..]));
+                        has_name = true;
+                    }
+                    b"TF" if entry.len() >= 5 => info.times = parse_timestamp_entry(entry),
+                    b"CL" if entry.len() >= 8 => info.child_link = Some(le_u32(entry, 4)),
+                    b"RE" => info.relocated = true,
+                    b"CE" if entry.len() >= 28 => {
+                        continuation = Some((le_u32(entry, 4), le_u32(entry, 12), le_u32(entry, 20)));
+                    }
+                    _ => {}
+                }
+            }
+
+            match continuation {
+                Some((block, offset, length)) if continuations < 16 && length as u64 <= SECTOR_SIZE => {
+                    continuations += 1;
+                    area = read_bytes(reader, block as u64 * self.block_size + offset as u64, length as usize)?;
+                }
+                _ => break,
+            }
+        }
+
+        if has_name {
+            info.name = Some(name);
+        }
+        Ok(info)
+    }
+}
+
+/// Iterates over the SUSP entries of a system use area as `(signature, entry)` pairs.
+fn susp_entries(area: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        if offset + 4 > area.len() {
+            return None;
+        }
+        let len = area[offset + 2] as usize;
+        if len < 4 || offset + len > area.len() {
+            return None;
+        }
+        let entry = &area[offset..offset + len];
+        offset += len;
+        if &entry[0..2] == b"ST" {
+            offset = area.len();
+        }
+        Some((&entry[0..2], entry))
+    })
+}
+
+/// Returns the times stored in a Rock Ridge `TF` entry.
+fn parse_timestamp_entry(entry: &[u8]) -> FileTimes {
+    let flags = entry[4];
+    let long_form = flags & 0x80 != 0;
+    let stamp_len = if long_form { 17 } else { 7 };
+
+    // Stamps follow in flag order: creation, modification, access and attribute change
+    let mut stamps = [None; 4];
+    let mut position = 5;
+    for (bit, stamp) in stamps.iter_mut().enumerate() {
+        if flags & (1 << bit) == 0 {
+            continue;
+        }
+        *stamp = entry.get(position..position + stamp_len).and_then(|stamp| {
+            if long_form {
+                parse_volume_date(stamp)
+            } else {
+                parse_record_date(stamp)
+            }
+        });
+        position += stamp_len;
+    }
+    let [born, modified, accessed, changed] = stamps;
+    FileTimes::new()
+        .with_modified(modified)
+        .with_accessed(accessed)
+        .with_changed(changed)
+        .with_born(born)
+}
+
+/// Returns true if a supplementary descriptor uses one of the Joliet escape sequences.
+fn is_joliet(descriptor: &[u8]) -> bool {
+    matches!(&descriptor[88..91], b"%/@" | b"%/C" | b"%/E")
+}
+
+/// Decodes a padded identifier field of a volume descriptor.
+fn decode_identifier(field: &[u8], ucs2: bool) -> String {
+    let text = if ucs2 {
+        decode_ucs2(field)
+    } else {
+        String::from_utf8_lossy(field).to_string()
+    };
+    text.trim_end_matches([' ', '\0']).to_string()
+}
+
+/// Decodes a file identifier, removing the version suffix and a trailing dot.
+fn decode_file_identifier(identifier: &[u8], ucs2: bool) -> String {
+    let name = if ucs2 {
+        decode_ucs2(identifier)
+    } else {
+        String::from_utf8_lossy(identifier).to_string()
+    };
+    let name = match name.rfind(';') {
+        Some(index) => &name[..index],
+        None => &name,
+    };
+    name.strip_suffix('.').unwrap_or(name).to_string()
+}
+
+/// Decodes big-endian UCS-2 text as used by Joliet.
+fn decode_ucs2(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Parses the 7-byte date of a directory record.
+fn parse_record_date(stamp: &[u8]) -> Option<DateTime<Utc>> {
+    let date = NaiveDate::from_ymd_opt(1900 + stamp[0] as i32, stamp[1] as u32, stamp[2] as u32)
+        .and_then(|date| date.and_hms_opt(stamp[3] as u32, stamp[4] as u32, stamp[5] as u32));
+    to_utc(date, stamp[6] as i8)
+}
+
+/// Parses the 17-byte ASCII date used by volume descriptors and long `TF` entries.
+fn parse_volume_date(stamp: &[u8]) -> Option<DateTime<Utc>> {
+    let field = |range: std::ops::Range<usize>| -> Option<u32> {
+        std::str::from_utf8(&stamp[range]).ok()?.parse().ok()
+    };
+    let date = field(0..4)
+        .zip(field(4..6))
+        .zip(field(6..8))
+        .and_then(|((year, month), day)| NaiveDate::from_ymd_opt(year as i32, month, day))
+        .and_then(|date| {
+            let hundredths = field(14..16)?;
+            date.and_hms_milli_opt(field(8..10)?, field(10..12)?, field(12..14)?, hundredths * 10)
+        });
+    to_utc(date, stamp[16] as i8)
+}
+
+/// Converts a date recorded with an offset in 15-minute units to UTC.
+fn to_utc(date: Option<chrono::NaiveDateTime>, quarter_hours: i8) -> Option<DateTime<Utc>> {
+    let offset = FixedOffset::east_opt(quarter_hours as i32 * 15 * 60)?;
+    offset
+        .from_local_datetime(&date?)
+        .single()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Builds a directory record with the given identifier and system use area.
+    pub(crate) fn directory_record(extent: u32, length: u32, flags: u8, identifier: &[u8], system_use: &[u8]) -> Vec<u8> {
+        let padding = 1 - identifier.len() % 2;
+        let record_len = 33 + identifier.len() + padding + system_use.len();
+        let mut record = vec![0u8; record_len];
+        record[0] = record_len as u8;
+        record[2..6].copy_from_slice(&extent.to_le_bytes());
+        record[6..10].copy_from_slice(&extent.to_be_bytes());
+        record[10..14].copy_from_slice(&length.to_le_bytes());
+        record[14..18].copy_from_slice(&length.to_be_bytes());
+        record[18..25].copy_from_slice(&[124, 3, 15, 10, 30, 0, 0]); // 2024-03-15 10:30:00 UTC
+        record[25] = flags;
+        record[32] = identifier.len() as u8;
+        record[33..33 + identifier.len()].copy_from_slice(identifier);
+        record[33 + identifier.len() + padding..].copy_from_slice(system_use);
+        record
+    }
+
+    /// Builds a volume descriptor of the given type whose root directory is at `root_extent`.
+    fn volume_descriptor(kind: u8, label: &[u8], root_extent: u32, escape: &[u8]) -> Vec<u8> {
+        let mut descriptor = vec![0u8; SECTOR_SIZE as usize];
+        descriptor[0] = kind;
+        descriptor[1..6].copy_from_slice(STANDARD_IDENTIFIER);
+        descriptor[6] = 1;
+        descriptor[40..72].fill(b' ');
+        descriptor[40..40 + label.len()].copy_from_slice(label);
+        descriptor[80..84].copy_from_slice(&32u32.to_le_bytes());
+        descriptor[88..88 + escape.len()].copy_from_slice(escape);
+        descriptor[128..130].copy_from_slice(&2048u16.to_le_bytes());
+        let root = directory_record(root_extent, 2048, FLAG_DIRECTORY, &[0], &[]);
+        descriptor[156..156 + root.len()].copy_from_slice(&root);
+        descriptor
+    }
+
+    /// Writes `data` at the start of `sector` in `image`.
+    fn put(image: &mut [u8], sector: u64, data: &[u8]) {
+        let start = (sector * SECTOR_SIZE) as usize;
+        image[start..start + data.len()].copy_from_slice(data);
+    }
+
+    /// Builds a small ISO image with a Joliet tree and one nested file.
+    ///
+    /// Layout: PVD at 16, Joliet SVD at 17, terminator at 18, ISO root at 20,
+    /// ISO `DOCS` at 21, Joliet root at 22, Joliet `Docs` at 23, file data at 24.
+    pub(crate) fn build_joliet_image() -> Vec<u8> {
+        let mut image = vec![0u8; 32 * SECTOR_SIZE as usize];
+        put(&mut image, 16, &volume_descriptor(DESCRIPTOR_PRIMARY, b"EVIDENCE", 20, &[]));
+        put(&mut image, 17, &volume_descriptor(DESCRIPTOR_SUPPLEMENTARY, &[], 22, b"%/E"));
+        let mut terminator = vec![DESCRIPTOR_TERMINATOR];
+        terminator.extend_from_slice(STANDARD_IDENTIFIER);
+        put(&mut image, 18, &terminator);
+
+        let ucs2 = |text: &str| -> Vec<u8> { text.encode_utf16().flat_map(|unit| unit.to_be_bytes()).collect() };
+
+        let mut root = directory_record(20, 2048, FLAG_DIRECTORY, &[0], &[]);
+        root.extend(directory_record(20, 2048, FLAG_DIRECTORY, &[1], &[]));
+        root.extend(directory_record(21, 2048, FLAG_DIRECTORY, b"DOCS", &[]));
+        put(&mut image, 20, &root);
+        let mut docs = directory_record(21, 2048, FLAG_DIRECTORY, &[0], &[]);
+        docs.extend(directory_record(20, 2048, FLAG_DIRECTORY, &[1], &[]));
+        docs.extend(directory_record(24, 11, 0, b"REPORT_F.TXT;1", &[]));
+        put(&mut image, 21, &docs);
+
+        let mut root = directory_record(22, 2048, FLAG_DIRECTORY, &[0], &[]);
+        root.extend(directory_record(22, 2048, FLAG_DIRECTORY, &[1], &[]));
+        root.extend(directory_record(23, 2048, FLAG_DIRECTORY, &ucs2("Docs"), &[]));
+        put(&mut image, 22, &root);
+        let mut docs = directory_record(23, 2048, FLAG_DIRECTORY, &[0], &[]);
+        docs.extend(directory_record(22, 2048, FLAG_DIRECTORY, &[1], &[]));
+        docs.extend(directory_record(24, 11, 0, &ucs2("Report final.txt;1"), &[]));
+        put(&mut image, 23, &docs);
+
+        put(&mut image, 24, b"hello world");
+        image
+    }
+
+    #[test]
+    fn test_joliet_names() {
+        let mut reader = Cursor::new(build_joliet_image());
+        let volume = Iso9660Volume::open(&mut reader).unwrap().unwrap();
+
+        assert_eq!(volume.description(), "ISO 9660 + Joliet");
+        assert_eq!(volume.size(), 32 * 2048);
+
+        let files = volume.files(&mut reader).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "/Docs/Report final.txt");
+        assert_eq!(files[0].size, 11);
+        assert_eq!(files[0].runs, vec![DataRun::Extent { offset: 24 * 2048, length: 11 }]);
+    }
+
+    #[test]
+    fn test_rock_ridge_names() {
+        let mut image = vec![0u8; 32 * SECTOR_SIZE as usize];
+        put(&mut image, 16, &volume_descriptor(DESCRIPTOR_PRIMARY, b"LINUX", 20, &[]));
+        let mut terminator = vec![DESCRIPTOR_TERMINATOR];
+        terminator.extend_from_slice(STANDARD_IDENTIFIER);
+        put(&mut image, 17, &terminator);
+
+        let sp = [b'S', b'P', 7, 1, 0xBE, 0xEF, 0];
+        let mut root = directory_record(20, 2048, FLAG_DIRECTORY, &[0], &sp);
+        root.extend(directory_record(20, 2048, FLAG_DIRECTORY, &[1], &[]));
+        let mut nm = vec![b'N', b'M', 0, 1, 0];
+        nm.extend_from_slice(b"notes.tar.gz");
+        nm[2] = nm.len() as u8;
+        root.extend(directory_record(21, 5, 0, b"NOTES_TA.GZ;1", &nm));
+        put(&mut image, 20, &root);
+        put(&mut image, 21, b"12345");
+
+        let mut reader = Cursor::new(image);
+        let volume = Iso9660Volume::open(&mut reader).unwrap().unwrap();
+        assert_eq!(volume.description(), "ISO 9660 + Rock Ridge");
+        assert_eq!(volume.label(), "LINUX");
+
+        let files = volume.files(&mut reader).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "/notes.tar.gz");
+    }
+
+    #[test]
+    fn test_not_iso9660() {
+        let mut reader = Cursor::new(vec![0u8; 40 * SECTOR_SIZE as usize]);
+        assert!(Iso9660Volume::open(&mut reader).unwrap().is_none());
+
+        let mut reader = Cursor::new(vec![0u8; 512]);
+        assert!(Iso9660Volume::open(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_decode_file_identifier() {
+        assert_eq!(decode_file_identifier(b"README.TXT;1", false), "README.TXT");
+        assert_eq!(decode_file_identifier(b"MAKEFILE.;1", false), "MAKEFILE");
+        assert_eq!(decode_file_identifier(b"DIR", false), "DIR");
+    }
+}
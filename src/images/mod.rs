@@ -0,0 +1,960 @@
+//! This module provides read-only access to disk and optical images.
+//!
+//! Every supported image format is exposed through `VirtualDisk`, a seekable
+//! reader over the media stored in the image, so partition and file system
+//! parsing work the same way regardless of the container format.
+
+mod deleted;
+mod ewf;
+mod fat;
+mod iso9660;
+mod ntfs;
+mod optical;
+mod qcow2;
+mod split;
+mod udf;
+mod vhd;
+mod vhdx;
+mod vmdk;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::file_system_detection::{detect_file_system, DetectedFileSystem};
+use crate::partition_table::read_partition_table;
+use crate::{DataRun, Disk, DiskExtent, DiskKind, ExtentKind, Partition};
+
+pub use deleted::get_deleted_files;
+pub(crate) use deleted::free_clusters;
+pub use ewf::{get_ewf_metadata, verify_ewf_image, EwfMetadata, EwfVerification};
+pub(crate) use optical::OpticalVolume;
+pub use optical::get_optical_files;
+
+/// Maximum number of differencing disks followed when resolving a parent chain
+const MAX_PARENT_DEPTH: usize = 16;
+
+/// Largest buffer allocated ahead of the data actually read by `read_bytes`
+const READ_AHEAD_LIMIT: usize = 1024 * 1024;
+
+/// Error type for image reading operations
+#[derive(Debug)]
+pub enum ImageError {
+    /// IO errors from reading the image file
+    Io(io::Error),
+    /// The image format or file system is not supported
+    UnsupportedFormat(String),
+    /// The image structures are damaged or inconsistent
+    InvalidImage(String),
+    /// The parent of a differencing disk could not be found
+    MissingParent(String),
+    /// A segment of a multi-file image could not be found
+    MissingSegment(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO error: {}", e),
+            Self::UnsupportedFormat(s) => write!(f, "Unsupported image format: {}", s),
+            Self::InvalidImage(s) => write!(f, "Invalid image: {}", s),
+            Self::MissingParent(s) => write!(f, "Parent disk not found: {}", s),
+            Self::MissingSegment(s) => write!(f, "Image segment not found: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ImageError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Container formats understood by `VirtualDisk`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+pub enum ImageFormat {
+    /// Plain sector-by-sector copy of the media (dd, .img, .iso)
+    Raw,
+    /// Microsoft Virtual PC / Hyper-V virtual hard disk (fixed, dynamic or differencing)
+    Vhd,
+    /// Hyper-V virtual hard disk v2
+    Vhdx,
+    /// QEMU copy-on-write image, version 2 or 3
+    Qcow2,
+    /// VMware virtual disk (sparse, stream-optimized or flat descriptor)
+    Vmdk,
+    /// Expert Witness Format evidence image (E01), possibly split in segments
+    Ewf,
+    /// Raw image split in numbered (.001, .002) or lettered (.aa, .ab) segments
+    SplitRaw,
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageFormat::Raw => write!(f, "Raw image"),
+            ImageFormat::Vhd => write!(f, "VHD image"),
+            ImageFormat::Vhdx => write!(f, "VHDX image"),
+            ImageFormat::Qcow2 => write!(f, "QCOW2 image"),
+            ImageFormat::Vmdk => write!(f, "VMDK image"),
+            ImageFormat::Ewf => write!(f, "EWF image"),
+            ImageFormat::SplitRaw => write!(f, "Split raw image"),
+        }
+    }
+}
+
+/// Random-access source of media bytes.
+///
+/// Each image format implements this trait and is wrapped in a `VirtualDisk`,
+/// which takes care of tracking the stream position.
+pub(crate) trait ReadAt: Send {
+    /// Returns the size of the virtual media in bytes.
+    fn size(&self) -> u64;
+
+    /// Reads bytes starting at `offset` of the virtual media.
+    ///
+    /// Returns the number of bytes read, which is 0 at the end of the media.
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// Returns the unique identifier stored in the image, if any.
+    fn identifier(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Image made of a single file holding the media bytes as-is.
+struct RawImage {
+    file: File,
+    size: u64,
+}
+
+impl RawImage {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+        Ok(RawImage { file, size })
+    }
+}
+
+impl ReadAt for RawImage {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if offset >= self.size {
+            return Ok(0);
+        }
+        let len = buf.len().min((self.size - offset) as usize);
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read(&mut buf[..len])
+    }
+}
+
+/// Detects the container format of an image file from its signatures.
+fn detect_format(path: &Path) -> io::Result<ImageFormat> {
+    let mut file = File::open(path)?;
+    let size = file.seek(SeekFrom::End(0))?;
+
+    let header = read_file_at(&mut file, 0, size.min(64) as usize)?;
+    if header.starts_with(b"vhdxfile") {
+        return Ok(ImageFormat::Vhdx);
+    }
+    if header.starts_with(qcow2::QCOW2_MAGIC) {
+        return Ok(ImageFormat::Qcow2);
+    }
+    if vmdk::is_vmdk(&header) {
+        return Ok(ImageFormat::Vmdk);
+    }
+    if ewf::is_ewf(&header) {
+        return Ok(ImageFormat::Ewf);
+    }
+
+    if size >= 512 {
+        let footer = read_file_at(&mut file, size - 512, 512)?;
+        if vhd::is_footer(&footer) {
+            return Ok(ImageFormat::Vhd);
+        }
+    }
+
+    // Split raw images have no signature and are recognized by their name
+    if split::is_first_segment(path) {
+        return Ok(ImageFormat::SplitRaw);
+    }
+
+    Ok(ImageFormat::Raw)
+}
+
+/// Opens the format-specific reader of an image file.
+///
+/// `depth` counts the differencing disks already opened above this one.
+fn open_source(path: &Path, depth: usize) -> Result<(ImageFormat, Box<dyn ReadAt>), ImageError> {
+    let format = detect_format(path)?;
+    // A later segment read alone would pass off part of the media as a whole disk
+    if let (ImageFormat::Raw, Some(first)) = (format, split::first_segment_of(path)) {
+        if first.is_file() {
+            return Err(ImageError::InvalidImage(format!(
+                "{} is not the first segment of a split image, open {} instead",
+                path.display(),
+                first.display()
+            )));
+        }
+        return Err(ImageError::MissingSegment(first.display().to_string()));
+    }
+    let source: Box<dyn ReadAt> = match format {
+        ImageFormat::Raw => Box::new(RawImage::open(path)?),
+        ImageFormat::Vhd => Box::new(vhd::VhdImage::open(path, depth)?),
+        ImageFormat::Vhdx => Box::new(vhdx::VhdxImage::open(path, depth)?),
+        ImageFormat::Qcow2 => Box::new(qcow2::Qcow2Image::open(path, depth)?),
+        ImageFormat::Vmdk => Box::new(vmdk::VmdkImage::open(path, depth)?),
+        ImageFormat::Ewf => Box::new(ewf::EwfImage::open(path)?),
+        ImageFormat::SplitRaw => Box::new(split::SplitRawImage::open(path)?),
+    };
+    Ok((format, source))
+}
+
+/// Resolves the locations recorded for the parent of a differencing disk.
+///
+/// Each candidate is tried as-is, then relative to the directory of the
+/// child image, and finally by file name next to the child image, which
+/// covers image sets copied from another machine.
+fn resolve_parent(child: &Path, candidate: &str) -> Option<PathBuf> {
+    let directory = child.parent().unwrap_or(Path::new(""));
+
+    let direct = PathBuf::from(candidate);
+    if direct.is_absolute() && direct.is_file() {
+        return Some(direct);
+    }
+
+    let parts: Vec<&str> = candidate
+        .split(['\\', '/'])
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect();
+    // Skip drive letters and volume prefixes, which only make sense on the source machine
+    let relative = parts.iter().skip_while(|part| part.ends_with(':') || part.starts_with('?'));
+    let mut joined = directory.to_path_buf();
+    joined.extend(relative);
+    if joined.is_file() {
+        return Some(joined);
+    }
+
+    let by_name = directory.join(parts.last()?);
+    by_name.is_file().then_some(by_name)
+}
+
+/// Opens the parent of the differencing disk at `child`.
+///
+/// # Arguments
+///
+/// * `child` - Path of the differencing disk
+/// * `candidates` - Parent locations recorded in the child, most reliable first
+/// * `depth` - Number of differencing disks already opened above the child
+pub(crate) fn open_parent(child: &Path, candidates: &[String], depth: usize) -> Result<Box<dyn ReadAt>, ImageError> {
+    if depth >= MAX_PARENT_DEPTH {
+        return Err(ImageError::InvalidImage(format!(
+            "parent chain of {} is deeper than {} disks",
+            child.display(),
+            MAX_PARENT_DEPTH
+        )));
+    }
+
+    let parent = candidates
+        .iter()
+        .find_map(|candidate| resolve_parent(child, candidate))
+        .ok_or_else(|| {
+            ImageError::MissingParent(format!("{} (recorded as {})", child.display(), candidates.join(", ")))
+        })?;
+
+    open_source(&parent, depth + 1).map(|(_, source)| source)
+}
+
+/// Reads exactly `len` bytes at `offset` of a file.
+///
+/// Reads past the end of a regular file fail before anything is allocated,
+/// so that lengths taken from corrupt headers cannot exhaust memory.
+pub(crate) fn read_file_at(file: &mut File, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    let metadata = file.metadata()?;
+    if metadata.is_file() && offset.checked_add(len as u64).is_none_or(|end| end > metadata.len()) {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "read past the end of the file"));
+    }
+    read_bytes(file, offset, len)
+}
+
+/// Fills `buf` from `offset` of a source, reading zeros past its end.
+pub(crate) fn read_source_at(source: &mut dyn ReadAt, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let read = source.read_at(offset + filled as u64, &mut buf[filled..])?;
+        if read == 0 {
+            buf[filled..].fill(0);
+            break;
+        }
+        filled += read;
+    }
+    Ok(())
+}
+
+/// Formats a 16-byte GUID stored in the mixed-endian Microsoft layout.
+pub(crate) fn format_guid(bytes: &[u8]) -> String {
+    format!(
+        "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
+        le_u32(bytes, 0),
+        le_u16(bytes, 4),
+        le_u16(bytes, 6),
+        bytes[8],
+        bytes[9],
+        bytes[10],
+        bytes[11],
+        bytes[12],
+        bytes[13],
+        bytes[14],
+        bytes[15]
+    )
+}
+
+/// A read-only virtual device backed by an image file.
+///
+/// `VirtualDisk` implements `Read` and `Seek` over the media contained in
+/// the image, hiding the layout of the container it is stored in.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io::Read;
+/// use win_disk_info::VirtualDisk;
+///
+/// let mut device = VirtualDisk::open("evidence.iso").unwrap();
+/// let mut boot_sector = [0u8; 512];
+/// device.read_exact(&mut boot_sector).unwrap();
+/// println!("{} bytes of {}", device.size(), device.format());
+/// ```
+pub struct VirtualDisk {
+    /// Path of the image file
+    path: PathBuf,
+    /// Container format of the image
+    format: ImageFormat,
+    /// Format-specific reader
+    source: Box<dyn ReadAt>,
+    /// Current stream position
+    position: u64,
+}
+
+impl VirtualDisk {
+    /// Opens an image file, detecting its container format.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the image file
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(VirtualDisk)` - A reader over the media stored in the image
+    /// * `Err(ImageError)` - If the image cannot be opened or parsed, or if `path`
+    ///   names a segment of a split image other than the first
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ImageError> {
+        let path = path.as_ref();
+        let (format, source) = open_source(path, 0)?;
+        Ok(VirtualDisk::new(path.to_path_buf(), format, source))
+    }
+
+    fn new(path: PathBuf, format: ImageFormat, source: Box<dyn ReadAt>) -> Self {
+        VirtualDisk {
+            path,
+            format,
+            source,
+            position: 0,
+        }
+    }
+
+    /// Returns the path of the image file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the container format of the image.
+    pub fn format(&self) -> ImageFormat {
+        self.format
+    }
+
+    /// Returns the size of the virtual media in bytes.
+    pub fn size(&self) -> u64 {
+        self.source.size()
+    }
+
+    /// Returns the unique identifier stored in the image, such as the
+    /// VHD or VHDX disk GUID, if the format records one.
+    pub fn identifier(&self) -> Option<String> {
+        self.source.identifier()
+    }
+}
+
+impl fmt::Debug for VirtualDisk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VirtualDisk")
+            .field("path", &self.path)
+            .field("format", &self.format)
+            .field("size", &self.size())
+            .field("position", &self.position)
+            .finish()
+    }
+}
+
+impl Read for VirtualDisk {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.source.read_at(self.position, buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for VirtualDisk {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.size().checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        match target {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+/// Reader over the content described by a list of data runs.
+///
+/// Extents are read from the underlying device, sparse runs read back as
+/// zeros and resident runs are returned from memory.
+pub(crate) struct RunReader<R> {
+    inner: R,
+    runs: Vec<DataRun>,
+    /// Index of the run currently being read
+    index: usize,
+    /// Offset inside the current run
+    offset: u64,
+}
+
+impl<R: Read + Seek> RunReader<R> {
+    /// Creates a reader over `runs` stored on `inner`.
+    pub(crate) fn new(inner: R, runs: Vec<DataRun>) -> Self {
+        RunReader {
+            inner,
+            runs,
+            index: 0,
+            offset: 0,
+        }
+    }
+
+    /// Moves the reader to `position` bytes from the start of the runs.
+    pub(crate) fn set_position(&mut self, position: u64) {
+        self.index = 0;
+        self.offset = position;
+        while let Some(run) = self.runs.get(self.index) {
+            if self.offset < run.len() {
+                break;
+            }
+            self.offset -= run.len();
+            self.index += 1;
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for RunReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some(run) = self.runs.get(self.index) {
+            let remaining = run.len() - self.offset;
+            if remaining == 0 {
+                self.index += 1;
+                self.offset = 0;
+                continue;
+            }
+            let len = buf.len().min(remaining.min(usize::MAX as u64) as usize);
+            let read = match run {
+                DataRun::Extent { offset, .. } => {
+                    self.inner.seek(SeekFrom::Start(offset + self.offset))?;
+                    let read = self.inner.read(&mut buf[..len])?;
+                    if read == 0 && len > 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "data run extends past the end of the image",
+                        ));
+                    }
+                    read
+                }
+                DataRun::Sparse { .. } => {
+                    buf[..len].fill(0);
+                    len
+                }
+                DataRun::Resident(data) => {
+                    let start = self.offset as usize;
+                    buf[..len].copy_from_slice(&data[start..start + len]);
+                    len
+                }
+            };
+            self.offset += read as u64;
+            return Ok(read);
+        }
+        Ok(0)
+    }
+}
+
+/// Window over a range of a seekable reader, such as a partition of a disk.
+///
+/// Offsets are relative to the start of the range and reads stop at its end.
+pub(crate) struct DeviceSlice<R> {
+    inner: R,
+    start: u64,
+    length: u64,
+    position: u64,
+}
+
+impl<R: Read + Seek> DeviceSlice<R> {
+    /// Creates a window over `length` bytes of `inner` starting at `start`.
+    pub(crate) fn new(inner: R, start: u64, length: u64) -> Self {
+        DeviceSlice {
+            inner,
+            start,
+            length,
+            position: 0,
+        }
+    }
+
+    /// Returns the number of bytes in the window.
+    pub(crate) fn length(&self) -> u64 {
+        self.length
+    }
+}
+
+impl<R: Read + Seek> Read for DeviceSlice<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.length {
+            return Ok(0);
+        }
+        let len = buf.len().min((self.length - self.position).min(usize::MAX as u64) as usize);
+        self.inner.seek(SeekFrom::Start(self.start + self.position))?;
+        let read = self.inner.read(&mut buf[..len])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek> Seek for DeviceSlice<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.length.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        match target {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+/// An image opened once and shared by the entries listed from it.
+///
+/// Opening some containers means parsing all of their metadata, such as the
+/// chunk tables of every EWF segment, so the entries found in an image read
+/// their content through a single device, opened on first use, rather than
+/// opening the image again for every file.
+#[derive(Clone)]
+pub(crate) struct SharedImage {
+    path: PathBuf,
+    device: Arc<Mutex<Option<VirtualDisk>>>,
+}
+
+impl SharedImage {
+    /// Creates a handle that opens the image at `path` when it is first read.
+    pub(crate) fn new(path: PathBuf) -> Self {
+        SharedImage {
+            path,
+            device: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Creates a handle over a device that is already open.
+    pub(crate) fn from_device(device: VirtualDisk) -> Self {
+        SharedImage {
+            path: device.path().to_path_buf(),
+            device: Arc::new(Mutex::new(Some(device))),
+        }
+    }
+
+    /// Returns the path of the image file.
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns a reader over the media of the image, with its own position.
+    pub(crate) fn reader(&self) -> SharedImageReader {
+        SharedImageReader {
+            image: self.clone(),
+            position: 0,
+        }
+    }
+
+    /// Runs `read` on the device, opening the image first if needed.
+    fn with_device<T>(&self, read: impl FnOnce(&mut VirtualDisk) -> io::Result<T>) -> io::Result<T> {
+        let mut device = self.device.lock().unwrap_or_else(|e| e.into_inner());
+        if device.is_none() {
+            *device = Some(VirtualDisk::open(&self.path).map_err(io::Error::other)?);
+        }
+        read(device.as_mut().expect("opened image"))
+    }
+}
+
+impl fmt::Debug for SharedImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedImage").field("path", &self.path).finish()
+    }
+}
+
+/// Seekable reader over a `SharedImage`.
+pub(crate) struct SharedImageReader {
+    image: SharedImage,
+    position: u64,
+}
+
+impl Read for SharedImageReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let position = self.position;
+        let read = self.image.with_device(|device| {
+            device.seek(SeekFrom::Start(position))?;
+            device.read(buf)
+        })?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for SharedImageReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.image.with_device(|device| Ok(device.size()))?.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        self.position = target.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position")
+        })?;
+        Ok(self.position)
+    }
+}
+
+/// Reads exactly `len` bytes at `offset` of a seekable reader.
+///
+/// The buffer grows with the data read rather than being allocated up
+/// front, so a length taken from a corrupt header fails at the end of the
+/// source instead of exhausting memory.
+pub(crate) fn read_bytes<R: Read + Seek>(reader: &mut R, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(len.min(READ_AHEAD_LIMIT));
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() < len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "failed to fill whole buffer"));
+    }
+    Ok(buf)
+}
+
+/// Reads the full content described by `runs` into memory.
+pub(crate) fn read_runs<R: Read + Seek>(reader: &mut R, runs: &[DataRun]) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    RunReader::new(reader, runs.to_vec()).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Reads a little-endian `u16` at `offset` of `buf`.
+pub(crate) fn le_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+/// Reads a little-endian `u32` at `offset` of `buf`.
+pub(crate) fn le_u32(buf: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&buf[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+/// Reads a little-endian `u64` at `offset` of `buf`.
+pub(crate) fn le_u64(buf: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&buf[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+/// Reads a big-endian `u16` at `offset` of `buf`.
+pub(crate) fn be_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([buf[offset], buf[offset + 1]])
+}
+
+/// Reads a big-endian `u32` at `offset` of `buf`.
+pub(crate) fn be_u32(buf: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&buf[offset..offset + 4]);
+    u32::from_be_bytes(bytes)
+}
+
+/// Reads a big-endian `u64` at `offset` of `buf`.
+pub(crate) fn be_u64(buf: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&buf[offset..offset + 8]);
+    u64::from_be_bytes(bytes)
+}
+
+/// Builds a `Disk` describing the media stored in an image file
+///
+/// The image is opened as a `VirtualDisk` and its MBR or GPT partition table
+/// is read. Each partition is probed for a known file system, which provides
+/// its label and capacity. Media without a partition table, such as optical
+/// images or formatted floppies, are reported as a single partition covering
+/// the whole device when a file system is found on it.
+///
+/// The layout of the disk, including partitioning metadata and unallocated
+/// gaps, is available through `Disk::extents`.
+///
+/// # Arguments
+/// * `path` - A string path to the image file
+///
+/// # Returns
+/// * `Ok(Disk)` - The disk stored in the image with its partitions
+/// * `Err(ImageError)` - If the image cannot be read
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::get_image_disk;
+///
+/// let disk = get_image_disk("D:/cases/2024-17/workstation.vhdx").unwrap();
+/// println!("{}", disk);
+/// for partition in disk.partitions() {
+///     println!("{} at offset {:?}", partition.name(), partition.offset());
+/// }
+/// ```
+pub fn get_image_disk(path: &str) -> Result<Disk, ImageError> {
+    let mut device = VirtualDisk::open(path)?;
+    let size = device.size();
+    let mount_path = PathBuf::from(path);
+
+    let mut kind = DiskKind::Virtual;
+    let mut model = device.format().to_string();
+    let mut partitions = Vec::new();
+    let mut extents = Vec::new();
+
+    match read_partition_table(&mut device, size)? {
+        Some(table) => {
+            extents = table.extents(size);
+            for (id, entry) in table.entries.iter().enumerate() {
+                let volume = detect_file_system(&mut device, entry.offset, entry.length)?;
+                let name = if !volume.label.is_empty() {
+                    volume.label.clone()
+                } else if !entry.name.is_empty() {
+                    entry.name.clone()
+                } else {
+                    format!("Partition {}", id + 1)
+                };
+                let total_space = match volume.file_system {
+                    DetectedFileSystem::Unknown => entry.length,
+                    _ => volume.total_space,
+                };
+                partitions.push(
+                    Partition::new(
+                        id,
+                        name,
+                        volume.to_file_system(mount_path.clone()),
+                        total_space,
+                        volume.available_space,
+                    )
+                    .with_offset(entry.offset),
+                );
+            }
+        }
+        None => {
+            let volume = detect_file_system(&mut device, 0, size)?;
+            if matches!(volume.file_system, DetectedFileSystem::Iso9660 | DetectedFileSystem::Udf) {
+                kind = DiskKind::Optical;
+                if let Some(optical) = OpticalVolume::open(&mut device)? {
+                    model = format!("{} ({})", model, optical.description());
+                }
+            }
+            if volume.file_system != DetectedFileSystem::Unknown {
+                extents.push(DiskExtent::new(0, size, ExtentKind::Partition(Some(0))));
+                partitions.push(
+                    Partition::new(
+                        0,
+                        volume.label.clone(),
+                        volume.to_file_system(mount_path.clone()),
+                        volume.total_space,
+                        volume.available_space,
+                    )
+                    .with_offset(0),
+                );
+            }
+        }
+    }
+
+    Ok(Disk::new(
+        path.to_string(),
+        model,
+        device.identifier().unwrap_or_default(),
+        kind,
+        size as usize,
+        false,
+        partitions,
+    )
+    .with_extents(extents))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    pub(crate) use super::fat::tests::build_fat32_volume;
+
+    /// Builds a 2 MiB raw disk with an MBR and a FAT32 partition labeled
+    /// EVIDENCE spanning its second half.
+    pub(crate) fn build_fat32_disk() -> Vec<u8> {
+        let mut image = vec![0u8; 4096 * 512];
+        crate::partition_table::tests::mbr_entry(&mut image[..512], 0, 0x0C, 2048, 2048);
+        let boot = &mut image[2048 * 512..2049 * 512];
+        boot[11..13].copy_from_slice(&512u16.to_le_bytes());
+        boot[13] = 1;
+        boot[32..36].copy_from_slice(&2048u32.to_le_bytes());
+        boot[71..82].copy_from_slice(b"EVIDENCE   ");
+        boot[82..90].copy_from_slice(b"FAT32   ");
+        image
+    }
+
+    #[test]
+    fn test_run_reader() {
+        let device = Cursor::new((0u8..100).collect::<Vec<u8>>());
+        let runs = vec![
+            DataRun::Extent { offset: 10, length: 3 },
+            DataRun::Sparse { length: 2 },
+            DataRun::Resident(vec![0xAA, 0xBB]),
+            DataRun::Extent { offset: 50, length: 1 },
+        ];
+
+        let mut data = Vec::new();
+        RunReader::new(device, runs).read_to_end(&mut data).unwrap();
+
+        assert_eq!(data, vec![10, 11, 12, 0, 0, 0xAA, 0xBB, 50]);
+    }
+
+    #[test]
+    fn test_run_reader_set_position() {
+        let device = Cursor::new((0u8..100).collect::<Vec<u8>>());
+        let runs = vec![
+            DataRun::Extent { offset: 10, length: 3 },
+            DataRun::Sparse { length: 2 },
+            DataRun::Resident(vec![0xAA, 0xBB]),
+        ];
+        let mut reader = RunReader::new(device, runs);
+
+        let mut data = Vec::new();
+        reader.set_position(4);
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, vec![0, 0xAA, 0xBB]);
+
+        data.clear();
+        reader.set_position(3);
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, vec![0, 0, 0xAA, 0xBB]);
+
+        data.clear();
+        reader.set_position(10);
+        reader.read_to_end(&mut data).unwrap();
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn test_run_reader_past_end() {
+        let device = Cursor::new(vec![0u8; 16]);
+        let runs = vec![DataRun::Extent { offset: 8, length: 16 }];
+
+        let mut data = Vec::new();
+        let result = RunReader::new(device, runs).read_to_end(&mut data);
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_virtual_disk_raw() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("disk.img");
+        std::fs::write(&path, (0u8..=255).collect::<Vec<u8>>()).unwrap();
+
+        let mut device = VirtualDisk::open(&path).unwrap();
+        assert_eq!(device.format(), ImageFormat::Raw);
+        assert_eq!(device.size(), 256);
+
+        device.seek(SeekFrom::End(-4)).unwrap();
+        let mut tail = Vec::new();
+        device.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, vec![252, 253, 254, 255]);
+    }
+
+    #[test]
+    fn test_get_image_disk_mbr() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("usb.img");
+        std::fs::write(&path, build_fat32_disk()).unwrap();
+        let path = path.to_str().unwrap();
+
+        let disk = get_image_disk(path).unwrap();
+        assert_eq!(disk.kind(), &DiskKind::Virtual);
+        assert_eq!(disk.partitions().len(), 1);
+        let partition = &disk.partitions()[0];
+        assert_eq!(partition.name(), "EVIDENCE");
+        assert_eq!(partition.file_system(), &crate::FileSystem::FAT32(PathBuf::from(path)));
+        assert_eq!(partition.offset(), Some(2048 * 512));
+        assert_eq!(partition.total_space(), 2048 * 512);
+
+        let kinds: Vec<&ExtentKind> = disk.extents().iter().map(DiskExtent::kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &ExtentKind::Metadata("MBR".to_string()),
+                &ExtentKind::Unallocated,
+                &ExtentKind::Partition(Some(0)),
+            ]
+        );
+        assert_eq!(disk.unallocated_space(), Some(2047 * 512));
+        assert!(disk.to_string().contains("Unallocated space: 1023.50 KiB"));
+    }
+
+    #[test]
+    fn test_get_image_disk_blank() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("blank.img");
+        std::fs::write(&path, vec![0u8; 64 * 1024]).unwrap();
+
+        let disk = get_image_disk(path.to_str().unwrap()).unwrap();
+        assert!(disk.partitions().is_empty());
+        assert_eq!(disk.extents(), &[DiskExtent::new(0, 64 * 1024, ExtentKind::Unallocated)]);
+    }
+}
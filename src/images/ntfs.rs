@@ -0,0 +1,586 @@
+//! NTFS metadata reader used to recover deleted files.
+//!
+//! Deleting a file on NTFS clears the in-use flag of its MFT record and the
+//! bits of its clusters in `$Bitmap`, but leaves the record itself, with its
+//! names, timestamps and data runs, in place until the record is reused.
+
+use std::io::{self, Read, Seek};
+
+
+use super::deleted::{free_runs, DeletedFile};
+use super::{le_u16, le_u32, le_u64, read_bytes, read_runs, DeviceSlice, ImageError, RunReader};
+use crate::{filetime_to_utc, DataRun, FileAttributes, FileTimes, Recoverability};
+
+/// MFT record of the root directory
+const ROOT_RECORD: u64 = 5;
+/// MFT record of `$Bitmap`, the cluster allocation bitmap
+const BITMAP_RECORD: u64 = 6;
+/// Stride of the update sequence array, independent of the sector size
+const FIXUP_STRIDE: usize = 512;
+const ATTRIBUTE_STANDARD_INFORMATION: u32 = 0x10;
+const ATTRIBUTE_FILE_NAME: u32 = 0x30;
+const ATTRIBUTE_DATA: u32 = 0x80;
+const ATTRIBUTE_END: u32 = 0xFFFF_FFFF;
+const RECORD_IN_USE: u16 = 0x01;
+const RECORD_DIRECTORY: u16 = 0x02;
+/// `$FILE_NAME` namespace holding only the 8.3 name
+const NAMESPACE_DOS: u8 = 2;
+/// Deepest directory nesting followed when resolving paths
+const MAX_PATH_DEPTH: usize = 256;
+/// Directory holding deleted files whose parent directory is gone
+const ORPHAN_DIRECTORY: &str = "$OrphanFiles";
+
+/// Name of a record and the reference to its parent directory.
+struct FileName {
+    name: String,
+    parent: u64,
+    parent_sequence: u16,
+    /// Size recorded in the name attribute, used when `$DATA` is not in the base record
+    size: u64,
+}
+
+/// Unnamed `$DATA` attribute of a record.
+struct Data {
+    /// Runs of clusters, `None` marking sparse runs, as `(lcn, clusters)`
+    clusters: Vec<(Option<u64>, u64)>,
+    /// Content of a resident attribute
+    resident: Option<Vec<u8>>,
+    size: u64,
+}
+
+/// The parts of an MFT record needed to recover a file.
+struct Record {
+    flags: u16,
+    sequence: u16,
+    name: Option<FileName>,
+    times: FileTimes,
+    attributes: FileAttributes,
+    data: Option<Data>,
+}
+
+/// Replaces the last two bytes of each sector of a record with the values
+/// saved in its update sequence array.
+///
+/// Returns false if a sector does not end with the update sequence number,
+/// meaning the record was torn or is not a record.
+fn apply_fixups(record: &mut [u8]) -> bool {
+    let offset = le_u16(record, 4) as usize;
+    let count = le_u16(record, 6) as usize;
+    if count == 0 || offset + count * 2 > record.len() || (count - 1) * FIXUP_STRIDE > record.len() {
+        return false;
+    }
+    let number = [record[offset], record[offset + 1]];
+    for index in 1..count {
+        let end = index * FIXUP_STRIDE;
+        if record[end - 2..end] != number {
+            return false;
+        }
+        let saved = offset + index * 2;
+        record.copy_within(saved..saved + 2, end - 2);
+    }
+    true
+}
+
+/// Decodes a runlist into `(lcn, clusters)` pairs, `None` marking sparse runs.
+fn parse_runlist(list: &[u8]) -> Option<Vec<(Option<u64>, u64)>> {
+    let mut runs = Vec::new();
+    let mut position = 0;
+    let mut lcn: i64 = 0;
+    while let Some(&header) = list.get(position) {
+        if header == 0 {
+            break;
+        }
+        let length_size = (header & 0x0F) as usize;
+        let offset_size = (header >> 4) as usize;
+        let fields = list.get(position + 1..position + 1 + length_size + offset_size)?;
+        if length_size == 0 || length_size > 8 || offset_size > 8 {
+            return None;
+        }
+
+        let mut clusters = [0u8; 8];
+        clusters[..length_size].copy_from_slice(&fields[..length_size]);
+        let clusters = u64::from_le_bytes(clusters);
+
+        if offset_size == 0 {
+            runs.push((None, clusters));
+        } else {
+            // The offset is signed and relative to the previous run
+            let sign = if fields[length_size + offset_size - 1] & 0x80 != 0 { 0xFF } else { 0 };
+            let mut delta = [sign; 8];
+            delta[..offset_size].copy_from_slice(&fields[length_size..]);
+            lcn = lcn.checked_add(i64::from_le_bytes(delta))?;
+            runs.push((Some(u64::try_from(lcn).ok()?), clusters));
+        }
+        position += 1 + length_size + offset_size;
+    }
+    Some(runs)
+}
+
+/// Parses the attributes of a record that passed its fixups.
+fn parse_record(record: &[u8]) -> Option<Record> {
+    if &record[0..4] != b"FILE" {
+        return None;
+    }
+    let mut parsed = Record {
+        flags: le_u16(record, 22),
+        sequence: le_u16(record, 16),
+        name: None,
+        times: FileTimes::new(),
+        attributes: FileAttributes::default(),
+        data: None,
+    };
+    // Extension records hold overflow attributes of another record
+    if le_u64(record, 32) & 0xFFFF_FFFF_FFFF != 0 {
+        return None;
+    }
+    let mut name_namespace = None;
+
+    let mut position = le_u16(record, 20) as usize;
+    while position + 16 <= record.len() {
+        let kind = le_u32(record, position);
+        let length = le_u32(record, position + 4) as usize;
+        if kind == ATTRIBUTE_END || length < 16 || position + length > record.len() {
+            break;
+        }
+        let attribute = &record[position..position + length];
+        position += length;
+
+        let non_resident = attribute[8] != 0;
+        let named = attribute[9] != 0;
+        let content = if non_resident || length < 24 {
+            None
+        } else {
+            let offset = le_u16(attribute, 20) as usize;
+            attribute.get(offset..offset + le_u32(attribute, 16) as usize)
+        };
+
+        match kind {
+            ATTRIBUTE_STANDARD_INFORMATION => {
+                if let Some(content) = content.filter(|content| content.len() >= 36) {
+                    parsed.times = FileTimes::new()
+                        .with_born(filetime_to_utc(le_u64(content, 0)))
+                        .with_modified(filetime_to_utc(le_u64(content, 8)))
+                        .with_changed(filetime_to_utc(le_u64(content, 16)))
+                        .with_accessed(filetime_to_utc(le_u64(content, 24)));
+                    parsed.attributes = FileAttributes::from_bits(le_u32(content, 32));
+                }
+            }
+            ATTRIBUTE_FILE_NAME => {
+                let Some(content) = content.filter(|content| content.len() >= 66) else {
+                    continue;
+                };
+                let namespace = content[65];
+                // Prefer the long name over the 8.3 alias
+                if name_namespace.is_some_and(|current| current != NAMESPACE_DOS || namespace == NAMESPACE_DOS) {
+                    continue;
+                }
+                let Some(units) = content.get(66..66 + content[64] as usize * 2) else {
+                    continue;
+                };
+                let units: Vec<u16> = units.chunks_exact(2).map(|unit| le_u16(unit, 0)).collect();
+                let reference = le_u64(content, 0);
+                parsed.name = Some(FileName {
+                    name: String::from_utf16_lossy(&units),
+                    parent: reference & 0xFFFF_FFFF_FFFF,
+                    parent_sequence: (reference >> 48) as u16,
+                    size: le_u64(content, 48),
+                });
+                name_namespace = Some(namespace);
+            }
+            ATTRIBUTE_DATA if !named => {
+                if non_resident {
+                    // Only the first piece of a fragmented attribute carries the sizes
+                    if attribute.len() < 64 || le_u64(attribute, 16) != 0 {
+                        continue;
+                    }
+                    let list = attribute.get(le_u16(attribute, 32) as usize..)?;
+                    parsed.data = parse_runlist(list).map(|clusters| Data {
+                        clusters,
+                        resident: None,
+                        size: le_u64(attribute, 48),
+                    });
+                } else if let Some(content) = content {
+                    parsed.data = Some(Data {
+                        clusters: Vec::new(),
+                        resident: Some(content.to_vec()),
+                        size: content.len() as u64,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(parsed)
+}
+
+/// An NTFS volume opened for deleted file recovery.
+struct NtfsVolume {
+    cluster_size: u64,
+    record_size: usize,
+    /// Runs of the `$MFT` data, relative to the volume
+    mft: Vec<DataRun>,
+}
+
+impl NtfsVolume {
+    /// Reads the boot sector and the first MFT record of the volume.
+    fn open<R: Read + Seek>(volume: &mut DeviceSlice<R>) -> Result<Self, ImageError> {
+        let boot = read_bytes(volume, 0, 512)?;
+        let sector_size = le_u16(&boot, 11) as u64;
+        let cluster_size = match boot[13] {
+            0 => 0,
+            sectors if sectors > 0x80 => sector_size.checked_shl(256 - sectors as u32).unwrap_or(0),
+            sectors => sectors as u64 * sector_size,
+        };
+        let record_size = match boot[64] as i8 {
+            size if size < 0 => 1u64.checked_shl(-(size as i32) as u32).unwrap_or(0),
+            clusters => clusters as u64 * cluster_size,
+        };
+        if cluster_size == 0 || !(1024..=65536).contains(&record_size) {
+            return Err(ImageError::InvalidImage("invalid NTFS boot sector".to_string()));
+        }
+
+        let mft_offset = le_u64(&boot, 48).saturating_mul(cluster_size);
+        let mut first = read_bytes(volume, mft_offset, record_size as usize)?;
+        if !apply_fixups(&mut first) {
+            return Err(ImageError::InvalidImage("torn $MFT record".to_string()));
+        }
+        let mft = parse_record(&first)
+            .and_then(|record| record.data)
+            .ok_or_else(|| ImageError::InvalidImage("$MFT record without data".to_string()))?;
+
+        let mut volume = NtfsVolume {
+            cluster_size,
+            record_size: record_size as usize,
+            mft: Vec::new(),
+        };
+        volume.mft = volume.runs(&mft, 0);
+        Ok(volume)
+    }
+
+    /// Converts the clusters of a `$DATA` attribute to data runs. Extents
+    /// are relative to the device when `base` is the volume offset, or to
+    /// the volume when it is 0.
+    fn runs(&self, data: &Data, base: u64) -> Vec<DataRun> {
+        if let Some(content) = &data.resident {
+            return vec![DataRun::Resident(content.clone())];
+        }
+        data.clusters
+            .iter()
+            .map(|&(lcn, clusters)| match lcn {
+                Some(lcn) => DataRun::Extent {
+                    offset: base + lcn * self.cluster_size,
+                    length: clusters * self.cluster_size,
+                },
+                None => DataRun::Sparse {
+                    length: clusters * self.cluster_size,
+                },
+            })
+            .collect()
+    }
+
+    /// Reads record `number` of the MFT, or `None` if it is unreadable.
+    fn record<R: Read + Seek>(&self, volume: &mut DeviceSlice<R>, number: u64) -> Result<Option<Record>, ImageError> {
+        let mut reader = RunReader::new(volume, self.mft.clone());
+        io::copy(&mut (&mut reader).take(number * self.record_size as u64), &mut io::sink())?;
+        let mut buffer = vec![0u8; self.record_size];
+        reader.read_exact(&mut buffer)?;
+        Ok(if apply_fixups(&mut buffer) { parse_record(&buffer) } else { None })
+    }
+
+    /// Reads the cluster allocation bitmap from the `$Bitmap` record, empty when the record has no data.
+    fn bitmap<R: Read + Seek>(&self, volume: &mut DeviceSlice<R>, record: Option<&Record>) -> Result<Vec<u8>, ImageError> {
+        match record.and_then(|record| record.data.as_ref()) {
+            Some(data) => Ok(read_runs(volume, &self.runs(data, 0))?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Reads every record of the MFT. Unreadable records are `None`.
+    fn records<R: Read + Seek>(&self, volume: &mut DeviceSlice<R>) -> Result<Vec<Option<Record>>, ImageError> {
+        let size: u64 = self.mft.iter().map(DataRun::len).sum();
+        let mut reader = RunReader::new(volume, self.mft.clone());
+        let mut records = Vec::new();
+        let mut buffer = vec![0u8; self.record_size];
+        for _ in 0..size / self.record_size as u64 {
+            reader.read_exact(&mut buffer)?;
+            let record = if apply_fixups(&mut buffer) { parse_record(&buffer) } else { None };
+            records.push(record);
+        }
+        Ok(records)
+    }
+}
+
+/// Resolves the path of a record from its chain of parent directories.
+///
+/// Returns `None` when a parent record is missing or was reused by another
+/// file, detected by a sequence number that does not match the reference.
+fn resolve_path(records: &[Option<Record>], index: usize) -> Option<String> {
+    let mut parts = Vec::new();
+    let mut current = index;
+    for _ in 0..MAX_PATH_DEPTH {
+        let name = records.get(current)?.as_ref()?.name.as_ref()?;
+        parts.push(name.name.as_str());
+        if name.parent == ROOT_RECORD {
+            parts.reverse();
+            return Some(format!("/{}", parts.join("/")));
+        }
+        let parent = records.get(name.parent as usize)?.as_ref()?;
+        // Freeing a record increments its sequence number
+        let expected = if parent.flags & RECORD_IN_USE != 0 {
+            parent.sequence == name.parent_sequence
+        } else {
+            parent.sequence == name.parent_sequence || parent.sequence == name.parent_sequence.wrapping_add(1)
+        };
+        if !expected || parent.flags & RECORD_DIRECTORY == 0 {
+            return None;
+        }
+        current = name.parent as usize;
+    }
+    None
+}
+
+/// Lists the files of an NTFS volume whose MFT record is no longer in use.
+pub(crate) fn deleted_files<R: Read + Seek>(
+    device: &mut R,
+    offset: u64,
+    length: u64,
+) -> Result<Vec<DeletedFile>, ImageError> {
+    let mut volume = DeviceSlice::new(device, offset, length);
+    let ntfs = NtfsVolume::open(&mut volume)?;
+    let records = ntfs.records(&mut volume)?;
+
+    let bitmap = ntfs.bitmap(&mut volume, records.get(BITMAP_RECORD as usize).and_then(Option::as_ref))?;
+    let is_allocated = |lcn: u64| match bitmap.get((lcn / 8) as usize) {
+        Some(byte) => byte & (1 << (lcn % 8)) != 0,
+        None => false,
+    };
+
+    let mut files = Vec::new();
+    for (index, record) in records.iter().enumerate() {
+        let Some(record) = record else {
+            continue;
+        };
+        if record.flags & (RECORD_IN_USE | RECORD_DIRECTORY) != 0 {
+            continue;
+        }
+        let Some(name) = &record.name else {
+            continue;
+        };
+
+        let path = resolve_path(&records, index).unwrap_or_else(|| format!("/{}/{}", ORPHAN_DIRECTORY, name.name));
+        let (size, runs, recoverability) = match &record.data {
+            Some(data) => {
+                let (total, reallocated) = data
+                    .clusters
+                    .iter()
+                    .filter_map(|&(lcn, clusters)| lcn.map(|lcn| (lcn, clusters)))
+                    .fold((0, 0), |(total, reallocated), (lcn, clusters)| {
+                        let used = (lcn..lcn + clusters).filter(|&lcn| is_allocated(lcn)).count() as u64;
+                        (total + clusters, reallocated + used)
+                    });
+                (data.size, ntfs.runs(data, offset), Recoverability::from_clusters(total, reallocated))
+            }
+            // The data attribute lives in an extension record listed by $ATTRIBUTE_LIST
+            None => (name.size, Vec::new(), Recoverability::Unknown),
+        };
+
+        files.push(DeletedFile {
+            path,
+            size,
+            times: record.times,
+            attributes: record.attributes,
+            // File reference numbers pair the record number with its sequence number
+            file_id: Some(index as u64 | (record.sequence as u64) << 48),
+            runs,
+            recoverability,
+        });
+    }
+    Ok(files)
+}
+
+/// Lists the clusters of an NTFS volume that `$Bitmap` marks as free.
+///
+/// Ranges are `(offset, length)` in the device, merged when the clusters are contiguous.
+pub(crate) fn free_extents<R: Read + Seek>(device: &mut R, offset: u64, length: u64) -> Result<Vec<(u64, u64)>, ImageError> {
+    let mut volume = DeviceSlice::new(device, offset, length);
+    let ntfs = NtfsVolume::open(&mut volume)?;
+    let record = ntfs.record(&mut volume, BITMAP_RECORD)?;
+    let bitmap = ntfs.bitmap(&mut volume, record.as_ref())?;
+    if bitmap.is_empty() {
+        return Err(ImageError::InvalidImage("NTFS $Bitmap is unreadable".to_string()));
+    }
+    // Clusters past the end of the bitmap are not known to be free
+    let clusters = (length / ntfs.cluster_size).min(bitmap.len() as u64 * 8);
+    let free = free_runs(clusters, |lcn| bitmap[(lcn / 8) as usize] & (1 << (lcn % 8)) == 0);
+    Ok(free
+        .into_iter()
+        .map(|(first, count)| (offset + first * ntfs.cluster_size, count * ntfs.cluster_size))
+        .collect())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const RECORD_SIZE: usize = 1024;
+    const CLUSTER_SIZE: usize = 512;
+    const MFT_LCN: usize = 16;
+    const MFT_RECORDS: usize = 24;
+
+    /// Builds a resident attribute.
+    fn resident(kind: u32, content: &[u8]) -> Vec<u8> {
+        let length = (24 + content.len()).div_ceil(8) * 8;
+        let mut attribute = vec![0u8; length];
+        attribute[0..4].copy_from_slice(&kind.to_le_bytes());
+        attribute[4..8].copy_from_slice(&(length as u32).to_le_bytes());
+        attribute[16..20].copy_from_slice(&(content.len() as u32).to_le_bytes());
+        attribute[20..22].copy_from_slice(&24u16.to_le_bytes());
+        attribute[24..24 + content.len()].copy_from_slice(content);
+        attribute
+    }
+
+    /// Builds a non-resident `$DATA` attribute with a single run.
+    fn non_resident(lcn: u8, clusters: u8, size: u64) -> Vec<u8> {
+        let mut attribute = vec![0u8; 72];
+        attribute[0..4].copy_from_slice(&ATTRIBUTE_DATA.to_le_bytes());
+        attribute[4..8].copy_from_slice(&72u32.to_le_bytes());
+        attribute[8] = 1;
+        attribute[32..34].copy_from_slice(&64u16.to_le_bytes());
+        attribute[48..56].copy_from_slice(&size.to_le_bytes());
+        attribute[64..67].copy_from_slice(&[0x11, clusters, lcn]);
+        attribute
+    }
+
+    fn file_name(name: &str, parent: u64, parent_sequence: u16) -> Vec<u8> {
+        let units: Vec<u16> = name.encode_utf16().collect();
+        let mut content = vec![0u8; 66 + units.len() * 2];
+        content[0..8].copy_from_slice(&(parent | (parent_sequence as u64) << 48).to_le_bytes());
+        content[64] = units.len() as u8;
+        content[65] = 1;
+        for (index, unit) in units.iter().enumerate() {
+            content[66 + index * 2..68 + index * 2].copy_from_slice(&unit.to_le_bytes());
+        }
+        resident(ATTRIBUTE_FILE_NAME, &content)
+    }
+
+    fn standard_information(modified: u64) -> Vec<u8> {
+        let mut content = vec![0u8; 48];
+        content[8..16].copy_from_slice(&modified.to_le_bytes());
+        resident(ATTRIBUTE_STANDARD_INFORMATION, &content)
+    }
+
+    /// Writes an MFT record with its update sequence array protecting both sectors.
+    fn write_record(image: &mut [u8], number: usize, flags: u16, sequence: u16, attributes: &[Vec<u8>]) {
+        let start = MFT_LCN * CLUSTER_SIZE + number * RECORD_SIZE;
+        let record = &mut image[start..start + RECORD_SIZE];
+        record[0..4].copy_from_slice(b"FILE");
+        record[4..6].copy_from_slice(&48u16.to_le_bytes());
+        record[6..8].copy_from_slice(&3u16.to_le_bytes());
+        record[16..18].copy_from_slice(&sequence.to_le_bytes());
+        record[20..22].copy_from_slice(&56u16.to_le_bytes());
+        record[22..24].copy_from_slice(&flags.to_le_bytes());
+
+        let mut position = 56;
+        for attribute in attributes {
+            record[position..position + attribute.len()].copy_from_slice(attribute);
+            position += attribute.len();
+        }
+        record[position..position + 4].copy_from_slice(&ATTRIBUTE_END.to_le_bytes());
+
+        record[48..50].copy_from_slice(&[0x01, 0x00]);
+        for sector in 1..3 {
+            let end = sector * FIXUP_STRIDE;
+            record.copy_within(end - 2..end, 48 + sector * 2);
+            record[end - 2..end].copy_from_slice(&[0x01, 0x00]);
+        }
+    }
+
+    /// Builds a 64 KiB NTFS volume with 512-byte clusters holding deleted files:
+    ///
+    /// * `/secret.docx`, 700 bytes in clusters 100-101, recoverable
+    /// * `/notes.txt`, resident
+    /// * `/Temp/a.bin` in a deleted directory, its cluster 90 reused
+    /// * `lost.txt`, whose parent record was reused by another directory
+    pub(crate) fn build_ntfs_volume() -> Vec<u8> {
+        let mut image = vec![0u8; 128 * CLUSTER_SIZE];
+        image[3..11].copy_from_slice(b"NTFS    ");
+        image[11..13].copy_from_slice(&512u16.to_le_bytes());
+        image[13] = 1;
+        image[40..48].copy_from_slice(&128u64.to_le_bytes());
+        image[48..56].copy_from_slice(&(MFT_LCN as u64).to_le_bytes());
+        image[64] = 0xF6;
+
+        let mft_clusters = (MFT_RECORDS * RECORD_SIZE / CLUSTER_SIZE) as u8;
+        let mft_size = (MFT_RECORDS * RECORD_SIZE) as u64;
+        write_record(&mut image, 0, RECORD_IN_USE, 1, &[non_resident(MFT_LCN as u8, mft_clusters, mft_size)]);
+        write_record(&mut image, 5, RECORD_IN_USE | RECORD_DIRECTORY, 5, &[file_name(".", 5, 5)]);
+
+        // Boot sector, MFT and cluster 90 are allocated
+        let mut bitmap = vec![0u8; 16];
+        for lcn in (0..1).chain(MFT_LCN..MFT_LCN + mft_clusters as usize).chain(90..91) {
+            bitmap[lcn / 8] |= 1 << (lcn % 8);
+        }
+        write_record(&mut image, 6, RECORD_IN_USE, 6, &[resident(ATTRIBUTE_DATA, &bitmap)]);
+
+        let modified = 133_000_000_000_000_000;
+        write_record(
+            &mut image,
+            16,
+            0,
+            2,
+            &[standard_information(modified), file_name("secret.docx", 5, 5), non_resident(100, 2, 700)],
+        );
+        write_record(&mut image, 17, 0, 2, &[file_name("notes.txt", 5, 5), resident(ATTRIBUTE_DATA, b"meet at noon")]);
+        write_record(&mut image, 18, RECORD_DIRECTORY, 3, &[file_name("Temp", 5, 5)]);
+        write_record(&mut image, 19, 0, 2, &[file_name("a.bin", 18, 2), non_resident(90, 1, 300)]);
+        write_record(&mut image, 20, RECORD_IN_USE | RECORD_DIRECTORY, 9, &[file_name("Reused", 5, 5)]);
+        write_record(&mut image, 21, 0, 2, &[file_name("lost.txt", 20, 4), resident(ATTRIBUTE_DATA, b"x")]);
+        write_record(&mut image, 22, RECORD_IN_USE, 1, &[file_name("live.txt", 5, 5), resident(ATTRIBUTE_DATA, b"y")]);
+
+        image[100 * CLUSTER_SIZE..100 * CLUSTER_SIZE + 4].copy_from_slice(b"PK\x03\x04");
+        image
+    }
+
+    #[test]
+    fn test_parse_runlist() {
+        let runs = parse_runlist(&[0x21, 0x10, 0x00, 0x01, 0x01, 0x08, 0x11, 0x04, 0xF0, 0x00]).unwrap();
+        assert_eq!(runs, vec![(Some(256), 16), (None, 8), (Some(240), 4)]);
+    }
+
+    #[test]
+    fn test_ntfs_deleted_files() {
+        let image = build_ntfs_volume();
+        let length = image.len() as u64;
+        let files = deleted_files(&mut std::io::Cursor::new(image), 0, length).unwrap();
+        let paths: Vec<&str> = files.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, vec!["/secret.docx", "/notes.txt", "/Temp/a.bin", "/$OrphanFiles/lost.txt"]);
+
+        assert_eq!(files[0].size, 700);
+        assert_eq!(files[0].recoverability, Recoverability::Recoverable);
+        assert_eq!(files[0].runs, vec![DataRun::Extent { offset: 100 * 512, length: 1024 }]);
+        assert_eq!(files[0].times.modified().unwrap().timestamp(), 133_000_000_000_000_000 / 10_000_000 - 11_644_473_600);
+        assert_eq!(files[0].times.born(), None);
+
+        assert_eq!(files[1].runs, vec![DataRun::Resident(b"meet at noon".to_vec())]);
+        assert_eq!(files[2].recoverability, Recoverability::Overwritten);
+    }
+
+    #[test]
+    fn test_torn_record() {
+        let mut image = build_ntfs_volume();
+        // Corrupt the sequence number at the end of the first sector of record 16
+        image[MFT_LCN * CLUSTER_SIZE + 16 * RECORD_SIZE + 510] = 0x02;
+        let length = image.len() as u64;
+        let files = deleted_files(&mut std::io::Cursor::new(image), 0, length).unwrap();
+        assert!(files.iter().all(|file| file.path != "/secret.docx"));
+    }
+
+    #[test]
+    fn test_free_extents() {
+        let image = build_ntfs_volume();
+        let length = image.len() as u64;
+        let free = free_extents(&mut std::io::Cursor::new(image), 0, length).unwrap();
+        // The boot sector, the MFT in clusters 16-63 and cluster 90 are in use
+        assert_eq!(free, vec![(512, 15 * 512), (64 * 512, 26 * 512), (91 * 512, 37 * 512)]);
+    }
+}
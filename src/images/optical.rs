@@ -0,0 +1,193 @@
+//! Optical media support shared by the ISO 9660 and UDF readers.
+//!
+//! Hybrid discs usually carry both file systems describing the same files.
+//! UDF is preferred when present, matching the behavior of Windows.
+
+use std::io::{Read, Seek};
+use std::path::PathBuf;
+
+use super::iso9660::Iso9660Volume;
+use super::udf::UdfVolume;
+use super::{ImageError, SharedImage, VirtualDisk};
+use crate::{DataRun, FileEntry, FileTimes};
+
+/// A regular file found on an optical volume.
+#[derive(Debug, Clone)]
+pub(crate) struct OpticalFile {
+    /// Path inside the volume, using `/` separators and a leading `/`
+    pub(crate) path: String,
+    /// File size in bytes
+    pub(crate) size: u64,
+    /// Timestamps recorded for the file
+    pub(crate) times: FileTimes,
+    /// Location of the content in the image
+    pub(crate) runs: Vec<DataRun>,
+}
+
+/// A file system recognized on optical media.
+#[derive(Debug)]
+pub(crate) enum OpticalVolume {
+    Iso9660(Iso9660Volume),
+    Udf(UdfVolume),
+}
+
+impl OpticalVolume {
+    /// Probes the media for a UDF or ISO 9660 file system.
+    ///
+    /// Returns `Ok(None)` if neither file system is present.
+    pub(crate) fn open<R: Read + Seek>(reader: &mut R) -> Result<Option<Self>, ImageError> {
+        // A damaged UDF tree should not hide a readable ISO 9660 bridge
+        if let Ok(Some(volume)) = UdfVolume::open(reader) {
+            return Ok(Some(OpticalVolume::Udf(volume)));
+        }
+        Ok(Iso9660Volume::open(reader)?.map(OpticalVolume::Iso9660))
+    }
+
+    /// Returns the volume label.
+    pub(crate) fn label(&self) -> &str {
+        match self {
+            OpticalVolume::Iso9660(volume) => volume.label(),
+            OpticalVolume::Udf(volume) => volume.label(),
+        }
+    }
+
+    /// Returns the size of the volume in bytes.
+    pub(crate) fn size(&self) -> u64 {
+        match self {
+            OpticalVolume::Iso9660(volume) => volume.size(),
+            OpticalVolume::Udf(volume) => volume.size(),
+        }
+    }
+
+    /// Returns a short description of the file system and its extensions.
+    pub(crate) fn description(&self) -> String {
+        match self {
+            OpticalVolume::Iso9660(volume) => volume.description().to_string(),
+            OpticalVolume::Udf(volume) => volume.description(),
+        }
+    }
+
+    /// Lists every regular file of the volume.
+    pub(crate) fn files<R: Read + Seek>(&self, reader: &mut R) -> Result<Vec<OpticalFile>, ImageError> {
+        match self {
+            OpticalVolume::Iso9660(volume) => volume.files(reader),
+            OpticalVolume::Udf(volume) => volume.files(reader),
+        }
+    }
+}
+
+/// Retrieves all files stored in an ISO 9660 or UDF optical image
+///
+/// UDF is used when the image carries both file systems. On ISO 9660 images,
+/// Rock Ridge names are preferred over Joliet names, which in turn are
+/// preferred over plain ISO 9660 names.
+///
+/// The returned entries have paths made of the image path followed by the
+/// location of the file inside the image, and their content can be read
+/// with `FileEntry::open`.
+///
+/// # Arguments
+/// * `path` - A string path to the optical image
+///
+/// # Returns
+/// * `Ok(Vec<FileEntry>)` - A vector of all files found in the image
+/// * `Err(ImageError)` - If the image cannot be read or holds no optical file system
+///
+/// # Examples
+/// ```no_run
+/// use std::io::Read;
+/// use win_disk_info::get_optical_files;
+///
+/// let files = get_optical_files("D:/cases/2024-17/install.iso").unwrap();
+/// for file in &files {
+///     let mut content = Vec::new();
+///     file.open().unwrap().read_to_end(&mut content).unwrap();
+///     println!("{} ({} bytes)", file.path().display(), content.len());
+/// }
+/// ```
+pub fn get_optical_files(path: &str) -> Result<Vec<FileEntry>, ImageError> {
+    let mut device = VirtualDisk::open(path)?;
+    let volume = OpticalVolume::open(&mut device)?.ok_or_else(|| {
+        ImageError::UnsupportedFormat(format!("no ISO 9660 or UDF file system in {}", path))
+    })?;
+
+    let image = PathBuf::from(path);
+    let files = volume.files(&mut device)?;
+    let shared = SharedImage::from_device(device);
+    let files = files
+        .into_iter()
+        .map(|file| {
+            let mut entry_path = image.clone();
+            entry_path.extend(file.path.split('/').filter(|part| !part.is_empty()));
+            FileEntry::from_image(entry_path, file.size, file.times, &shared, file.runs)
+        })
+        .collect();
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::{get_image_disk, FileSystem};
+    use crate::images::iso9660::tests::build_joliet_image;
+    use crate::images::udf::tests::build_udf_image;
+
+    #[test]
+    fn test_get_optical_files_iso() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let image_path = temp_dir.path().join("evidence.iso");
+        std::fs::write(&image_path, build_joliet_image()).unwrap();
+
+        let files = get_optical_files(image_path.to_str().unwrap()).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name(), "Report final.txt");
+        assert_eq!(files[0].extension(), Some("txt"));
+        assert_eq!(files[0].path(), image_path.join("Docs").join("Report final.txt"));
+
+        let mut content = String::new();
+        files[0].open().unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, "hello world");
+    }
+
+    #[test]
+    fn test_get_optical_files_udf() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let image_path = temp_dir.path().join("backup.iso");
+        std::fs::write(&image_path, build_udf_image()).unwrap();
+
+        let files = get_optical_files(image_path.to_str().unwrap()).unwrap();
+        let photo = files.iter().find(|file| file.name() == "holiday.jpg").unwrap();
+
+        let mut content = Vec::new();
+        photo.open().unwrap().read_to_end(&mut content).unwrap();
+        assert_eq!(content.len(), 3000);
+        assert_eq!(&content[..4], &[0xFF, 0xD8, 0xFF, 0xE0]);
+    }
+
+    #[test]
+    fn test_get_image_disk_optical() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let image_path = temp_dir.path().join("evidence.iso");
+        std::fs::write(&image_path, build_joliet_image()).unwrap();
+        let path = image_path.to_str().unwrap();
+
+        let disk = get_image_disk(path).unwrap();
+        assert_eq!(disk.kind(), &crate::DiskKind::Optical);
+        assert_eq!(disk.partitions().len(), 1);
+        assert_eq!(disk.partitions()[0].file_system(), &FileSystem::ISO9660(PathBuf::from(path)));
+        assert_eq!(disk.partitions()[0].total_space(), 32 * 2048);
+    }
+
+    #[test]
+    fn test_get_optical_files_not_optical() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let image_path = temp_dir.path().join("blank.img");
+        std::fs::write(&image_path, vec![0u8; 64 * 1024]).unwrap();
+
+        let result = get_optical_files(image_path.to_str().unwrap());
+        assert!(matches!(result, Err(ImageError::UnsupportedFormat(_))));
+    }
+}
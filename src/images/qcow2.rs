@@ -0,0 +1,344 @@
+//! QEMU copy-on-write (QCOW2) image reader.
+//!
+//! The virtual disk is split into clusters mapped through a two-level table:
+//! the L1 table points to L2 tables, whose entries give the location of each
+//! cluster in the file. Clusters may be stored as-is, compressed with raw
+//! deflate, marked as reading back zeros, or left unallocated, in which case
+//! they are read from the backing file when one is set.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use flate2::read::DeflateDecoder;
+
+use super::{be_u32, be_u64, open_parent, read_file_at, read_source_at, ImageError, ReadAt};
+
+/// Magic number at the start of the header ("QFI\xfb")
+pub(crate) const QCOW2_MAGIC: &[u8] = b"QFI\xfb";
+/// Mask of the table or cluster offset stored in L1 and L2 entries
+const OFFSET_MASK: u64 = 0x00FF_FFFF_FFFF_FE00;
+/// L2 entry flag of a compressed cluster
+const COMPRESSED_FLAG: u64 = 1 << 62;
+/// L2 entry flag of a cluster that reads back as zeros (version 3)
+const ZERO_FLAG: u64 = 1;
+/// Smallest and largest cluster sizes accepted, as a power of two
+const MIN_CLUSTER_BITS: u32 = 9;
+const MAX_CLUSTER_BITS: u32 = 21;
+/// Largest L1 table accepted, to bound allocations on corrupt headers
+const MAX_L1_SIZE: u64 = 32 * 1024 * 1024;
+/// Longest backing file name allowed by the format
+const MAX_BACKING_NAME: u32 = 1023;
+
+/// Incompatible feature bits of version 3 headers
+const FEATURE_DIRTY: u64 = 1 << 0;
+const FEATURE_CORRUPT: u64 = 1 << 1;
+const FEATURE_EXTERNAL_DATA: u64 = 1 << 2;
+const FEATURE_COMPRESSION_TYPE: u64 = 1 << 3;
+const FEATURE_EXTENDED_L2: u64 = 1 << 4;
+
+/// A QCOW2 image, version 2 or 3.
+pub(crate) struct Qcow2Image {
+    file: File,
+    /// Virtual disk size in bytes
+    size: u64,
+    /// Cluster size as a power of two
+    cluster_bits: u32,
+    /// Offsets of the L2 tables in the file
+    l1_table: Vec<u64>,
+    /// Most recently read L2 table and its offset
+    cached_l2: Option<(u64, Vec<u64>)>,
+    /// Most recently decompressed cluster and its L2 entry
+    cached_cluster: Option<(u64, Vec<u8>)>,
+    /// Image providing the unallocated clusters
+    backing: Option<Box<dyn ReadAt>>,
+}
+
+impl Qcow2Image {
+    /// Opens a QCOW2 image, resolving its chain of backing files.
+    ///
+    /// `depth` counts the images already opened above this one.
+    pub(crate) fn open(path: &Path, depth: usize) -> Result<Self, ImageError> {
+        let mut file = File::open(path)?;
+        let header = read_file_at(&mut file, 0, 104)?;
+        if !header.starts_with(QCOW2_MAGIC) {
+            return Err(ImageError::InvalidImage("QCOW2 header not found".to_string()));
+        }
+
+        let version = be_u32(&header, 4);
+        if version != 2 && version != 3 {
+            return Err(ImageError::UnsupportedFormat(format!("QCOW version {}", version)));
+        }
+
+        let cluster_bits = be_u32(&header, 20);
+        if !(MIN_CLUSTER_BITS..=MAX_CLUSTER_BITS).contains(&cluster_bits) {
+            return Err(ImageError::InvalidImage(format!("invalid QCOW2 cluster size 2^{}", cluster_bits)));
+        }
+        if be_u32(&header, 32) != 0 {
+            return Err(ImageError::UnsupportedFormat("encrypted QCOW2 image".to_string()));
+        }
+
+        if version == 3 {
+            let features = be_u64(&header, 72);
+            let header_length = be_u32(&header, 100);
+            if features & FEATURE_EXTERNAL_DATA != 0 {
+                return Err(ImageError::UnsupportedFormat("QCOW2 image with an external data file".to_string()));
+            }
+            if features & FEATURE_EXTENDED_L2 != 0 {
+                return Err(ImageError::UnsupportedFormat("QCOW2 image with subclusters".to_string()));
+            }
+            if features & FEATURE_COMPRESSION_TYPE != 0 && header_length > 104 {
+                let compression = read_file_at(&mut file, 104, 1)?[0];
+                if compression != 0 {
+                    return Err(ImageError::UnsupportedFormat("QCOW2 image compressed with zstd".to_string()));
+                }
+            }
+            // Dirty and corrupt images only have stale reference counts, which are not needed to read
+            let known = FEATURE_DIRTY | FEATURE_CORRUPT | FEATURE_COMPRESSION_TYPE;
+            if features & !known != 0 {
+                return Err(ImageError::UnsupportedFormat(format!(
+                    "QCOW2 incompatible features {:#x}",
+                    features & !known
+                )));
+            }
+        }
+
+        let size = be_u64(&header, 24);
+        let l1_size = be_u32(&header, 36) as u64;
+        let l1_offset = be_u64(&header, 40);
+        let l2_bits = cluster_bits - 3;
+        let needed = size.div_ceil(1 << (cluster_bits + l2_bits));
+        if l1_size < needed || l1_size * 8 > MAX_L1_SIZE {
+            return Err(ImageError::InvalidImage(format!("invalid QCOW2 L1 table size {}", l1_size)));
+        }
+        let table = read_file_at(&mut file, l1_offset, (l1_size * 8) as usize)?;
+        let l1_table = table.chunks_exact(8).map(|entry| be_u64(entry, 0) & OFFSET_MASK).collect();
+
+        let backing_offset = be_u64(&header, 8);
+        let backing_length = be_u32(&header, 16);
+        let backing = if backing_offset != 0 && backing_length != 0 {
+            if backing_length > MAX_BACKING_NAME {
+                return Err(ImageError::InvalidImage("QCOW2 backing file name is too long".to_string()));
+            }
+            let name = read_file_at(&mut file, backing_offset, backing_length as usize)?;
+            let name = String::from_utf8_lossy(&name).to_string();
+            Some(open_parent(path, &[name], depth)?)
+        } else {
+            None
+        };
+
+        Ok(Qcow2Image {
+            file,
+            size,
+            cluster_bits,
+            l1_table,
+            cached_l2: None,
+            cached_cluster: None,
+            backing,
+        })
+    }
+
+    /// Returns the L2 entry mapping the cluster containing `offset`, or 0 if
+    /// the cluster is unallocated.
+    fn l2_entry(&mut self, offset: u64) -> io::Result<u64> {
+        let l2_bits = self.cluster_bits - 3;
+        let l1_index = (offset >> (self.cluster_bits + l2_bits)) as usize;
+        let l2_index = ((offset >> self.cluster_bits) & ((1 << l2_bits) - 1)) as usize;
+
+        let table_offset = self.l1_table.get(l1_index).copied().unwrap_or(0);
+        if table_offset == 0 {
+            return Ok(0);
+        }
+        if self.cached_l2.as_ref().map(|(cached, _)| *cached) != Some(table_offset) {
+            let table = read_file_at(&mut self.file, table_offset, 1 << self.cluster_bits)?;
+            let entries = table.chunks_exact(8).map(|entry| be_u64(entry, 0)).collect();
+            self.cached_l2 = Some((table_offset, entries));
+        }
+        Ok(self.cached_l2.as_ref().expect("cached L2 table").1[l2_index])
+    }
+
+    /// Decompresses the cluster described by a compressed L2 entry.
+    fn read_compressed(&mut self, entry: u64) -> io::Result<&[u8]> {
+        if self.cached_cluster.as_ref().map(|(cached, _)| *cached) != Some(entry) {
+            let cluster_size = 1u64 << self.cluster_bits;
+            let shift = 62 - (self.cluster_bits - 8);
+            let host_offset = entry & ((1 << shift) - 1);
+            let sectors = ((entry >> shift) & ((1 << (self.cluster_bits - 8)) - 1)) + 1;
+            let length = sectors * 512 - (host_offset & 511);
+
+            // The last compressed cluster may end before its recorded length
+            let file_size = self.file.seek(SeekFrom::End(0))?;
+            let length = length.min(file_size.saturating_sub(host_offset));
+            let compressed = read_file_at(&mut self.file, host_offset, length as usize)?;
+
+            let mut cluster = Vec::with_capacity(cluster_size as usize);
+            DeflateDecoder::new(&compressed[..]).take(cluster_size).read_to_end(&mut cluster)?;
+            if cluster.len() as u64 != cluster_size {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "compressed QCOW2 cluster is truncated",
+                ));
+            }
+            self.cached_cluster = Some((entry, cluster));
+        }
+        Ok(&self.cached_cluster.as_ref().expect("cached cluster").1)
+    }
+}
+
+impl ReadAt for Qcow2Image {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if offset >= self.size {
+            return Ok(0);
+        }
+        let cluster_size = 1u64 << self.cluster_bits;
+        let within = offset % cluster_size;
+        let len = buf.len().min((self.size - offset).min(cluster_size - within) as usize);
+        let buf = &mut buf[..len];
+
+        let entry = self.l2_entry(offset)?;
+        if entry & COMPRESSED_FLAG != 0 {
+            let cluster = self.read_compressed(entry)?;
+            buf.copy_from_slice(&cluster[within as usize..within as usize + len]);
+        } else if entry & ZERO_FLAG != 0 {
+            buf.fill(0);
+        } else if entry & OFFSET_MASK != 0 {
+            let data = read_file_at(&mut self.file, (entry & OFFSET_MASK) + within, len)?;
+            buf.copy_from_slice(&data);
+        } else {
+            match self.backing.as_mut() {
+                Some(backing) => read_source_at(backing.as_mut(), offset, buf)?,
+                None => buf.fill(0),
+            }
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+
+    use super::*;
+    use crate::images::{ImageFormat, VirtualDisk};
+
+    const CLUSTER_BITS: u32 = 12;
+    const CLUSTER_SIZE: usize = 1 << CLUSTER_BITS;
+
+    /// Content of a cluster in a test image.
+    enum Cluster {
+        Data(Vec<u8>),
+        Compressed(Vec<u8>),
+        Zero,
+    }
+
+    /// Builds a version 3 QCOW2 image with 4 KiB clusters and a single L2 table.
+    fn build_qcow2(size: u64, clusters: &[(usize, Cluster)], backing: Option<&str>) -> Vec<u8> {
+        // Cluster 0: header, cluster 1: L1 table, cluster 2: L2 table
+        let mut image = vec![0u8; 3 * CLUSTER_SIZE];
+        image[0..4].copy_from_slice(QCOW2_MAGIC);
+        image[4..8].copy_from_slice(&3u32.to_be_bytes());
+        if let Some(backing) = backing {
+            image[8..16].copy_from_slice(&512u64.to_be_bytes());
+            image[16..20].copy_from_slice(&(backing.len() as u32).to_be_bytes());
+            image[512..512 + backing.len()].copy_from_slice(backing.as_bytes());
+        }
+        image[20..24].copy_from_slice(&CLUSTER_BITS.to_be_bytes());
+        image[24..32].copy_from_slice(&size.to_be_bytes());
+        image[36..40].copy_from_slice(&1u32.to_be_bytes());
+        image[40..48].copy_from_slice(&(CLUSTER_SIZE as u64).to_be_bytes());
+        image[100..104].copy_from_slice(&104u32.to_be_bytes());
+        image[CLUSTER_SIZE..CLUSTER_SIZE + 8].copy_from_slice(&(2 * CLUSTER_SIZE as u64).to_be_bytes());
+
+        for (index, cluster) in clusters {
+            let host_offset = image.len() as u64;
+            let entry = match cluster {
+                Cluster::Data(data) => {
+                    let mut data = data.clone();
+                    data.resize(CLUSTER_SIZE, 0);
+                    image.extend(data);
+                    host_offset
+                }
+                Cluster::Compressed(data) => {
+                    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                    encoder.write_all(data).unwrap();
+                    let compressed = encoder.finish().unwrap();
+                    let sectors = compressed.len().div_ceil(512) as u64;
+                    image.extend(&compressed);
+                    image.resize(image.len().div_ceil(512) * 512, 0);
+                    let shift = 62 - (CLUSTER_BITS - 8);
+                    COMPRESSED_FLAG | (sectors - 1) << shift | host_offset
+                }
+                Cluster::Zero => ZERO_FLAG,
+            };
+            let position = 2 * CLUSTER_SIZE + index * 8;
+            image[position..position + 8].copy_from_slice(&entry.to_be_bytes());
+        }
+        image
+    }
+
+    #[test]
+    fn test_qcow2_clusters() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("guest.qcow2");
+        let clusters = [
+            (0, Cluster::Data(vec![0x11; CLUSTER_SIZE])),
+            (2, Cluster::Compressed((0..CLUSTER_SIZE).map(|i| (i % 251) as u8).collect())),
+            (3, Cluster::Zero),
+        ];
+        std::fs::write(&path, build_qcow2(5 * CLUSTER_SIZE as u64, &clusters, None)).unwrap();
+
+        let mut device = VirtualDisk::open(&path).unwrap();
+        assert_eq!(device.format(), ImageFormat::Qcow2);
+        assert_eq!(device.size(), 5 * CLUSTER_SIZE as u64);
+
+        let mut data = Vec::new();
+        device.read_to_end(&mut data).unwrap();
+        assert_eq!(&data[..CLUSTER_SIZE], &[0x11; CLUSTER_SIZE][..]);
+        assert_eq!(&data[CLUSTER_SIZE..2 * CLUSTER_SIZE], &[0u8; CLUSTER_SIZE][..]);
+        let expected: Vec<u8> = (0..CLUSTER_SIZE).map(|i| (i % 251) as u8).collect();
+        assert_eq!(&data[2 * CLUSTER_SIZE..3 * CLUSTER_SIZE], &expected[..]);
+        assert_eq!(&data[3 * CLUSTER_SIZE..], &[0u8; 2 * CLUSTER_SIZE][..]);
+    }
+
+    #[test]
+    fn test_qcow2_backing_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("base.raw"), vec![0x22; 2 * CLUSTER_SIZE]).unwrap();
+        let path = temp_dir.path().join("overlay.qcow2");
+        let clusters = [(1, Cluster::Data(vec![0x33; CLUSTER_SIZE]))];
+        std::fs::write(&path, build_qcow2(2 * CLUSTER_SIZE as u64, &clusters, Some("base.raw"))).unwrap();
+
+        let mut device = VirtualDisk::open(&path).unwrap();
+        let mut data = Vec::new();
+        device.read_to_end(&mut data).unwrap();
+        assert_eq!(&data[..CLUSTER_SIZE], &[0x22; CLUSTER_SIZE][..]);
+        assert_eq!(&data[CLUSTER_SIZE..], &[0x33; CLUSTER_SIZE][..]);
+    }
+
+    #[test]
+    fn test_qcow2_missing_backing_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("overlay.qcow2");
+        std::fs::write(&path, build_qcow2(CLUSTER_SIZE as u64, &[], Some("/nowhere/base.qcow2"))).unwrap();
+
+        assert!(matches!(VirtualDisk::open(&path), Err(ImageError::MissingParent(_))));
+    }
+
+    #[test]
+    fn test_qcow2_encrypted() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("secret.qcow2");
+        let mut image = build_qcow2(CLUSTER_SIZE as u64, &[], None);
+        image[32..36].copy_from_slice(&1u32.to_be_bytes());
+        std::fs::write(&path, image).unwrap();
+
+        assert!(matches!(VirtualDisk::open(&path), Err(ImageError::UnsupportedFormat(_))));
+    }
+}
@@ -0,0 +1,337 @@
+//! Split raw image reader.
+//!
+//! Acquisition tools often split a raw copy of the media into fixed-size
+//! segments named with a numeric extension (`disk.001`, `disk.002`, ...) or
+//! with the alphabetic suffixes of the Unix `split` tool (`disk.aa`,
+//! `disk.ab`, ...). The segments are concatenated into a single device.
+//!
+//! Split images have no signature, so the names alone decide: numeric
+//! extensions need at least three digits, and alphabetic sets are only
+//! recognized when their second segment is present, since a lone `.aa` file
+//! says nothing about being split.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use super::{ImageError, ReadAt};
+
+/// Fewest digits of a numeric segment extension
+const MIN_NUMERIC_WIDTH: usize = 3;
+
+/// Naming scheme of the segments of a split image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scheme {
+    /// Zero-padded decimal extensions, starting at 000 or 001
+    Numeric { first: u64, width: usize },
+    /// Base-26 lowercase extensions starting at `aa`
+    Alphabetic { width: usize },
+}
+
+impl Scheme {
+    /// Detects the naming scheme of the set a segment belongs to.
+    ///
+    /// Returns the scheme and the index of the segment in the set.
+    fn of_segment(path: &Path) -> Option<(Self, u64)> {
+        let extension = path.extension()?.to_str()?;
+        if extension.len() >= MIN_NUMERIC_WIDTH && extension.bytes().all(|byte| byte.is_ascii_digit()) {
+            let width = extension.len();
+            let number: u64 = extension.parse().ok()?;
+            // Sets numbered from 000 have a 001 segment too, which is their second one
+            let first = if number > 0 && path.with_extension(format!("{:0width$}", 0)).is_file() { 0 } else { 1 };
+            let scheme = Scheme::Numeric {
+                first: first.min(number),
+                width,
+            };
+            return Some((scheme, number - first.min(number)));
+        }
+        if extension.len() >= 2 && extension.bytes().all(|byte| byte.is_ascii_lowercase()) {
+            let scheme = Scheme::Alphabetic {
+                width: extension.len(),
+            };
+            let index = scheme.index(extension)?;
+            let first = path.with_extension(scheme.extension(0)?);
+            let second = path.with_extension(scheme.extension(1)?);
+            // Plenty of extensions are two lowercase letters, so the set must show itself
+            return (first.is_file() && second.is_file()).then_some((scheme, index));
+        }
+        None
+    }
+
+    /// Detects the naming scheme from the first segment of a set.
+    fn detect(path: &Path) -> Option<Self> {
+        Self::of_segment(path).filter(|(_, index)| *index == 0).map(|(scheme, _)| scheme)
+    }
+
+    /// Returns the extension of the segment at `index` (0-based).
+    fn extension(&self, index: u64) -> Option<String> {
+        match *self {
+            Scheme::Numeric { first, width } => Some(format!("{:0width$}", first + index, width = width)),
+            Scheme::Alphabetic { width } => {
+                let mut letters = vec![b'a'; width];
+                let mut rest = index;
+                for letter in letters.iter_mut().rev() {
+                    *letter = b'a' + (rest % 26) as u8;
+                    rest /= 26;
+                }
+                (rest == 0).then(|| String::from_utf8_lossy(&letters).to_string())
+            }
+        }
+    }
+
+    /// Returns the index of a segment extension of this scheme.
+    fn index(&self, extension: &str) -> Option<u64> {
+        match *self {
+            Scheme::Numeric { first, width } => {
+                if extension.len() < width || !extension.bytes().all(|byte| byte.is_ascii_digit()) {
+                    return None;
+                }
+                extension.parse::<u64>().ok()?.checked_sub(first)
+            }
+            Scheme::Alphabetic { width } => {
+                if extension.len() != width || !extension.bytes().all(|byte| byte.is_ascii_lowercase()) {
+                    return None;
+                }
+                Some(extension.bytes().fold(0, |index, byte| index * 26 + (byte - b'a') as u64))
+            }
+        }
+    }
+}
+
+/// Returns true if `path` names the first segment of a split image.
+pub(crate) fn is_first_segment(path: &Path) -> bool {
+    Scheme::detect(path).is_some()
+}
+
+/// Returns the path of the first segment if `path` names a later segment of a split image.
+pub(crate) fn first_segment_of(path: &Path) -> Option<PathBuf> {
+    let (scheme, index) = Scheme::of_segment(path)?;
+    (index > 0).then(|| path.with_extension(scheme.extension(0).unwrap_or_default()))
+}
+
+/// A segment of a split image.
+struct Segment {
+    file: File,
+    /// Offset of the segment in the device
+    start: u64,
+    length: u64,
+}
+
+/// A raw image split in several files.
+pub(crate) struct SplitRawImage {
+    segments: Vec<Segment>,
+    size: u64,
+}
+
+impl SplitRawImage {
+    /// Opens a split image from its first segment.
+    ///
+    /// Segments are followed until the next name does not exist. A gap in
+    /// the numbering or a segment shorter than the first one, other than the
+    /// last, is reported as an error instead of silently truncating the media.
+    pub(crate) fn open(path: &Path) -> Result<Self, ImageError> {
+        let scheme = Scheme::detect(path)
+            .ok_or_else(|| ImageError::UnsupportedFormat(format!("{} is not a split image segment", path.display())))?;
+
+        let mut paths = Vec::new();
+        while let Some(extension) = scheme.extension(paths.len() as u64) {
+            let segment = path.with_extension(extension);
+            if !segment.is_file() {
+                break;
+            }
+            paths.push(segment);
+        }
+
+        // Segments numbered after the first missing one reveal a gap in the set
+        if let Some(last) = Self::last_index(path, scheme) {
+            if last >= paths.len() as u64 {
+                let missing = scheme.extension(paths.len() as u64).map(|extension| path.with_extension(extension));
+                return Err(ImageError::MissingSegment(missing.unwrap_or_default().display().to_string()));
+            }
+        }
+
+        let mut segments = Vec::with_capacity(paths.len());
+        let mut start = 0;
+        for segment in &paths {
+            let file = File::open(segment)?;
+            let length = file.metadata()?.len();
+            segments.push(Segment { file, start, length });
+            start += length;
+        }
+
+        let expected = segments.first().map(|segment| segment.length).unwrap_or(0);
+        for (segment, name) in segments.iter().zip(&paths).take(segments.len().saturating_sub(1)) {
+            if segment.length != expected {
+                return Err(ImageError::InvalidImage(format!(
+                    "segment {} is {} bytes long, {} expected",
+                    name.display(),
+                    segment.length,
+                    expected
+                )));
+            }
+        }
+
+        Ok(SplitRawImage { segments, size: start })
+    }
+
+    /// Returns the highest segment index present next to the first segment.
+    fn last_index(path: &Path, scheme: Scheme) -> Option<u64> {
+        let stem = path.file_stem()?.to_str()?;
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        fs::read_dir(directory)
+            .ok()?
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name();
+                let extension = name.to_str()?.strip_prefix(stem)?.strip_prefix('.')?.to_string();
+                scheme.index(&extension)
+            })
+            .max()
+    }
+}
+
+impl ReadAt for SplitRawImage {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if offset >= self.size {
+            return Ok(0);
+        }
+        let index = self.segments.partition_point(|segment| segment.start + segment.length <= offset);
+        let segment = &mut self.segments[index];
+        let within = offset - segment.start;
+        let len = buf.len().min((segment.length - within) as usize);
+        segment.file.seek(SeekFrom::Start(within))?;
+        segment.file.read(&mut buf[..len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::images::{ImageFormat, VirtualDisk};
+
+    /// Writes `data` split in `segment_size` pieces named with `extension(index)`.
+    fn write_split(directory: &Path, name: &str, data: &[u8], segment_size: usize, extension: impl Fn(usize) -> String) {
+        for (index, piece) in data.chunks(segment_size).enumerate() {
+            fs::write(directory.join(format!("{}.{}", name, extension(index))), piece).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_scheme_names() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let numeric = Scheme::detect(Path::new("disk.001")).unwrap();
+        assert_eq!(numeric.extension(0).unwrap(), "001");
+        assert_eq!(numeric.extension(9).unwrap(), "010");
+        assert_eq!(numeric.index("010"), Some(9));
+
+        // A lone `.aa` file is not a split set
+        let first = temp_dir.path().join("disk.dd.aa");
+        fs::write(&first, b"a").unwrap();
+        assert_eq!(Scheme::detect(&first), None);
+        fs::write(temp_dir.path().join("disk.dd.ab"), b"b").unwrap();
+        let alphabetic = Scheme::detect(&first).unwrap();
+        assert_eq!(alphabetic.extension(1).unwrap(), "ab");
+        assert_eq!(alphabetic.extension(26).unwrap(), "ba");
+        assert_eq!(alphabetic.extension(26 * 26), None);
+        assert_eq!(alphabetic.index("ba"), Some(26));
+
+        assert_eq!(Scheme::detect(Path::new("disk.002")), None);
+        assert_eq!(Scheme::detect(Path::new("disk.dd")), None);
+        assert_eq!(Scheme::detect(Path::new("disk.00")), None);
+        assert_eq!(Scheme::detect(Path::new("disk.01")), None);
+        assert_eq!(Scheme::detect(Path::new("archive.gz")), None);
+    }
+
+    #[test]
+    fn test_later_segment() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        write_split(temp_dir.path(), "usb", &[0u8; 3000], 1000, |index| format!("{:03}", index + 1));
+        let second = temp_dir.path().join("usb.002");
+        assert_eq!(first_segment_of(&second), Some(temp_dir.path().join("usb.001")));
+        assert_eq!(first_segment_of(&temp_dir.path().join("usb.001")), None);
+        let result = VirtualDisk::open(&second);
+        assert!(matches!(result, Err(ImageError::InvalidImage(message)) if message.contains("usb.001")));
+
+        // The set numbered from 000 starts before the 001 segment
+        fs::write(temp_dir.path().join("usb.000"), [0u8; 1000]).unwrap();
+        assert_eq!(first_segment_of(&temp_dir.path().join("usb.001")), Some(temp_dir.path().join("usb.000")));
+
+        fs::remove_file(temp_dir.path().join("usb.000")).unwrap();
+        fs::remove_file(temp_dir.path().join("usb.001")).unwrap();
+        let result = VirtualDisk::open(&second);
+        assert!(matches!(result, Err(ImageError::MissingSegment(segment)) if segment.ends_with("usb.001")));
+    }
+
+    #[test]
+    fn test_numeric_split_image() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let data: Vec<u8> = (0..2500u32).map(|i| (i % 251) as u8).collect();
+        write_split(temp_dir.path(), "usb", &data, 1000, |index| format!("{:03}", index + 1));
+
+        let mut device = VirtualDisk::open(temp_dir.path().join("usb.001")).unwrap();
+        assert_eq!(device.format(), ImageFormat::SplitRaw);
+        assert_eq!(device.size(), 2500);
+
+        let mut content = Vec::new();
+        device.read_to_end(&mut content).unwrap();
+        assert_eq!(content, data);
+    }
+
+    #[test]
+    fn test_alphabetic_split_image() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let data: Vec<u8> = (0..3000u32).map(|i| (i % 241) as u8).collect();
+        write_split(temp_dir.path(), "disk.dd", &data, 1024, |index| {
+            format!("a{}", (b'a' + index as u8) as char)
+        });
+
+        let mut device = VirtualDisk::open(temp_dir.path().join("disk.dd.aa")).unwrap();
+        device.seek(SeekFrom::Start(1000)).unwrap();
+        let mut across = [0u8; 100];
+        device.read_exact(&mut across).unwrap();
+        assert_eq!(&across[..], &data[1000..1100]);
+    }
+
+    #[test]
+    fn test_split_image_missing_segment() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        write_split(temp_dir.path(), "usb", &[0u8; 4000], 1000, |index| format!("{:03}", index + 1));
+        fs::remove_file(temp_dir.path().join("usb.003")).unwrap();
+
+        let result = VirtualDisk::open(temp_dir.path().join("usb.001"));
+        assert!(matches!(result, Err(ImageError::MissingSegment(segment)) if segment.ends_with("usb.003")));
+    }
+
+    #[test]
+    fn test_split_image_short_segment() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        write_split(temp_dir.path(), "usb", &[0u8; 3000], 1000, |index| format!("{:03}", index + 1));
+        fs::write(temp_dir.path().join("usb.002"), [0u8; 600]).unwrap();
+
+        let result = VirtualDisk::open(temp_dir.path().join("usb.001"));
+        assert!(matches!(result, Err(ImageError::InvalidImage(_))));
+    }
+
+    #[test]
+    fn test_split_image_disk() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let data = crate::images::tests::build_fat32_disk();
+        write_split(temp_dir.path(), "usb", &data, 700 * 1024, |index| format!("{:03}", index));
+
+        let path = temp_dir.path().join("usb.000");
+        let disk = crate::get_image_disk(path.to_str().unwrap()).unwrap();
+        assert_eq!(disk.model(), "Split raw image");
+        assert_eq!(disk.size(), data.len());
+        assert_eq!(disk.partitions()[0].name(), "EVIDENCE");
+    }
+}
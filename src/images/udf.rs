@@ -0,0 +1,774 @@
+//! UDF (Universal Disk Format) file system reader.
+//!
+//! Follows the ECMA-167 descriptor chain from the anchor volume descriptor
+//! pointer to the file set descriptor, then walks the directory hierarchy
+//! through file entries and file identifier descriptors. Physical, sparable
+//! and metadata partitions are supported; virtual (VAT) partitions used by
+//! incrementally written CD-R media are not.
+
+use std::collections::HashSet;
+use std::io::{Read, Seek};
+
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone, Utc};
+
+use super::optical::OpticalFile;
+use super::{le_u16, le_u32, le_u64, read_bytes, read_runs, ImageError};
+use crate::{DataRun, FileTimes};
+
+/// Byte offset of the volume recognition sequence
+const RECOGNITION_OFFSET: u64 = 16 * 2048;
+/// Size of each volume recognition descriptor
+const RECOGNITION_DESCRIPTOR_SIZE: u64 = 2048;
+/// Logical sector holding the anchor volume descriptor pointer
+const ANCHOR_SECTOR: u64 = 256;
+/// Block sizes tried when locating the anchor, most common first
+const BLOCK_SIZES: [u64; 3] = [2048, 512, 4096];
+/// Upper bound on the number of descriptors read from the volume descriptor sequence
+const MAX_VOLUME_DESCRIPTORS: u64 = 64;
+/// Upper bound on chained allocation extent descriptors per file
+const MAX_ALLOCATION_EXTENTS: usize = 1024;
+
+/// Descriptor tag identifiers
+const TAG_PRIMARY_VOLUME: u16 = 1;
+const TAG_ANCHOR: u16 = 2;
+const TAG_PARTITION: u16 = 5;
+const TAG_LOGICAL_VOLUME: u16 = 6;
+const TAG_TERMINATING: u16 = 8;
+const TAG_FILE_SET: u16 = 256;
+const TAG_FILE_IDENTIFIER: u16 = 257;
+const TAG_ALLOCATION_EXTENT: u16 = 258;
+const TAG_FILE_ENTRY: u16 = 261;
+const TAG_EXTENDED_FILE_ENTRY: u16 = 266;
+
+/// ICB file types
+const FILE_TYPE_UNSPECIFIED: u8 = 0;
+const FILE_TYPE_DIRECTORY: u8 = 4;
+const FILE_TYPE_REGULAR: u8 = 5;
+
+/// File identifier characteristics
+const CHARACTERISTIC_DELETED: u8 = 0x04;
+const CHARACTERISTIC_PARENT: u8 = 0x08;
+
+/// Allocation descriptor types stored in the ICB flags
+const AD_SHORT: u16 = 0;
+const AD_LONG: u16 = 1;
+const AD_EMBEDDED: u16 = 3;
+
+/// Extent types stored in the two upper bits of an extent length
+const EXTENT_RECORDED: u32 = 0;
+const EXTENT_CONTINUATION: u32 = 3;
+
+/// Address of a logical block inside a partition (`lb_addr` / `long_ad`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct LongAd {
+    /// Extent length in bytes, including the extent type bits
+    length: u32,
+    /// Logical block number inside the partition
+    block: u32,
+    /// Index in the logical volume partition map table
+    partition: u16,
+}
+
+impl LongAd {
+    fn parse(buf: &[u8]) -> Self {
+        LongAd {
+            length: le_u32(buf, 0),
+            block: le_u32(buf, 4),
+            partition: le_u16(buf, 8),
+        }
+    }
+}
+
+/// Translation from partition-relative blocks to media offsets.
+#[derive(Debug)]
+enum PartitionMapping {
+    /// Blocks stored contiguously starting at a media block
+    Physical { number: u16, start: u64 },
+    /// Blocks stored inside the metadata file of a physical partition
+    Metadata { runs: Vec<DataRun> },
+    /// Partition map this reader cannot resolve
+    Unsupported,
+}
+
+/// A file entry parsed from an ICB.
+#[derive(Debug)]
+struct Node {
+    /// ICB file type
+    file_type: u8,
+    /// Information length in bytes
+    size: u64,
+    /// Access, modification, attribute change and creation times
+    times: FileTimes,
+    /// Location of the content
+    runs: Vec<DataRun>,
+}
+
+/// A UDF logical volume.
+#[derive(Debug)]
+pub(crate) struct UdfVolume {
+    /// Logical volume identifier
+    label: String,
+    /// UDF revision from the domain identifier (e.g. 0x0201)
+    revision: u16,
+    /// Logical block size in bytes
+    block_size: u64,
+    /// Size of the recorded partitions in bytes
+    size: u64,
+    /// Partition map table of the logical volume
+    mappings: Vec<PartitionMapping>,
+    /// ICB of the root directory
+    root: LongAd,
+}
+
+impl UdfVolume {
+    /// Parses the UDF volume structures of an image.
+    ///
+    /// Returns `Ok(None)` when the media does not contain a UDF volume.
+    pub(crate) fn open<R: Read + Seek>(reader: &mut R) -> Result<Option<Self>, ImageError> {
+        if !has_nsr_descriptor(reader) {
+            return Ok(None);
+        }
+
+        let (block_size, anchor) = match find_anchor(reader) {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+
+        let sequence_length = le_u32(&anchor, 16) as u64;
+        let sequence_start = le_u32(&anchor, 20) as u64;
+
+        let mut volume_label = String::new();
+        let mut partitions = Vec::new();
+        let mut logical_volume = None;
+
+        for index in 0..(sequence_length / block_size).min(MAX_VOLUME_DESCRIPTORS) {
+            let offset = (sequence_start + index) * block_size;
+            let descriptor = read_bytes(reader, offset, block_size as usize)?;
+            match le_u16(&descriptor, 0) {
+                TAG_PRIMARY_VOLUME if volume_label.is_empty() => {
+                    volume_label = decode_dstring(&descriptor[24..56]);
+                }
+                TAG_PARTITION => {
+                    let number = le_u16(&descriptor, 22);
+                    let start = le_u32(&descriptor, 188) as u64;
+                    let length = le_u32(&descriptor, 192) as u64;
+                    partitions.push((number, start, length));
+                }
+                TAG_LOGICAL_VOLUME if logical_volume.is_none() => {
+                    let table_length = le_u32(&descriptor, 264) as usize;
+                    logical_volume = Some(if 440 + table_length > descriptor.len() {
+                        read_bytes(reader, offset, 440 + table_length)?
+                    } else {
+                        descriptor
+                    });
+                }
+                TAG_TERMINATING => break,
+                _ => {}
+            }
+        }
+
+        let logical_volume = logical_volume
+            .ok_or_else(|| ImageError::InvalidImage("UDF logical volume descriptor not found".to_string()))?;
+
+        let logical_block_size = match le_u32(&logical_volume, 212) as u64 {
+            0 => block_size,
+            size => size,
+        };
+        let label = match decode_dstring(&logical_volume[84..212]) {
+            label if label.is_empty() => volume_label,
+            label => label,
+        };
+        let size = partitions
+            .iter()
+            .map(|(_, start, length)| (start + length) * block_size)
+            .max()
+            .unwrap_or(0);
+
+        let mut volume = UdfVolume {
+            label,
+            revision: le_u16(&logical_volume, 216 + 24),
+            block_size: logical_block_size,
+            size,
+            mappings: Vec::new(),
+            root: LongAd { length: 0, block: 0, partition: 0 },
+        };
+
+        // Metadata partitions can only be resolved once physical partitions are known
+        let mut metadata_files = Vec::new();
+        let table_length = le_u32(&logical_volume, 264) as usize;
+        let table = &logical_volume[440..440 + table_length];
+        let mut offset = 0;
+        while offset + 2 <= table.len() {
+            let map_type = table[offset];
+            let map_length = table[offset + 1] as usize;
+            if map_length < 2 || offset + map_length > table.len() {
+                break;
+            }
+            let map = &table[offset..offset + map_length];
+            let mapping = match map_type {
+                1 if map_length >= 6 => physical_mapping(&partitions, le_u16(map, 4)),
+                2 if map_length >= 64 => {
+                    let identifier = &map[5..28];
+                    let number = le_u16(map, 38);
+                    if identifier.starts_with(b"*UDF Metadata Partition") {
+                        metadata_files.push((volume.mappings.len(), number, le_u32(map, 40)));
+                        PartitionMapping::Metadata { runs: Vec::new() }
+                    } else if identifier.starts_with(b"*UDF Sparable Partition") {
+                        physical_mapping(&partitions, number)
+                    } else {
+                        PartitionMapping::Unsupported
+                    }
+                }
+                _ => PartitionMapping::Unsupported,
+            };
+            volume.mappings.push(mapping);
+            offset += map_length;
+        }
+
+        for (index, number, location) in metadata_files {
+            let physical = volume.mappings.iter().position(|mapping| {
+                matches!(mapping, PartitionMapping::Physical { number: n, .. } if *n == number)
+            });
+            let physical = physical.ok_or_else(|| {
+                ImageError::InvalidImage(format!("UDF metadata partition refers to missing partition {}", number))
+            })?;
+            let icb = LongAd { length: 0, block: location, partition: physical as u16 };
+            let node = volume.read_node(reader, icb)?.ok_or_else(|| {
+                ImageError::InvalidImage("UDF metadata file entry not found".to_string())
+            })?;
+            volume.mappings[index] = PartitionMapping::Metadata { runs: node.runs };
+        }
+
+        let file_set = LongAd::parse(&logical_volume[248..264]);
+        let file_set_offset = volume.resolve(file_set.partition, file_set.block).ok_or_else(|| {
+            ImageError::InvalidImage("UDF file set descriptor is outside the partitions".to_string())
+        })?;
+        let descriptor = read_bytes(reader, file_set_offset, 512)?;
+        if le_u16(&descriptor, 0) != TAG_FILE_SET {
+            return Err(ImageError::InvalidImage("UDF file set descriptor not found".to_string()));
+        }
+        volume.root = LongAd::parse(&descriptor[400..416]);
+
+        Ok(Some(volume))
+    }
+
+    /// Returns the logical volume identifier.
+    pub(crate) fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Returns the size of the recorded partitions in bytes.
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns a short description including the UDF revision.
+    pub(crate) fn description(&self) -> String {
+        if self.revision == 0 {
+            "UDF".to_string()
+        } else {
+            format!("UDF {:x}.{:02x}", self.revision >> 8, self.revision & 0xFF)
+        }
+    }
+
+    /// Lists every regular file of the volume.
+    pub(crate) fn files<R: Read + Seek>(&self, reader: &mut R) -> Result<Vec<OpticalFile>, ImageError> {
+        let mut files = Vec::new();
+        let mut visited = HashSet::new();
+        let mut pending = vec![(String::new(), self.root)];
+
+        while let Some((dir_path, icb)) = pending.pop() {
+            if !visited.insert((icb.partition, icb.block)) {
+                continue;
+            }
+            let directory = match self.read_node(reader, icb)? {
+                Some(node) if node.file_type == FILE_TYPE_DIRECTORY => node,
+                _ => continue,
+            };
+            let content = read_runs(reader, &directory.runs)?;
+
+            for (name, child) in parse_file_identifiers(&content) {
+                let path = format!("{}/{}", dir_path, name);
+                let node = match self.read_node(reader, child)? {
+                    Some(node) => node,
+                    None => continue,
+                };
+                match node.file_type {
+                    FILE_TYPE_DIRECTORY => pending.push((path, child)),
+                    FILE_TYPE_REGULAR | FILE_TYPE_UNSPECIFIED => files.push(OpticalFile {
+                        path,
+                        size: node.size,
+                        times: node.times,
+                        runs: node.runs,
+                    }),
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Translates a partition-relative block into a media byte offset.
+    fn resolve(&self, partition: u16, block: u32) -> Option<u64> {
+        match self.mappings.get(partition as usize)? {
+            PartitionMapping::Physical { start, .. } => Some((start + block as u64) * self.block_size),
+            PartitionMapping::Metadata { runs } => {
+                let mut position = block as u64 * self.block_size;
+                for run in runs {
+                    if position < run.len() {
+                        return match run {
+                            DataRun::Extent { offset, .. } => Some(offset + position),
+                            _ => None,
+                        };
+                    }
+                    position -= run.len();
+                }
+                None
+            }
+            PartitionMapping::Unsupported => None,
+        }
+    }
+
+    /// Translates a partition-relative extent into media data runs.
+    fn resolve_extent(&self, partition: u16, block: u32, length: u64) -> Option<Vec<DataRun>> {
+        match self.mappings.get(partition as usize)? {
+            PartitionMapping::Metadata { .. } => {
+                // Metadata file extents may be fragmented; translate block by block
+                let mut runs: Vec<DataRun> = Vec::new();
+                let mut remaining = length;
+                let mut current = block;
+                while remaining > 0 {
+                    let offset = self.resolve(partition, current)?;
+                    let chunk = remaining.min(self.block_size);
+                    match runs.last_mut() {
+                        Some(DataRun::Extent { offset: start, length }) if *start + *length == offset => {
+                            *length += chunk
+                        }
+                        _ => runs.push(DataRun::Extent { offset, length: chunk }),
+                    }
+                    remaining -= chunk;
+                    current += 1;
+                }
+                Some(runs)
+            }
+            _ => Some(vec![DataRun::Extent {
+                offset: self.resolve(partition, block)?,
+                length,
+            }]),
+        }
+    }
+
+    /// Reads the file entry stored at an ICB.
+    ///
+    /// Returns `Ok(None)` if the ICB does not hold a file entry this reader understands.
+    fn read_node<R: Read + Seek>(&self, reader: &mut R, icb: LongAd) -> Result<Option<Node>, ImageError> {
+        let offset = match self.resolve(icb.partition, icb.block) {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+        let entry = read_bytes(reader, offset, self.block_size as usize)?;
+
+        // Offsets of the access, modification, creation and attribute times; plain file entries have no creation time
+        let (access_offset, modified_offset, created_offset, changed_offset, lengths_offset) = match le_u16(&entry, 0) {
+            TAG_FILE_ENTRY => (72, 84, None, 96, 168),
+            TAG_EXTENDED_FILE_ENTRY => (80, 92, Some(104), 116, 208),
+            _ => return Ok(None),
+        };
+        let file_type = entry[27];
+        let ad_type = le_u16(&entry, 34) & 0x07;
+        let size = le_u64(&entry, 56);
+        let ea_length = le_u32(&entry, lengths_offset) as usize;
+        let ad_length = le_u32(&entry, lengths_offset + 4) as usize;
+        let ad_start = lengths_offset + 8 + ea_length;
+        if ad_start + ad_length > entry.len() {
+            return Ok(None);
+        }
+        let descriptors = &entry[ad_start..ad_start + ad_length];
+
+        let runs = match ad_type {
+            AD_EMBEDDED => vec![DataRun::Resident(descriptors.to_vec())],
+            AD_SHORT | AD_LONG => match self.read_allocation(reader, descriptors, ad_type, icb.partition)? {
+                Some(runs) => runs,
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+
+        Ok(Some(Node {
+            file_type,
+            size,
+            times: FileTimes::new()
+                .with_modified(parse_timestamp(&entry[modified_offset..modified_offset + 12]))
+                .with_accessed(parse_timestamp(&entry[access_offset..access_offset + 12]))
+                .with_changed(parse_timestamp(&entry[changed_offset..changed_offset + 12]))
+                .with_born(created_offset.and_then(|offset| parse_timestamp(&entry[offset..offset + 12]))),
+            runs: truncate_runs(runs, size),
+        }))
+    }
+
+    /// Decodes short or long allocation descriptors, following continuation extents.
+    fn read_allocation<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        descriptors: &[u8],
+        ad_type: u16,
+        partition: u16,
+    ) -> Result<Option<Vec<DataRun>>, ImageError> {
+        let ad_size = if ad_type == AD_SHORT { 8 } else { 16 };
+        let mut runs = Vec::new();
+        let mut descriptors = descriptors.to_vec();
+
+        for _ in 0..MAX_ALLOCATION_EXTENTS {
+            let mut continuation = None;
+            for ad in descriptors.chunks_exact(ad_size) {
+                let raw_length = le_u32(ad, 0);
+                let length = (raw_length & 0x3FFF_FFFF) as u64;
+                if length == 0 {
+                    break;
+                }
+                let block = le_u32(ad, 4);
+                let ad_partition = if ad_type == AD_SHORT { partition } else { le_u16(ad, 8) };
+
+                match raw_length >> 30 {
+                    EXTENT_RECORDED => match self.resolve_extent(ad_partition, block, length) {
+                        Some(extent) => runs.extend(extent),
+                        None => return Ok(None),
+                    },
+                    EXTENT_CONTINUATION => {
+                        continuation = Some((ad_partition, block));
+                        break;
+                    }
+                    _ => runs.push(DataRun::Sparse { length }),
+                }
+            }
+
+            let (ad_partition, block) = match continuation {
+                Some(continuation) => continuation,
+                None => return Ok(Some(runs)),
+            };
+            let offset = match self.resolve(ad_partition, block) {
+                Some(offset) => offset,
+                None => return Ok(None),
+            };
+            let extent = read_bytes(reader, offset, self.block_size as usize)?;
+            if le_u16(&extent, 0) != TAG_ALLOCATION_EXTENT {
+                return Ok(None);
+            }
+            let length = (le_u32(&extent, 20) as usize).min(extent.len() - 24);
+            descriptors = extent[24..24 + length].to_vec();
+        }
+
+        Ok(Some(runs))
+    }
+}
+
+/// Builds the mapping of a physical partition from the partition descriptors.
+fn physical_mapping(partitions: &[(u16, u64, u64)], number: u16) -> PartitionMapping {
+    match partitions.iter().find(|(n, _, _)| *n == number) {
+        Some((_, start, _)) => PartitionMapping::Physical { number, start: *start },
+        None => PartitionMapping::Unsupported,
+    }
+}
+
+/// Checks the volume recognition sequence for an NSR02 or NSR03 descriptor.
+fn has_nsr_descriptor<R: Read + Seek>(reader: &mut R) -> bool {
+    for index in 0..32 {
+        let offset = RECOGNITION_OFFSET + index * RECOGNITION_DESCRIPTOR_SIZE;
+        let descriptor = match read_bytes(reader, offset, 7) {
+            Ok(descriptor) => descriptor,
+            Err(_) => return false,
+        };
+        match &descriptor[1..6] {
+            b"NSR02" | b"NSR03" => return true,
+            b"BEA01" | b"BOOT2" | b"CD001" | b"CDW02" | b"TEA01" => {}
+            _ => return false,
+        }
+    }
+    false
+}
+
+/// Locates the anchor volume descriptor pointer, returning the block size and descriptor.
+fn find_anchor<R: Read + Seek>(reader: &mut R) -> Option<(u64, Vec<u8>)> {
+    BLOCK_SIZES.iter().find_map(|&block_size| {
+        let anchor = read_bytes(reader, ANCHOR_SECTOR * block_size, 512).ok()?;
+        let valid = le_u16(&anchor, 0) == TAG_ANCHOR && le_u32(&anchor, 12) as u64 == ANCHOR_SECTOR;
+        valid.then_some((block_size, anchor))
+    })
+}
+
+/// Parses a directory's file identifier descriptors into `(name, icb)` pairs.
+///
+/// Deleted entries and the parent directory entry are skipped.
+fn parse_file_identifiers(content: &[u8]) -> Vec<(String, LongAd)> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+
+    while offset + 38 <= content.len() {
+        if le_u16(content, offset) != TAG_FILE_IDENTIFIER {
+            break;
+        }
+        let characteristics = content[offset + 18];
+        let name_length = content[offset + 19] as usize;
+        let icb = LongAd::parse(&content[offset + 20..offset + 36]);
+        let use_length = le_u16(content, offset + 36) as usize;
+        let name_start = offset + 38 + use_length;
+        if name_start + name_length > content.len() {
+            break;
+        }
+
+        if characteristics & (CHARACTERISTIC_DELETED | CHARACTERISTIC_PARENT) == 0 && name_length > 0 {
+            entries.push((decode_cs0(&content[name_start..name_start + name_length]), icb));
+        }
+
+        offset += (38 + use_length + name_length + 3) & !3;
+    }
+
+    entries
+}
+
+/// Decodes an OSTA compressed unicode string whose first byte is the compression id.
+fn decode_cs0(bytes: &[u8]) -> String {
+    match bytes.split_first() {
+        Some((16, rest)) | Some((255, rest)) => {
+            let units: Vec<u16> = rest
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        Some((_, rest)) => rest.iter().map(|&byte| byte as char).collect(),
+        None => String::new(),
+    }
+}
+
+/// Decodes a fixed-length `dstring` field whose last byte holds the used length.
+fn decode_dstring(field: &[u8]) -> String {
+    let length = (*field.last().unwrap_or(&0) as usize).min(field.len() - 1);
+    decode_cs0(&field[..length]).trim_end_matches([' ', '\0']).to_string()
+}
+
+/// Parses an ECMA-167 timestamp.
+fn parse_timestamp(stamp: &[u8]) -> Option<DateTime<Utc>> {
+    let type_and_zone = le_u16(stamp, 0);
+    let mut zone = (type_and_zone & 0x0FFF) as i32;
+    if zone & 0x0800 != 0 {
+        zone -= 0x1000;
+    }
+    // -2047 means the time zone was not specified
+    if zone == -2047 || type_and_zone >> 12 != 1 {
+        zone = 0;
+    }
+    let micros = stamp[9] as u32 * 10_000 + stamp[10] as u32 * 100 + stamp[11] as u32;
+
+    let date = NaiveDate::from_ymd_opt(le_u16(stamp, 2) as i16 as i32, stamp[4] as u32, stamp[5] as u32)
+        .and_then(|date| date.and_hms_micro_opt(stamp[6] as u32, stamp[7] as u32, stamp[8] as u32, micros));
+    let offset = FixedOffset::east_opt(zone * 60);
+
+    date.zip(offset)
+        .and_then(|(date, offset)| offset.from_local_datetime(&date).single())
+        .map(|date| date.with_timezone(&Utc))
+}
+
+/// Cuts a list of runs down to `size` bytes, dropping the block padding at the end.
+fn truncate_runs(runs: Vec<DataRun>, size: u64) -> Vec<DataRun> {
+    let mut remaining = size;
+    let mut truncated = Vec::new();
+    for run in runs {
+        if remaining == 0 {
+            break;
+        }
+        let length = run.len().min(remaining);
+        remaining -= length;
+        truncated.push(match run {
+            DataRun::Extent { offset, .. } => DataRun::Extent { offset, length },
+            DataRun::Sparse { .. } => DataRun::Sparse { length },
+            DataRun::Resident(mut data) => {
+                data.truncate(length as usize);
+                DataRun::Resident(data)
+            }
+        });
+    }
+    truncated
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const BLOCK: usize = 2048;
+    const PARTITION_START: usize = 300;
+
+    /// Writes a descriptor tag with the given identifier and location.
+    fn tag(block: &mut [u8], identifier: u16, location: u32) {
+        block[0..2].copy_from_slice(&identifier.to_le_bytes());
+        block[2..4].copy_from_slice(&2u16.to_le_bytes());
+        block[12..16].copy_from_slice(&location.to_le_bytes());
+    }
+
+    /// Builds a file identifier descriptor pointing at `icb_block` of partition 0.
+    fn file_identifier(name: &str, icb_block: u32, characteristics: u8) -> Vec<u8> {
+        let mut encoded = if name.is_empty() { Vec::new() } else { vec![8] };
+        encoded.extend_from_slice(name.as_bytes());
+        let length = (38 + encoded.len() + 3) & !3;
+        let mut fid = vec![0u8; length];
+        tag(&mut fid, TAG_FILE_IDENTIFIER, 0);
+        fid[18] = characteristics;
+        fid[19] = encoded.len() as u8;
+        fid[20..24].copy_from_slice(&(BLOCK as u32).to_le_bytes());
+        fid[24..28].copy_from_slice(&icb_block.to_le_bytes());
+        fid[38..38 + encoded.len()].copy_from_slice(&encoded);
+        fid
+    }
+
+    /// Builds a file entry with the given allocation descriptors.
+    fn file_entry(file_type: u8, size: u64, ad_type: u16, descriptors: &[u8]) -> Vec<u8> {
+        let mut entry = vec![0u8; BLOCK];
+        tag(&mut entry, TAG_FILE_ENTRY, 0);
+        entry[27] = file_type;
+        entry[34..36].copy_from_slice(&ad_type.to_le_bytes());
+        entry[56..64].copy_from_slice(&size.to_le_bytes());
+        // 2024-03-15 10:30:00 UTC
+        entry[84..86].copy_from_slice(&0x1000u16.to_le_bytes());
+        entry[86..88].copy_from_slice(&2024u16.to_le_bytes());
+        entry[88..93].copy_from_slice(&[3, 15, 10, 30, 0]);
+        entry[172..176].copy_from_slice(&(descriptors.len() as u32).to_le_bytes());
+        entry[176..176 + descriptors.len()].copy_from_slice(descriptors);
+        entry
+    }
+
+    /// Copies `data` into `image` at the start of media block `block`.
+    fn put(image: &mut [u8], block: usize, data: &[u8]) {
+        image[block * BLOCK..block * BLOCK + data.len()].copy_from_slice(data);
+    }
+
+    /// Builds a UDF 2.01 image with `/Photos/holiday.jpg` and `/readme.txt`.
+    pub(crate) fn build_udf_image() -> Vec<u8> {
+        let mut image = vec![0u8; (PARTITION_START + 40) * BLOCK];
+
+        for (index, identifier) in [b"BEA01", b"NSR02", b"TEA01"].iter().enumerate() {
+            let mut descriptor = vec![0u8; 7];
+            descriptor[1..6].copy_from_slice(*identifier);
+            descriptor[6] = 1;
+            put(&mut image, 16 + index, &descriptor);
+        }
+
+        let mut anchor = vec![0u8; 512];
+        tag(&mut anchor, TAG_ANCHOR, 256);
+        anchor[16..20].copy_from_slice(&(4 * BLOCK as u32).to_le_bytes());
+        anchor[20..24].copy_from_slice(&32u32.to_le_bytes());
+        put(&mut image, 256, &anchor);
+
+        let mut primary = vec![0u8; 512];
+        tag(&mut primary, TAG_PRIMARY_VOLUME, 32);
+        put(&mut image, 32, &primary);
+
+        let mut partition = vec![0u8; 512];
+        tag(&mut partition, TAG_PARTITION, 33);
+        partition[188..192].copy_from_slice(&(PARTITION_START as u32).to_le_bytes());
+        partition[192..196].copy_from_slice(&40u32.to_le_bytes());
+        put(&mut image, 33, &partition);
+
+        let mut logical = vec![0u8; 512];
+        tag(&mut logical, TAG_LOGICAL_VOLUME, 34);
+        let label = b"\x08CASE_0042";
+        logical[84..84 + label.len()].copy_from_slice(label);
+        logical[211] = label.len() as u8;
+        logical[212..216].copy_from_slice(&(BLOCK as u32).to_le_bytes());
+        logical[240..242].copy_from_slice(&0x0201u16.to_le_bytes());
+        logical[248..252].copy_from_slice(&(BLOCK as u32).to_le_bytes());
+        logical[264..268].copy_from_slice(&6u32.to_le_bytes());
+        logical[268..272].copy_from_slice(&1u32.to_le_bytes());
+        logical[440..446].copy_from_slice(&[1, 6, 1, 0, 0, 0]);
+        put(&mut image, 34, &logical);
+
+        let mut terminating = vec![0u8; 512];
+        tag(&mut terminating, TAG_TERMINATING, 35);
+        put(&mut image, 35, &terminating);
+
+        // Partition blocks: 0 file set, 1 root, 2 photos directory, 3 holiday.jpg, 4 readme.txt
+        let mut file_set = vec![0u8; 512];
+        tag(&mut file_set, TAG_FILE_SET, 0);
+        file_set[400..404].copy_from_slice(&(BLOCK as u32).to_le_bytes());
+        file_set[404..408].copy_from_slice(&1u32.to_le_bytes());
+        put(&mut image, PARTITION_START, &file_set);
+
+        let mut root = file_identifier("", 1, CHARACTERISTIC_PARENT);
+        root.extend(file_identifier("Photos", 2, 0x02));
+        root.extend(file_identifier("readme.txt", 4, 0));
+        root.extend(file_identifier("old.txt", 4, CHARACTERISTIC_DELETED));
+        put(&mut image, PARTITION_START + 1, &file_entry(FILE_TYPE_DIRECTORY, root.len() as u64, AD_EMBEDDED, &root));
+
+        let mut photos = file_identifier("", 1, CHARACTERISTIC_PARENT);
+        photos.extend(file_identifier("holiday.jpg", 3, 0));
+        put(&mut image, PARTITION_START + 2, &file_entry(FILE_TYPE_DIRECTORY, photos.len() as u64, AD_EMBEDDED, &photos));
+
+        // holiday.jpg: 3000 bytes in two short_ad extents at blocks 10 and 20
+        let mut descriptors = Vec::new();
+        descriptors.extend_from_slice(&2048u32.to_le_bytes());
+        descriptors.extend_from_slice(&10u32.to_le_bytes());
+        descriptors.extend_from_slice(&2048u32.to_le_bytes());
+        descriptors.extend_from_slice(&20u32.to_le_bytes());
+        put(&mut image, PARTITION_START + 3, &file_entry(FILE_TYPE_REGULAR, 3000, AD_SHORT, &descriptors));
+        put(&mut image, PARTITION_START + 10, &[0xFF, 0xD8, 0xFF, 0xE0]);
+
+        put(&mut image, PARTITION_START + 4, &file_entry(FILE_TYPE_REGULAR, 5, AD_EMBEDDED, b"hello"));
+
+        image
+    }
+
+    #[test]
+    fn test_udf_volume() {
+        let mut reader = Cursor::new(build_udf_image());
+        let volume = UdfVolume::open(&mut reader).unwrap().unwrap();
+
+        assert_eq!(volume.label(), "CASE_0042");
+        assert_eq!(volume.description(), "UDF 2.01");
+        assert_eq!(volume.size(), (PARTITION_START as u64 + 40) * BLOCK as u64);
+
+        let mut files = volume.files(&mut reader).unwrap();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(files.len(), 2);
+
+        assert_eq!(files[0].path, "/Photos/holiday.jpg");
+        assert_eq!(files[0].size, 3000);
+        assert_eq!(
+            files[0].runs,
+            vec![
+                DataRun::Extent { offset: (PARTITION_START as u64 + 10) * 2048, length: 2048 },
+                DataRun::Extent { offset: (PARTITION_START as u64 + 20) * 2048, length: 952 },
+            ]
+        );
+        assert_eq!(files[0].times.modified().unwrap().to_string(), "2024-03-15 10:30:00 UTC");
+
+        assert_eq!(files[1].path, "/readme.txt");
+        assert_eq!(files[1].runs, vec![DataRun::Resident(b"hello".to_vec())]);
+    }
+
+    #[test]
+    fn test_not_udf() {
+        let mut reader = Cursor::new(vec![0u8; 300 * BLOCK]);
+        assert!(UdfVolume::open(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_decode_cs0() {
+        assert_eq!(decode_cs0(b"\x08plain"), "plain");
+        assert_eq!(decode_cs0(&[16, 0x00, 0x41, 0x00, 0xE9]), "A\u{e9}");
+        assert_eq!(decode_cs0(&[]), "");
+    }
+
+    #[test]
+    fn test_truncate_runs() {
+        let runs = vec![
+            DataRun::Extent { offset: 0, length: 2048 },
+            DataRun::Sparse { length: 2048 },
+        ];
+        assert_eq!(
+            truncate_runs(runs, 2100),
+            vec![DataRun::Extent { offset: 0, length: 2048 }, DataRun::Sparse { length: 52 }]
+        );
+    }
+}
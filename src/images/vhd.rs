@@ -0,0 +1,442 @@
+//! Microsoft Virtual Hard Disk (VHD) reader.
+//!
+//! Fixed disks store the media followed by a 512-byte footer. Dynamic and
+//! differencing disks map fixed-size blocks through a block allocation table
+//! (BAT); each allocated block starts with a sector bitmap that tells a
+//! differencing disk which sectors must be read from its parent instead.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use super::{be_u32, be_u64, format_guid, open_parent, read_file_at, read_source_at, ImageError, ReadAt};
+
+/// Size of the footer and of a VHD sector
+const SECTOR_SIZE: u64 = 512;
+/// Cookie identifying the footer
+const FOOTER_COOKIE: &[u8] = b"conectix";
+/// Cookie identifying the dynamic disk header
+const DYNAMIC_COOKIE: &[u8] = b"cxsparse";
+/// Size of the dynamic disk header
+const DYNAMIC_HEADER_SIZE: usize = 1024;
+/// BAT entry of a block that has not been allocated
+const UNALLOCATED_BLOCK: u32 = 0xFFFF_FFFF;
+/// Largest block size accepted, to bound allocations on corrupt headers
+const MAX_BLOCK_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Disk types stored in the footer
+const DISK_TYPE_FIXED: u32 = 2;
+const DISK_TYPE_DYNAMIC: u32 = 3;
+const DISK_TYPE_DIFFERENCING: u32 = 4;
+
+/// Parent locator platform codes
+const PLATFORM_WINDOWS_RELATIVE: u32 = 0x5732_7275; // "W2ru"
+const PLATFORM_WINDOWS_ABSOLUTE: u32 = 0x5732_6B75; // "W2ku"
+const PLATFORM_MAC_OS_X: u32 = 0x4D61_6358; // "MacX"
+
+/// Returns true if `buf` starts with a VHD footer cookie.
+pub(crate) fn is_footer(buf: &[u8]) -> bool {
+    buf.starts_with(FOOTER_COOKIE)
+}
+
+/// Block map of a dynamic or differencing disk.
+struct BlockTable {
+    /// Size of each block in bytes
+    block_size: u64,
+    /// Size of the sector bitmap preceding each block, in bytes
+    bitmap_size: u64,
+    /// Sector offset of each block in the file
+    entries: Vec<u32>,
+    /// Bitmap of the most recently read block
+    cached_bitmap: Option<(u64, Vec<u8>)>,
+}
+
+/// A VHD image of any disk type.
+pub(crate) struct VhdImage {
+    file: File,
+    /// Virtual disk size in bytes
+    size: u64,
+    /// Unique identifier of the virtual disk
+    identifier: String,
+    /// Block map, absent for fixed disks
+    blocks: Option<BlockTable>,
+    /// Parent of a differencing disk
+    parent: Option<Box<dyn ReadAt>>,
+}
+
+impl VhdImage {
+    /// Opens a VHD image, resolving the parent chain of differencing disks.
+    ///
+    /// `depth` counts the differencing disks already opened above this one.
+    pub(crate) fn open(path: &Path, depth: usize) -> Result<Self, ImageError> {
+        let mut file = File::open(path)?;
+        let file_size = file.seek(SeekFrom::End(0))?;
+        if file_size < SECTOR_SIZE {
+            return Err(ImageError::InvalidImage("VHD file is smaller than its footer".to_string()));
+        }
+
+        let mut footer = read_file_at(&mut file, file_size - SECTOR_SIZE, SECTOR_SIZE as usize)?;
+        if !is_footer(&footer) {
+            // Dynamic disks keep a copy of the footer at the start of the file
+            footer = read_file_at(&mut file, 0, SECTOR_SIZE as usize)?;
+            if !is_footer(&footer) {
+                return Err(ImageError::InvalidImage("VHD footer not found".to_string()));
+            }
+        }
+
+        let size = be_u64(&footer, 48);
+        let disk_type = be_u32(&footer, 60);
+        let identifier = format_guid(&footer[68..84]);
+
+        let mut image = VhdImage {
+            file,
+            size,
+            identifier,
+            blocks: None,
+            parent: None,
+        };
+
+        match disk_type {
+            DISK_TYPE_FIXED => {
+                if size > file_size - SECTOR_SIZE {
+                    return Err(ImageError::InvalidImage("fixed VHD is truncated".to_string()));
+                }
+            }
+            DISK_TYPE_DYNAMIC | DISK_TYPE_DIFFERENCING => {
+                let header_offset = be_u64(&footer, 16);
+                let header = read_file_at(&mut image.file, header_offset, DYNAMIC_HEADER_SIZE)?;
+                if !header.starts_with(DYNAMIC_COOKIE) {
+                    return Err(ImageError::InvalidImage("VHD dynamic header not found".to_string()));
+                }
+                image.blocks = Some(image.read_block_table(&header)?);
+                if disk_type == DISK_TYPE_DIFFERENCING {
+                    let candidates = image.parent_candidates(&header)?;
+                    image.parent = Some(open_parent(path, &candidates, depth)?);
+                }
+            }
+            other => {
+                return Err(ImageError::UnsupportedFormat(format!("VHD disk type {}", other)));
+            }
+        }
+
+        Ok(image)
+    }
+
+    /// Loads the block allocation table described by the dynamic header.
+    fn read_block_table(&mut self, header: &[u8]) -> Result<BlockTable, ImageError> {
+        let table_offset = be_u64(header, 16);
+        let entry_count = be_u32(header, 28) as u64;
+        let block_size = be_u32(header, 32) as u64;
+
+        if block_size == 0 || !block_size.is_multiple_of(SECTOR_SIZE) || block_size > MAX_BLOCK_SIZE {
+            return Err(ImageError::InvalidImage(format!("invalid VHD block size {}", block_size)));
+        }
+        if entry_count < self.size.div_ceil(block_size) {
+            return Err(ImageError::InvalidImage("VHD block table is too small".to_string()));
+        }
+        let file_size = self.file.metadata()?.len();
+        if table_offset.checked_add(entry_count * 4).is_none_or(|end| end > file_size) {
+            return Err(ImageError::InvalidImage("VHD block table extends past the end of the file".to_string()));
+        }
+
+        let table = read_file_at(&mut self.file, table_offset, (entry_count * 4) as usize)?;
+        let entries = table.chunks_exact(4).map(|entry| be_u32(entry, 0)).collect();
+        let sectors = block_size / SECTOR_SIZE;
+        let bitmap_size = sectors.div_ceil(8).div_ceil(SECTOR_SIZE) * SECTOR_SIZE;
+
+        Ok(BlockTable {
+            block_size,
+            bitmap_size,
+            entries,
+            cached_bitmap: None,
+        })
+    }
+
+    /// Collects the possible parent paths stored in the dynamic header.
+    fn parent_candidates(&mut self, header: &[u8]) -> Result<Vec<String>, ImageError> {
+        let mut relative = Vec::new();
+        let mut absolute = Vec::new();
+
+        for index in 0..8 {
+            let locator = &header[576 + index * 24..576 + (index + 1) * 24];
+            let platform = be_u32(locator, 0);
+            let length = be_u32(locator, 8) as usize;
+            let offset = be_u64(locator, 16);
+            if length == 0 || length > 64 * 1024 {
+                continue;
+            }
+            match platform {
+                PLATFORM_WINDOWS_RELATIVE | PLATFORM_WINDOWS_ABSOLUTE => {
+                    let data = read_file_at(&mut self.file, offset, length)?;
+                    let units: Vec<u16> = data.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+                    let path = String::from_utf16_lossy(&units).trim_end_matches('\0').to_string();
+                    if platform == PLATFORM_WINDOWS_RELATIVE {
+                        relative.push(path);
+                    } else {
+                        absolute.push(path);
+                    }
+                }
+                PLATFORM_MAC_OS_X => {
+                    let data = read_file_at(&mut self.file, offset, length)?;
+                    let url = String::from_utf8_lossy(&data).trim_end_matches('\0').to_string();
+                    absolute.push(url.trim_start_matches("file://").to_string());
+                }
+                _ => {}
+            }
+        }
+
+        // The parent name is stored on its own as a last resort
+        let units: Vec<u16> = header[64..576].chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+        let name = String::from_utf16_lossy(&units).trim_end_matches('\0').to_string();
+
+        let mut candidates = relative;
+        candidates.extend(absolute);
+        if !name.is_empty() {
+            candidates.push(name);
+        }
+        Ok(candidates)
+    }
+
+    /// Reads part of a single block of a dynamic or differencing disk.
+    fn read_block(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let blocks = self.blocks.as_mut().expect("block table of a dynamic VHD");
+        let block = offset / blocks.block_size;
+        let within = offset % blocks.block_size;
+        let entry = blocks.entries.get(block as usize).copied().unwrap_or(UNALLOCATED_BLOCK);
+
+        if entry == UNALLOCATED_BLOCK {
+            return match self.parent.as_mut() {
+                Some(parent) => read_source_at(parent.as_mut(), offset, buf),
+                None => {
+                    buf.fill(0);
+                    Ok(())
+                }
+            };
+        }
+
+        let block_start = entry as u64 * SECTOR_SIZE;
+        let data_start = block_start + blocks.bitmap_size;
+        let parent = match self.parent.as_mut() {
+            Some(parent) => parent,
+            None => {
+                let data = read_file_at(&mut self.file, data_start + within, buf.len())?;
+                buf.copy_from_slice(&data);
+                return Ok(());
+            }
+        };
+
+        // Differencing disk: sectors whose bitmap bit is clear belong to the parent
+        if blocks.cached_bitmap.as_ref().map(|(cached, _)| *cached) != Some(block) {
+            let bitmap = read_file_at(&mut self.file, block_start, blocks.bitmap_size as usize)?;
+            blocks.cached_bitmap = Some((block, bitmap));
+        }
+        let bitmap = &blocks.cached_bitmap.as_ref().expect("cached bitmap").1;
+
+        let mut done = 0;
+        while done < buf.len() {
+            let position = within + done as u64;
+            let sector = position / SECTOR_SIZE;
+            let present = bitmap[(sector / 8) as usize] & (0x80 >> (sector % 8)) != 0;
+
+            // Extend the run over following sectors with the same state
+            let mut end = ((sector + 1) * SECTOR_SIZE).min(within + buf.len() as u64);
+            while end < within + buf.len() as u64 {
+                let next = end / SECTOR_SIZE;
+                if (bitmap[(next / 8) as usize] & (0x80 >> (next % 8)) != 0) != present {
+                    break;
+                }
+                end = ((next + 1) * SECTOR_SIZE).min(within + buf.len() as u64);
+            }
+
+            let chunk = &mut buf[done..(end - within) as usize];
+            if present {
+                let data = read_file_at(&mut self.file, data_start + position, chunk.len())?;
+                chunk.copy_from_slice(&data);
+            } else {
+                read_source_at(parent.as_mut(), block * blocks.block_size + position, chunk)?;
+            }
+            done = (end - within) as usize;
+        }
+        Ok(())
+    }
+}
+
+impl ReadAt for VhdImage {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn identifier(&self) -> Option<String> {
+        Some(self.identifier.clone())
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if offset >= self.size {
+            return Ok(0);
+        }
+        let len = buf.len().min((self.size - offset) as usize);
+
+        let block_size = match &self.blocks {
+            Some(blocks) => blocks.block_size,
+            None => {
+                self.file.seek(SeekFrom::Start(offset))?;
+                return self.file.read(&mut buf[..len]);
+            }
+        };
+
+        let within = offset % block_size;
+        let len = len.min((block_size - within) as usize);
+        self.read_block(offset, &mut buf[..len])?;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::images::{ImageFormat, VirtualDisk};
+
+    /// Builds a VHD footer for a disk of the given type and size.
+    fn footer(disk_type: u32, size: u64, header_offset: u64) -> Vec<u8> {
+        let mut footer = vec![0u8; 512];
+        footer[0..8].copy_from_slice(FOOTER_COOKIE);
+        footer[16..24].copy_from_slice(&header_offset.to_be_bytes());
+        footer[40..48].copy_from_slice(&size.to_be_bytes());
+        footer[48..56].copy_from_slice(&size.to_be_bytes());
+        footer[60..64].copy_from_slice(&disk_type.to_be_bytes());
+        footer[68..84].copy_from_slice(&[0x11; 16]);
+        footer
+    }
+
+    /// Builds a dynamic or differencing VHD with 4 KiB blocks.
+    ///
+    /// `blocks` lists `(block index, sector bitmap byte, data)` for allocated blocks.
+    pub(crate) fn build_dynamic_vhd(size: u64, blocks: &[(usize, u8, Vec<u8>)], parent: Option<&str>) -> Vec<u8> {
+        const BLOCK_SIZE: usize = 4096;
+        let entries = (size as usize).div_ceil(BLOCK_SIZE);
+        let table_offset = 512 + 1024;
+        let table_size = (entries * 4).div_ceil(512) * 512;
+        let locator_offset = table_offset + table_size;
+        let mut image = vec![0u8; locator_offset + 512];
+
+        let disk_type = if parent.is_some() { DISK_TYPE_DIFFERENCING } else { DISK_TYPE_DYNAMIC };
+        let footer = footer(disk_type, size, 512);
+        image[0..512].copy_from_slice(&footer);
+
+        let header = &mut image[512..1536];
+        header[0..8].copy_from_slice(DYNAMIC_COOKIE);
+        header[16..24].copy_from_slice(&(table_offset as u64).to_be_bytes());
+        header[28..32].copy_from_slice(&(entries as u32).to_be_bytes());
+        header[32..36].copy_from_slice(&(BLOCK_SIZE as u32).to_be_bytes());
+        if let Some(parent) = parent {
+            let locator = &mut header[576..600];
+            let encoded: Vec<u8> = format!(".\\{}", parent).encode_utf16().flat_map(|unit| unit.to_le_bytes()).collect();
+            locator[0..4].copy_from_slice(&PLATFORM_WINDOWS_RELATIVE.to_be_bytes());
+            locator[4..8].copy_from_slice(&512u32.to_be_bytes());
+            locator[8..12].copy_from_slice(&(encoded.len() as u32).to_be_bytes());
+            locator[16..24].copy_from_slice(&(locator_offset as u64).to_be_bytes());
+            image[locator_offset..locator_offset + encoded.len()].copy_from_slice(&encoded);
+        }
+
+        for entry in 0..entries {
+            let position = table_offset + entry * 4;
+            image[position..position + 4].copy_from_slice(&UNALLOCATED_BLOCK.to_be_bytes());
+        }
+        for (index, bitmap, data) in blocks {
+            let sector = (image.len() / 512) as u32;
+            let position = table_offset + index * 4;
+            image[position..position + 4].copy_from_slice(&sector.to_be_bytes());
+            let mut block = vec![0u8; 512 + BLOCK_SIZE];
+            block[0] = *bitmap;
+            block[512..512 + data.len()].copy_from_slice(data);
+            image.extend(block);
+        }
+
+        image.extend(footer);
+        image
+    }
+
+    #[test]
+    fn test_fixed_vhd() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("fixed.vhd");
+        let mut image = vec![0x5Au8; 2048];
+        image.extend(footer(DISK_TYPE_FIXED, 2048, u64::MAX));
+        std::fs::write(&path, image).unwrap();
+
+        let mut device = VirtualDisk::open(&path).unwrap();
+        assert_eq!(device.format(), ImageFormat::Vhd);
+        assert_eq!(device.size(), 2048);
+
+        let mut data = Vec::new();
+        device.read_to_end(&mut data).unwrap();
+        assert_eq!(data, vec![0x5A; 2048]);
+    }
+
+    #[test]
+    fn test_dynamic_vhd() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("dynamic.vhd");
+        let image = build_dynamic_vhd(12288, &[(1, 0xFF, vec![0xAB; 4096])], None);
+        std::fs::write(&path, image).unwrap();
+
+        let mut device = VirtualDisk::open(&path).unwrap();
+        assert_eq!(device.size(), 12288);
+
+        let mut data = Vec::new();
+        device.read_to_end(&mut data).unwrap();
+        assert_eq!(&data[..4096], &[0u8; 4096][..]);
+        assert_eq!(&data[4096..8192], &[0xAB; 4096][..]);
+        assert_eq!(&data[8192..], &[0u8; 4096][..]);
+    }
+
+    #[test]
+    fn test_differencing_vhd() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let parent = build_dynamic_vhd(8192, &[(0, 0xFF, vec![0x01; 4096]), (1, 0xFF, vec![0x02; 4096])], None);
+        std::fs::write(temp_dir.path().join("base.vhd"), parent).unwrap();
+
+        // Only the first two sectors of block 0 are stored in the child
+        let child = build_dynamic_vhd(8192, &[(0, 0xC0, vec![0xCC; 1024])], Some("base.vhd"));
+        let child_path = temp_dir.path().join("child.vhd");
+        std::fs::write(&child_path, child).unwrap();
+
+        let mut device = VirtualDisk::open(&child_path).unwrap();
+        let mut data = Vec::new();
+        device.read_to_end(&mut data).unwrap();
+
+        assert_eq!(&data[..1024], &[0xCC; 1024][..]);
+        assert_eq!(&data[1024..4096], &[0x01; 3072][..]);
+        assert_eq!(&data[4096..], &[0x02; 4096][..]);
+    }
+
+    #[test]
+    fn test_differencing_vhd_missing_parent() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let child = build_dynamic_vhd(8192, &[], Some("missing.vhd"));
+        let child_path = temp_dir.path().join("child.vhd");
+        std::fs::write(&child_path, child).unwrap();
+
+        assert!(matches!(VirtualDisk::open(&child_path), Err(ImageError::MissingParent(_))));
+    }
+
+    #[test]
+    fn test_oversized_block_table() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("corrupt.vhd");
+        let mut image = build_dynamic_vhd(8192, &[], None);
+        image[512 + 28..512 + 32].copy_from_slice(&u32::MAX.to_be_bytes());
+        std::fs::write(&path, &image).unwrap();
+        assert!(matches!(VirtualDisk::open(&path), Err(ImageError::InvalidImage(_))));
+
+        // Lengths read from headers never allocate more than the source holds
+        let mut file = File::open(&path).unwrap();
+        let error = read_file_at(&mut file, 512, u32::MAX as usize * 4).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        let mut reader = io::Cursor::new(image);
+        let error = crate::images::read_bytes(&mut reader, 512, u32::MAX as usize * 4).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,707 @@
+//! Microsoft VHDX virtual disk reader.
+//!
+//! The current header is selected by sequence number, then any pending log
+//! entries are replayed into an in-memory overlay so the image is read in
+//! the consistent state Hyper-V would restore, without modifying the file.
+//! Payload blocks are located through the block allocation table (BAT);
+//! differencing disks consult sector bitmaps to fall back to their parent.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use super::{format_guid, le_u16, le_u32, le_u64, open_parent, read_source_at, ImageError, ReadAt};
+
+/// File type identifier at the start of every VHDX file
+const FILE_SIGNATURE: &[u8] = b"vhdxfile";
+/// Offsets of the two header copies
+const HEADER_OFFSETS: [u64; 2] = [64 * 1024, 128 * 1024];
+/// Size covered by the header checksum
+const HEADER_SIZE: usize = 4096;
+/// Offset of the region table
+const REGION_TABLE_OFFSET: u64 = 192 * 1024;
+/// Size covered by the region table checksum
+const REGION_TABLE_SIZE: usize = 64 * 1024;
+/// Unit of log entries and of log data sectors
+const LOG_SECTOR_SIZE: u64 = 4096;
+/// Unit of BAT file offsets
+const MEGABYTE: u64 = 1024 * 1024;
+/// Number of sectors described by one sector bitmap block
+const SECTORS_PER_BITMAP: u64 = 1 << 23;
+/// Largest log accepted, to bound allocations on corrupt headers
+const MAX_LOG_SIZE: u64 = 256 * MEGABYTE;
+
+/// Region identifiers
+const REGION_BAT: &str = "2DC27766-F623-4200-9D64-115E9BFD4A08";
+const REGION_METADATA: &str = "8B7CA206-4790-4B9A-B8FE-575F050F886E";
+
+/// Metadata item identifiers
+const METADATA_FILE_PARAMETERS: &str = "CAA16737-FA36-4D43-B3B6-33F0AA44E76B";
+const METADATA_VIRTUAL_DISK_SIZE: &str = "2FA54224-CD1B-4876-B211-5DBED83BF4B8";
+const METADATA_VIRTUAL_DISK_ID: &str = "BECA12AB-B2E6-4523-93EF-C309E000C746";
+const METADATA_LOGICAL_SECTOR_SIZE: &str = "8141BF1D-A96F-4709-BA47-F233A8FAAB5F";
+const METADATA_PARENT_LOCATOR: &str = "A8D35F2D-B30B-454D-ABF7-D3D84834AB0C";
+
+/// Payload block states stored in the low bits of a BAT entry
+const BLOCK_NOT_PRESENT: u64 = 0;
+const BLOCK_FULLY_PRESENT: u64 = 6;
+const BLOCK_PARTIALLY_PRESENT: u64 = 7;
+
+/// Computes the CRC-32C (Castagnoli) checksum used by VHDX structures.
+pub(crate) fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0x82F6_3B78 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Checks a structure whose CRC-32C is stored at `checksum_offset`.
+fn checksum_matches(data: &[u8], checksum_offset: usize) -> bool {
+    let stored = le_u32(data, checksum_offset);
+    let mut copy = data.to_vec();
+    copy[checksum_offset..checksum_offset + 4].fill(0);
+    crc32c(&copy) == stored
+}
+
+/// A write recovered from the log, applied over the file contents on read.
+#[derive(Debug)]
+struct LogWrite {
+    /// File offset of the write
+    offset: u64,
+    /// Number of bytes written
+    length: u64,
+    /// Written bytes, or `None` for a zeroing write
+    data: Option<Vec<u8>>,
+}
+
+/// The VHDX file with replayed log writes overlaid.
+struct LoggedFile {
+    file: File,
+    writes: Vec<LogWrite>,
+}
+
+impl LoggedFile {
+    /// Reads `len` bytes at `offset`, treating bytes past the end of the file as zeros.
+    fn read(&mut self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        self.file.seek(SeekFrom::Start(offset))?;
+        let mut filled = 0;
+        while filled < len {
+            let read = self.file.read(&mut buf[filled..])?;
+            if read == 0 {
+                break;
+            }
+            filled += read;
+        }
+
+        let end = offset + len as u64;
+        for write in &self.writes {
+            let write_end = write.offset + write.length;
+            if write_end <= offset || write.offset >= end {
+                continue;
+            }
+            let start = write.offset.max(offset);
+            let stop = write_end.min(end);
+            let target = &mut buf[(start - offset) as usize..(stop - offset) as usize];
+            match &write.data {
+                Some(data) => {
+                    let source = (start - write.offset) as usize;
+                    target.copy_from_slice(&data[source..source + target.len()]);
+                }
+                None => target.fill(0),
+            }
+        }
+        Ok(buf)
+    }
+}
+
+/// A VHDX image of any disk type.
+pub(crate) struct VhdxImage {
+    file: LoggedFile,
+    /// Virtual disk size in bytes
+    size: u64,
+    /// Payload block size in bytes
+    block_size: u64,
+    /// Logical sector size in bytes
+    sector_size: u64,
+    /// Number of payload blocks per sector bitmap block
+    chunk_ratio: u64,
+    /// Block allocation table entries
+    bat: Vec<u64>,
+    /// Virtual disk identifier
+    identifier: Option<String>,
+    /// Parent of a differencing disk
+    parent: Option<Box<dyn ReadAt>>,
+}
+
+impl VhdxImage {
+    /// Opens a VHDX image, replaying its log and resolving the parent chain.
+    ///
+    /// `depth` counts the differencing disks already opened above this one.
+    pub(crate) fn open(path: &Path, depth: usize) -> Result<Self, ImageError> {
+        let mut file = LoggedFile {
+            file: File::open(path)?,
+            writes: Vec::new(),
+        };
+
+        if file.read(0, FILE_SIGNATURE.len())? != FILE_SIGNATURE {
+            return Err(ImageError::InvalidImage("VHDX file identifier not found".to_string()));
+        }
+
+        let mut header = None;
+        for offset in HEADER_OFFSETS {
+            let candidate = file.read(offset, HEADER_SIZE)?;
+            if &candidate[0..4] != b"head" || !checksum_matches(&candidate, 4) {
+                continue;
+            }
+            let newer = header
+                .as_ref()
+                .is_none_or(|current: &Vec<u8>| le_u64(&candidate, 8) > le_u64(current, 8));
+            if newer {
+                header = Some(candidate);
+            }
+        }
+        let header = header.ok_or_else(|| ImageError::InvalidImage("no valid VHDX header".to_string()))?;
+
+        let log_guid = &header[48..64];
+        if log_guid.iter().any(|&byte| byte != 0) {
+            let log_length = le_u32(&header, 68) as u64;
+            let log_offset = le_u64(&header, 72);
+            file.writes = replay_log(&mut file, log_offset, log_length, log_guid)?;
+        }
+
+        let regions = file.read(REGION_TABLE_OFFSET, REGION_TABLE_SIZE)?;
+        if &regions[0..4] != b"regi" || !checksum_matches(&regions, 4) {
+            return Err(ImageError::InvalidImage("invalid VHDX region table".to_string()));
+        }
+        let mut bat_region = None;
+        let mut metadata_region = None;
+        let region_count = (le_u32(&regions, 8) as usize).min((REGION_TABLE_SIZE - 16) / 32);
+        for index in 0..region_count {
+            let entry = &regions[16 + index * 32..16 + (index + 1) * 32];
+            let location = (le_u64(entry, 16), le_u32(entry, 24) as u64);
+            match format_guid(&entry[0..16]).as_str() {
+                REGION_BAT => bat_region = Some(location),
+                REGION_METADATA => metadata_region = Some(location),
+                _ => {}
+            }
+        }
+        let (bat_offset, bat_length) = bat_region
+            .ok_or_else(|| ImageError::InvalidImage("VHDX BAT region not found".to_string()))?;
+        let (metadata_offset, metadata_length) = metadata_region
+            .ok_or_else(|| ImageError::InvalidImage("VHDX metadata region not found".to_string()))?;
+
+        let metadata = Metadata::read(&mut file, metadata_offset, metadata_length)?;
+        let parameters = metadata
+            .item(METADATA_FILE_PARAMETERS)
+            .filter(|item| item.len() >= 8)
+            .ok_or_else(|| ImageError::InvalidImage("VHDX file parameters not found".to_string()))?;
+        let block_size = le_u32(parameters, 0) as u64;
+        let has_parent = le_u32(parameters, 4) & 0x2 != 0;
+        let size = metadata
+            .item(METADATA_VIRTUAL_DISK_SIZE)
+            .filter(|item| item.len() >= 8)
+            .map(|item| le_u64(item, 0))
+            .ok_or_else(|| ImageError::InvalidImage("VHDX virtual disk size not found".to_string()))?;
+        let sector_size = metadata
+            .item(METADATA_LOGICAL_SECTOR_SIZE)
+            .filter(|item| item.len() >= 4)
+            .map(|item| le_u32(item, 0) as u64)
+            .unwrap_or(512);
+        let identifier = metadata
+            .item(METADATA_VIRTUAL_DISK_ID)
+            .filter(|item| item.len() >= 16)
+            .map(|item| format_guid(&item[0..16]));
+
+        if !block_size.is_power_of_two() || !(MEGABYTE..=256 * MEGABYTE).contains(&block_size) {
+            return Err(ImageError::InvalidImage(format!("invalid VHDX block size {}", block_size)));
+        }
+        if sector_size != 512 && sector_size != 4096 {
+            return Err(ImageError::InvalidImage(format!("invalid VHDX sector size {}", sector_size)));
+        }
+
+        let chunk_ratio = SECTORS_PER_BITMAP * sector_size / block_size;
+        let payload_blocks = size.div_ceil(block_size);
+        let entry_count = if has_parent {
+            payload_blocks.div_ceil(chunk_ratio) * (chunk_ratio + 1)
+        } else {
+            payload_blocks + (payload_blocks.saturating_sub(1)) / chunk_ratio
+        };
+        if entry_count * 8 > bat_length {
+            return Err(ImageError::InvalidImage("VHDX BAT region is too small".to_string()));
+        }
+        let table = file.read(bat_offset, (entry_count * 8) as usize)?;
+        let bat = table.chunks_exact(8).map(|entry| le_u64(entry, 0)).collect();
+
+        let parent = if has_parent {
+            let candidates = metadata.parent_candidates();
+            Some(open_parent(path, &candidates, depth)?)
+        } else {
+            None
+        };
+
+        Ok(VhdxImage {
+            file,
+            size,
+            block_size,
+            sector_size,
+            chunk_ratio,
+            bat,
+            identifier,
+            parent,
+        })
+    }
+
+    /// Reads part of a single payload block.
+    fn read_block(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let block = offset / self.block_size;
+        let within = offset % self.block_size;
+        let index = block + block / self.chunk_ratio;
+        let entry = self.bat.get(index as usize).copied().unwrap_or(BLOCK_NOT_PRESENT);
+        let block_offset = (entry >> 20) * MEGABYTE;
+
+        match entry & 0x7 {
+            BLOCK_FULLY_PRESENT => {
+                let data = self.file.read(block_offset + within, buf.len())?;
+                buf.copy_from_slice(&data);
+                Ok(())
+            }
+            BLOCK_PARTIALLY_PRESENT if self.parent.is_some() => {
+                self.read_partial_block(block, block_offset, within, buf)
+            }
+            BLOCK_NOT_PRESENT if self.parent.is_some() => {
+                let parent = self.parent.as_mut().expect("parent of a differencing VHDX");
+                read_source_at(parent.as_mut(), offset, buf)
+            }
+            _ => {
+                buf.fill(0);
+                Ok(())
+            }
+        }
+    }
+
+    /// Reads a partially present block, taking absent sectors from the parent.
+    fn read_partial_block(&mut self, block: u64, block_offset: u64, within: u64, buf: &mut [u8]) -> io::Result<()> {
+        let bitmap_index = (block / self.chunk_ratio) * (self.chunk_ratio + 1) + self.chunk_ratio;
+        let bitmap_entry = self.bat.get(bitmap_index as usize).copied().unwrap_or(0);
+        let bitmap_offset = (bitmap_entry >> 20) * MEGABYTE;
+        // First sector of this block within the chunk covered by the bitmap
+        let chunk_sector = (block % self.chunk_ratio) * self.block_size / self.sector_size;
+
+        let first_sector = within / self.sector_size;
+        let last_sector = (within + buf.len() as u64 - 1) / self.sector_size;
+        let bitmap = self.file.read(
+            bitmap_offset + (chunk_sector + first_sector) / 8,
+            ((last_sector - first_sector) / 8 + 2) as usize,
+        )?;
+        let bit_base = (chunk_sector + first_sector) % 8;
+        let present = |sector: u64| {
+            let bit = bit_base + sector - first_sector;
+            bitmap[(bit / 8) as usize] & (1 << (bit % 8)) != 0
+        };
+
+        let mut done = 0;
+        while done < buf.len() {
+            let position = within + done as u64;
+            let sector = position / self.sector_size;
+            let state = present(sector);
+            let mut end_sector = sector + 1;
+            while end_sector <= last_sector && present(end_sector) == state {
+                end_sector += 1;
+            }
+            let end = (end_sector * self.sector_size).min(within + buf.len() as u64);
+
+            let chunk = &mut buf[done..(end - within) as usize];
+            if state {
+                let data = self.file.read(block_offset + position, chunk.len())?;
+                chunk.copy_from_slice(&data);
+            } else {
+                let parent = self.parent.as_mut().expect("parent of a differencing VHDX");
+                read_source_at(parent.as_mut(), block * self.block_size + position, chunk)?;
+            }
+            done = (end - within) as usize;
+        }
+        Ok(())
+    }
+}
+
+impl ReadAt for VhdxImage {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn identifier(&self) -> Option<String> {
+        self.identifier.clone()
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if offset >= self.size {
+            return Ok(0);
+        }
+        let within = offset % self.block_size;
+        let len = buf
+            .len()
+            .min((self.size - offset) as usize)
+            .min((self.block_size - within) as usize);
+        self.read_block(offset, &mut buf[..len])?;
+        Ok(len)
+    }
+}
+
+/// The metadata region with its item table.
+struct Metadata {
+    region: Vec<u8>,
+    /// `(item id, offset, length)` of each entry
+    items: Vec<(String, usize, usize)>,
+}
+
+impl Metadata {
+    fn read(file: &mut LoggedFile, offset: u64, length: u64) -> Result<Self, ImageError> {
+        if length > 64 * MEGABYTE {
+            return Err(ImageError::InvalidImage("VHDX metadata region is too large".to_string()));
+        }
+        let region = file.read(offset, length as usize)?;
+        if region.len() < 32 || &region[0..8] != b"metadata" {
+            return Err(ImageError::InvalidImage("invalid VHDX metadata table".to_string()));
+        }
+        let count = le_u16(&region, 10) as usize;
+        let mut items = Vec::new();
+        for index in 0..count {
+            let start = 32 + index * 32;
+            if start + 32 > region.len() {
+                break;
+            }
+            let entry = &region[start..start + 32];
+            let item_offset = le_u32(entry, 16) as usize;
+            let item_length = le_u32(entry, 20) as usize;
+            if item_offset + item_length <= region.len() {
+                items.push((format_guid(&entry[0..16]), item_offset, item_length));
+            }
+        }
+        Ok(Metadata { region, items })
+    }
+
+    /// Returns the data of the item with the given identifier.
+    fn item(&self, id: &str) -> Option<&[u8]> {
+        self.items
+            .iter()
+            .find(|(item_id, _, _)| item_id == id)
+            .map(|(_, offset, length)| &self.region[*offset..*offset + *length])
+    }
+
+    /// Returns the parent paths from the parent locator, most specific first.
+    fn parent_candidates(&self) -> Vec<String> {
+        let locator = match self.item(METADATA_PARENT_LOCATOR) {
+            Some(locator) if locator.len() >= 20 => locator,
+            _ => return Vec::new(),
+        };
+        let decode = |offset: usize, length: usize| -> Option<String> {
+            let bytes = locator.get(offset..offset + length)?;
+            let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+            Some(String::from_utf16_lossy(&units))
+        };
+
+        let mut entries = Vec::new();
+        let count = le_u16(locator, 18) as usize;
+        for index in 0..count {
+            let start = 20 + index * 12;
+            if start + 12 > locator.len() {
+                break;
+            }
+            let key = decode(le_u32(locator, start) as usize, le_u16(locator, start + 8) as usize);
+            let value = decode(le_u32(locator, start + 4) as usize, le_u16(locator, start + 10) as usize);
+            if let (Some(key), Some(value)) = (key, value) {
+                entries.push((key, value));
+            }
+        }
+
+        ["relative_path", "absolute_win32_path", "volume_path"]
+            .iter()
+            .filter_map(|wanted| entries.iter().find(|(key, _)| key == wanted))
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+}
+
+/// A log entry header that passed validation.
+#[derive(Debug, Clone, Copy)]
+struct LogEntry {
+    /// Offset of the entry inside the log
+    position: u64,
+    /// Length of the entry in bytes
+    length: u64,
+    /// Offset of the oldest entry still needed when this entry is the head
+    tail: u64,
+    /// Sequence number of the entry
+    sequence: u64,
+}
+
+/// Finds the active log sequence and returns its writes in replay order.
+fn replay_log(file: &mut LoggedFile, offset: u64, length: u64, log_guid: &[u8]) -> Result<Vec<LogWrite>, ImageError> {
+    if length == 0 || !length.is_multiple_of(LOG_SECTOR_SIZE) || length > MAX_LOG_SIZE {
+        return Err(ImageError::InvalidImage("invalid VHDX log size".to_string()));
+    }
+    let log = file.read(offset, length as usize)?;
+
+    let entry_at = |position: u64| -> Option<LogEntry> {
+        let start = position as usize;
+        let header = &log[start..start + LOG_SECTOR_SIZE as usize];
+        let entry_length = le_u32(header, 8) as u64;
+        if &header[0..4] != b"loge"
+            || &header[32..48] != log_guid
+            || entry_length == 0
+            || !entry_length.is_multiple_of(LOG_SECTOR_SIZE)
+            || position + entry_length > length
+            || !checksum_matches(&log[start..start + entry_length as usize], 4)
+        {
+            return None;
+        }
+        Some(LogEntry {
+            position,
+            length: entry_length,
+            tail: le_u32(header, 12) as u64,
+            sequence: le_u64(header, 16),
+        })
+    };
+
+    // The head of the active sequence is the valid entry with the highest sequence number
+    let head = (0..length / LOG_SECTOR_SIZE)
+        .filter_map(|sector| entry_at(sector * LOG_SECTOR_SIZE))
+        .max_by_key(|entry| entry.sequence);
+    let head = match head {
+        Some(head) => head,
+        None => return Ok(Vec::new()),
+    };
+
+    // Walk from the tail to the head, requiring consecutive sequence numbers
+    let mut sequence = Vec::new();
+    let mut position = head.tail;
+    loop {
+        let entry = entry_at(position)
+            .ok_or_else(|| ImageError::InvalidImage("VHDX log sequence is broken".to_string()))?;
+        if let Some(previous) = sequence.last() {
+            let previous: &LogEntry = previous;
+            if entry.sequence != previous.sequence + 1 {
+                return Err(ImageError::InvalidImage("VHDX log sequence is broken".to_string()));
+            }
+        }
+        sequence.push(entry);
+        if entry.position == head.position || sequence.len() as u64 > length / LOG_SECTOR_SIZE {
+            break;
+        }
+        position = (entry.position + entry.length) % length;
+    }
+
+    let mut writes = Vec::new();
+    for entry in sequence {
+        let start = entry.position as usize;
+        let data = &log[start..start + entry.length as usize];
+        let descriptor_count = le_u32(data, 24) as usize;
+        let descriptor_sectors = (64 + descriptor_count * 32).div_ceil(LOG_SECTOR_SIZE as usize);
+        let mut data_sector = descriptor_sectors;
+
+        for index in 0..descriptor_count {
+            let descriptor = &data[64 + index * 32..64 + (index + 1) * 32];
+            match &descriptor[0..4] {
+                b"zero" => writes.push(LogWrite {
+                    offset: le_u64(descriptor, 16),
+                    length: le_u64(descriptor, 8),
+                    data: None,
+                }),
+                b"desc" => {
+                    let sector_start = data_sector * LOG_SECTOR_SIZE as usize;
+                    let sector = data
+                        .get(sector_start..sector_start + LOG_SECTOR_SIZE as usize)
+                        .filter(|sector| &sector[0..4] == b"data")
+                        .ok_or_else(|| ImageError::InvalidImage("VHDX log data sector missing".to_string()))?;
+                    let mut content = Vec::with_capacity(LOG_SECTOR_SIZE as usize);
+                    content.extend_from_slice(&descriptor[8..16]);
+                    content.extend_from_slice(&sector[8..4092]);
+                    content.extend_from_slice(&descriptor[4..8]);
+                    writes.push(LogWrite {
+                        offset: le_u64(descriptor, 16),
+                        length: LOG_SECTOR_SIZE,
+                        data: Some(content),
+                    });
+                    data_sector += 1;
+                }
+                _ => return Err(ImageError::InvalidImage("unknown VHDX log descriptor".to_string())),
+            }
+        }
+    }
+
+    Ok(writes)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::images::{ImageFormat, VirtualDisk};
+
+    /// Parses a GUID string into its on-disk mixed-endian representation.
+    fn guid(text: &str) -> [u8; 16] {
+        let hex: String = text.chars().filter(|c| *c != '-').collect();
+        let mut bytes = [0u8; 16];
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).unwrap();
+        }
+        bytes[0..4].reverse();
+        bytes[4..6].reverse();
+        bytes[6..8].reverse();
+        bytes
+    }
+
+    /// Stores the CRC-32C of `data` at `offset`.
+    fn seal(data: &mut [u8], offset: usize) {
+        data[offset..offset + 4].fill(0);
+        let crc = crc32c(data);
+        data[offset..offset + 4].copy_from_slice(&crc.to_le_bytes());
+    }
+
+    /// Builds a 4 MiB dynamic VHDX with 1 MiB blocks and 512-byte sectors.
+    ///
+    /// Block 1 is fully present and filled with `0x42`. When `log_write` is set,
+    /// the log holds one entry writing a 4 KiB sector of `0x99` at the start of block 1.
+    pub(crate) fn build_vhdx(log_write: bool) -> Vec<u8> {
+        let mut image = vec![0u8; 5 * MEGABYTE as usize];
+        image[0..8].copy_from_slice(FILE_SIGNATURE);
+
+        let log_guid = [0x77u8; 16];
+        let log_offset = MEGABYTE;
+        for (index, offset) in HEADER_OFFSETS.iter().enumerate() {
+            let mut header = vec![0u8; HEADER_SIZE];
+            header[0..4].copy_from_slice(b"head");
+            header[8..16].copy_from_slice(&(index as u64 + 1).to_le_bytes());
+            if log_write {
+                header[48..64].copy_from_slice(&log_guid);
+            }
+            header[66..68].copy_from_slice(&1u16.to_le_bytes());
+            header[68..72].copy_from_slice(&(MEGABYTE as u32).to_le_bytes());
+            header[72..80].copy_from_slice(&log_offset.to_le_bytes());
+            seal(&mut header, 4);
+            image[*offset as usize..*offset as usize + HEADER_SIZE].copy_from_slice(&header);
+        }
+
+        let bat_offset = 2 * MEGABYTE;
+        let metadata_offset = 3 * MEGABYTE;
+        let mut regions = vec![0u8; REGION_TABLE_SIZE];
+        regions[0..4].copy_from_slice(b"regi");
+        regions[8..12].copy_from_slice(&2u32.to_le_bytes());
+        regions[16..32].copy_from_slice(&guid(REGION_BAT));
+        regions[32..40].copy_from_slice(&bat_offset.to_le_bytes());
+        regions[40..44].copy_from_slice(&(MEGABYTE as u32).to_le_bytes());
+        regions[48..64].copy_from_slice(&guid(REGION_METADATA));
+        regions[64..72].copy_from_slice(&metadata_offset.to_le_bytes());
+        regions[72..76].copy_from_slice(&(MEGABYTE as u32).to_le_bytes());
+        seal(&mut regions, 4);
+        let start = REGION_TABLE_OFFSET as usize;
+        image[start..start + REGION_TABLE_SIZE].copy_from_slice(&regions);
+
+        let items: [(&str, Vec<u8>); 4] = [
+            (METADATA_FILE_PARAMETERS, [(MEGABYTE as u32).to_le_bytes(), 0u32.to_le_bytes()].concat()),
+            (METADATA_VIRTUAL_DISK_SIZE, (4 * MEGABYTE).to_le_bytes().to_vec()),
+            (METADATA_LOGICAL_SECTOR_SIZE, 512u32.to_le_bytes().to_vec()),
+            (METADATA_VIRTUAL_DISK_ID, guid("6F1C2B3A-0000-4000-8000-123456789ABC").to_vec()),
+        ];
+        let metadata = &mut image[metadata_offset as usize..(metadata_offset + MEGABYTE) as usize];
+        metadata[0..8].copy_from_slice(b"metadata");
+        metadata[10..12].copy_from_slice(&(items.len() as u16).to_le_bytes());
+        let mut item_offset = 64 * 1024;
+        for (index, (id, data)) in items.iter().enumerate() {
+            let entry = &mut metadata[32 + index * 32..64 + index * 32];
+            entry[0..16].copy_from_slice(&guid(id));
+            entry[16..20].copy_from_slice(&(item_offset as u32).to_le_bytes());
+            entry[20..24].copy_from_slice(&(data.len() as u32).to_le_bytes());
+            metadata[item_offset..item_offset + data.len()].copy_from_slice(data);
+            item_offset += data.len();
+        }
+
+        // Block 1 lives at 4 MiB in the file
+        let payload_offset = 4 * MEGABYTE;
+        let entry = (payload_offset / MEGABYTE) << 20 | BLOCK_FULLY_PRESENT;
+        image[(bat_offset + 8) as usize..(bat_offset + 16) as usize].copy_from_slice(&entry.to_le_bytes());
+        image[payload_offset as usize..(payload_offset + MEGABYTE) as usize].fill(0x42);
+
+        if log_write {
+            let mut entry = vec![0u8; 2 * LOG_SECTOR_SIZE as usize];
+            entry[0..4].copy_from_slice(b"loge");
+            entry[8..12].copy_from_slice(&(2 * LOG_SECTOR_SIZE as u32).to_le_bytes());
+            entry[16..24].copy_from_slice(&10u64.to_le_bytes());
+            entry[24..28].copy_from_slice(&1u32.to_le_bytes());
+            entry[32..48].copy_from_slice(&log_guid);
+            let descriptor = &mut entry[64..96];
+            descriptor[0..4].copy_from_slice(b"desc");
+            descriptor[4..8].copy_from_slice(&[0x99; 4]);
+            descriptor[8..16].copy_from_slice(&[0x99; 8]);
+            descriptor[16..24].copy_from_slice(&payload_offset.to_le_bytes());
+            descriptor[24..32].copy_from_slice(&10u64.to_le_bytes());
+            let sector = &mut entry[4096..8192];
+            sector[0..4].copy_from_slice(b"data");
+            sector[8..4092].fill(0x99);
+            seal(&mut entry, 4);
+            image[log_offset as usize..log_offset as usize + entry.len()].copy_from_slice(&entry);
+        }
+
+        image
+    }
+
+    #[test]
+    fn test_crc32c() {
+        assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+    }
+
+    #[test]
+    fn test_dynamic_vhdx() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("disk.vhdx");
+        std::fs::write(&path, build_vhdx(false)).unwrap();
+
+        let mut device = VirtualDisk::open(&path).unwrap();
+        assert_eq!(device.format(), ImageFormat::Vhdx);
+        assert_eq!(device.size(), 4 * MEGABYTE);
+        assert_eq!(device.identifier().as_deref(), Some("6F1C2B3A-0000-4000-8000-123456789ABC"));
+
+        let mut data = Vec::new();
+        device.read_to_end(&mut data).unwrap();
+        assert!(data[..MEGABYTE as usize].iter().all(|&byte| byte == 0));
+        assert!(data[MEGABYTE as usize..2 * MEGABYTE as usize].iter().all(|&byte| byte == 0x42));
+        assert!(data[2 * MEGABYTE as usize..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn test_vhdx_log_replay() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("dirty.vhdx");
+        let image = build_vhdx(true);
+        std::fs::write(&path, &image).unwrap();
+
+        let mut device = VirtualDisk::open(&path).unwrap();
+        device.seek(SeekFrom::Start(MEGABYTE)).unwrap();
+        let mut data = vec![0u8; 8192];
+        device.read_exact(&mut data).unwrap();
+        assert!(data[..4096].iter().all(|&byte| byte == 0x99));
+        assert!(data[4096..].iter().all(|&byte| byte == 0x42));
+
+        // Replay happens in memory only
+        assert_eq!(std::fs::read(&path).unwrap(), image);
+    }
+
+    #[test]
+    fn test_vhdx_corrupt_header() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("corrupt.vhdx");
+        let mut image = build_vhdx(false);
+        for offset in HEADER_OFFSETS {
+            image[offset as usize + 100] ^= 0xFF;
+        }
+        std::fs::write(&path, image).unwrap();
+
+        assert!(matches!(VirtualDisk::open(&path), Err(ImageError::InvalidImage(_))));
+    }
+}
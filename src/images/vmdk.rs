@@ -0,0 +1,631 @@
+//! VMware virtual disk (VMDK) reader.
+//!
+//! A VMDK disk is described by a text descriptor listing its extents, either
+//! as a standalone file or embedded in a sparse extent. Flat extents store
+//! the media as-is, while hosted sparse extents map fixed-size grains through
+//! a grain directory and grain tables. Stream-optimized images compress each
+//! grain with zlib and may store the grain directory at the end of the file.
+//! Grains missing from a child disk are read from its parent.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use flate2::read::ZlibDecoder;
+
+use super::{le_u32, le_u64, open_parent, read_file_at, read_source_at, ImageError, ReadAt};
+
+/// Magic number of a hosted sparse extent header ("KDMV")
+pub(crate) const SPARSE_MAGIC: &[u8] = b"KDMV";
+/// First line written at the start of a standalone descriptor
+pub(crate) const DESCRIPTOR_SIGNATURE: &[u8] = b"# Disk DescriptorFile";
+/// Size of a VMDK sector
+const SECTOR_SIZE: u64 = 512;
+/// Grain directory offset of stream-optimized images, whose real header is in the footer
+const GD_AT_END: u64 = u64::MAX;
+/// Header flag of extents whose grains are compressed
+const FLAG_COMPRESSED: u32 = 1 << 16;
+/// Grain table entry of a grain that reads back as zeros
+const ZERO_GRAIN: u32 = 1;
+/// Parent CID of a disk without a parent
+const NO_PARENT_CID: &str = "ffffffff";
+/// Largest grain accepted, to bound allocations on corrupt headers
+const MAX_GRAIN_SIZE: u64 = 1024 * 1024;
+/// Largest standalone or embedded descriptor accepted
+const MAX_DESCRIPTOR_SIZE: u64 = 1024 * 1024;
+
+/// Hosted sparse extent header fields.
+struct SparseHeader {
+    flags: u32,
+    /// Capacity of the extent in sectors
+    capacity: u64,
+    /// Grain size in sectors
+    grain_size: u64,
+    descriptor_offset: u64,
+    descriptor_size: u64,
+    gtes_per_gt: u32,
+    gd_offset: u64,
+}
+
+impl SparseHeader {
+    fn parse(buf: &[u8]) -> Option<Self> {
+        if !buf.starts_with(SPARSE_MAGIC) {
+            return None;
+        }
+        Some(SparseHeader {
+            flags: le_u32(buf, 8),
+            capacity: le_u64(buf, 12),
+            grain_size: le_u64(buf, 20),
+            descriptor_offset: le_u64(buf, 28),
+            descriptor_size: le_u64(buf, 36),
+            gtes_per_gt: le_u32(buf, 44),
+            gd_offset: le_u64(buf, 56),
+        })
+    }
+}
+
+/// A hosted sparse extent (monolithicSparse, twoGbMaxExtentSparse or streamOptimized).
+struct SparseExtent {
+    file: File,
+    /// Grain size in bytes
+    grain_size: u64,
+    gtes_per_gt: u64,
+    compressed: bool,
+    /// Sector offset of each grain table
+    directory: Vec<u32>,
+    /// Most recently read grain table and its index
+    cached_table: Option<(usize, Vec<u32>)>,
+    /// Most recently decompressed grain and its sector offset
+    cached_grain: Option<(u32, Vec<u8>)>,
+}
+
+impl SparseExtent {
+    /// Opens a sparse extent, returning it with its header.
+    fn open(mut file: File) -> Result<(Self, SparseHeader), ImageError> {
+        let buf = read_file_at(&mut file, 0, SECTOR_SIZE as usize)?;
+        let mut header = SparseHeader::parse(&buf)
+            .ok_or_else(|| ImageError::InvalidImage("VMDK sparse header not found".to_string()))?;
+
+        if header.gd_offset == GD_AT_END {
+            // Stream-optimized images are followed by a footer marker, the footer and an end-of-stream marker
+            let file_size = file.seek(SeekFrom::End(0))?;
+            let footer = read_file_at(&mut file, file_size.saturating_sub(2 * SECTOR_SIZE), SECTOR_SIZE as usize)?;
+            header = SparseHeader::parse(&footer)
+                .filter(|footer| footer.gd_offset != GD_AT_END)
+                .ok_or_else(|| ImageError::InvalidImage("VMDK footer not found".to_string()))?;
+        }
+
+        let grain_size = header
+            .grain_size
+            .checked_mul(SECTOR_SIZE)
+            .filter(|size| *size != 0 && size.is_power_of_two() && *size <= MAX_GRAIN_SIZE)
+            .ok_or_else(|| ImageError::InvalidImage(format!("invalid VMDK grain size of {} sectors", header.grain_size)))?;
+        let gtes_per_gt = header.gtes_per_gt as u64;
+        if gtes_per_gt == 0 || gtes_per_gt > 64 * 1024 {
+            return Err(ImageError::InvalidImage("invalid VMDK grain table size".to_string()));
+        }
+
+        // The grain directory and tables are read whole, so they must fit in the extent file
+        let file_size = file.seek(SeekFrom::End(0))?;
+        let grains = header.capacity.div_ceil(header.grain_size);
+        let tables = grains.div_ceil(gtes_per_gt);
+        let directory_offset = header.gd_offset.checked_mul(SECTOR_SIZE);
+        let directory_length = tables.checked_mul(4);
+        let fits = directory_offset
+            .zip(directory_length)
+            .and_then(|(offset, length)| offset.checked_add(length))
+            .is_some_and(|end| end <= file_size);
+        if !fits || gtes_per_gt * 4 > file_size {
+            return Err(ImageError::InvalidImage("VMDK grain directory extends past the end of the extent".to_string()));
+        }
+        let directory = read_file_at(&mut file, header.gd_offset * SECTOR_SIZE, (tables * 4) as usize)?;
+        let directory = directory.chunks_exact(4).map(|entry| le_u32(entry, 0)).collect();
+
+        let extent = SparseExtent {
+            file,
+            grain_size,
+            gtes_per_gt,
+            compressed: header.flags & FLAG_COMPRESSED != 0,
+            directory,
+            cached_table: None,
+            cached_grain: None,
+        };
+        Ok((extent, header))
+    }
+
+    /// Returns the grain table entry of the grain containing `offset`, or 0 if
+    /// the grain is unallocated.
+    fn grain_entry(&mut self, offset: u64) -> io::Result<u32> {
+        let grain = offset / self.grain_size;
+        let table = (grain / self.gtes_per_gt) as usize;
+        let index = (grain % self.gtes_per_gt) as usize;
+
+        let table_sector = self.directory.get(table).copied().unwrap_or(0);
+        if table_sector == 0 {
+            return Ok(0);
+        }
+        if self.cached_table.as_ref().map(|(cached, _)| *cached) != Some(table) {
+            let data = read_file_at(
+                &mut self.file,
+                table_sector as u64 * SECTOR_SIZE,
+                (self.gtes_per_gt * 4) as usize,
+            )?;
+            let entries = data.chunks_exact(4).map(|entry| le_u32(entry, 0)).collect();
+            self.cached_table = Some((table, entries));
+        }
+        Ok(self.cached_table.as_ref().expect("cached grain table").1[index])
+    }
+
+    /// Decompresses the grain stored at `sector`.
+    fn read_compressed(&mut self, sector: u32) -> io::Result<&[u8]> {
+        if self.cached_grain.as_ref().map(|(cached, _)| *cached) != Some(sector) {
+            // Each compressed grain starts with its LBA (8 bytes) and compressed size (4 bytes)
+            let offset = sector as u64 * SECTOR_SIZE;
+            let marker = read_file_at(&mut self.file, offset, 12)?;
+            let length = le_u32(&marker, 8) as u64;
+            if length > 2 * MAX_GRAIN_SIZE {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "compressed VMDK grain is too large"));
+            }
+            let compressed = read_file_at(&mut self.file, offset + 12, length as usize)?;
+
+            let mut grain = Vec::with_capacity(self.grain_size as usize);
+            ZlibDecoder::new(&compressed[..]).take(self.grain_size).read_to_end(&mut grain)?;
+            // The last grain of a disk may be shorter than the grain size
+            grain.resize(self.grain_size as usize, 0);
+            self.cached_grain = Some((sector, grain));
+        }
+        Ok(&self.cached_grain.as_ref().expect("cached grain").1)
+    }
+}
+
+/// Storage of an extent.
+enum ExtentData {
+    /// Media stored as-is at `offset` of `file`
+    Flat { file: File, offset: u64 },
+    Sparse(Box<SparseExtent>),
+    /// Extent reading back as zeros
+    Zero,
+}
+
+/// An extent of the virtual disk.
+struct Extent {
+    /// Offset of the extent in the virtual disk
+    start: u64,
+    /// Length of the extent in bytes
+    length: u64,
+    data: ExtentData,
+}
+
+/// Extent line of a descriptor, e.g. `RW 4192256 SPARSE "disk-s001.vmdk"`.
+#[derive(Debug, Clone, PartialEq)]
+struct ExtentLine {
+    /// Length in sectors
+    sectors: u64,
+    /// Extent type (FLAT, SPARSE, ZERO, VMFS...)
+    kind: String,
+    /// File name, relative to the descriptor
+    file: Option<String>,
+    /// Offset of the extent in a flat file, in sectors
+    offset: u64,
+}
+
+/// Content of a VMDK descriptor.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Descriptor {
+    extents: Vec<ExtentLine>,
+    /// Content identifier of this disk
+    cid: Option<String>,
+    /// Content identifier of the parent, `ffffffff` if there is none
+    parent_cid: Option<String>,
+    /// Path of the parent as recorded by VMware
+    parent_hint: Option<String>,
+    /// UUID of the disk, when recorded in the disk database
+    uuid: Option<String>,
+}
+
+impl Descriptor {
+    fn parse(text: &str) -> Self {
+        let mut descriptor = Descriptor::default();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(extent) = Self::parse_extent(line) {
+                descriptor.extents.push(extent);
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim().trim_matches('"').to_string();
+            match key.trim() {
+                "CID" => descriptor.cid = Some(value),
+                "parentCID" => descriptor.parent_cid = Some(value),
+                "parentFileNameHint" => descriptor.parent_hint = Some(value),
+                "ddb.uuid.image" => descriptor.uuid = Some(value),
+                _ => {}
+            }
+        }
+        descriptor
+    }
+
+    fn parse_extent(line: &str) -> Option<ExtentLine> {
+        let (access, rest) = line.split_once(char::is_whitespace)?;
+        if !matches!(access, "RW" | "RDONLY" | "NOACCESS") {
+            return None;
+        }
+        let mut fields = rest.split_whitespace();
+        let sectors = fields.next()?.parse().ok()?;
+        let kind = fields.next()?.to_string();
+
+        // The file name is quoted and may contain spaces
+        let (file, offset) = match (rest.find('"'), rest.rfind('"')) {
+            (Some(open), Some(close)) if close > open => {
+                let offset = rest[close + 1..].trim().parse().unwrap_or(0);
+                (Some(rest[open + 1..close].to_string()), offset)
+            }
+            _ => (None, 0),
+        };
+
+        Some(ExtentLine {
+            sectors,
+            kind,
+            file,
+            offset,
+        })
+    }
+
+    /// Returns true if the disk is a child of another disk.
+    fn has_parent(&self) -> bool {
+        self.parent_cid.as_deref().is_some_and(|cid| !cid.eq_ignore_ascii_case(NO_PARENT_CID))
+    }
+}
+
+/// A VMDK disk made of one or more extents.
+pub(crate) struct VmdkImage {
+    extents: Vec<Extent>,
+    /// Virtual disk size in bytes
+    size: u64,
+    /// Disk UUID or content identifier
+    identifier: Option<String>,
+    /// Parent of a child disk
+    parent: Option<Box<dyn ReadAt>>,
+}
+
+impl VmdkImage {
+    /// Opens a VMDK descriptor or sparse extent, resolving its parent chain.
+    ///
+    /// `depth` counts the child disks already opened above this one.
+    pub(crate) fn open(path: &Path, depth: usize) -> Result<Self, ImageError> {
+        let mut file = File::open(path)?;
+        let file_size = file.seek(SeekFrom::End(0))?;
+        let first = read_file_at(&mut file, 0, file_size.min(SECTOR_SIZE) as usize)?;
+
+        let (extents, descriptor) = if first.starts_with(SPARSE_MAGIC) {
+            // A sparse extent opened directly describes itself, whatever its embedded descriptor names it
+            let (extent, header) = SparseExtent::open(file)?;
+            let sectors = |count: u64, field: &str| {
+                count
+                    .checked_mul(SECTOR_SIZE)
+                    .ok_or_else(|| ImageError::InvalidImage(format!("VMDK {} {} is out of range", field, count)))
+            };
+            let descriptor = if header.descriptor_offset != 0 && header.descriptor_size != 0 {
+                let offset = sectors(header.descriptor_offset, "descriptor offset")?;
+                let length = sectors(header.descriptor_size, "descriptor size")?.min(MAX_DESCRIPTOR_SIZE);
+                let text = read_file_at(&mut extent.file.try_clone()?, offset, length as usize)?;
+                Descriptor::parse(&String::from_utf8_lossy(&text))
+            } else {
+                Descriptor::default()
+            };
+            let extent = Extent {
+                start: 0,
+                length: sectors(header.capacity, "capacity")?,
+                data: ExtentData::Sparse(Box::new(extent)),
+            };
+            (vec![extent], descriptor)
+        } else {
+            if file_size > MAX_DESCRIPTOR_SIZE {
+                return Err(ImageError::InvalidImage("VMDK descriptor is too large".to_string()));
+            }
+            let text = read_file_at(&mut file, 0, file_size as usize)?;
+            let descriptor = Descriptor::parse(&String::from_utf8_lossy(&text));
+            if descriptor.extents.is_empty() {
+                return Err(ImageError::InvalidImage("VMDK descriptor lists no extents".to_string()));
+            }
+            (Self::open_extents(path, &descriptor)?, descriptor)
+        };
+
+        let size = extents.last().map(|extent| extent.start + extent.length).unwrap_or(0);
+        let parent = if descriptor.has_parent() {
+            let candidates: Vec<String> = descriptor.parent_hint.iter().cloned().collect();
+            Some(open_parent(path, &candidates, depth)?)
+        } else {
+            None
+        };
+
+        Ok(VmdkImage {
+            extents,
+            size,
+            identifier: descriptor.uuid.or(descriptor.cid),
+            parent,
+        })
+    }
+
+    /// Opens the extent files listed in a standalone descriptor.
+    fn open_extents(path: &Path, descriptor: &Descriptor) -> Result<Vec<Extent>, ImageError> {
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let mut extents = Vec::new();
+        let mut start = 0;
+
+        for line in &descriptor.extents {
+            let length = line.sectors * SECTOR_SIZE;
+            let open_file = || -> Result<File, ImageError> {
+                let name = line.file.as_deref().ok_or_else(|| {
+                    ImageError::InvalidImage(format!("VMDK {} extent without a file name", line.kind))
+                })?;
+                let extent_path: PathBuf = directory.join(name);
+                File::open(&extent_path).map_err(|err| {
+                    ImageError::InvalidImage(format!("cannot open VMDK extent {}: {}", extent_path.display(), err))
+                })
+            };
+
+            let data = match line.kind.as_str() {
+                "FLAT" | "VMFS" => ExtentData::Flat {
+                    file: open_file()?,
+                    offset: line.offset * SECTOR_SIZE,
+                },
+                "SPARSE" => ExtentData::Sparse(Box::new(SparseExtent::open(open_file()?)?.0)),
+                "ZERO" => ExtentData::Zero,
+                other => {
+                    return Err(ImageError::UnsupportedFormat(format!("VMDK {} extent", other)));
+                }
+            };
+            extents.push(Extent { start, length, data });
+            start += length;
+        }
+        Ok(extents)
+    }
+}
+
+impl ReadAt for VmdkImage {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn identifier(&self) -> Option<String> {
+        self.identifier.clone()
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if offset >= self.size {
+            return Ok(0);
+        }
+        let index = self.extents.partition_point(|extent| extent.start + extent.length <= offset);
+        let extent = &mut self.extents[index];
+        let within = offset - extent.start;
+        let len = buf.len().min((extent.length - within) as usize);
+        let buf = &mut buf[..len];
+
+        match &mut extent.data {
+            ExtentData::Flat { file, offset: base } => {
+                let data = read_file_at(file, *base + within, len)?;
+                buf.copy_from_slice(&data);
+                Ok(len)
+            }
+            ExtentData::Zero => {
+                buf.fill(0);
+                Ok(len)
+            }
+            ExtentData::Sparse(sparse) => {
+                let grain_within = within % sparse.grain_size;
+                let len = len.min((sparse.grain_size - grain_within) as usize);
+                let buf = &mut buf[..len];
+
+                match sparse.grain_entry(within)? {
+                    0 => match self.parent.as_mut() {
+                        Some(parent) => read_source_at(parent.as_mut(), offset, buf)?,
+                        None => buf.fill(0),
+                    },
+                    ZERO_GRAIN => buf.fill(0),
+                    sector if sparse.compressed => {
+                        let grain = sparse.read_compressed(sector)?;
+                        buf.copy_from_slice(&grain[grain_within as usize..grain_within as usize + len]);
+                    }
+                    sector => {
+                        let data = read_file_at(&mut sparse.file, sector as u64 * SECTOR_SIZE + grain_within, len)?;
+                        buf.copy_from_slice(&data);
+                    }
+                }
+                Ok(len)
+            }
+        }
+    }
+}
+
+/// Returns true if `buf`, read from the start of a file, is a VMDK sparse
+/// extent header or descriptor.
+pub(crate) fn is_vmdk(buf: &[u8]) -> bool {
+    buf.starts_with(SPARSE_MAGIC) || buf.starts_with(DESCRIPTOR_SIGNATURE)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    use super::*;
+    use crate::images::{ImageFormat, VirtualDisk};
+
+    /// Grain size of the test images, in sectors
+    const GRAIN_SECTORS: u64 = 8;
+    const GRAIN_SIZE: usize = (GRAIN_SECTORS * SECTOR_SIZE) as usize;
+
+    /// Builds a sparse extent header.
+    fn header(capacity: u64, descriptor: (u64, u64), gd_offset: u64, flags: u32) -> Vec<u8> {
+        let mut header = vec![0u8; SECTOR_SIZE as usize];
+        header[0..4].copy_from_slice(SPARSE_MAGIC);
+        header[4..8].copy_from_slice(&3u32.to_le_bytes());
+        header[8..12].copy_from_slice(&flags.to_le_bytes());
+        header[12..20].copy_from_slice(&capacity.to_le_bytes());
+        header[20..28].copy_from_slice(&GRAIN_SECTORS.to_le_bytes());
+        header[28..36].copy_from_slice(&descriptor.0.to_le_bytes());
+        header[36..44].copy_from_slice(&descriptor.1.to_le_bytes());
+        header[44..48].copy_from_slice(&512u32.to_le_bytes());
+        header[56..64].copy_from_slice(&gd_offset.to_le_bytes());
+        header
+    }
+
+    /// Builds a sparse extent with 4 KiB grains and a single grain table.
+    ///
+    /// Stream-optimized extents compress their grains and keep the grain
+    /// directory location in a footer.
+    fn build_sparse_vmdk(capacity: u64, grains: &[(usize, Vec<u8>)], descriptor: &str, stream: bool) -> Vec<u8> {
+        // Sector 0: header, 1-2: descriptor, 3: grain directory, 4-7: grain table
+        let mut image = vec![0u8; 8 * SECTOR_SIZE as usize];
+        let flags = if stream { FLAG_COMPRESSED } else { 0 };
+        let gd_offset = if stream { GD_AT_END } else { 3 };
+        image[..512].copy_from_slice(&header(capacity, (1, 2), gd_offset, flags));
+        image[512..512 + descriptor.len()].copy_from_slice(descriptor.as_bytes());
+        image[1536..1540].copy_from_slice(&4u32.to_le_bytes());
+
+        for (index, data) in grains {
+            let sector = (image.len() as u64 / SECTOR_SIZE) as u32;
+            let position = 2048 + index * 4;
+            image[position..position + 4].copy_from_slice(&sector.to_le_bytes());
+            if stream {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data).unwrap();
+                let compressed = encoder.finish().unwrap();
+                image.extend(((*index as u64) * GRAIN_SECTORS).to_le_bytes());
+                image.extend((compressed.len() as u32).to_le_bytes());
+                image.extend(compressed);
+                image.resize(image.len().div_ceil(512) * 512, 0);
+            } else {
+                image.extend(data);
+            }
+        }
+
+        if stream {
+            image.extend(vec![0u8; 512]);
+            image.extend(header(capacity, (1, 2), 3, flags));
+            image.extend(vec![0u8; 512]);
+        }
+        image
+    }
+
+    #[test]
+    fn test_parse_descriptor() {
+        let descriptor = Descriptor::parse(
+            "# Disk DescriptorFile\nversion=1\nCID=fb183c20\nparentCID=ffffffff\n\
+             createType=\"twoGbMaxExtentFlat\"\n\n# Extent description\n\
+             RW 4192256 FLAT \"My Disk-f001.vmdk\" 0\nRW 2048 ZERO\n",
+        );
+        assert_eq!(descriptor.cid.as_deref(), Some("fb183c20"));
+        assert!(!descriptor.has_parent());
+        assert_eq!(descriptor.extents.len(), 2);
+        assert_eq!(descriptor.extents[0].file.as_deref(), Some("My Disk-f001.vmdk"));
+        assert_eq!(descriptor.extents[0].sectors, 4192256);
+        assert_eq!(descriptor.extents[1].kind, "ZERO");
+        assert_eq!(descriptor.extents[1].file, None);
+    }
+
+    #[test]
+    fn test_monolithic_sparse_vmdk() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("renamed.vmdk");
+        let descriptor = "# Disk DescriptorFile\nCID=12345678\nparentCID=ffffffff\nRW 32 SPARSE \"original.vmdk\"\n";
+        let image = build_sparse_vmdk(32, &[(1, vec![0x44; GRAIN_SIZE])], descriptor, false);
+        std::fs::write(&path, image).unwrap();
+
+        let mut device = VirtualDisk::open(&path).unwrap();
+        assert_eq!(device.format(), ImageFormat::Vmdk);
+        assert_eq!(device.size(), 32 * 512);
+        assert_eq!(device.identifier().as_deref(), Some("12345678"));
+
+        let mut data = Vec::new();
+        device.read_to_end(&mut data).unwrap();
+        assert_eq!(&data[..GRAIN_SIZE], &[0u8; GRAIN_SIZE][..]);
+        assert_eq!(&data[GRAIN_SIZE..2 * GRAIN_SIZE], &[0x44; GRAIN_SIZE][..]);
+        assert_eq!(&data[2 * GRAIN_SIZE..], &[0u8; 2 * GRAIN_SIZE][..]);
+    }
+
+    #[test]
+    fn test_malformed_sparse_header() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("corrupt.vmdk");
+        let open = |offset: usize, value: u64| {
+            let mut image = build_sparse_vmdk(32, &[(1, vec![0x44; GRAIN_SIZE])], "", false);
+            image[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+            std::fs::write(&path, image).unwrap();
+            SparseExtent::open(File::open(&path).unwrap()).map(|_| ())
+        };
+        assert!(open(20, 8).is_ok());
+        // Grain sizes that overflow, are not powers of two or are too large
+        for grain_size in [0, 3, 1 << 20, u64::MAX / 2 + 1] {
+            assert!(matches!(open(20, grain_size), Err(ImageError::InvalidImage(_))), "grain size {}", grain_size);
+        }
+        // A capacity or grain directory offset putting the directory far past the end of the file
+        assert!(matches!(open(12, u64::MAX - 7), Err(ImageError::InvalidImage(_))));
+        assert!(matches!(open(56, u64::MAX / 4), Err(ImageError::InvalidImage(_))));
+
+        // Descriptor offsets and sizes whose byte values overflow
+        for offset in [28, 36] {
+            let mut image = build_sparse_vmdk(32, &[(1, vec![0x44; GRAIN_SIZE])], "", false);
+            image[offset..offset + 8].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+            std::fs::write(&path, image).unwrap();
+            assert!(matches!(VmdkImage::open(&path, 0), Err(ImageError::InvalidImage(_))), "field at {}", offset);
+        }
+    }
+
+    #[test]
+    fn test_stream_optimized_vmdk() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("export.vmdk");
+        let pattern: Vec<u8> = (0..GRAIN_SIZE).map(|i| (i % 13) as u8).collect();
+        std::fs::write(&path, build_sparse_vmdk(16, &[(0, pattern.clone())], "", true)).unwrap();
+
+        let mut device = VirtualDisk::open(&path).unwrap();
+        let mut data = Vec::new();
+        device.read_to_end(&mut data).unwrap();
+        assert_eq!(&data[..GRAIN_SIZE], &pattern[..]);
+        assert_eq!(&data[GRAIN_SIZE..], &[0u8; GRAIN_SIZE][..]);
+    }
+
+    #[test]
+    fn test_child_vmdk() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let base = build_sparse_vmdk(16, &[(0, vec![0x01; GRAIN_SIZE]), (1, vec![0x02; GRAIN_SIZE])], "", false);
+        std::fs::write(temp_dir.path().join("base.vmdk"), base).unwrap();
+
+        let descriptor = "CID=22222222\nparentCID=11111111\nparentFileNameHint=\"C:\\VMs\\base.vmdk\"\n";
+        let child = build_sparse_vmdk(16, &[(0, vec![0xCC; GRAIN_SIZE])], descriptor, false);
+        let path = temp_dir.path().join("base-000001.vmdk");
+        std::fs::write(&path, child).unwrap();
+
+        let mut device = VirtualDisk::open(&path).unwrap();
+        let mut data = Vec::new();
+        device.read_to_end(&mut data).unwrap();
+        assert_eq!(&data[..GRAIN_SIZE], &[0xCC; GRAIN_SIZE][..]);
+        assert_eq!(&data[GRAIN_SIZE..], &[0x02; GRAIN_SIZE][..]);
+    }
+
+    #[test]
+    fn test_flat_vmdk_disk() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("server-flat.vmdk"), crate::images::tests::build_fat32_disk()).unwrap();
+        let descriptor = "# Disk DescriptorFile\nCID=fffffffe\nparentCID=ffffffff\n\
+                          RW 4096 FLAT \"server-flat.vmdk\" 0\nRW 2048 ZERO\n";
+        let path = temp_dir.path().join("server.vmdk");
+        std::fs::write(&path, descriptor).unwrap();
+        let path = path.to_str().unwrap();
+
+        let disk = crate::get_image_disk(path).unwrap();
+        assert_eq!(disk.size(), 6144 * 512);
+        assert_eq!(disk.model(), "VMDK image");
+        assert_eq!(disk.partitions().len(), 1);
+        assert_eq!(disk.partitions()[0].name(), "EVIDENCE");
+        assert_eq!(disk.partitions()[0].file_system(), &crate::FileSystem::FAT32(PathBuf::from(path)));
+    }
+}
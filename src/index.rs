@@ -0,0 +1,924 @@
+//! This module keeps the metadata of a directory tree in an index file, so
+//! that the tree can be queried and scanned again without walking it all.
+//!
+//! A `FileIndex` records the `FileEntry` of every file of a tree, with the
+//! digests and the content type computed for it. A rescan walks the tree
+//! again, keeps the digests and types of the files whose size,
+//! modification time and file ID did not change, and reports the files
+//! added, removed and changed since the previous scan. A rescan can save
+//! checkpoints as it goes, from which it resumes after a crash or a reboot
+//! instead of starting over.
+//!
+//! Index and checkpoint files are text files holding a header line and one
+//! tab-separated record per line.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use tempfile::NamedTempFile;
+
+#[cfg(feature = "serialize")]
+use serde::Serialize;
+
+use crate::file_identification::detect_content_type;
+use crate::progress::Tracker;
+use crate::scanner::{walk_files, SaveDirectories};
+use crate::{
+    hash_files_with_monitor, FileAttributes, FileEntry, FileHashes, FileTimes, HashAlgorithm, Monitor, NativeMetadata,
+    ScanError, ScanOptions,
+};
+
+/// First field of the header of an index file
+const INDEX_HEADER: &str = "win-disk-info index";
+
+/// First field of the header of a checkpoint file
+const CHECKPOINT_HEADER: &str = "win-disk-info checkpoint";
+
+/// Version of the layout of index and checkpoint files
+const FORMAT_VERSION: &str = "1";
+
+/// Number of fields of a file record
+const FILE_FIELDS: usize = 18;
+
+/// Category of files without a recognized signature, as named by `identify_files`
+const UNKNOWN: &str = "Unknown";
+
+/// Error type for index operations
+#[derive(Debug)]
+pub enum IndexError {
+    /// IO errors from reading or writing an index or checkpoint file, or from reading the root of the tree
+    Io(io::Error),
+    /// The index or checkpoint file is malformed
+    InvalidFormat(String),
+    /// The root of the indexed tree is not a directory
+    InvalidRoot(PathBuf),
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO error: {}", e),
+            Self::InvalidFormat(s) => write!(f, "Invalid index: {}", s),
+            Self::InvalidRoot(path) => write!(f, "Index root is not a directory: {}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for IndexError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for IndexError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// A file recorded in an index, with the digests and content type computed for it.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct IndexedFile {
+    file: FileEntry,
+    /// Category as named by `identify_files`, `None` until identified
+    category: Option<String>,
+    /// MIME type, `None` until identified or when the content is not recognized
+    mime_type: Option<String>,
+}
+
+impl IndexedFile {
+    fn new(file: FileEntry) -> Self {
+        IndexedFile {
+            file,
+            category: None,
+            mime_type: None,
+        }
+    }
+
+    /// Returns the entry of the file as of the last scan, with its digests
+    pub fn file(&self) -> &FileEntry {
+        &self.file
+    }
+
+    /// Returns the content category, such as `Image` or `Unknown`, if the file was identified
+    pub fn category(&self) -> Option<&str> {
+        self.category.as_deref()
+    }
+
+    /// Returns the MIME type, if the file was identified and its content recognized
+    pub fn mime_type(&self) -> Option<&str> {
+        self.mime_type.as_deref()
+    }
+
+    /// Returns true if a newly scanned entry has the size, modification time and file ID recorded.
+    fn is_unchanged(&self, file: &FileEntry) -> bool {
+        self.file.size() == file.size() && self.file.modified() == file.modified() && self.file.file_id() == file.file_id()
+    }
+
+    /// Records a newly scanned entry of an unchanged file, keeping the digests and type computed earlier.
+    fn reused(&self, mut file: FileEntry) -> Self {
+        file.merge_hashes(self.file.hashes().clone());
+        IndexedFile {
+            file,
+            category: self.category.clone(),
+            mime_type: self.mime_type.clone(),
+        }
+    }
+}
+
+/// Differences found by a rescan of an index.
+///
+/// Paths are sorted. Added and changed files keep no digests nor type;
+/// `FileIndex::hash_missing` and `FileIndex::identify_missing` compute them.
+#[derive(Debug, Default)]
+pub struct IndexChanges {
+    added: Vec<PathBuf>,
+    removed: Vec<PathBuf>,
+    changed: Vec<PathBuf>,
+    unchanged: u64,
+    errors: Vec<ScanError>,
+    resumed: bool,
+    cancelled: bool,
+}
+
+impl IndexChanges {
+    /// Returns the files found that were not in the index
+    pub fn added(&self) -> &[PathBuf] {
+        &self.added
+    }
+
+    /// Returns the files of the index that were not found, empty if the rescan was cancelled
+    pub fn removed(&self) -> &[PathBuf] {
+        &self.removed
+    }
+
+    /// Returns the files whose size, modification time or file ID changed
+    pub fn changed(&self) -> &[PathBuf] {
+        &self.changed
+    }
+
+    /// Returns the number of files found unchanged
+    pub fn unchanged_count(&self) -> u64 {
+        self.unchanged
+    }
+
+    /// Returns the errors met by this run of the rescan, sorted by path
+    pub fn errors(&self) -> &[ScanError] {
+        &self.errors
+    }
+
+    /// Returns true if the rescan resumed from a checkpoint
+    pub fn is_resumed(&self) -> bool {
+        self.resumed
+    }
+
+    /// Returns true if the rescan was cancelled, in which case the index was left as it was
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+
+    /// Returns true if no file was added, removed or changed
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// What a rescan has found so far, as saved in its checkpoints.
+#[derive(Default)]
+struct ScanState {
+    found: BTreeMap<PathBuf, IndexedFile>,
+    /// Directories and entries that could not be read, whose recorded files are kept
+    failed: Vec<PathBuf>,
+}
+
+/// The contents of an index or checkpoint file.
+struct IndexFile {
+    root: PathBuf,
+    scanned: Option<DateTime<Utc>>,
+    /// Directories a checkpointed rescan has left to list
+    directories: Vec<PathBuf>,
+    state: ScanState,
+}
+
+/// The metadata of the files of a directory tree, kept between scans.
+///
+/// # Examples
+/// ```no_run
+/// use std::path::Path;
+/// use std::time::Duration;
+/// use win_disk_info::{FileIndex, HashAlgorithm, Monitor, ScanOptions};
+///
+/// let index_path = Path::new("D-drive.index");
+/// let mut index = if index_path.exists() {
+///     FileIndex::open(index_path).unwrap()
+/// } else {
+///     FileIndex::new("D:\\")
+/// };
+///
+/// // Resumes from the checkpoint if an earlier rescan was interrupted
+/// let changes = index
+///     .rescan_resumable(&ScanOptions::volume(), Path::new("D-drive.checkpoint"), Duration::from_secs(60))
+///     .unwrap();
+/// println!("{} added, {} removed, {} changed", changes.added().len(), changes.removed().len(), changes.changed().len());
+///
+/// // Only new and changed files are read
+/// index.hash_missing(&[HashAlgorithm::Sha256], 0, &Monitor::new());
+/// index.identify_missing(&Monitor::new());
+/// index.save(index_path).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct FileIndex {
+    root: PathBuf,
+    /// Time the last complete scan ended
+    scanned: Option<DateTime<Utc>>,
+    files: BTreeMap<PathBuf, IndexedFile>,
+}
+
+impl FileIndex {
+    /// Creates an empty index of the tree below `root`, filled by the first rescan.
+    pub fn new(root: &str) -> Self {
+        FileIndex {
+            root: PathBuf::from(root),
+            scanned: None,
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Opens an index previously written by `save`.
+    ///
+    /// # Arguments
+    /// * `path` - The index file
+    ///
+    /// # Returns
+    /// * `Ok(FileIndex)` - The files recorded in the index
+    /// * `Err(IndexError)` - If the file cannot be read or is not a valid index
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, IndexError> {
+        let index = read_index_file(path.as_ref(), INDEX_HEADER)?;
+        Ok(FileIndex {
+            root: index.root,
+            scanned: index.scanned,
+            files: index.state.found,
+        })
+    }
+
+    /// Writes the index to a file that can be reopened with `open`.
+    ///
+    /// The file is replaced at once, so that an interrupted save leaves the
+    /// previous index in place.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), IndexError> {
+        write_atomically(path.as_ref(), |writer| {
+            write_header(writer, INDEX_HEADER, &self.root, self.scanned)?;
+            for indexed in self.files.values() {
+                write_file(writer, indexed)?;
+            }
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// Returns the root of the indexed tree
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the time the last complete scan ended, `None` before the first one
+    pub fn last_scan(&self) -> Option<DateTime<Utc>> {
+        self.scanned
+    }
+
+    /// Returns the number of files recorded
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns true if no file is recorded
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Returns the record of the file at `path`, if any
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<&IndexedFile> {
+        self.files.get(path.as_ref())
+    }
+
+    /// Returns the records of the files, sorted by path
+    pub fn iter(&self) -> impl Iterator<Item = &IndexedFile> {
+        self.files.values()
+    }
+
+    /// Returns the entries of the files, sorted by path, as a scan would have returned them
+    pub fn files(&self) -> impl Iterator<Item = &FileEntry> {
+        self.files.values().map(IndexedFile::file)
+    }
+
+    /// Walks the tree again and updates the index
+    ///
+    /// Files whose size, modification time and file ID are those recorded
+    /// keep their digests and content type; the others are recorded anew.
+    /// Files that are no longer found are removed, except those below a
+    /// directory that could not be listed, which are kept as they were.
+    /// The options select the files as in `scan_files_with_errors`, except
+    /// for the sort and limit, which are ignored: files that the options of
+    /// an earlier scan kept but that these leave out are reported as removed.
+    ///
+    /// A rescan cancelled through the monitor of the options leaves the
+    /// index as it was and reports the files added and changed so far.
+    ///
+    /// # Arguments
+    /// * `options` - What to walk and which files to record
+    ///
+    /// # Returns
+    /// * `Ok(IndexChanges)` - The files added, removed and changed, and the errors met
+    /// * `Err(IndexError)` - If the root of the tree is not a readable directory
+    pub fn rescan(&mut self, options: &ScanOptions) -> Result<IndexChanges, IndexError> {
+        let root = self.root.clone();
+        self.rescan_from(options, &[root], ScanState::default(), None)
+    }
+
+    /// Walks the tree again like `rescan`, saving checkpoints it can resume from
+    ///
+    /// The files found so far and the directories left to list are written
+    /// to `checkpoint` every `interval`, and when the rescan is cancelled.
+    /// If `checkpoint` holds such a file for this index, the rescan resumes
+    /// from it: directories listed before the interruption are not listed
+    /// again, and the changes reported cover the whole rescan. A checkpoint
+    /// left by another index, or by a rescan of this one that has since
+    /// been completed, is ignored. The checkpoint is deleted once the
+    /// rescan completes; the index should then be saved.
+    ///
+    /// Each checkpoint holds every file found so far, so the interval
+    /// should grow with the size of the tree.
+    ///
+    /// # Arguments
+    /// * `options` - What to walk and which files to record, the same for every run of the rescan
+    /// * `checkpoint` - The checkpoint file to resume from and to write
+    /// * `interval` - The minimum time between two checkpoints
+    ///
+    /// # Returns
+    /// * `Ok(IndexChanges)` - The files added, removed and changed, and the errors met by this run
+    /// * `Err(IndexError)` - If the root is not a readable directory, the checkpoint cannot be
+    ///   read, or the checkpoint of a cancelled rescan cannot be written
+    pub fn rescan_resumable<P: AsRef<Path>>(
+        &mut self,
+        options: &ScanOptions,
+        checkpoint: P,
+        interval: Duration,
+    ) -> Result<IndexChanges, IndexError> {
+        let checkpoint = checkpoint.as_ref();
+        let saved = match read_index_file(checkpoint, CHECKPOINT_HEADER) {
+            Ok(saved) => Some(saved).filter(|saved| saved.root == self.root && saved.scanned == self.scanned),
+            Err(IndexError::Io(e)) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        let (directories, state, resumed) = match saved {
+            Some(saved) => (saved.directories, saved.state, true),
+            None => (vec![self.root.clone()], ScanState::default(), false),
+        };
+        let mut changes = self.rescan_from(options, &directories, state, Some((checkpoint, interval)))?;
+        changes.resumed = resumed;
+        Ok(changes)
+    }
+
+    /// Walks `directories` and records what is found with what `state` found earlier.
+    fn rescan_from(
+        &mut self,
+        options: &ScanOptions,
+        directories: &[PathBuf],
+        state: ScanState,
+        checkpoint: Option<(&Path, Duration)>,
+    ) -> Result<IndexChanges, IndexError> {
+        if !fs::metadata(&self.root)?.is_dir() {
+            return Err(IndexError::InvalidRoot(self.root.clone()));
+        }
+        let state = Mutex::new(state);
+        let errors = Mutex::new(Vec::new());
+        let saved = Mutex::new(Ok(()));
+        let this = &*self;
+        let save = |remaining: Vec<PathBuf>| {
+            if let Some((path, _)) = checkpoint {
+                let state = state.lock().unwrap_or_else(|e| e.into_inner());
+                let written = this.write_checkpoint(path, &remaining, &state);
+                *saved.lock().unwrap_or_else(|e| e.into_inner()) = written;
+            }
+        };
+        let periodic = checkpoint.map(|(_, interval)| (interval, &save as SaveDirectories));
+        walk_files(&this.root, directories, options, periodic, |found| match found {
+            Ok(file) => {
+                let indexed = match this.files.get(file.path()) {
+                    Some(previous) if previous.is_unchanged(&file) => previous.reused(file),
+                    _ => IndexedFile::new(file),
+                };
+                let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+                state.found.insert(indexed.file.path().to_path_buf(), indexed);
+            }
+            Err(error) => {
+                if let Some(path) = error.path() {
+                    state.lock().unwrap_or_else(|e| e.into_inner()).failed.push(path.to_path_buf());
+                }
+                errors.lock().unwrap_or_else(|e| e.into_inner()).push(error);
+            }
+        });
+
+        let mut state = state.into_inner().unwrap_or_else(|e| e.into_inner());
+        let mut changes = IndexChanges {
+            errors: errors.into_inner().unwrap_or_else(|e| e.into_inner()),
+            cancelled: options.monitor().is_cancelled(),
+            ..IndexChanges::default()
+        };
+        changes.errors.sort_by(|a, b| a.path().cmp(&b.path()));
+        for (path, indexed) in &state.found {
+            match self.files.get(path) {
+                None => changes.added.push(path.clone()),
+                Some(previous) if previous.is_unchanged(indexed.file()) => changes.unchanged += 1,
+                Some(_) => changes.changed.push(path.clone()),
+            }
+        }
+        if changes.cancelled {
+            // The last checkpoint, written when the walk stopped, is the one to resume from
+            saved.into_inner().unwrap_or_else(|e| e.into_inner())?;
+            return Ok(changes);
+        }
+
+        for (path, previous) in std::mem::take(&mut self.files) {
+            if state.found.contains_key(&path) {
+                continue;
+            }
+            if state.failed.iter().any(|failed| path.starts_with(failed)) {
+                state.found.insert(path, previous);
+            } else {
+                changes.removed.push(path);
+            }
+        }
+        self.files = state.found;
+        self.scanned = Some(Utc::now());
+        if let Some((path, _)) = checkpoint {
+            // A checkpoint left behind no longer matches the scan time of the index and is ignored
+            let _ = fs::remove_file(path);
+        }
+        Ok(changes)
+    }
+
+    /// Writes what a rescan has found so far and the directories it has left to list.
+    fn write_checkpoint(&self, path: &Path, remaining: &[PathBuf], state: &ScanState) -> io::Result<()> {
+        write_atomically(path, |writer| {
+            write_header(writer, CHECKPOINT_HEADER, &self.root, self.scanned)?;
+            for directory in remaining {
+                writeln!(writer, "D\t{}", encode_path(directory))?;
+            }
+            for failed in &state.failed {
+                writeln!(writer, "E\t{}", encode_path(failed))?;
+            }
+            for indexed in state.found.values() {
+                write_file(writer, indexed)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Hashes the recorded files that lack a digest of one of `algorithms`
+    ///
+    /// Files kept unchanged by a rescan keep their digests, so that only new
+    /// and changed files are read. Works as `hash_files_with_monitor`; a
+    /// cancelled run keeps the digests computed so far.
+    ///
+    /// # Arguments
+    /// * `algorithms` - The digests to compute
+    /// * `threads` - The number of threads to hash with, 0 for one per processor
+    /// * `monitor` - Progress reporting and cancellation
+    ///
+    /// # Returns
+    /// * `Vec<(PathBuf, io::Error)>` - The files that could not be read
+    pub fn hash_missing(&mut self, algorithms: &[HashAlgorithm], threads: usize, monitor: &Monitor) -> Vec<(PathBuf, io::Error)> {
+        let mut missing: Vec<FileEntry> = self
+            .files
+            .values()
+            .filter(|indexed| algorithms.iter().any(|algorithm| indexed.file.hashes().get(*algorithm).is_none()))
+            .map(|indexed| indexed.file.clone())
+            .collect();
+        let errors = hash_files_with_monitor(&mut missing, algorithms, threads, monitor);
+        for file in missing {
+            if let Some(indexed) = self.files.get_mut(file.path()) {
+                indexed.file.merge_hashes(file.hashes().clone());
+            }
+        }
+        errors
+    }
+
+    /// Identifies the content type of the recorded files not identified yet
+    ///
+    /// Files are identified from their signature, as `identify_files` does.
+    /// Files kept unchanged by a rescan keep their type, so that only new
+    /// and changed files are read. A cancelled run keeps the types found so far.
+    ///
+    /// # Arguments
+    /// * `monitor` - Progress reporting and cancellation
+    ///
+    /// # Returns
+    /// * `Vec<(PathBuf, io::Error)>` - The files that could not be read
+    pub fn identify_missing(&mut self, monitor: &Monitor) -> Vec<(PathBuf, io::Error)> {
+        let missing: Vec<&mut IndexedFile> = self.files.values_mut().filter(|indexed| indexed.category.is_none()).collect();
+        let tracker = Tracker::with_totals(monitor, missing.len() as u64, missing.iter().map(|indexed| indexed.file.size()).sum());
+        let mut errors = Vec::new();
+        for indexed in missing {
+            if tracker.is_cancelled() {
+                break;
+            }
+            match detect_content_type(&indexed.file) {
+                Ok(Some((category, mime_type))) => {
+                    indexed.category = Some(category);
+                    indexed.mime_type = Some(mime_type);
+                }
+                Ok(None) => indexed.category = Some(UNKNOWN.to_string()),
+                Err(error) => errors.push((indexed.file.path().to_path_buf(), error)),
+            }
+            tracker.advance(indexed.file.path(), 1, indexed.file.size());
+        }
+        tracker.finish();
+        errors
+    }
+}
+
+/// Writes a file through a temporary file in the same directory, which then replaces it.
+fn write_atomically<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut BufWriter<&File>) -> io::Result<()>,
+{
+    let directory = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let temporary = NamedTempFile::new_in(directory)?;
+    let mut writer = BufWriter::new(temporary.as_file());
+    write(&mut writer)?;
+    writer.flush()?;
+    drop(writer);
+    temporary.as_file().sync_all()?;
+    temporary.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+fn write_header(writer: &mut impl Write, header: &str, root: &Path, scanned: Option<DateTime<Utc>>) -> io::Result<()> {
+    writeln!(writer, "{}\t{}\t{}\t{}", header, FORMAT_VERSION, encode_path(root), encode_time(scanned))
+}
+
+/// Writes the record of a file: its metadata, type and digests.
+fn write_file(writer: &mut impl Write, indexed: &IndexedFile) -> io::Result<()> {
+    let file = &indexed.file;
+    let times = file.times();
+    let number = |value: Option<u64>| value.map(|value| value.to_string()).unwrap_or_default();
+    let hashes: Vec<String> = file.hashes().iter().map(|(algorithm, digest)| format!("{}={}", algorithm, digest)).collect();
+    let fields = [
+        "F".to_string(),
+        encode_path(file.path()),
+        file.size().to_string(),
+        encode_time(times.modified()),
+        encode_time(times.accessed()),
+        encode_time(times.changed()),
+        encode_time(times.born()),
+        file.attributes().bits().to_string(),
+        number(file.file_id()),
+        number(file.volume_id()),
+        number(file.hard_links()),
+        number(file.allocated_size()),
+        number(file.mode().map(u64::from)),
+        number(file.uid().map(u64::from)),
+        number(file.gid().map(u64::from)),
+        indexed.category.as_deref().map(escape).unwrap_or_default(),
+        indexed.mime_type.as_deref().map(escape).unwrap_or_default(),
+        hashes.join(","),
+    ];
+    writeln!(writer, "{}", fields.join("\t"))
+}
+
+fn encode_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|time| time.to_rfc3339_opts(SecondsFormat::Nanos, true)).unwrap_or_default()
+}
+
+/// Escapes the backslashes, tabs and line breaks of a field.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escapes a path, writing the bytes that are not valid UTF-8 as `\xHH`.
+#[cfg(unix)]
+fn encode_path(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut encoded = String::new();
+    for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+        encoded.push_str(&escape(chunk.valid()));
+        for byte in chunk.invalid() {
+            encoded.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+    encoded
+}
+
+/// Escapes a path.
+#[cfg(not(unix))]
+fn encode_path(path: &Path) -> String {
+    escape(&path.to_string_lossy())
+}
+
+/// Reverses `escape` and the `\xHH` escapes of paths.
+fn unescape(field: &str) -> Result<Vec<u8>, String> {
+    let invalid = || format!("invalid escape in {:?}", field);
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut rest = field.bytes();
+    while let Some(byte) = rest.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match rest.next().ok_or_else(invalid)? {
+            b'\\' => bytes.push(b'\\'),
+            b't' => bytes.push(b'\t'),
+            b'n' => bytes.push(b'\n'),
+            b'r' => bytes.push(b'\r'),
+            b'x' => {
+                let digits = [rest.next().ok_or_else(invalid)?, rest.next().ok_or_else(invalid)?];
+                let digits = std::str::from_utf8(&digits).map_err(|_| invalid())?;
+                bytes.push(u8::from_str_radix(digits, 16).map_err(|_| invalid())?);
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(bytes)
+}
+
+fn decode_text(field: &str) -> Result<String, String> {
+    String::from_utf8(unescape(field)?).map_err(|_| format!("invalid text {:?}", field))
+}
+
+#[cfg(unix)]
+fn decode_path(field: &str) -> Result<PathBuf, String> {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    Ok(PathBuf::from(OsString::from_vec(unescape(field)?)))
+}
+
+#[cfg(not(unix))]
+fn decode_path(field: &str) -> Result<PathBuf, String> {
+    decode_text(field).map(PathBuf::from)
+}
+
+/// Parses an optional field, empty when the value is unknown.
+fn decode_optional<T, F>(field: &str, parse: F) -> Result<Option<T>, String>
+where
+    F: FnOnce(&str) -> Result<T, String>,
+{
+    if field.is_empty() {
+        Ok(None)
+    } else {
+        parse(field).map(Some)
+    }
+}
+
+fn decode_number<T: std::str::FromStr>(field: &str) -> Result<T, String> {
+    field.parse().map_err(|_| format!("invalid number {:?}", field))
+}
+
+fn decode_time(field: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(field)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| format!("invalid time {:?}", field))
+}
+
+/// Parses the fields of a file record, after its `F` tag.
+fn decode_file(fields: &[&str]) -> Result<IndexedFile, String> {
+    if fields.len() != FILE_FIELDS - 1 {
+        return Err(format!("expected {} fields, found {}", FILE_FIELDS, fields.len() + 1));
+    }
+    let path = decode_path(fields[0])?;
+    let time = |field: &str| decode_optional(field, decode_time);
+    let times = FileTimes::new()
+        .with_modified(time(fields[2])?)
+        .with_accessed(time(fields[3])?)
+        .with_changed(time(fields[4])?)
+        .with_born(time(fields[5])?);
+    let native = NativeMetadata {
+        attributes: FileAttributes::from_bits(decode_number(fields[6])?),
+        file_id: decode_optional(fields[7], decode_number)?,
+        volume_id: decode_optional(fields[8], decode_number)?,
+        links: decode_optional(fields[9], decode_number)?,
+        allocated_size: decode_optional(fields[10], decode_number)?,
+        mode: decode_optional(fields[11], decode_number)?,
+        uid: decode_optional(fields[12], decode_number)?,
+        gid: decode_optional(fields[13], decode_number)?,
+        changed: times.changed(),
+    };
+
+    let mut hashes = FileHashes::default();
+    for digest in fields[16].split(',').filter(|digest| !digest.is_empty()) {
+        let (name, digest) = digest.split_once('=').ok_or_else(|| format!("invalid digest {:?}", digest))?;
+        let algorithm = HashAlgorithm::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.to_string() == name)
+            .ok_or_else(|| format!("unknown algorithm {:?}", name))?;
+        hashes.insert(algorithm, digest.to_string());
+    }
+
+    let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let mut file = FileEntry::from_native(path, name, decode_number(fields[1])?, times, native);
+    file.merge_hashes(hashes);
+    Ok(IndexedFile {
+        file,
+        category: decode_optional(fields[14], decode_text)?,
+        mime_type: decode_optional(fields[15], decode_text)?,
+    })
+}
+
+/// Reads an index or checkpoint file whose header starts with `header`.
+fn read_index_file(path: &Path, header: &str) -> Result<IndexFile, IndexError> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let first = lines.next().transpose()?.unwrap_or_default();
+    let fields: Vec<&str> = first.split('\t').collect();
+    if fields.len() != 4 || fields[0] != header || fields[1] != FORMAT_VERSION {
+        return Err(IndexError::InvalidFormat(format!("{} is not a {} file", path.display(), header)));
+    }
+    let invalid = |line: usize, message: String| IndexError::InvalidFormat(format!("line {}: {}", line, message));
+    let mut index = IndexFile {
+        root: decode_path(fields[2]).map_err(|message| invalid(1, message))?,
+        scanned: decode_optional(fields[3], decode_time).map_err(|message| invalid(1, message))?,
+        directories: Vec::new(),
+        state: ScanState::default(),
+    };
+
+    for (number, line) in lines.enumerate() {
+        let line = line?;
+        let fields: Vec<&str> = line.split('\t').collect();
+        let record = match fields[..] {
+            ["F", ..] => decode_file(&fields[1..]).map(|indexed| {
+                index.state.found.insert(indexed.file.path().to_path_buf(), indexed);
+            }),
+            ["D", directory] => decode_path(directory).map(|directory| index.directories.push(directory)),
+            ["E", failed] => decode_path(failed).map(|failed| index.state.failed.push(failed)),
+            _ => Err("unknown record".to_string()),
+        };
+        record.map_err(|message| invalid(number + 2, message))?;
+    }
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::CancellationToken;
+
+    fn indexed_tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("docs/old")).unwrap();
+        fs::create_dir(dir.path().join("photos")).unwrap();
+        fs::write(dir.path().join("notes.txt"), b"notes").unwrap();
+        fs::write(dir.path().join("docs/report.txt"), b"report").unwrap();
+        fs::write(dir.path().join("docs/old/draft.txt"), b"draft").unwrap();
+        let png = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        fs::write(dir.path().join("photos/cat.png"), png).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_save_and_open() {
+        let dir = indexed_tree();
+        let tabbed = dir.path().join("tab\there\\and\nline.txt");
+        fs::write(&tabbed, b"odd name").unwrap();
+        let mut index = FileIndex::new(dir.path().to_str().unwrap());
+        index.rescan(&ScanOptions::new()).unwrap();
+        assert!(index.hash_missing(&[HashAlgorithm::Md5, HashAlgorithm::Sha1], 2, &Monitor::new()).is_empty());
+        assert!(index.identify_missing(&Monitor::new()).is_empty());
+
+        let saved = dir.path().join("tree.index");
+        index.save(&saved).unwrap();
+        let opened = FileIndex::open(&saved).unwrap();
+        assert_eq!((opened.root(), opened.last_scan(), opened.len()), (index.root(), index.last_scan(), 5));
+        for (original, reopened) in index.iter().zip(opened.iter()) {
+            assert_eq!(original.file().to_string(), reopened.file().to_string());
+            assert_eq!(original.file().times(), reopened.file().times());
+            assert_eq!(original.file().attributes(), reopened.file().attributes());
+            assert_eq!(original.file().file_id(), reopened.file().file_id());
+            assert_eq!(original.file().allocated_size(), reopened.file().allocated_size());
+            assert_eq!(original.file().hashes(), reopened.file().hashes());
+            assert_eq!((original.category(), original.mime_type()), (reopened.category(), reopened.mime_type()));
+        }
+        let cat = opened.get(dir.path().join("photos/cat.png")).unwrap();
+        assert_eq!((cat.category(), cat.mime_type()), (Some("Image"), Some("image/png")));
+        assert_eq!(opened.get(&tabbed).unwrap().category(), Some("Unknown"));
+        assert_eq!(opened.get(&tabbed).unwrap().file().hashes().md5(), Some("4ac47f4a91ac94c171ac29ea63719e32"));
+
+        fs::write(&saved, "win-disk-info index\t1\t/\t\nF\tshort\n").unwrap();
+        assert!(matches!(FileIndex::open(&saved), Err(IndexError::InvalidFormat(message)) if message.starts_with("line 2:")));
+        assert!(matches!(FileIndex::open(dir.path().join("notes.txt")), Err(IndexError::InvalidFormat(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_paths() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"/data/caf\xe9\\x41.txt"));
+        assert_eq!(encode_path(path), "/data/caf\\xe9\\\\x41.txt");
+        assert_eq!(decode_path(&encode_path(path)).unwrap(), path);
+        assert!(decode_path("bad\\x4").is_err());
+    }
+
+    #[test]
+    fn test_rescan_reports_changes_and_keeps_digests() {
+        let dir = indexed_tree();
+        let mut index = FileIndex::new(dir.path().to_str().unwrap());
+        let changes = index.rescan(&ScanOptions::new()).unwrap();
+        assert_eq!(changes.added().len(), 4);
+        assert!(changes.removed().is_empty() && changes.changed().is_empty() && !changes.is_resumed());
+        index.hash_missing(&[HashAlgorithm::Sha256], 1, &Monitor::new());
+        index.identify_missing(&Monitor::new());
+
+        fs::write(dir.path().join("docs/report.txt"), b"report, second edition").unwrap();
+        fs::remove_file(dir.path().join("notes.txt")).unwrap();
+        fs::write(dir.path().join("photos/dog.png"), b"not yet a picture").unwrap();
+        let changes = index.rescan(&ScanOptions::new()).unwrap();
+        assert_eq!(changes.added(), [dir.path().join("photos/dog.png")]);
+        assert_eq!(changes.removed(), [dir.path().join("notes.txt")]);
+        assert_eq!(changes.changed(), [dir.path().join("docs/report.txt")]);
+        assert_eq!(changes.unchanged_count(), 2);
+        assert_eq!(index.len(), 4);
+
+        // Unchanged files keep their digests and types, the others are computed again
+        let cat = index.get(dir.path().join("photos/cat.png")).unwrap();
+        assert!(cat.file().hashes().sha256().is_some() && cat.category() == Some("Image"));
+        let report = index.get(dir.path().join("docs/report.txt")).unwrap();
+        assert!(report.file().hashes().is_empty() && report.category().is_none());
+        index.hash_missing(&[HashAlgorithm::Sha256], 1, &Monitor::new());
+        assert!(index.files().all(|file| file.hashes().sha256().is_some()));
+
+        assert!(index.rescan(&ScanOptions::new()).unwrap().is_empty());
+        let missing = FileIndex::new(dir.path().join("missing").to_str().unwrap()).rescan(&ScanOptions::new());
+        assert!(matches!(missing, Err(IndexError::Io(_))));
+        let not_a_directory = FileIndex::new(dir.path().join("docs/report.txt").to_str().unwrap()).rescan(&ScanOptions::new());
+        assert!(matches!(not_a_directory, Err(IndexError::InvalidRoot(_))));
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        let dir = indexed_tree();
+        let state = tempfile::tempdir().unwrap();
+        let checkpoint = state.path().join("tree.checkpoint");
+        let mut index = FileIndex::new(dir.path().to_str().unwrap());
+        index.rescan(&ScanOptions::new()).unwrap();
+        fs::write(dir.path().join("docs/old/draft.txt"), b"draft, revised").unwrap();
+        fs::remove_file(dir.path().join("notes.txt")).unwrap();
+
+        // The rescan is cancelled once the first file is found
+        let token = CancellationToken::new();
+        let seen = Arc::new(AtomicUsize::new(0));
+        let (cancel, counter) = (token.clone(), Arc::clone(&seen));
+        let monitor = Monitor::new()
+            .with_cancellation(token)
+            .with_interval(Duration::ZERO)
+            .with_progress(move |progress| {
+                if progress.files() > 0 && !progress.is_finished() {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    cancel.cancel();
+                }
+            });
+        let options = ScanOptions::new().with_threads(1);
+        let cancelled = index.rescan_resumable(&options.clone().with_monitor(monitor), &checkpoint, Duration::from_secs(3600)).unwrap();
+        assert!(cancelled.is_cancelled() && cancelled.removed().is_empty());
+        assert!(seen.load(Ordering::Relaxed) > 0 && checkpoint.exists());
+        assert!(index.get(dir.path().join("notes.txt")).is_some());
+
+        let resumed = index.rescan_resumable(&options, &checkpoint, Duration::from_secs(3600)).unwrap();
+        assert!(resumed.is_resumed() && !resumed.is_cancelled());
+        assert!(resumed.added().is_empty());
+        assert_eq!(resumed.removed(), [dir.path().join("notes.txt")]);
+        assert_eq!(resumed.changed(), [dir.path().join("docs/old/draft.txt")]);
+        assert_eq!(resumed.unchanged_count(), 2);
+        assert!(!checkpoint.exists());
+
+        // A checkpoint of an earlier scan is ignored
+        fs::write(&checkpoint, format!("{}\t1\t{}\t\n", CHECKPOINT_HEADER, encode_path(dir.path()))).unwrap();
+        let restarted = index.rescan_resumable(&options, &checkpoint, Duration::from_secs(3600)).unwrap();
+        assert!(!restarted.is_resumed() && restarted.is_empty());
+    }
+}
@@ -3,29 +3,52 @@
 //! A Rust library for retrieving and analyzing information about disks, 
 //! partitions, and files on Windows systems.
 //!
+//! Querying physical disks requires Windows. Disk images, file listing and
+//! file identification also work on Linux and other platforms.
+//!
 //! This library provides functionality to:
 //! - Query physical disk information using Windows WMI
 //! - List partitions and their properties
+//! - Map the layout of a disk: partitions, partitioning metadata and unallocated gaps
+//! - Read raw (single or split), VHD, VHDX, QCOW2 and VMDK disk images, including parent and backing chains
+//! - Read Expert Witness (E01) evidence images, their case metadata and verify their hashes
+//! - Parse MBR and GPT partition tables and detect the file systems they hold
+//! - Read optical images (ISO 9660, Joliet, Rock Ridge and UDF)
 //! - Extract file information from directories
+//! - Scan directory trees with depth, size, date, attribute, glob, regular expression and ignore file filters, sorted or limited to the top entries
 //! - Identify file types based on content
 //! - Find files with incorrect extensions
-//! - Calculate directory sizes
+//! - Summarize the space used per content category, MIME type and extension
+//! - Hash files with MD5, SHA-1, SHA-256, SHA-512 and BLAKE3 in a single read pass
+//! - Find duplicate files and the space they waste
+//! - Match files against NSRL, hashdeep and plain hash sets of known-good and known-bad files
+//! - Recover deleted files from NTFS and FAT metadata, or by their signatures from raw or unallocated space
+//! - Calculate directory sizes, apparent or allocated on disk with hard links counted once, or break them down into a tree of the largest directories
+//! - Walk large trees on several threads, or stream their files in constant memory
+//! - Keep the metadata, digests and types of a tree in an index file, rescanned incrementally and resumable after an interruption
+//! - Report the progress of scans, hashing and identification, and cancel them while keeping partial results
+//! - Export modified, accessed, changed and born times as bodyfile or mactime timelines
+//! - Acquire selected files into verifiable ZIP or tar containers with a hash manifest
+//! - Produce HTML, JSON and CSV reports of disks, files and identification results
 //!
 //! ## Example
 //!
 //! ```no_run
-//! use win_disk_info::{get_disks, get_files, identify_files};
+//! use win_disk_info::{get_files, get_image_disk, identify_files};
 //!
 //! fn main() -> Result<(), Box<dyn std::error::Error>> {
-//!     // Get information about all physical disks
-//!     let disks = get_disks()?;
-//!     
-//!     for disk in disks {
+//!     // Get information about all physical disks (Windows only)
+//!     #[cfg(windows)]
+//!     for disk in win_disk_info::get_disks()? {
 //!         println!("Disk: {} ({} GB)", 
 //!             disk.model(), 
 //!             disk.size() / 1_000_000_000);
 //!     }
 //!     
+//!     // Inspect the disk stored in a virtual machine image
+//!     let image = get_image_disk("evidence/workstation.qcow2")?;
+//!     println!("{}", image);
+//!     
 //!     // List files in a directory
 //!     let files = get_files("C:\\Documents")?;
 //!     
@@ -37,11 +60,52 @@
 //! ```
 
 mod models;
+#[cfg(windows)]
 mod windows_storage;
 mod file_extraction;
 mod file_identification;
+mod images;
+mod partition_table;
+mod file_system_detection;
+mod carving;
+mod file_hashing;
+mod duplicates;
+mod hash_sets;
+mod report;
+mod acquisition;
+mod timeline;
+mod pattern;
+mod scanner;
+mod progress;
+mod directory_tree;
+mod usage;
+mod index;
 
 pub use models::*;
+#[cfg(windows)]
 pub use windows_storage::get_disks;
-pub use file_extraction::{get_files, get_files_by_pattern, get_recently_modified_files, calculate_directory_size, format_file_size};
-pub use file_identification::{identify_files, validate_file_extension, find_mismatched_extensions};
\ No newline at end of file
+pub use scanner::{
+    scan_directory_size, scan_files, scan_files_iter, scan_files_with_errors, FileScan, MatchTarget, ScanError, ScanOptions, ScanOutcome,
+    SizeKind, SortKey,
+};
+pub use file_extraction::{
+    get_files, get_files_by_pattern, get_recently_modified_files, calculate_allocated_size, calculate_directory_size, format_file_size,
+};
+pub use progress::{CancellationToken, Monitor, Progress};
+pub use directory_tree::{directory_tree, DirectoryNode, DirectoryTree};
+pub use usage::{usage_summary, UsageBucket, UsageSummary};
+pub use index::{FileIndex, IndexChanges, IndexError, IndexedFile};
+pub use file_identification::{
+    find_mismatched_extensions, identify_files, identify_files_with_monitor, identify_iter, mismatched_extensions_iter, validate_extensions_iter, validate_file_extension,
+};
+pub use file_hashing::{hash_file, hash_files, hash_files_parallel, hash_files_with_monitor, hash_reader};
+pub use duplicates::{find_duplicate_files, find_duplicates, DuplicateGroup, DuplicateOptions};
+pub use hash_sets::{exclude_known_good, HashSetError, HashSetFormat, KnownFileSet};
+pub use report::Report;
+pub use timeline::{timeline, write_bodyfile, write_mactime_csv, TimelineEvent};
+pub use acquisition::{acquire_files, verify_container, AcquiredFile, Acquisition, AcquisitionError, ContainerFormat, ContainerVerification};
+pub use carving::{carve_files, carve_range, carve_unallocated, DEFAULT_MAX_CARVE_SIZE};
+pub use images::{
+    get_deleted_files, get_ewf_metadata, get_image_disk, get_optical_files, verify_ewf_image, EwfMetadata, EwfVerification, ImageError,
+    ImageFormat, VirtualDisk,
+};
\ No newline at end of file
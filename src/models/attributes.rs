@@ -0,0 +1,414 @@
+//! This module provides structures for representing file attributes.
+//!
+//! It contains the `FileAttributes` set, using the values of the Windows
+//! `FILE_ATTRIBUTE_*` constants, and the capture of the attributes, file
+//! identity, ownership and allocated size of a file on a mounted file
+//! system, read once when the file is scanned.
+
+use std::fmt;
+use std::fs::Metadata;
+use std::ops::BitOr;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+
+#[cfg(feature = "serialize")]
+use serde::Serialize;
+
+/// Set of file attributes.
+///
+/// The bits are those of the Windows `FILE_ATTRIBUTE_*` constants, also used
+/// by NTFS and, for the lower ones, by FAT. On Unix the set is derived from
+/// the file: names starting with a dot are hidden, files without write
+/// permission are read-only and symbolic links are reparse points.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct FileAttributes(u32);
+
+impl FileAttributes {
+    /// The file cannot be written
+    pub const READONLY: FileAttributes = FileAttributes(0x0001);
+    /// The file is not shown in normal directory listings
+    pub const HIDDEN: FileAttributes = FileAttributes(0x0002);
+    /// The file is used by the operating system
+    pub const SYSTEM: FileAttributes = FileAttributes(0x0004);
+    /// The entry is a directory
+    pub const DIRECTORY: FileAttributes = FileAttributes(0x0010);
+    /// The file is marked for backup
+    pub const ARCHIVE: FileAttributes = FileAttributes(0x0020);
+    /// The file has unallocated ranges that read back as zeros
+    pub const SPARSE: FileAttributes = FileAttributes(0x0200);
+    /// The entry is a symbolic link, junction or other reparse point
+    pub const REPARSE_POINT: FileAttributes = FileAttributes(0x0400);
+    /// The content is compressed by the file system
+    pub const COMPRESSED: FileAttributes = FileAttributes(0x0800);
+    /// The content is stored offline, for instance by a cloud or archive provider
+    pub const OFFLINE: FileAttributes = FileAttributes(0x1000);
+    /// The content is encrypted by the file system
+    pub const ENCRYPTED: FileAttributes = FileAttributes(0x4000);
+
+    /// Named attributes, in the order they are displayed
+    const NAMES: [(FileAttributes, &'static str); 10] = [
+        (FileAttributes::READONLY, "Read-only"),
+        (FileAttributes::HIDDEN, "Hidden"),
+        (FileAttributes::SYSTEM, "System"),
+        (FileAttributes::DIRECTORY, "Directory"),
+        (FileAttributes::ARCHIVE, "Archive"),
+        (FileAttributes::SPARSE, "Sparse"),
+        (FileAttributes::REPARSE_POINT, "Reparse point"),
+        (FileAttributes::COMPRESSED, "Compressed"),
+        (FileAttributes::OFFLINE, "Offline"),
+        (FileAttributes::ENCRYPTED, "Encrypted"),
+    ];
+
+    /// Creates a set from raw `FILE_ATTRIBUTE_*` bits, keeping unnamed bits
+    pub fn from_bits(bits: u32) -> Self {
+        FileAttributes(bits)
+    }
+
+    /// Parses the names written by `Display`, returning None for an unknown name
+    pub(crate) fn from_names(names: &str) -> Option<Self> {
+        if names == "None" {
+            return Some(FileAttributes::default());
+        }
+        names.split(", ").try_fold(FileAttributes::default(), |attributes, name| {
+            let (attribute, _) = Self::NAMES.iter().find(|(_, known)| *known == name)?;
+            Some(FileAttributes(attributes.0 | attribute.0))
+        })
+    }
+
+    /// Returns the raw `FILE_ATTRIBUTE_*` bits
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    /// Returns true if every attribute of `other` is set
+    pub fn contains(&self, other: FileAttributes) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns true if no attribute is set
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns true if the file cannot be written
+    pub fn is_readonly(&self) -> bool {
+        self.contains(FileAttributes::READONLY)
+    }
+
+    /// Returns true if the file is hidden
+    pub fn is_hidden(&self) -> bool {
+        self.contains(FileAttributes::HIDDEN)
+    }
+
+    /// Returns true if the file is used by the operating system
+    pub fn is_system(&self) -> bool {
+        self.contains(FileAttributes::SYSTEM)
+    }
+
+    /// Returns true if the entry is a directory
+    pub fn is_directory(&self) -> bool {
+        self.contains(FileAttributes::DIRECTORY)
+    }
+
+    /// Returns true if the file is marked for backup
+    pub fn is_archive(&self) -> bool {
+        self.contains(FileAttributes::ARCHIVE)
+    }
+
+    /// Returns true if the file is sparse
+    pub fn is_sparse(&self) -> bool {
+        self.contains(FileAttributes::SPARSE)
+    }
+
+    /// Returns true if the entry is a symbolic link, junction or other reparse point
+    pub fn is_reparse_point(&self) -> bool {
+        self.contains(FileAttributes::REPARSE_POINT)
+    }
+
+    /// Returns true if the content is compressed by the file system
+    pub fn is_compressed(&self) -> bool {
+        self.contains(FileAttributes::COMPRESSED)
+    }
+
+    /// Returns true if the content is stored offline
+    pub fn is_offline(&self) -> bool {
+        self.contains(FileAttributes::OFFLINE)
+    }
+
+    /// Returns true if the content is encrypted by the file system
+    pub fn is_encrypted(&self) -> bool {
+        self.contains(FileAttributes::ENCRYPTED)
+    }
+}
+
+impl BitOr for FileAttributes {
+    type Output = FileAttributes;
+
+    fn bitor(self, other: FileAttributes) -> FileAttributes {
+        FileAttributes(self.0 | other.0)
+    }
+}
+
+impl fmt::Display for FileAttributes {
+    /// Formats the named attributes separated by commas, or `None`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = Self::NAMES
+            .iter()
+            .filter(|(attribute, _)| self.contains(*attribute))
+            .map(|(_, name)| *name)
+            .collect();
+        if names.is_empty() {
+            write!(f, "None")
+        } else {
+            write!(f, "{}", names.join(", "))
+        }
+    }
+}
+
+/// Metadata of a file on a mounted file system that `std::fs::Metadata`
+/// does not expose on every platform.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct NativeMetadata {
+    pub(crate) attributes: FileAttributes,
+    /// Inode or NTFS file reference number
+    pub(crate) file_id: Option<u64>,
+    /// Device number or volume serial number
+    pub(crate) volume_id: Option<u64>,
+    pub(crate) links: Option<u64>,
+    pub(crate) mode: Option<u32>,
+    pub(crate) uid: Option<u32>,
+    pub(crate) gid: Option<u32>,
+    /// Last metadata change time
+    pub(crate) changed: Option<DateTime<Utc>>,
+    /// Space allocated to the content on disk
+    pub(crate) allocated_size: Option<u64>,
+}
+
+/// Reads the native metadata of a file from its inode.
+///
+/// The inode holds everything, so the details are always read.
+#[cfg(unix)]
+pub(crate) fn native_metadata(_path: &Path, name: &str, metadata: &Metadata, _detailed: bool) -> NativeMetadata {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut attributes = FileAttributes::default();
+    if name.starts_with('.') {
+        attributes = attributes | FileAttributes::HIDDEN;
+    }
+    if metadata.permissions().readonly() {
+        attributes = attributes | FileAttributes::READONLY;
+    }
+    if metadata.is_dir() {
+        attributes = attributes | FileAttributes::DIRECTORY;
+    }
+    if metadata.file_type().is_symlink() {
+        attributes = attributes | FileAttributes::REPARSE_POINT;
+    }
+
+    NativeMetadata {
+        attributes,
+        file_id: Some(metadata.ino()),
+        volume_id: Some(metadata.dev()),
+        links: Some(metadata.nlink()),
+        mode: Some(metadata.mode()),
+        uid: Some(metadata.uid()),
+        gid: Some(metadata.gid()),
+        changed: DateTime::from_timestamp(metadata.ctime(), metadata.ctime_nsec() as u32),
+        // `st_blocks` counts 512-byte units whatever the block size of the file system
+        allocated_size: Some(metadata.blocks() * 512),
+    }
+}
+
+/// Reads the native metadata of a file from its attributes and an open handle.
+///
+/// The handle is opened without data access, so files locked by other
+/// processes still report their identity. Only the attributes are returned
+/// when `detailed` is false, sparing the handle and the size queries, or
+/// when the handle cannot be opened.
+#[cfg(windows)]
+pub(crate) fn native_metadata(path: &Path, _name: &str, metadata: &Metadata, detailed: bool) -> NativeMetadata {
+    use std::os::windows::fs::{MetadataExt, OpenOptionsExt};
+    use std::os::windows::io::AsRawHandle;
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::Storage::FileSystem::{
+        FileBasicInfo, GetFileInformationByHandle, GetFileInformationByHandleEx, BY_HANDLE_FILE_INFORMATION,
+        FILE_BASIC_INFO, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_REPARSE_POINT,
+    };
+
+    let mut native = NativeMetadata {
+        attributes: FileAttributes::from_bits(metadata.file_attributes()),
+        ..NativeMetadata::default()
+    };
+    if !detailed {
+        return native;
+    }
+
+    // Backup semantics allow opening directories, and links are described rather than followed
+    let Ok(file) = std::fs::OpenOptions::new()
+        .access_mode(0)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS.0 | FILE_FLAG_OPEN_REPARSE_POINT.0)
+        .open(path)
+    else {
+        return native;
+    };
+    let handle = HANDLE(file.as_raw_handle());
+
+    let mut info = BY_HANDLE_FILE_INFORMATION::default();
+    // SAFETY: the handle stays open for the duration of the call and `info` is a valid output buffer
+    if unsafe { GetFileInformationByHandle(handle, &mut info) }.is_ok() {
+        native.file_id = Some(((info.nFileIndexHigh as u64) << 32) | info.nFileIndexLow as u64);
+        native.volume_id = Some(info.dwVolumeSerialNumber as u64);
+        native.links = Some(info.nNumberOfLinks as u64);
+    }
+
+    let mut basic = FILE_BASIC_INFO::default();
+    // SAFETY: the handle stays open for the duration of the call and `basic` is a buffer of the given size
+    let queried = unsafe {
+        GetFileInformationByHandleEx(
+            handle,
+            FileBasicInfo,
+            &mut basic as *mut FILE_BASIC_INFO as *mut _,
+            std::mem::size_of::<FILE_BASIC_INFO>() as u32,
+        )
+    };
+    if queried.is_ok() {
+        native.changed = super::filetime_to_utc(basic.ChangeTime as u64);
+    }
+    if !metadata.is_dir() {
+        native.allocated_size = native.volume_id.and_then(|volume_id| allocated_size(path, volume_id));
+    }
+    native
+}
+
+/// Returns the space allocated to a file: its compressed or sparse size, rounded up to whole clusters.
+#[cfg(windows)]
+fn allocated_size(path: &Path, volume_id: u64) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::core::PCWSTR;
+    use windows::Win32::Foundation::{GetLastError, NO_ERROR};
+    use windows::Win32::Storage::FileSystem::GetCompressedFileSizeW;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut high = 0u32;
+    // SAFETY: `wide` is a NUL-terminated path and `high` a valid output
+    let low = unsafe { GetCompressedFileSizeW(PCWSTR(wide.as_ptr()), Some(&mut high)) };
+    // An all-ones low part is also a valid size, told apart by the last error
+    // SAFETY: reads the last error of the calling thread
+    if low == u32::MAX && unsafe { GetLastError() } != NO_ERROR {
+        return None;
+    }
+    let size = ((high as u64) << 32) | low as u64;
+    let cluster = cluster_size(&wide, volume_id)?;
+    Some(size.div_ceil(cluster) * cluster)
+}
+
+/// Returns the cluster size of the volume holding a file, given as a NUL-terminated wide path.
+///
+/// Sizes are cached by volume serial number, as every file of a scan asks again.
+#[cfg(windows)]
+fn cluster_size(path: &[u16], volume_id: u64) -> Option<u64> {
+    use std::collections::HashMap;
+    use std::sync::{Mutex, OnceLock};
+    use windows::core::PCWSTR;
+    use windows::Win32::Storage::FileSystem::{GetDiskFreeSpaceW, GetVolumePathNameW};
+
+    static CLUSTER_SIZES: OnceLock<Mutex<HashMap<u64, u64>>> = OnceLock::new();
+    let sizes = CLUSTER_SIZES.get_or_init(Default::default);
+    if let Some(size) = sizes.lock().unwrap_or_else(|e| e.into_inner()).get(&volume_id) {
+        return Some(*size);
+    }
+
+    // The mount point is never longer than the path itself
+    let mut root = vec![0u16; path.len() + 1];
+    // SAFETY: `path` is NUL-terminated and `root` is a writable buffer of the given length
+    unsafe { GetVolumePathNameW(PCWSTR(path.as_ptr()), &mut root) }.ok()?;
+    let (mut sectors_per_cluster, mut bytes_per_sector) = (0u32, 0u32);
+    // SAFETY: `root` holds a NUL-terminated path and the outputs are valid
+    unsafe {
+        GetDiskFreeSpaceW(
+            PCWSTR(root.as_ptr()),
+            Some(&mut sectors_per_cluster),
+            Some(&mut bytes_per_sector),
+            None,
+            None,
+        )
+    }
+    .ok()?;
+    let size = sectors_per_cluster as u64 * bytes_per_sector as u64;
+    if size == 0 {
+        return None;
+    }
+    sizes.lock().unwrap_or_else(|e| e.into_inner()).insert(volume_id, size);
+    Some(size)
+}
+
+/// Only the read-only and directory attributes are available on this platform.
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn native_metadata(_path: &Path, _name: &str, metadata: &Metadata, _detailed: bool) -> NativeMetadata {
+    let mut attributes = FileAttributes::default();
+    if metadata.permissions().readonly() {
+        attributes = attributes | FileAttributes::READONLY;
+    }
+    if metadata.is_dir() {
+        attributes = attributes | FileAttributes::DIRECTORY;
+    }
+    NativeMetadata {
+        attributes,
+        ..NativeMetadata::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attributes_display() {
+        let attributes = FileAttributes::from_bits(0x0002 | 0x0004 | 0x0800);
+        assert!(attributes.is_hidden() && attributes.is_system() && attributes.is_compressed());
+        assert!(!attributes.is_readonly());
+        assert_eq!(attributes.to_string(), "Hidden, System, Compressed");
+        assert_eq!(FileAttributes::default().to_string(), "None");
+        assert_eq!((FileAttributes::READONLY | FileAttributes::ARCHIVE).bits(), 0x21);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_native_metadata() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".profile");
+        std::fs::write(&path, b"export A=1").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o444)).unwrap();
+        std::fs::hard_link(&path, dir.path().join("link")).unwrap();
+
+        let metadata = std::fs::symlink_metadata(&path).unwrap();
+        let native = native_metadata(&path, ".profile", &metadata, true);
+        assert!(native.attributes.is_hidden());
+        assert!(native.attributes.is_readonly());
+        assert!(!native.attributes.is_directory());
+        assert_eq!(native.links, Some(2));
+        assert_eq!(native.mode.map(|mode| mode & 0o777), Some(0o444));
+        assert!(native.file_id.is_some() && native.changed.is_some());
+        assert_eq!(native.allocated_size, Some(std::os::unix::fs::MetadataExt::blocks(&metadata) * 512));
+        assert_eq!(native_metadata(&path, ".profile", &metadata, false).file_id, native.file_id);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_native_metadata_details() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        std::fs::write(&path, vec![1u8; 10_000]).unwrap();
+        let metadata = std::fs::symlink_metadata(&path).unwrap();
+
+        let detailed = native_metadata(&path, "data.bin", &metadata, true);
+        assert!(detailed.file_id.is_some() && detailed.links == Some(1) && detailed.allocated_size.is_some());
+        let listed = native_metadata(&path, "data.bin", &metadata, false);
+        assert_eq!(listed.attributes, detailed.attributes);
+        assert!(listed.file_id.is_none() && listed.links.is_none() && listed.allocated_size.is_none());
+    }
+}
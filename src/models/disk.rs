@@ -22,6 +22,10 @@ pub enum DiskKind {
     SSD,
     /// Storage Class Memory - advanced persistent memory technology
     SCM,
+    /// Optical media (CD, DVD, Blu-ray) or an image of one
+    Optical,
+    /// Virtual hard disk or raw image of a partitioned disk
+    Virtual,
     /// Unknown disk type with a media type identifier value
     Unknown(isize),
 }
@@ -35,6 +39,134 @@ impl Default for DiskKind {
     }
 }
 
+impl fmt::Display for DiskKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiskKind::HDD => write!(f, "HDD"),
+            DiskKind::SSD => write!(f, "SSD"),
+            DiskKind::SCM => write!(f, "SCM"),
+            DiskKind::Optical => write!(f, "Optical"),
+            DiskKind::Virtual => write!(f, "Virtual"),
+            DiskKind::Unknown(val) => write!(f, "Unknown Disk Type ({})", val),
+        }
+    }
+}
+
+/// Describes what occupies an extent of a disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub enum ExtentKind {
+    /// Space of a partition, with the id of the matching `Partition` of the
+    /// disk, or `None` for partitions that are not listed (e.g. hidden ones)
+    Partition(Option<usize>),
+    /// Partitioning structure, such as the MBR, a GPT header or a partition entry array
+    Metadata(String),
+    /// Space not covered by any partition or partitioning structure
+    Unallocated,
+}
+
+impl fmt::Display for ExtentKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtentKind::Partition(Some(id)) => write!(f, "Partition {}", id),
+            ExtentKind::Partition(None) => write!(f, "Partition (not listed)"),
+            ExtentKind::Metadata(name) => write!(f, "{}", name),
+            ExtentKind::Unallocated => write!(f, "Unallocated"),
+        }
+    }
+}
+
+/// A contiguous range of bytes of a disk.
+///
+/// The extents of a disk, as returned by `Disk::extents`, are ordered by
+/// offset and cover the whole disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct DiskExtent {
+    /// Byte offset of the extent from the start of the disk
+    offset: u64,
+    /// Length of the extent in bytes
+    length: u64,
+    /// What occupies the extent
+    kind: ExtentKind,
+}
+
+impl DiskExtent {
+    /// Creates a new extent.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use win_disk_info::{DiskExtent, ExtentKind};
+    ///
+    /// let extent = DiskExtent::new(0, 512, ExtentKind::Metadata(String::from("MBR")));
+    /// assert_eq!(extent.end(), 512);
+    /// ```
+    pub fn new(offset: u64, length: u64, kind: ExtentKind) -> Self {
+        DiskExtent { offset, length, kind }
+    }
+
+    /// Returns the byte offset of the extent from the start of the disk.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the length of the extent in bytes.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Returns the offset of the first byte after the extent.
+    pub fn end(&self) -> u64 {
+        self.offset.saturating_add(self.length)
+    }
+
+    /// Returns what occupies the extent.
+    pub fn kind(&self) -> &ExtentKind {
+        &self.kind
+    }
+
+    /// Orders the used extents of a disk of `size` bytes and fills the space
+    /// between them with unallocated extents.
+    ///
+    /// Overlapping extents, found on damaged partition tables, are kept as
+    /// recorded.
+    pub(crate) fn layout(size: u64, mut used: Vec<DiskExtent>) -> Vec<DiskExtent> {
+        used.retain(|extent| extent.length > 0);
+        used.sort_by_key(|extent| (extent.offset, extent.length));
+
+        let mut extents = Vec::with_capacity(used.len() * 2 + 1);
+        let mut position = 0;
+        for extent in used {
+            if extent.offset > position {
+                extents.push(DiskExtent::new(position, extent.offset - position, ExtentKind::Unallocated));
+            }
+            position = position.max(extent.end());
+            extents.push(extent);
+        }
+        if position < size {
+            extents.push(DiskExtent::new(position, size - position, ExtentKind::Unallocated));
+        }
+        extents
+    }
+}
+
+/// Returns a size in bytes scaled to binary units for display.
+fn scale_size(bytes: u64) -> (f64, &'static str) {
+    let bytes = bytes as f64;
+    if bytes >= 1_099_511_627_776.0 {
+        (bytes / 1_099_511_627_776.0, "TiB")
+    } else if bytes >= 1_073_741_824.0 {
+        (bytes / 1_073_741_824.0, "GiB")
+    } else if bytes >= 1_048_576.0 {
+        (bytes / 1_048_576.0, "MiB")
+    } else if bytes >= 1_024.0 {
+        (bytes / 1_024.0, "KiB")
+    } else {
+        (bytes, "bytes")
+    }
+}
+
 /// Represents a physical storage device in the system.
 ///
 /// The `Disk` struct contains comprehensive information about a storage device,
@@ -56,6 +188,8 @@ pub struct Disk {
     removable: bool,
     /// List of partitions on this disk
     partitions: Vec<Partition>,
+    /// Ordered layout of the disk, empty when its partition table is unknown
+    extents: Vec<DiskExtent>,
 }
 
 impl Disk {
@@ -103,9 +237,41 @@ impl Disk {
             size,
             removable,
             partitions,
+            extents: Vec::new(),
         }
     }
 
+    /// Sets the layout of the disk, as read from its partition table.
+    ///
+    /// The extents are ordered by offset and any space they leave uncovered
+    /// is added as `ExtentKind::Unallocated`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use win_disk_info::{Disk, DiskExtent, DiskKind, ExtentKind};
+    ///
+    /// let disk = Disk::new(
+    ///     String::from("usb.img"),
+    ///     String::from("Raw image"),
+    ///     String::new(),
+    ///     DiskKind::Virtual,
+    ///     4_194_304,
+    ///     false,
+    ///     vec![],
+    /// )
+    /// .with_extents(vec![
+    ///     DiskExtent::new(0, 512, ExtentKind::Metadata(String::from("MBR"))),
+    ///     DiskExtent::new(1_048_576, 2_097_152, ExtentKind::Partition(None)),
+    /// ]);
+    /// assert_eq!(disk.extents().len(), 4);
+    /// assert_eq!(disk.unallocated_space(), Some(4_194_304 - 512 - 2_097_152));
+    /// ```
+    pub fn with_extents(mut self, extents: Vec<DiskExtent>) -> Self {
+        self.extents = DiskExtent::layout(self.size as u64, extents);
+        self
+    }
+
     /// Returns the physical device identifier.
     pub fn device_name(&self) -> &str {
         &self.device_name
@@ -140,6 +306,33 @@ impl Disk {
     pub fn partitions(&self) -> &[Partition] {
         &self.partitions
     }
+
+    /// Returns the layout of the disk as an ordered list of extents.
+    ///
+    /// Every byte of the disk belongs to an extent labelled as a partition,
+    /// partitioning metadata (MBR, GPT headers and entry arrays, extended boot
+    /// records) or unallocated space. Partitions that Windows does not list,
+    /// such as recovery or hidden partitions, are included.
+    ///
+    /// The slice is empty when the partition table could not be read.
+    pub fn extents(&self) -> &[DiskExtent] {
+        &self.extents
+    }
+
+    /// Returns the number of bytes not covered by any partition or
+    /// partitioning metadata, or `None` if the layout of the disk is unknown.
+    pub fn unallocated_space(&self) -> Option<u64> {
+        if self.extents.is_empty() {
+            return None;
+        }
+        Some(
+            self.extents
+                .iter()
+                .filter(|extent| extent.kind == ExtentKind::Unallocated)
+                .map(DiskExtent::length)
+                .sum(),
+        )
+    }
 }
 
 impl fmt::Display for Disk {
@@ -150,25 +343,8 @@ impl fmt::Display for Disk {
     /// information about each partition on the disk.
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // Format disk size in appropriate units
-        let (size_value, size_unit) = if self.size >= 1_099_511_627_776 {
-            (self.size as f64 / 1_099_511_627_776.0, "TiB")
-        } else if self.size >= 1_073_741_824 {
-            (self.size as f64 / 1_073_741_824.0, "GiB")
-        } else if self.size >= 1_048_576 {
-            (self.size as f64 / 1_048_576.0, "MiB")
-        } else if self.size >= 1_024 {
-            (self.size as f64 / 1_024.0, "KiB")
-        } else {
-            (self.size as f64, "bytes")
-        };
+        let (size_value, size_unit) = scale_size(self.size as u64);
 
-        // Format disk kind as a string
-        let kind_str = match &self.kind {
-            DiskKind::HDD => "HDD",
-            DiskKind::SSD => "SSD",
-            DiskKind::SCM => "SCM",
-            DiskKind::Unknown(val) => &format!("Unknown Disk Type ({})", val),
-        };
 
         // Write basic disk information
         write!(
@@ -176,7 +352,7 @@ impl fmt::Display for Disk {
             "{}\n  Device: {}\n  Type: {}{}\n  Capacity: {:.2} {}\n  Serial: {}\n  Partitions: {}",
             self.model,
             self.device_name,
-            kind_str,
+            self.kind,
             if self.removable { " (Removable)" } else { "" },
             size_value,
             size_unit,
@@ -184,12 +360,6 @@ impl fmt::Display for Disk {
             self.partitions.len()
         )?;
 
-        // Calculate total allocated space
-        let total_allocated: u64 = self.partitions
-            .iter()
-            .map(|p| p.total_space())
-            .sum();
-
         // If there are partitions, include their details
         if !self.partitions.is_empty() {
             writeln!(f, "\n\nPartition Details:")?;
@@ -216,25 +386,41 @@ impl fmt::Display for Disk {
                 
                 writeln!(f, "{}", indented_lines.join("\n"))?;
             }
-            
-            // Calculate and display unallocated space if any
-            let unallocated = self.size as i64 - total_allocated as i64;
-            if unallocated > 1024 { // Only show if significant
-                let (unalloc_val, unalloc_unit) = if unallocated >= 1_099_511_627_776 {
-                    (unallocated as f64 / 1_099_511_627_776.0, "TiB")
-                } else if unallocated >= 1_073_741_824 {
-                    (unallocated as f64 / 1_073_741_824.0, "GiB")
-                } else if unallocated >= 1_048_576 {
-                    (unallocated as f64 / 1_048_576.0, "MiB")
-                } else if unallocated >= 1_024 {
-                    (unallocated as f64 / 1_024.0, "KiB")
-                } else {
-                    (unallocated as f64, "bytes")
-                };
-                
-                writeln!(f, "\n  Unallocated space: {:.2} {}", unalloc_val, unalloc_unit)?;
+        }
+
+        // Show where the partitions and gaps are when the layout is known
+        if !self.extents.is_empty() {
+            writeln!(f, "\nLayout:")?;
+            for extent in &self.extents {
+                let (length_val, length_unit) = scale_size(extent.length);
+                writeln!(
+                    f,
+                    "  {:>16} - {:>16}  {:>10.2} {:<5}  {}",
+                    extent.offset,
+                    extent.end(),
+                    length_val,
+                    length_unit,
+                    extent.kind
+                )?;
             }
         }
+
+        // Without a layout, estimate the unallocated space from the partition capacities
+        let unallocated = match self.unallocated_space() {
+            Some(unallocated) => unallocated as i64,
+            None if !self.partitions.is_empty() => {
+                let total_allocated: u64 = self.partitions
+                    .iter()
+                    .map(|p| p.total_space())
+                    .sum();
+                self.size as i64 - total_allocated as i64
+            }
+            None => 0,
+        };
+        if unallocated > 1024 { // Only show if significant
+            let (unalloc_val, unalloc_unit) = scale_size(unallocated as u64);
+            writeln!(f, "\n  Unallocated space: {:.2} {}", unalloc_val, unalloc_unit)?;
+        }
         
         Ok(())
     }
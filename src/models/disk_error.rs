@@ -6,6 +6,7 @@
 //! Windows Management Instrumentation (WMI) errors.
 
 use std::fmt;
+#[cfg(windows)]
 use wmi::WMIError;
 
 /// Represents a disk-related operation error.
@@ -48,6 +49,7 @@ impl fmt::Display for DiskError {
 ///
 /// Allows capturing and converting WMI errors to our custom error type,
 /// facilitating consistent error propagation throughout the application.
+#[cfg(windows)]
 impl From<WMIError> for DiskError {
     fn from(value: WMIError) -> Self {
         DiskError {
@@ -4,18 +4,26 @@
 //! and directories, along with supporting error types and utility methods for
 //! working with file system entries.
 
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 use std::{fmt, io};
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Utc};
 use walkdir::DirEntry;
 
+use crate::images::{RunReader, SharedImage};
+use crate::file_hashing::hash_file;
+use crate::{native_metadata, FileAttributes, NativeMetadata, FileHashes, FileTimes, HashAlgorithm, KnownStatus};
+
 #[cfg(feature = "serialize")]
 use serde::Serialize;
 
 /// Represents a file system entry with its metadata.
 /// 
 /// This structure holds information about a file or directory
-/// including its path, name, size, modification time, and extension.
+/// including its path, name, size, timestamps, extension, attributes and
+/// identity. Everything is read once when the entry is created, so that
+/// formatting and filtering never access the file system again.
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 pub struct FileEntry {
@@ -28,8 +36,153 @@ pub struct FileEntry {
     
     /// File size in bytes
     size: u64,
-    /// Last modification timestamp
-    modified: DateTime<Local>,
+    /// Modified, accessed, changed and born times, in UTC
+    times: FileTimes,
+    /// Attributes such as hidden, system or compressed
+    attributes: FileAttributes,
+    /// Inode or NTFS file reference number, if known
+    file_id: Option<u64>,
+    /// Device number or volume serial number, if known
+    volume_id: Option<u64>,
+    /// Number of hard links to the file, if known
+    links: Option<u64>,
+    /// Space allocated on disk in bytes, if known
+    allocated_size: Option<u64>,
+    /// Unix mode, including the file type and permission bits
+    mode: Option<u32>,
+    /// Unix owner user ID
+    uid: Option<u32>,
+    /// Unix owner group ID
+    gid: Option<u32>,
+    /// Where the content of this entry is stored
+    source: FileSource,
+    /// Set for entries recovered from deleted file system metadata
+    deleted: Option<Recoverability>,
+    /// Digests computed for the content, empty until hashed
+    hashes: FileHashes,
+    /// Label given by matching the digests against hash sets
+    known: KnownStatus,
+    /// Opened image shared with the other entries found in it
+    #[cfg_attr(feature = "serialize", serde(skip))]
+    shared_image: Option<SharedImage>,
+}
+
+/// Identifies a file independently of the path used to reach it.
+///
+/// Hard links to the same file share the same identity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileIdentity {
+    /// Device or volume serial number holding the file
+    volume: u64,
+    /// Inode or file index inside the volume
+    index: u64,
+}
+
+impl FileIdentity {
+    /// Creates the identity of the file with number `index` on volume `volume`.
+    pub(crate) fn new(volume: u64, index: u64) -> Self {
+        FileIdentity { volume, index }
+    }
+
+    /// Returns the device number or volume serial number holding the file
+    pub fn volume(&self) -> u64 {
+        self.volume
+    }
+
+    /// Returns the inode number or NTFS file reference number inside the volume
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+}
+
+/// Describes where the content of a `FileEntry` can be read from.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub enum FileSource {
+    /// The entry lives on a mounted file system and is read through its path
+    FileSystem,
+    /// The entry lives inside a disk or optical image
+    Image {
+        /// Path of the image file holding the data
+        image: PathBuf,
+        /// Ordered list of runs that make up the file content
+        runs: Vec<DataRun>,
+    },
+}
+
+/// A contiguous piece of file content inside an image.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub enum DataRun {
+    /// Bytes stored at an absolute offset of the image
+    Extent {
+        /// Byte offset from the start of the image
+        offset: u64,
+        /// Number of bytes in this run
+        length: u64,
+    },
+    /// Unallocated range that reads back as zeros
+    Sparse {
+        /// Number of bytes in this run
+        length: u64,
+    },
+    /// Data stored inline in the file system metadata
+    Resident(Vec<u8>),
+}
+
+/// Estimates how much of a deleted file can still be recovered.
+///
+/// The estimate is based on whether the clusters that held the file have
+/// since been allocated to other files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub enum Recoverability {
+    /// None of the clusters of the file are in use
+    Recoverable,
+    /// Some of the clusters of the file are allocated to other files
+    PartiallyOverwritten,
+    /// Every cluster of the file is allocated to other files
+    Overwritten,
+    /// The location of the content could not be determined
+    Unknown,
+}
+
+impl Recoverability {
+    /// Computes the estimate from the number of clusters of a file and how
+    /// many of them are allocated again.
+    pub(crate) fn from_clusters(total: u64, reallocated: u64) -> Self {
+        match reallocated {
+            0 => Recoverability::Recoverable,
+            _ if reallocated >= total => Recoverability::Overwritten,
+            _ => Recoverability::PartiallyOverwritten,
+        }
+    }
+}
+
+impl fmt::Display for Recoverability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Recoverability::Recoverable => write!(f, "Recoverable"),
+            Recoverability::PartiallyOverwritten => write!(f, "Partially overwritten"),
+            Recoverability::Overwritten => write!(f, "Overwritten"),
+            Recoverability::Unknown => write!(f, "Unknown"),
+        }
+    }
+}
+
+impl DataRun {
+    /// Returns the number of bytes this run contributes to the file content.
+    pub fn len(&self) -> u64 {
+        match self {
+            DataRun::Extent { length, .. } | DataRun::Sparse { length } => *length,
+            DataRun::Resident(data) => data.len() as u64,
+        }
+    }
+
+    /// Returns true if this run holds no data.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 /// Error type for FileEntry creation failures
@@ -41,6 +194,17 @@ pub enum FileEntryError {
     TimeError(io::Error),
 }
 
+impl fmt::Display for FileEntryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MetadataError(e) => write!(f, "Metadata error: {}", e),
+            Self::TimeError(e) => write!(f, "Timestamp error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FileEntryError {}
+
 impl FileEntry {
     /// Creates a new FileEntry from a walkdir::DirEntry.
     ///
@@ -68,6 +232,14 @@ impl FileEntry {
     /// }
     /// ```
     pub fn from_dir_entry(entry: &DirEntry) -> Result<Self, FileEntryError> {
+        Self::from_walked(entry, true)
+    }
+
+    /// Creates a FileEntry from a walkdir::DirEntry, reading the native details only if `detailed`.
+    ///
+    /// Without the details, entries on Windows carry what the directory
+    /// listing gives: no file ID, link count, change time or allocated size.
+    pub(crate) fn from_walked(entry: &DirEntry, detailed: bool) -> Result<Self, FileEntryError> {
         let path = entry.path().to_path_buf();
         let name = entry.file_name().to_string_lossy().to_string();
         
@@ -77,21 +249,103 @@ impl FileEntry {
         
         let size = metadata.len();
         
-        // Convert SystemTime to DateTime<Local> safely
-        let modified = metadata.modified()
-            .map_err(FileEntryError::TimeError)
-            .map(DateTime::<Local>::from)?;
+        // The modification time is required, the other times are kept when available
+        metadata.modified().map_err(FileEntryError::TimeError)?;
+        let native = native_metadata(&path, &name, &metadata, detailed);
+        let times = FileTimes::from_metadata(&metadata, native.changed);
         
+        Ok(Self::from_native(path, name, size, times, native))
+    }
+
+    /// Creates a FileEntry for a file on a mounted file system from metadata read earlier.
+    ///
+    /// Used to restore the entries recorded in an index file.
+    pub(crate) fn from_native(path: PathBuf, name: String, size: u64, times: FileTimes, native: NativeMetadata) -> Self {
         let extension = path.extension()
             .map(|ext| ext.to_string_lossy().to_string());
-        
-        Ok(FileEntry {
+
+        FileEntry {
             path,
             name,
             size,
-            modified,
+            times,
+            extension,
+            attributes: native.attributes,
+            file_id: native.file_id,
+            volume_id: native.volume_id,
+            links: native.links,
+            allocated_size: native.allocated_size,
+            mode: native.mode,
+            uid: native.uid,
+            gid: native.gid,
+            source: FileSource::FileSystem,
+            deleted: None,
+            hashes: FileHashes::default(),
+            known: KnownStatus::Unknown,
+            shared_image: None,
+        }
+    }
+
+    /// Creates a FileEntry for a file stored inside an image.
+    ///
+    /// `path` is the virtual path reported for the entry, usually the image
+    /// path joined with the location of the file inside the image.
+    /// Entries listed from the same image share its `SharedImage`, so that
+    /// reading their content does not open the image again for each of them.
+    pub(crate) fn from_image(
+        path: PathBuf,
+        size: u64,
+        times: FileTimes,
+        image: &SharedImage,
+        runs: Vec<DataRun>,
+    ) -> Self {
+        let name = path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let extension = path.extension()
+            .map(|ext| ext.to_string_lossy().to_string());
+
+        FileEntry {
+            path,
+            name,
             extension,
-        })
+            size,
+            times,
+            attributes: FileAttributes::default(),
+            file_id: None,
+            volume_id: None,
+            links: None,
+            allocated_size: None,
+            mode: None,
+            uid: None,
+            gid: None,
+            source: FileSource::Image {
+                image: image.path().to_path_buf(),
+                runs,
+            },
+            deleted: None,
+            hashes: FileHashes::default(),
+            known: KnownStatus::Unknown,
+            shared_image: Some(image.clone()),
+        }
+    }
+
+    /// Marks an entry recovered from deleted file system metadata.
+    pub(crate) fn with_deleted(mut self, recoverability: Recoverability) -> Self {
+        self.deleted = Some(recoverability);
+        self
+    }
+
+    /// Sets the attributes recorded for an entry found inside an image.
+    pub(crate) fn with_attributes(mut self, attributes: FileAttributes) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    /// Sets the file ID recorded for an entry found inside an image.
+    pub(crate) fn with_file_id(mut self, file_id: Option<u64>) -> Self {
+        self.file_id = file_id;
+        self
     }
 
     /// Returns the file name component of this path
@@ -119,59 +373,197 @@ impl FileEntry {
         self.size
     }
 
-    /// Returns the last modification time
-    pub fn modified(&self) -> DateTime<Local> {
-        self.modified
+    /// Returns the last modification time, if known
+    pub fn modified(&self) -> Option<DateTime<Utc>> {
+        self.times.modified()
+    }
+
+    /// Returns the modified, accessed, changed and born times
+    pub fn times(&self) -> &FileTimes {
+        &self.times
+    }
+
+    /// Returns the attributes of the entry
+    pub fn attributes(&self) -> FileAttributes {
+        self.attributes
+    }
+
+    /// Returns true if this entry is a directory
+    pub fn is_dir(&self) -> bool {
+        self.attributes.is_directory()
+    }
+
+    /// Returns the inode number or NTFS file reference number, if known
+    ///
+    /// Together with `volume_id`, it identifies the file: hard links to the
+    /// same file share both values.
+    pub fn file_id(&self) -> Option<u64> {
+        self.file_id
+    }
+
+    /// Returns the device number or volume serial number, if known
+    pub fn volume_id(&self) -> Option<u64> {
+        self.volume_id
+    }
+
+    /// Returns the identity shared by all hard links to the file, if both its volume and file IDs are known
+    pub fn identity(&self) -> Option<FileIdentity> {
+        Some(FileIdentity::new(self.volume_id?, self.file_id?))
+    }
+
+    /// Returns the number of hard links to the file, if known
+    pub fn hard_links(&self) -> Option<u64> {
+        self.links
+    }
+
+    /// Returns the space the file takes on disk, in bytes, if known
+    ///
+    /// On Windows this is the compressed or sparse size of the file rounded up
+    /// to whole clusters; on Unix, the blocks allocated to it. Unlike `size`,
+    /// it is what deleting the file gives back to the volume, once all its
+    /// hard links are gone.
+    pub fn allocated_size(&self) -> Option<u64> {
+        self.allocated_size
+    }
+
+    /// Returns the Unix mode, with the file type and permission bits, on Unix
+    pub fn mode(&self) -> Option<u32> {
+        self.mode
+    }
+
+    /// Returns the user ID of the owner, on Unix
+    pub fn uid(&self) -> Option<u32> {
+        self.uid
+    }
+
+    /// Returns the group ID of the owner, on Unix
+    pub fn gid(&self) -> Option<u32> {
+        self.gid
+    }
+
+    /// Returns where the content of this entry is stored
+    pub fn source(&self) -> &FileSource {
+        &self.source
+    }
+
+    /// Returns true if this entry was recovered from deleted file system metadata
+    pub fn is_deleted(&self) -> bool {
+        self.deleted.is_some()
+    }
+
+    /// Returns the digests computed for this entry, empty until `hash` is called
+    pub fn hashes(&self) -> &FileHashes {
+        &self.hashes
+    }
+
+    /// Computes digests of the content and stores them on the entry
+    ///
+    /// Every algorithm is computed in a single read of the content. Digests
+    /// computed earlier with other algorithms are kept.
+    ///
+    /// # Arguments
+    /// * `algorithms` - The digests to compute
+    ///
+    /// # Returns
+    /// * `io::Result<&FileHashes>` - Every digest stored on the entry, or the read error
+    pub fn hash(&mut self, algorithms: &[HashAlgorithm]) -> io::Result<&FileHashes> {
+        let hashes = hash_file(self, algorithms)?;
+        self.hashes.merge(hashes);
+        Ok(&self.hashes)
+    }
+
+    /// Adds digests computed elsewhere to the stored ones.
+    pub(crate) fn merge_hashes(&mut self, hashes: FileHashes) {
+        self.hashes.merge(hashes);
+    }
+
+    /// Returns whether this entry was found in the hash sets it was matched against
+    pub fn known_status(&self) -> KnownStatus {
+        self.known
+    }
+
+    /// Records the result of matching this entry against hash sets.
+    pub(crate) fn set_known_status(&mut self, known: KnownStatus) {
+        self.known = known;
+    }
+
+    /// Returns how much of a deleted entry can still be recovered, or `None`
+    /// for entries that are not deleted
+    pub fn recoverability(&self) -> Option<Recoverability> {
+        self.deleted
+    }
+
+    /// Returns the byte offset of the start of this entry inside its image
+    ///
+    /// This is the offset of the first data run of the entry, or `None` for
+    /// entries on a mounted file system and entries whose first run is not
+    /// stored at a fixed place of the image.
+    pub fn image_offset(&self) -> Option<u64> {
+        match &self.source {
+            FileSource::Image { runs, .. } => match runs.first() {
+                Some(DataRun::Extent { offset, .. }) => Some(*offset),
+                _ => None,
+            },
+            FileSource::FileSystem => None,
+        }
+    }
+
+    /// Opens the content of this entry for reading
+    ///
+    /// Entries on a mounted file system are opened through their path, while
+    /// entries found inside an image are read from the image runs.
+    ///
+    /// # Returns
+    ///
+    /// * `io::Result<Box<dyn Read + Send>>` - A reader over the file content
+    pub fn open(&self) -> io::Result<Box<dyn Read + Send>> {
+        self.open_at(0)
+    }
+
+    /// Opens the content of this entry for reading from `offset` bytes
+    pub(crate) fn open_at(&self, offset: u64) -> io::Result<Box<dyn Read + Send>> {
+        match &self.source {
+            FileSource::FileSystem => {
+                let mut file = File::open(&self.path)?;
+                if offset > 0 {
+                    file.seek(SeekFrom::Start(offset))?;
+                }
+                Ok(Box::new(file))
+            }
+            FileSource::Image { image, runs } => {
+                let device = match &self.shared_image {
+                    Some(shared) => shared.reader(),
+                    None => SharedImage::new(image.clone()).reader(),
+                };
+                let mut reader = RunReader::new(device, runs.clone());
+                reader.set_position(offset);
+                Ok(Box::new(reader.take(self.size.saturating_sub(offset))))
+            }
+        }
     }
     
     /// Determines if this is a hidden file
     ///
-    /// On Windows, checks the hidden file attribute.
-    /// On Unix-like systems, checks if the filename starts with a dot.
+    /// On Windows and for entries found in NTFS and FAT images, this is the
+    /// hidden file attribute. On Unix-like systems, hidden files are those
+    /// whose name starts with a dot. The attribute is read when the entry is
+    /// created.
     ///
     /// # Returns
     ///
     /// * `bool` - true if the file is hidden, false otherwise
     pub fn is_hidden(&self) -> bool {
-        #[cfg(windows)]
-        {
-            use std::os::windows::fs::MetadataExt;
-            if let Ok(metadata) = std::fs::metadata(&self.path) {
-                // Check FILE_ATTRIBUTE_HIDDEN (0x2) on Windows
-                return (metadata.file_attributes() & 0x2) != 0;
-            }
-        }
-        
-        #[cfg(not(windows))]
-        {
-            // On Unix-like systems, hidden files start with '.'
-            return self.name.starts_with('.');
-        }
-        
-        false
+        self.attributes.is_hidden()
     }
 }
 
-/// For backward compatibility with code that uses the From trait
-///
-/// This implementation provides a way to create a FileEntry from a DirEntry
-/// while handling errors internally to avoid propagating them.
-impl From<DirEntry> for FileEntry {
-    fn from(entry: DirEntry) -> Self {
-        Self::from_dir_entry(&entry).unwrap_or_else(|err| {
-            // Log the error and return a fallback value
-            eprintln!("Error creating FileEntry: {:?}", err);
-            let path = entry.path().to_path_buf();
-            let name = entry.file_name().to_string_lossy().to_string();
-            
-            FileEntry {
-                path,
-                name,
-                size: 0,
-                modified: Local::now(),
-                extension: None,
-            }
-        })
+/// Creates a FileEntry from a DirEntry, failing like `FileEntry::from_dir_entry`
+/// when the metadata of the entry cannot be read.
+impl TryFrom<DirEntry> for FileEntry {
+    type Error = FileEntryError;
+
+    fn try_from(entry: DirEntry) -> Result<Self, Self::Error> {
+        Self::from_dir_entry(&entry)
     }
 }
 
@@ -182,11 +574,12 @@ impl fmt::Display for FileEntry {
     /// - Name and path
     /// - Type (file/directory) and extension
     /// - Size in appropriate units
-    /// - Last modification timestamp
+    /// - Known timestamps, in UTC
+    /// - Attributes, when any is set
     /// - Hidden status
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // Determine if it's a file or directory
-        let entry_type = if self.path.is_dir() { "Directory" } else { "File" };
+        let entry_type = if self.is_dir() { "Directory" } else { "File" };
         
         // Format file size in appropriate units
         let (size_value, size_unit) = if self.size >= 1_000_000_000 {
@@ -199,23 +592,38 @@ impl fmt::Display for FileEntry {
             (self.size as f64, "bytes")
         };
         
-        // Format modification time
-        let mod_time = self.modified.format("%Y-%m-%d %H:%M:%S");
-        
         // Format hidden status
         let hidden_status = if self.is_hidden() { " (Hidden)" } else { "" };
         
         // Write formatted output
         write!(
             f,
-            "{}{}\n  Type: {}\n  Path: {}\n  Size: {:.2} {}\n  Modified: {}",
+            "{}{}\n  Type: {}\n  Path: {}\n  Size: {:.2} {}",
             self.name,
             hidden_status,
             entry_type,
             self.path.display(),
             size_value,
-            size_unit,
-            mod_time
-        )
+            size_unit
+        )?;
+
+        // Format timestamps
+        for (kind, time) in self.times.iter() {
+            write!(f, "\n  {}: {}", kind, time.format("%Y-%m-%d %H:%M:%S UTC"))?;
+        }
+
+        if !self.attributes.is_empty() {
+            write!(f, "\n  Attributes: {}", self.attributes)?;
+        }
+        if let Some(recoverability) = self.deleted {
+            write!(f, "\n  Deleted: {}", recoverability)?;
+        }
+        if self.known != KnownStatus::Unknown {
+            write!(f, "\n  Hash set: {}", self.known)?;
+        }
+        for (algorithm, digest) in self.hashes.iter() {
+            write!(f, "\n  {}: {}", algorithm, digest)?;
+        }
+        Ok(())
     }
 }
\ No newline at end of file
@@ -0,0 +1,166 @@
+//! This module provides structures for representing file digests.
+//!
+//! It contains the `HashAlgorithm` enum listing the supported algorithms, the
+//! `FileHashes` struct holding the digests computed for a file and the
+//! `KnownStatus` label given to files matched against hash sets.
+
+use std::fmt;
+
+#[cfg(feature = "serialize")]
+use serde::Serialize;
+
+/// Cryptographic hash algorithms supported for file hashing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub enum HashAlgorithm {
+    /// MD5, 128-bit digest
+    Md5,
+    /// SHA-1, 160-bit digest
+    Sha1,
+    /// SHA-256, 256-bit digest
+    Sha256,
+    /// SHA-512, 512-bit digest
+    Sha512,
+    /// BLAKE3, 256-bit digest
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// Every supported algorithm
+    pub const ALL: [HashAlgorithm; 5] = [
+        HashAlgorithm::Md5,
+        HashAlgorithm::Sha1,
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Sha512,
+        HashAlgorithm::Blake3,
+    ];
+
+    /// The digests usually recorded side by side for evidence handling
+    pub const EVIDENCE: [HashAlgorithm; 3] = [HashAlgorithm::Md5, HashAlgorithm::Sha1, HashAlgorithm::Sha256];
+
+    /// Returns the length in bytes of the digests computed with this algorithm
+    pub fn digest_len(&self) -> usize {
+        match self {
+            HashAlgorithm::Md5 => 16,
+            HashAlgorithm::Sha1 => 20,
+            HashAlgorithm::Sha256 | HashAlgorithm::Blake3 => 32,
+            HashAlgorithm::Sha512 => 64,
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashAlgorithm::Md5 => write!(f, "MD5"),
+            HashAlgorithm::Sha1 => write!(f, "SHA-1"),
+            HashAlgorithm::Sha256 => write!(f, "SHA-256"),
+            HashAlgorithm::Sha512 => write!(f, "SHA-512"),
+            HashAlgorithm::Blake3 => write!(f, "BLAKE3"),
+        }
+    }
+}
+
+/// Digests computed for a file, as lowercase hexadecimal strings.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct FileHashes {
+    /// Digests ordered by algorithm
+    digests: Vec<(HashAlgorithm, String)>,
+}
+
+impl FileHashes {
+    /// Records the digest computed with `algorithm`, replacing any previous one.
+    pub(crate) fn insert(&mut self, algorithm: HashAlgorithm, digest: String) {
+        match self.digests.binary_search_by_key(&algorithm, |(existing, _)| *existing) {
+            Ok(index) => self.digests[index].1 = digest,
+            Err(index) => self.digests.insert(index, (algorithm, digest)),
+        }
+    }
+
+    /// Adds the digests of `other`, replacing those computed with the same algorithm.
+    pub(crate) fn merge(&mut self, other: FileHashes) {
+        for (algorithm, digest) in other.digests {
+            self.insert(algorithm, digest);
+        }
+    }
+
+    /// Returns the digest computed with `algorithm`, if any.
+    pub fn get(&self, algorithm: HashAlgorithm) -> Option<&str> {
+        self.digests
+            .iter()
+            .find(|(existing, _)| *existing == algorithm)
+            .map(|(_, digest)| digest.as_str())
+    }
+
+    /// Returns the MD5 digest, if computed.
+    pub fn md5(&self) -> Option<&str> {
+        self.get(HashAlgorithm::Md5)
+    }
+
+    /// Returns the SHA-1 digest, if computed.
+    pub fn sha1(&self) -> Option<&str> {
+        self.get(HashAlgorithm::Sha1)
+    }
+
+    /// Returns the SHA-256 digest, if computed.
+    pub fn sha256(&self) -> Option<&str> {
+        self.get(HashAlgorithm::Sha256)
+    }
+
+    /// Returns the SHA-512 digest, if computed.
+    pub fn sha512(&self) -> Option<&str> {
+        self.get(HashAlgorithm::Sha512)
+    }
+
+    /// Returns the BLAKE3 digest, if computed.
+    pub fn blake3(&self) -> Option<&str> {
+        self.get(HashAlgorithm::Blake3)
+    }
+
+    /// Returns an iterator over the computed digests, ordered by algorithm.
+    pub fn iter(&self) -> impl Iterator<Item = (HashAlgorithm, &str)> {
+        self.digests.iter().map(|(algorithm, digest)| (*algorithm, digest.as_str()))
+    }
+
+    /// Returns true if no digest has been computed.
+    pub fn is_empty(&self) -> bool {
+        self.digests.is_empty()
+    }
+}
+
+impl fmt::Display for FileHashes {
+    /// Formats the digests one per line, as `ALGORITHM: digest`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, (algorithm, digest)) in self.digests.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}: {}", algorithm, digest)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether a file appears in the hash sets it was matched against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub enum KnownStatus {
+    /// Not found in any hash set, or not matched yet
+    #[default]
+    Unknown,
+    /// Found in a set of known-good files, such as operating system and application files
+    KnownGood,
+    /// Found in a set of known-bad files, such as malware or contraband
+    KnownBad,
+}
+
+impl fmt::Display for KnownStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KnownStatus::Unknown => write!(f, "Unknown"),
+            KnownStatus::KnownGood => write!(f, "Known good"),
+            KnownStatus::KnownBad => write!(f, "Known bad"),
+        }
+    }
+}
@@ -1,9 +1,17 @@
+mod attributes;
 mod disk;
 mod disk_error;
 mod file;
+mod hashes;
 mod partition;
+mod timestamps;
 
-pub use disk::{Disk, DiskKind};
+pub use attributes::FileAttributes;
+pub(crate) use attributes::{native_metadata, NativeMetadata};
+pub use disk::{Disk, DiskExtent, DiskKind, ExtentKind};
 pub use disk_error::DiskError;
-pub use file::FileEntry;
+pub use file::{DataRun, FileEntry, FileEntryError, FileIdentity, FileSource, Recoverability};
+pub use hashes::{FileHashes, HashAlgorithm, KnownStatus};
 pub use partition::{FileSystem, Partition};
+pub use timestamps::{FileTimes, TimestampKind};
+pub(crate) use timestamps::filetime_to_utc;
@@ -29,6 +29,10 @@ pub enum FileSystem {
     XFS(PathBuf),
     /// ZFS file system with its mount point
     ZFS(PathBuf),
+    /// ISO 9660 optical file system (including Joliet and Rock Ridge) with its mount point
+    ISO9660(PathBuf),
+    /// UDF optical file system with its mount point
+    UDF(PathBuf),
     /// Recognized but not fully implemented file system with type name and mount point
     NotImplemented(String, PathBuf),
     /// Unknown or unrecognized file system
@@ -52,6 +56,8 @@ pub struct Partition {
     total_space: u64,
     /// Available free space in bytes
     available_space: u64,
+    /// Byte offset of the partition from the start of the disk, when known
+    offset: Option<u64>,
 }
 
 impl Partition {
@@ -92,9 +98,33 @@ impl Partition {
             file_system,
             total_space,
             available_space,
+            offset: None,
         }
     }
 
+    /// Sets the byte offset of the partition from the start of the disk.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use win_disk_info::{Partition, FileSystem};
+    /// use std::path::PathBuf;
+    ///
+    /// let partition = Partition::new(
+    ///     1,
+    ///     String::from("Basic data partition"),
+    ///     FileSystem::NTFS(PathBuf::from("disk.vhdx")),
+    ///     64_000_000_000,
+    ///     0,
+    /// )
+    /// .with_offset(1_048_576);
+    /// assert_eq!(partition.offset(), Some(1_048_576));
+    /// ```
+    pub fn with_offset(mut self, offset: u64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
     /// Returns the unique identifier of this partition.
     pub fn id(&self) -> usize {
         self.id
@@ -119,6 +149,12 @@ impl Partition {
     pub fn available_space(&self) -> u64 {
         self.available_space
     }
+
+    /// Returns the byte offset of this partition from the start of the disk,
+    /// or `None` if it is not known.
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
 }
 
 impl fmt::Display for FileSystem {
@@ -147,6 +183,8 @@ impl fmt::Display for FileSystem {
             FileSystem::EXFAT(path) => write!(f, "exFAT [{}]", path.display()),
             FileSystem::XFS(path) => write!(f, "XFS [{}]", path.display()),
             FileSystem::ZFS(path) => write!(f, "ZFS [{}]", path.display()),
+            FileSystem::ISO9660(path) => write!(f, "ISO 9660 [{}]", path.display()),
+            FileSystem::UDF(path) => write!(f, "UDF [{}]", path.display()),
             FileSystem::NotImplemented(name, path) => write!(f, "{} [{}]", name, path.display()),
             FileSystem::Unknown => write!(f, "Unknown"),
         }
@@ -0,0 +1,212 @@
+//! This module provides structures for representing file timestamps.
+//!
+//! It contains the `FileTimes` struct holding the modified, accessed, changed
+//! and born (MACB) times of a file in UTC, and the `TimestampKind` enum naming
+//! each of them.
+
+use std::fmt;
+use std::fs::Metadata;
+
+use chrono::{DateTime, Utc};
+
+#[cfg(feature = "serialize")]
+use serde::Serialize;
+
+/// Seconds between 1601-01-01, the FILETIME epoch, and the Unix epoch
+const FILETIME_UNIX_OFFSET: i64 = 11_644_473_600;
+
+/// The four timestamps kept by file systems, in MACB order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub enum TimestampKind {
+    /// Last change of the content
+    Modified,
+    /// Last access to the content
+    Accessed,
+    /// Last change of the metadata (ctime on Unix, MFT change time on NTFS)
+    Changed,
+    /// Creation of the file
+    Born,
+}
+
+impl TimestampKind {
+    /// Every timestamp kind, in MACB order
+    pub const ALL: [TimestampKind; 4] = [
+        TimestampKind::Modified,
+        TimestampKind::Accessed,
+        TimestampKind::Changed,
+        TimestampKind::Born,
+    ];
+
+    /// Returns the letter used for this timestamp in MACB strings
+    pub fn letter(&self) -> char {
+        match self {
+            TimestampKind::Modified => 'm',
+            TimestampKind::Accessed => 'a',
+            TimestampKind::Changed => 'c',
+            TimestampKind::Born => 'b',
+        }
+    }
+}
+
+impl fmt::Display for TimestampKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimestampKind::Modified => write!(f, "Modified"),
+            TimestampKind::Accessed => write!(f, "Accessed"),
+            TimestampKind::Changed => write!(f, "Changed"),
+            TimestampKind::Born => write!(f, "Created"),
+        }
+    }
+}
+
+/// Modified, accessed, changed and born times of a file.
+///
+/// Times are kept in UTC with the precision recorded by the file system.
+/// A time is `None` when the file system does not record it or when the
+/// recorded value is invalid.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct FileTimes {
+    modified: Option<DateTime<Utc>>,
+    accessed: Option<DateTime<Utc>>,
+    changed: Option<DateTime<Utc>>,
+    born: Option<DateTime<Utc>>,
+}
+
+impl FileTimes {
+    /// Creates a set of timestamps where every time is unknown
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the last modification time
+    pub fn with_modified(mut self, time: Option<DateTime<Utc>>) -> Self {
+        self.modified = time;
+        self
+    }
+
+    /// Sets the last access time
+    pub fn with_accessed(mut self, time: Option<DateTime<Utc>>) -> Self {
+        self.accessed = time;
+        self
+    }
+
+    /// Sets the last metadata change time
+    pub fn with_changed(mut self, time: Option<DateTime<Utc>>) -> Self {
+        self.changed = time;
+        self
+    }
+
+    /// Sets the creation time
+    pub fn with_born(mut self, time: Option<DateTime<Utc>>) -> Self {
+        self.born = time;
+        self
+    }
+
+    /// Returns the last modification time
+    pub fn modified(&self) -> Option<DateTime<Utc>> {
+        self.modified
+    }
+
+    /// Returns the last access time
+    pub fn accessed(&self) -> Option<DateTime<Utc>> {
+        self.accessed
+    }
+
+    /// Returns the last metadata change time
+    pub fn changed(&self) -> Option<DateTime<Utc>> {
+        self.changed
+    }
+
+    /// Returns the creation time
+    pub fn born(&self) -> Option<DateTime<Utc>> {
+        self.born
+    }
+
+    /// Returns the time of the given kind
+    pub fn get(&self, kind: TimestampKind) -> Option<DateTime<Utc>> {
+        match kind {
+            TimestampKind::Modified => self.modified,
+            TimestampKind::Accessed => self.accessed,
+            TimestampKind::Changed => self.changed,
+            TimestampKind::Born => self.born,
+        }
+    }
+
+    /// Iterates over the known times in MACB order
+    pub fn iter(&self) -> impl Iterator<Item = (TimestampKind, DateTime<Utc>)> + '_ {
+        TimestampKind::ALL
+            .into_iter()
+            .filter_map(|kind| self.get(kind).map(|time| (kind, time)))
+    }
+
+    /// Returns true if no time is known
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Reads the times of a file on a mounted file system.
+    ///
+    /// The change time is not part of `Metadata` and is read with the rest of
+    /// the native metadata of the file.
+    pub(crate) fn from_metadata(metadata: &Metadata, changed: Option<DateTime<Utc>>) -> Self {
+        FileTimes {
+            modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+            accessed: metadata.accessed().ok().map(DateTime::<Utc>::from),
+            changed,
+            born: metadata.created().ok().map(DateTime::<Utc>::from),
+        }
+    }
+}
+
+/// Converts a FILETIME (100 ns intervals since 1601) to UTC, `None` for zero.
+pub(crate) fn filetime_to_utc(filetime: u64) -> Option<DateTime<Utc>> {
+    if filetime == 0 {
+        return None;
+    }
+    let seconds = (filetime / 10_000_000) as i64 - FILETIME_UNIX_OFFSET;
+    let nanos = (filetime % 10_000_000) as u32 * 100;
+    DateTime::from_timestamp(seconds, nanos)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_filetime_to_utc() {
+        let time = filetime_to_utc(116_444_736_000_000_000 + 10_000_005).unwrap();
+        assert_eq!(time.timestamp(), 1);
+        assert_eq!(time.timestamp_subsec_nanos(), 500);
+        assert_eq!(filetime_to_utc(0), None);
+    }
+
+    #[test]
+    fn test_iter_skips_unknown_times() {
+        let modified = Utc.with_ymd_and_hms(2024, 3, 15, 10, 30, 0).unwrap();
+        let born = Utc.with_ymd_and_hms(2023, 1, 2, 3, 4, 5).unwrap();
+        let times = FileTimes::new().with_modified(Some(modified)).with_born(Some(born));
+
+        let known: Vec<_> = times.iter().collect();
+        assert_eq!(known, vec![(TimestampKind::Modified, modified), (TimestampKind::Born, born)]);
+        assert!(!times.is_empty());
+        assert!(FileTimes::new().is_empty());
+    }
+
+    #[test]
+    fn test_from_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        std::fs::write(&path, b"a").unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+
+        let changed = Utc.with_ymd_and_hms(2024, 3, 15, 10, 30, 0).unwrap();
+        let times = FileTimes::from_metadata(&metadata, Some(changed));
+        assert_eq!(times.modified(), Some(DateTime::<Utc>::from(metadata.modified().unwrap())));
+        assert!(times.accessed().is_some());
+        assert_eq!(times.changed(), Some(changed));
+    }
+}
@@ -0,0 +1,465 @@
+//! This module parses MBR and GPT partition tables from raw disk content.
+//!
+//! It works on any seekable reader, so the same code serves image files
+//! exposed through `VirtualDisk` and raw device handles.
+
+use std::io::{Read, Seek};
+
+use crate::images::{format_guid, le_u16, le_u32, le_u64, read_bytes, ImageError};
+use crate::{DiskExtent, ExtentKind};
+
+/// Offset of the MBR boot signature
+const MBR_SIGNATURE_OFFSET: usize = 510;
+/// Offset of the first MBR partition entry
+const MBR_ENTRIES_OFFSET: usize = 446;
+/// MBR partition type of a GPT protective partition
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+/// MBR partition types of extended partitions
+pub(crate) const MBR_TYPES_EXTENDED: [u8; 3] = [0x05, 0x0F, 0x85];
+/// Upper bound on the number of logical partitions followed in an extended partition
+const MAX_LOGICAL_PARTITIONS: usize = 128;
+/// Sector sizes tried when looking for a GPT header
+const GPT_SECTOR_SIZES: [u64; 2] = [512, 4096];
+/// Signature of a GPT header
+const GPT_SIGNATURE: &[u8] = b"EFI PART";
+/// Upper bound on the size of the GPT entry array, to bound allocations
+const MAX_GPT_ENTRIES_SIZE: u64 = 1024 * 1024;
+/// Size of the GPT entry array written by partitioning tools (128 entries of 128 bytes)
+#[cfg(any(windows, test))]
+const GPT_DEFAULT_ENTRIES_SIZE: u64 = 128 * 128;
+
+/// Partitioning scheme of a disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PartitionScheme {
+    /// Master Boot Record with optional extended partitions
+    Mbr,
+    /// GUID Partition Table
+    Gpt,
+}
+
+/// The type of a partition as recorded in the partition table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum PartitionType {
+    /// MBR system identifier byte
+    Mbr(u8),
+    /// GPT partition type GUID
+    Gpt(String),
+}
+
+/// A partition found in a partition table.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PartitionEntry {
+    /// Byte offset of the partition from the start of the disk
+    pub(crate) offset: u64,
+    /// Length of the partition in bytes
+    pub(crate) length: u64,
+    /// Partition type
+    pub(crate) partition_type: PartitionType,
+    /// Partition name (GPT only)
+    pub(crate) name: String,
+}
+
+/// Location of the GPT structures on the disk, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct GptLayout {
+    /// Logical sector size in bytes
+    pub(crate) sector_size: u64,
+    /// Offset of the primary GPT header
+    pub(crate) primary_header: u64,
+    /// Offset of the primary partition entry array
+    pub(crate) primary_entries: u64,
+    /// Size of a partition entry array in bytes
+    pub(crate) entries_size: u64,
+    /// Offset of the backup GPT header
+    pub(crate) backup_header: u64,
+    /// First byte usable by partitions
+    pub(crate) first_usable: u64,
+    /// Last byte usable by partitions, exclusive
+    pub(crate) last_usable: u64,
+}
+
+impl GptLayout {
+    /// Returns the layout written by partitioning tools on a disk of `disk_size` bytes.
+    ///
+    /// Used when the GPT headers cannot be read: the entry arrays hold 128
+    /// entries and sit right after the primary header and before the backup one.
+    #[cfg(any(windows, test))]
+    pub(crate) fn standard(sector_size: u64, disk_size: u64) -> Self {
+        let backup_header = disk_size.saturating_sub(sector_size);
+        GptLayout {
+            sector_size,
+            primary_header: sector_size,
+            primary_entries: 2 * sector_size,
+            entries_size: GPT_DEFAULT_ENTRIES_SIZE,
+            backup_header,
+            first_usable: 2 * sector_size + GPT_DEFAULT_ENTRIES_SIZE,
+            last_usable: backup_header.saturating_sub(GPT_DEFAULT_ENTRIES_SIZE),
+        }
+    }
+
+    /// Returns the extents of the protective MBR, the GPT headers and the entry arrays.
+    pub(crate) fn extents(&self) -> Vec<DiskExtent> {
+        let metadata = |offset: u64, length: u64, name: &str| DiskExtent::new(offset, length, ExtentKind::Metadata(name.to_string()));
+        let entries_size = self.entries_size.div_ceil(self.sector_size) * self.sector_size;
+        vec![
+            metadata(0, self.sector_size, "Protective MBR"),
+            metadata(self.primary_header, self.sector_size, "GPT header"),
+            metadata(self.primary_entries, entries_size, "GPT partition entries"),
+            metadata(self.backup_header.saturating_sub(entries_size), entries_size, "Backup GPT partition entries"),
+            metadata(self.backup_header, self.sector_size, "Backup GPT header"),
+        ]
+    }
+}
+
+/// A parsed partition table.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PartitionTable {
+    /// Partitioning scheme
+    pub(crate) scheme: PartitionScheme,
+    /// Partitions in table order
+    pub(crate) entries: Vec<PartitionEntry>,
+    /// GPT structure locations, for GPT disks
+    pub(crate) gpt: Option<GptLayout>,
+    /// Offsets of the extended boot records, for MBR disks with logical partitions
+    pub(crate) boot_records: Vec<u64>,
+}
+
+impl PartitionTable {
+    /// Returns the layout of a disk of `disk_size` bytes holding this table.
+    ///
+    /// Partitions are labelled with their index in `entries`.
+    pub(crate) fn extents(&self, disk_size: u64) -> Vec<DiskExtent> {
+        let metadata = |offset: u64, length: u64, name: &str| DiskExtent::new(offset, length, ExtentKind::Metadata(name.to_string()));
+
+        let mut used = match self.gpt {
+            Some(gpt) => gpt.extents(),
+            None => vec![metadata(0, 512, "MBR")],
+        };
+        used.extend(self.boot_records.iter().map(|&offset| metadata(offset, 512, "Extended boot record")));
+        used.extend(
+            self.entries
+                .iter()
+                .enumerate()
+                .map(|(index, entry)| DiskExtent::new(entry.offset, entry.length, ExtentKind::Partition(Some(index)))),
+        );
+        DiskExtent::layout(disk_size, used)
+    }
+}
+
+/// Reads the partition table at the start of a disk.
+///
+/// Returns `Ok(None)` if the disk has no valid MBR or GPT.
+pub(crate) fn read_partition_table<R: Read + Seek>(
+    reader: &mut R,
+    disk_size: u64,
+) -> Result<Option<PartitionTable>, ImageError> {
+    let mbr = match read_bytes(reader, 0, 512) {
+        Ok(mbr) => mbr,
+        Err(_) => return Ok(None),
+    };
+    if mbr[MBR_SIGNATURE_OFFSET..MBR_SIGNATURE_OFFSET + 2] != [0x55, 0xAA] {
+        return Ok(None);
+    }
+
+    let primary: Vec<(u8, u64, u64)> = (0..4)
+        .map(|index| {
+            let entry = &mbr[MBR_ENTRIES_OFFSET + index * 16..MBR_ENTRIES_OFFSET + (index + 1) * 16];
+            (entry[4], le_u32(entry, 8) as u64, le_u32(entry, 12) as u64)
+        })
+        .collect();
+
+    if primary.iter().any(|(kind, _, _)| *kind == MBR_TYPE_GPT_PROTECTIVE) {
+        if let Some(table) = read_gpt(reader, disk_size)? {
+            return Ok(Some(table));
+        }
+    }
+
+    // A FAT or NTFS boot sector also ends with 0x55AA; reject entries that are not plausible
+    let plausible = primary.iter().all(|(kind, start, sectors)| {
+        *kind == 0 || (*start > 0 && *sectors > 0 && (start + sectors) * 512 <= disk_size.max(512))
+    });
+    let status_valid = (0..4).all(|index| matches!(mbr[MBR_ENTRIES_OFFSET + index * 16], 0x00 | 0x80));
+    if !plausible || !status_valid || primary.iter().all(|(kind, _, _)| *kind == 0) {
+        return Ok(None);
+    }
+
+    let mut entries = Vec::new();
+    let mut boot_records = Vec::new();
+    for (kind, start, sectors) in primary {
+        if kind == 0 || sectors == 0 {
+            continue;
+        }
+        if MBR_TYPES_EXTENDED.contains(&kind) {
+            read_logical_partitions(reader, start, disk_size, &mut entries, &mut boot_records)?;
+            continue;
+        }
+        entries.push(PartitionEntry {
+            offset: start * 512,
+            length: sectors * 512,
+            partition_type: PartitionType::Mbr(kind),
+            name: String::new(),
+        });
+    }
+
+    Ok(Some(PartitionTable {
+        scheme: PartitionScheme::Mbr,
+        entries,
+        gpt: None,
+        boot_records,
+    }))
+}
+
+/// Follows the chain of extended boot records of an extended partition,
+/// collecting the logical partitions and the offsets of the records.
+fn read_logical_partitions<R: Read + Seek>(
+    reader: &mut R,
+    extended_start: u64,
+    disk_size: u64,
+    entries: &mut Vec<PartitionEntry>,
+    boot_records: &mut Vec<u64>,
+) -> Result<(), ImageError> {
+    let mut ebr_sector = extended_start;
+
+    for _ in 0..MAX_LOGICAL_PARTITIONS {
+        if ebr_sector * 512 >= disk_size {
+            break;
+        }
+        let ebr = read_bytes(reader, ebr_sector * 512, 512)?;
+        if ebr[MBR_SIGNATURE_OFFSET..MBR_SIGNATURE_OFFSET + 2] != [0x55, 0xAA] {
+            break;
+        }
+        boot_records.push(ebr_sector * 512);
+        let logical = &ebr[MBR_ENTRIES_OFFSET..MBR_ENTRIES_OFFSET + 16];
+        let next = &ebr[MBR_ENTRIES_OFFSET + 16..MBR_ENTRIES_OFFSET + 32];
+
+        if logical[4] != 0 && le_u32(logical, 12) > 0 {
+            entries.push(PartitionEntry {
+                offset: (ebr_sector + le_u32(logical, 8) as u64) * 512,
+                length: le_u32(logical, 12) as u64 * 512,
+                partition_type: PartitionType::Mbr(logical[4]),
+                name: String::new(),
+            });
+        }
+
+        // Links to the next EBR are relative to the start of the extended partition
+        if next[4] == 0 || le_u32(next, 8) == 0 {
+            break;
+        }
+        ebr_sector = extended_start + le_u32(next, 8) as u64;
+    }
+
+    Ok(())
+}
+
+/// Reads a GPT, trying the supported logical sector sizes.
+fn read_gpt<R: Read + Seek>(reader: &mut R, disk_size: u64) -> Result<Option<PartitionTable>, ImageError> {
+    for sector_size in GPT_SECTOR_SIZES {
+        let header = match read_bytes(reader, sector_size, 92) {
+            Ok(header) => header,
+            Err(_) => continue,
+        };
+        if &header[0..8] != GPT_SIGNATURE {
+            continue;
+        }
+
+        let entries_lba = le_u64(&header, 72);
+        let entry_count = le_u32(&header, 80) as u64;
+        let entry_size = le_u32(&header, 84) as u64;
+        let entries_size = entry_count * entry_size;
+        if entry_size < 128 || entries_size > MAX_GPT_ENTRIES_SIZE {
+            return Err(ImageError::InvalidImage("invalid GPT partition entry array".to_string()));
+        }
+
+        let offset_of = |lba: u64| {
+            lba.checked_mul(sector_size)
+                .ok_or_else(|| ImageError::InvalidImage(format!("GPT sector {} is out of range", lba)))
+        };
+        let array = read_bytes(reader, offset_of(entries_lba)?, entries_size as usize)?;
+        let entries = array
+            .chunks_exact(entry_size as usize)
+            .filter(|entry| entry[0..16].iter().any(|&byte| byte != 0))
+            .filter_map(|entry| {
+                // Entries whose sectors are reversed or out of range are corrupt and skipped
+                let first = le_u64(entry, 32);
+                let sectors = le_u64(entry, 40).checked_sub(first)?.checked_add(1)?;
+                let units: Vec<u16> = (0..36).map(|index| le_u16(entry, 56 + index * 2)).collect();
+                Some(PartitionEntry {
+                    offset: first.checked_mul(sector_size)?,
+                    length: sectors.checked_mul(sector_size)?,
+                    partition_type: PartitionType::Gpt(format_guid(&entry[0..16])),
+                    name: String::from_utf16_lossy(&units).trim_end_matches('\0').to_string(),
+                })
+            })
+            .collect();
+
+        let backup_lba = le_u64(&header, 32);
+        let layout = GptLayout {
+            sector_size,
+            primary_header: sector_size,
+            primary_entries: offset_of(entries_lba)?,
+            entries_size,
+            backup_header: if backup_lba == 0 { disk_size.saturating_sub(sector_size) } else { offset_of(backup_lba)? },
+            first_usable: offset_of(le_u64(&header, 40))?,
+            last_usable: offset_of(le_u64(&header, 48).saturating_add(1))?,
+        };
+
+        return Ok(Some(PartitionTable {
+            scheme: PartitionScheme::Gpt,
+            entries,
+            gpt: Some(layout),
+            boot_records: Vec::new(),
+        }));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Writes an MBR partition entry into a sector.
+    pub(crate) fn mbr_entry(sector: &mut [u8], index: usize, kind: u8, start: u32, sectors: u32) {
+        let entry = &mut sector[MBR_ENTRIES_OFFSET + index * 16..MBR_ENTRIES_OFFSET + (index + 1) * 16];
+        entry[4] = kind;
+        entry[8..12].copy_from_slice(&start.to_le_bytes());
+        entry[12..16].copy_from_slice(&sectors.to_le_bytes());
+        sector[510] = 0x55;
+        sector[511] = 0xAA;
+    }
+
+    #[test]
+    fn test_mbr_with_logical_partitions() {
+        let mut disk = vec![0u8; 4096 * 512];
+        mbr_entry(&mut disk[0..512], 0, 0x07, 2048, 1024);
+        mbr_entry(&mut disk[0..512], 1, 0x0F, 3072, 1024);
+        // First EBR at 3072: logical at +63, next EBR at extended + 512
+        let ebr = 3072 * 512;
+        mbr_entry(&mut disk[ebr..ebr + 512], 0, 0x83, 63, 100);
+        mbr_entry(&mut disk[ebr..ebr + 512], 1, 0x05, 512, 200);
+        let ebr = (3072 + 512) * 512;
+        mbr_entry(&mut disk[ebr..ebr + 512], 0, 0x0B, 63, 100);
+
+        let size = disk.len() as u64;
+        let table = read_partition_table(&mut Cursor::new(disk), size).unwrap().unwrap();
+        assert_eq!(table.scheme, PartitionScheme::Mbr);
+        assert_eq!(
+            table.entries.iter().map(|entry| (entry.offset / 512, entry.length / 512)).collect::<Vec<_>>(),
+            vec![(2048, 1024), (3072 + 63, 100), (3072 + 512 + 63, 100)]
+        );
+        assert_eq!(table.entries[2].partition_type, PartitionType::Mbr(0x0B));
+
+        let extents: Vec<(u64, u64, String)> = table
+            .extents(size)
+            .iter()
+            .map(|extent| (extent.offset() / 512, extent.length() / 512, extent.kind().to_string()))
+            .collect();
+        assert_eq!(
+            extents[..5],
+            [
+                (0, 1, "MBR".to_string()),
+                (1, 2047, "Unallocated".to_string()),
+                (2048, 1024, "Partition 0".to_string()),
+                (3072, 1, "Extended boot record".to_string()),
+                (3073, 62, "Unallocated".to_string()),
+            ]
+        );
+        assert_eq!(extents.last().unwrap(), &(3072 + 512 + 63 + 100, 4096 - 3747, "Unallocated".to_string()));
+    }
+
+    /// Builds a 1 MiB disk with a GPT holding one partition, `Data`, on sectors 40-1999.
+    fn build_gpt_disk() -> Vec<u8> {
+        let mut disk = vec![0u8; 2048 * 512];
+        mbr_entry(&mut disk[0..512], 0, MBR_TYPE_GPT_PROTECTIVE, 1, 2047);
+
+        let header = &mut disk[512..1024];
+        header[0..8].copy_from_slice(GPT_SIGNATURE);
+        header[32..40].copy_from_slice(&2047u64.to_le_bytes());
+        header[40..48].copy_from_slice(&34u64.to_le_bytes());
+        header[48..56].copy_from_slice(&2014u64.to_le_bytes());
+        header[72..80].copy_from_slice(&2u64.to_le_bytes());
+        header[80..84].copy_from_slice(&128u32.to_le_bytes());
+        header[84..88].copy_from_slice(&128u32.to_le_bytes());
+
+        let entry = &mut disk[1024..1152];
+        entry[0..16].copy_from_slice(&[0xA2, 0xA0, 0xD0, 0xEB, 0xE5, 0xB9, 0x33, 0x44, 0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7]);
+        entry[16] = 1;
+        entry[32..40].copy_from_slice(&40u64.to_le_bytes());
+        entry[40..48].copy_from_slice(&1999u64.to_le_bytes());
+        for (index, unit) in "Data".encode_utf16().enumerate() {
+            entry[56 + index * 2..58 + index * 2].copy_from_slice(&unit.to_le_bytes());
+        }
+        disk
+    }
+
+    #[test]
+    fn test_gpt() {
+        let disk = build_gpt_disk();
+        let size = disk.len() as u64;
+        let table = read_partition_table(&mut Cursor::new(disk), size).unwrap().unwrap();
+        assert_eq!(table.scheme, PartitionScheme::Gpt);
+        assert_eq!(table.entries.len(), 1);
+        assert_eq!(table.entries[0].offset, 40 * 512);
+        assert_eq!(table.entries[0].length, 1960 * 512);
+        assert_eq!(table.entries[0].name, "Data");
+        assert_eq!(
+            table.entries[0].partition_type,
+            PartitionType::Gpt("EBD0A0A2-B9E5-4433-87C0-68B6B72699C7".to_string())
+        );
+
+        let layout = table.gpt.unwrap();
+        assert_eq!(layout.backup_header, 2047 * 512);
+        assert_eq!(layout.primary_entries, 1024);
+        assert_eq!(layout.entries_size, 128 * 128);
+        assert_eq!(GptLayout::standard(512, size), layout);
+
+        let extents: Vec<(u64, u64, String)> = table
+            .extents(size)
+            .iter()
+            .map(|extent| (extent.offset() / 512, extent.length() / 512, extent.kind().to_string()))
+            .collect();
+        assert_eq!(
+            extents,
+            vec![
+                (0, 1, "Protective MBR".to_string()),
+                (1, 1, "GPT header".to_string()),
+                (2, 32, "GPT partition entries".to_string()),
+                (34, 6, "Unallocated".to_string()),
+                (40, 1960, "Partition 0".to_string()),
+                (2000, 15, "Unallocated".to_string()),
+                (2015, 32, "Backup GPT partition entries".to_string()),
+                (2047, 1, "Backup GPT header".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_corrupt_gpt() {
+        let read = |offset: usize, value: u64| {
+            let mut disk = build_gpt_disk();
+            disk[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+            let size = disk.len() as u64;
+            read_partition_table(&mut Cursor::new(disk), size)
+        };
+        // Header sectors whose offsets do not fit in 64 bits
+        for offset in [512 + 72, 512 + 32, 512 + 40, 512 + 48] {
+            assert!(matches!(read(offset, u64::MAX / 2), Err(ImageError::InvalidImage(_))), "header field {}", offset - 512);
+        }
+        // Entries with reversed or out of range sectors are skipped
+        for (offset, value) in [(1024 + 40, 39), (1024 + 32, u64::MAX / 2), (1024 + 40, u64::MAX)] {
+            let table = read(offset, value).unwrap().unwrap();
+            assert!(table.entries.is_empty(), "entry field {} set to {}", offset - 1024, value);
+        }
+        let mut disk = build_gpt_disk();
+        disk[1024 + 32..1024 + 40].copy_from_slice(&0u64.to_le_bytes());
+        disk[1024 + 40..1024 + 48].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(read_partition_table(&mut Cursor::new(disk), 2048 * 512).unwrap().unwrap().entries.is_empty());
+    }
+
+    #[test]
+    fn test_no_partition_table() {
+        let disk = vec![0u8; 8192];
+        assert!(read_partition_table(&mut Cursor::new(disk), 8192).unwrap().is_none());
+    }
+}
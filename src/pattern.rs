@@ -0,0 +1,447 @@
+//! Wildcard matching of file names and relative paths.
+//!
+//! Patterns use the usual shell syntax: `*` matches any run of characters
+//! within a path component, `?` matches a single character, `[abc]`, `[a-z]`
+//! or `[!abc]` match one character of a set and `{jpg,png}` matches any of
+//! the comma-separated alternatives. `**` matches any number of whole path
+//! components, so `**/*.jpg` matches JPEG files at any depth. A backslash
+//! makes the next character literal, except in native patterns on Windows
+//! where it separates path components like `/`.
+//!
+//! The module also reads `.gitignore`-style ignore files.
+
+use std::fs;
+use std::path::Path;
+
+/// One element of a compiled glob.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(char),
+    /// `?`: any character but a separator
+    Any,
+    /// `*`: any run of characters without a separator
+    Star,
+    /// `**/`, or a trailing `**`: any number of whole components
+    GlobStar,
+    /// `[...]`: ranges of characters, possibly negated
+    Class { negated: bool, ranges: Vec<(char, char)> },
+    Separator,
+}
+
+/// A compiled wildcard pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Glob {
+    /// The brace alternatives, each a sequence of tokens
+    alternatives: Vec<Vec<Token>>,
+    case_insensitive: bool,
+    has_separator: bool,
+}
+
+impl Glob {
+    /// Compiles a pattern where a backslash escapes the next character.
+    pub(crate) fn new(pattern: &str, case_insensitive: bool) -> Self {
+        let alternatives: Vec<Vec<Token>> = expand_braces(pattern)
+            .iter()
+            .map(|alternative| tokenize(alternative, case_insensitive))
+            .collect();
+        let has_separator = alternatives
+            .iter()
+            .any(|tokens| tokens.iter().any(|token| matches!(token, Token::Separator | Token::GlobStar)));
+        Glob {
+            alternatives,
+            case_insensitive,
+            has_separator,
+        }
+    }
+
+    /// Compiles a pattern typed by a user, where a backslash also separates
+    /// path components on Windows.
+    pub(crate) fn native(pattern: &str, case_insensitive: bool) -> Self {
+        if cfg!(windows) {
+            Self::new(&pattern.replace('\\', "/"), case_insensitive)
+        } else {
+            Self::new(pattern, case_insensitive)
+        }
+    }
+
+    /// Returns true if the pattern spans path components and is matched against relative paths.
+    pub(crate) fn has_separator(&self) -> bool {
+        self.has_separator
+    }
+
+    /// Returns true if `text`, a name or a relative path separated by `/`, matches as a whole.
+    pub(crate) fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().map(|c| fold(c, self.case_insensitive)).collect();
+        self.alternatives.iter().any(|tokens| {
+            // Failed states are remembered so that consecutive stars stay polynomial
+            let mut failed = vec![false; (tokens.len() + 1) * (text.len() + 1)];
+            match_tokens(tokens, &text, 0, 0, &mut failed)
+        })
+    }
+}
+
+/// Returns true if a pattern uses any wildcard syntax.
+pub(crate) fn has_wildcards(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '{'])
+}
+
+/// Escapes the wildcard characters of `text` so that it matches literally.
+///
+/// Characters are escaped with classes such as `[*]`, which mean the same on
+/// every platform.
+pub(crate) fn escape_glob(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '{' | '}' | ',' | '\\') {
+            escaped.push('[');
+            escaped.push(c);
+            escaped.push(']');
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// Folds a character for comparison, using simple lowercase mapping when case is ignored.
+fn fold(c: char, case_insensitive: bool) -> char {
+    if case_insensitive {
+        let mut lower = c.to_lowercase();
+        match (lower.next(), lower.next()) {
+            (Some(lower), None) => lower,
+            // Characters lowering to several ones, like `İ`, compare as themselves
+            _ => c,
+        }
+    } else {
+        c
+    }
+}
+
+/// Expands `{a,b}` alternatives, including nested ones, into separate patterns.
+///
+/// Braces without a matching closing brace or without a comma are literal.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut index = 0;
+    while index < chars.len() {
+        match chars[index] {
+            '\\' => index += 2,
+            '[' => index = class_end(&chars, index).map_or(index + 1, |end| end + 1),
+            '{' => {
+                if let Some((end, commas)) = brace_end(&chars, index) {
+                    if !commas.is_empty() {
+                        let prefix: String = chars[..index].iter().collect();
+                        let suffix: String = chars[end + 1..].iter().collect();
+                        let mut bounds = vec![index];
+                        bounds.extend(&commas);
+                        bounds.push(end);
+                        return bounds
+                            .windows(2)
+                            .flat_map(|bound| {
+                                let alternative: String = chars[bound[0] + 1..bound[1]].iter().collect();
+                                expand_braces(&format!("{}{}{}", prefix, alternative, suffix))
+                            })
+                            .collect();
+                    }
+                }
+                index += 1;
+            }
+            _ => index += 1,
+        }
+    }
+    vec![pattern.to_string()]
+}
+
+/// Finds the brace closing the one at `start` and the top-level commas inside it.
+fn brace_end(chars: &[char], start: usize) -> Option<(usize, Vec<usize>)> {
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    let mut index = start + 1;
+    while index < chars.len() {
+        match chars[index] {
+            '\\' => index += 1,
+            '[' => index = class_end(chars, index).unwrap_or(index),
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some((index, commas)),
+            '}' => depth -= 1,
+            ',' if depth == 0 => commas.push(index),
+            _ => {}
+        }
+        index += 1;
+    }
+    None
+}
+
+/// Finds the `]` closing the class at `start`; a `]` right after the opening is part of the class.
+fn class_end(chars: &[char], start: usize) -> Option<usize> {
+    let mut index = start + 1;
+    if matches!(chars.get(index), Some('!') | Some('^')) {
+        index += 1;
+    }
+    if chars.get(index) == Some(&']') {
+        index += 1;
+    }
+    (index..chars.len()).find(|&index| chars[index] == ']')
+}
+
+/// Compiles a pattern without braces into tokens.
+fn tokenize(pattern: &str, case_insensitive: bool) -> Vec<Token> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        match chars[index] {
+            '*' => {
+                let mut end = index;
+                while chars.get(end) == Some(&'*') {
+                    end += 1;
+                }
+                // `**` is only special as a whole component, elsewhere it is a plain star
+                let starts_component = index == 0 || chars[index - 1] == '/';
+                let ends_component = end == chars.len() || chars[end] == '/';
+                if end - index >= 2 && starts_component && ends_component {
+                    tokens.push(Token::GlobStar);
+                    end += 1;
+                } else {
+                    tokens.push(Token::Star);
+                }
+                index = end;
+            }
+            '?' => {
+                tokens.push(Token::Any);
+                index += 1;
+            }
+            '/' => {
+                tokens.push(Token::Separator);
+                index += 1;
+            }
+            '[' => match class_end(&chars, index) {
+                Some(end) => {
+                    let mut inner = index + 1;
+                    let negated = matches!(chars[inner], '!' | '^');
+                    if negated {
+                        inner += 1;
+                    }
+                    let mut ranges = Vec::new();
+                    while inner < end {
+                        let low = fold(chars[inner], case_insensitive);
+                        if chars[inner + 1] == '-' && inner + 2 < end {
+                            ranges.push((low, fold(chars[inner + 2], case_insensitive)));
+                            inner += 3;
+                        } else {
+                            ranges.push((low, low));
+                            inner += 1;
+                        }
+                    }
+                    tokens.push(Token::Class { negated, ranges });
+                    index = end + 1;
+                }
+                None => {
+                    tokens.push(Token::Literal('['));
+                    index += 1;
+                }
+            },
+            '\\' if index + 1 < chars.len() => {
+                tokens.push(Token::Literal(fold(chars[index + 1], case_insensitive)));
+                index += 2;
+            }
+            c => {
+                tokens.push(Token::Literal(fold(c, case_insensitive)));
+                index += 1;
+            }
+        }
+    }
+    tokens
+}
+
+/// Matches `text[t..]` against `tokens[p..]`.
+fn match_tokens(tokens: &[Token], text: &[char], p: usize, t: usize, failed: &mut [bool]) -> bool {
+    let state = p * (text.len() + 1) + t;
+    if failed[state] {
+        return false;
+    }
+    let matched = match tokens.get(p) {
+        None => t == text.len(),
+        Some(Token::Star) => {
+            let run = text[t..].iter().take_while(|&&c| c != '/').count();
+            (0..=run).any(|skip| match_tokens(tokens, text, p + 1, t + skip, failed))
+        }
+        Some(Token::GlobStar) => {
+            // Zero components, or any prefix ending with a separator
+            match_tokens(tokens, text, p + 1, t, failed)
+                || (t..text.len())
+                    .filter(|&index| text[index] == '/')
+                    .any(|index| match_tokens(tokens, text, p + 1, index + 1, failed))
+                || (p + 1 == tokens.len() && t < text.len())
+        }
+        Some(token) => {
+            t < text.len()
+                && match token {
+                    Token::Literal(c) => *c == text[t],
+                    Token::Any => text[t] != '/',
+                    Token::Separator => text[t] == '/',
+                    Token::Class { negated, ranges } => {
+                        text[t] != '/' && ranges.iter().any(|(low, high)| (*low..=*high).contains(&text[t])) != *negated
+                    }
+                    Token::Star | Token::GlobStar => unreachable!(),
+                }
+                && match_tokens(tokens, text, p + 1, t + 1, failed)
+        }
+    };
+    if !matched {
+        failed[state] = true;
+    }
+    matched
+}
+
+/// One line of an ignore file.
+#[derive(Debug, Clone)]
+struct IgnoreRule {
+    glob: Glob,
+    /// `!pattern`: re-includes what earlier rules ignored
+    negated: bool,
+    /// `pattern/`: only matches directories
+    directory_only: bool,
+    /// Patterns with a `/` before their end match paths relative to the ignore file
+    anchored: bool,
+}
+
+/// Rules of one `.gitignore`-style ignore file.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct IgnoreFile {
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreFile {
+    /// Reads the ignore file `name` in `directory`, returning `None` if there is none.
+    pub(crate) fn read(directory: &Path, name: &str, case_insensitive: bool) -> Option<Self> {
+        let content = fs::read(directory.join(name)).ok()?;
+        Some(Self::parse(&String::from_utf8_lossy(&content), case_insensitive))
+    }
+
+    /// Parses the content of an ignore file.
+    ///
+    /// Blank lines and lines starting with `#` are skipped, `!` negates a
+    /// pattern, a trailing `/` restricts it to directories and a `/` at the
+    /// start or in the middle anchors it to the directory of the file.
+    pub(crate) fn parse(content: &str, case_insensitive: bool) -> Self {
+        let mut rules = Vec::new();
+        for line in content.lines() {
+            let line = trim_unescaped_spaces(line.trim_end_matches('\r'));
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let (directory_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let anchored = line.contains('/');
+            let pattern = line.strip_prefix('/').unwrap_or(line);
+            if pattern.is_empty() {
+                continue;
+            }
+            rules.push(IgnoreRule {
+                glob: Glob::new(pattern, case_insensitive),
+                negated,
+                directory_only,
+                anchored,
+            });
+        }
+        IgnoreFile { rules }
+    }
+
+    /// Returns whether the last rule matching an entry ignores it, or `None` if no rule matches.
+    ///
+    /// `relative` is the path of the entry relative to the directory of the
+    /// ignore file, separated by `/`.
+    pub(crate) fn decision(&self, relative: &str, is_dir: bool) -> Option<bool> {
+        let name = relative.rsplit('/').next().unwrap_or(relative);
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| {
+                (is_dir || !rule.directory_only)
+                    && rule.glob.is_match(if rule.anchored { relative } else { name })
+            })
+            .map(|rule| !rule.negated)
+    }
+}
+
+/// Removes trailing spaces from a line of an ignore file, except those escaped with a backslash.
+fn trim_unescaped_spaces(line: &str) -> &str {
+    let mut end = line.len();
+    while line[..end].ends_with(' ') && !line[..end - 1].ends_with('\\') {
+        end -= 1;
+    }
+    &line[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob_match(pattern: &str, text: &str, case_insensitive: bool) -> bool {
+        Glob::new(pattern, case_insensitive).is_match(text)
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.pst", "Outlook.pst", false));
+        assert!(!glob_match("*.pst", "Outlook.pst.bak", false));
+        assert!(glob_match("2023-*", "2023-01-report.pdf", false));
+        assert!(glob_match("IMG_????.JPG", "img_0042.jpg", true));
+        assert!(!glob_match("IMG_????.JPG", "img_0042.jpg", false));
+        assert!(glob_match("report[0-9].*", "report7.docx", false));
+        assert!(!glob_match("report[!0-9].*", "report7.docx", false));
+        assert!(glob_match("*a*b*c", "xxaxxbxxc", false));
+        assert!(glob_match("[abc", "[abc", false));
+        assert!(glob_match("[]]", "]", false));
+    }
+
+    #[test]
+    fn test_glob_paths_and_braces() {
+        let glob = Glob::new("**/*.{jpg,png}", true);
+        assert!(glob.has_separator());
+        assert!(glob.is_match("holiday.JPG"));
+        assert!(glob.is_match("Pictures/2023/beach.png"));
+        assert!(!glob.is_match("Pictures/2023/beach.gif"));
+
+        assert!(glob_match("docs/*.pdf", "docs/a.pdf", false));
+        assert!(!glob_match("docs/*.pdf", "docs/old/a.pdf", false));
+        assert!(glob_match("docs/**/*.pdf", "docs/old/a.pdf", false));
+        assert!(glob_match("docs/**", "docs/old/a.pdf", false));
+        assert!(!glob_match("docs/**", "docs", false));
+        assert!(glob_match("a/**/b", "a/b", false));
+        assert!(glob_match("{report,draft}-{20[0-9][0-9],old}.txt", "draft-2023.txt", false));
+        assert!(glob_match("{a,b{c,d}}", "bd", false));
+        assert!(glob_match("{single}", "{single}", false));
+        assert!(!Glob::new("*.pst", false).has_separator());
+        assert!(glob_match(&"*".repeat(30), &"a".repeat(60), false));
+    }
+
+    #[test]
+    fn test_escape_glob() {
+        let name = "notes [draft]*{1,2}.txt";
+        assert!(glob_match(&format!("*{}*", escape_glob(name)), "my notes [draft]*{1,2}.txt", false));
+        assert!(!glob_match(&escape_glob("a*"), "abc", false));
+        assert!(!has_wildcards("report") && has_wildcards("2023-*"));
+    }
+
+    #[test]
+    fn test_ignore_file() {
+        let ignore = IgnoreFile::parse("# build output\n*.tmp  \nspace\\ \n!keep.tmp\ntarget/\n/notes.txt\ndocs/**/*.bak\n\n", false);
+        assert_eq!(ignore.decision("cache.tmp", false), Some(true));
+        assert_eq!(ignore.decision("src/keep.tmp", false), Some(false));
+        assert_eq!(ignore.decision("target", true), Some(true));
+        assert_eq!(ignore.decision("target", false), None);
+        assert_eq!(ignore.decision("notes.txt", false), Some(true));
+        assert_eq!(ignore.decision("src/notes.txt", false), None);
+        assert_eq!(ignore.decision("docs/a/b/old.bak", false), Some(true));
+        assert_eq!(ignore.decision("main.rs", false), None);
+        assert_eq!(ignore.decision("space ", false), Some(true));
+    }
+}
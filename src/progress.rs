@@ -0,0 +1,334 @@
+//! Progress reporting and cancellation of long-running operations.
+//!
+//! A `Monitor` is handed to scans, hashing and identification. It calls a
+//! progress callback at a bounded rate with the files seen, the bytes
+//! processed, the current path and the expected totals when they are known,
+//! and carries a `CancellationToken` that the operation checks as it goes.
+//! A cancelled operation returns what it gathered so far.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Default time between two progress reports
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Shared flag asking an operation to stop.
+///
+/// Clones share the same flag, so a token can be kept by a user interface
+/// while a clone is given to an operation running on another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a token that is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the operations holding this token to stop as soon as possible
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true once `cancel` has been called on this token or a clone of it
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Snapshot of the progress of an operation, passed to progress callbacks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progress {
+    files: u64,
+    bytes: u64,
+    current_path: Option<PathBuf>,
+    total_files: Option<u64>,
+    total_bytes: Option<u64>,
+    finished: bool,
+    cancelled: bool,
+}
+
+impl Progress {
+    /// Returns the number of files seen so far
+    pub fn files(&self) -> u64 {
+        self.files
+    }
+
+    /// Returns the number of bytes processed so far
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns the path being processed, `None` in the final report
+    pub fn current_path(&self) -> Option<&Path> {
+        self.current_path.as_deref()
+    }
+
+    /// Returns the expected number of files, if known
+    pub fn total_files(&self) -> Option<u64> {
+        self.total_files
+    }
+
+    /// Returns the expected number of bytes, if known
+    pub fn total_bytes(&self) -> Option<u64> {
+        self.total_bytes
+    }
+
+    /// Returns the completed fraction, between 0 and 1, from the bytes or else the files, if a total is known
+    pub fn fraction(&self) -> Option<f64> {
+        let ratio = |done: u64, total: u64| if total == 0 { 1.0 } else { (done as f64 / total as f64).min(1.0) };
+        match (self.total_bytes, self.total_files) {
+            (Some(total), _) => Some(ratio(self.bytes, total)),
+            (None, Some(total)) => Some(ratio(self.files, total)),
+            (None, None) => None,
+        }
+    }
+
+    /// Returns true for the last report of an operation
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Returns true if the operation was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+}
+
+/// Function receiving progress reports
+type ProgressCallback = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+/// Progress reporting and cancellation settings of an operation.
+///
+/// # Examples
+/// ```no_run
+/// use std::sync::mpsc;
+/// use std::thread;
+/// use win_disk_info::{scan_files, CancellationToken, Monitor, ScanOptions};
+///
+/// let token = CancellationToken::new();
+/// let (sender, receiver) = mpsc::channel();
+/// let monitor = Monitor::new()
+///     .with_progress(move |progress| {
+///         let _ = sender.send(progress.clone());
+///     })
+///     .with_cancellation(token.clone());
+///
+/// let worker = thread::spawn(move || scan_files("D:\\", &ScanOptions::volume().with_monitor(monitor)));
+/// for progress in receiver {
+///     println!("{} files, {} bytes", progress.files(), progress.bytes());
+///     if progress.files() > 1_000_000 {
+///         token.cancel();
+///     }
+/// }
+/// let partial = worker.join().unwrap().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct Monitor {
+    callback: Option<ProgressCallback>,
+    cancellation: CancellationToken,
+    interval: Duration,
+    estimated_files: Option<u64>,
+    estimated_bytes: Option<u64>,
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Monitor {
+            callback: None,
+            cancellation: CancellationToken::new(),
+            interval: DEFAULT_INTERVAL,
+            estimated_files: None,
+            estimated_bytes: None,
+        }
+    }
+}
+
+impl Monitor {
+    /// Creates a monitor without progress callback nor cancellation
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calls `callback` with the progress of the operation.
+    ///
+    /// Calls are spaced by the report interval, never overlap, and may come
+    /// from any of the threads of the operation. A last call, marked as
+    /// finished, is made when the operation ends.
+    pub fn with_progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Progress) + Send + Sync + 'static,
+    {
+        self.callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Stops the operation when `token` is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Sets the minimum time between two progress reports, 100 ms by default
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the expected totals reported for operations that cannot know them in advance.
+    ///
+    /// Scans do not know how many files they will find; an estimate, such as
+    /// the count of a previous scan or the used space of the volume, lets
+    /// progress reports show a completed fraction. Hashing and
+    /// identification report the exact totals of their input instead.
+    pub fn with_estimated_total(mut self, files: Option<u64>, bytes: Option<u64>) -> Self {
+        self.estimated_files = files;
+        self.estimated_bytes = bytes;
+        self
+    }
+
+    /// Returns the cancellation token checked by the operation
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Returns true if the operation was asked to stop
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// Returns the minimum time between two progress reports
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+impl fmt::Debug for Monitor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Monitor")
+            .field("callback", &self.callback.as_ref().map(|_| "Fn(&Progress)"))
+            .field("cancellation", &self.cancellation)
+            .field("interval", &self.interval)
+            .field("estimated_files", &self.estimated_files)
+            .field("estimated_bytes", &self.estimated_bytes)
+            .finish()
+    }
+}
+
+/// Counters of one run of an operation, shared by its threads.
+pub(crate) struct Tracker<'a> {
+    monitor: &'a Monitor,
+    files: AtomicU64,
+    bytes: AtomicU64,
+    total_files: Option<u64>,
+    total_bytes: Option<u64>,
+    /// Time of the last report, locked while a report is being made
+    last_report: Mutex<Option<Instant>>,
+}
+
+impl<'a> Tracker<'a> {
+    /// Starts tracking an operation whose totals are the estimates of the monitor.
+    pub(crate) fn new(monitor: &'a Monitor) -> Self {
+        Tracker {
+            monitor,
+            files: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            total_files: monitor.estimated_files,
+            total_bytes: monitor.estimated_bytes,
+            last_report: Mutex::new(None),
+        }
+    }
+
+    /// Starts tracking an operation over a known number of files and bytes.
+    pub(crate) fn with_totals(monitor: &'a Monitor, files: u64, bytes: u64) -> Self {
+        Tracker {
+            total_files: Some(files),
+            total_bytes: Some(bytes),
+            ..Self::new(monitor)
+        }
+    }
+
+    /// Returns true if the operation was asked to stop.
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.monitor.is_cancelled()
+    }
+
+    /// Counts `files` more files and `bytes` more bytes while processing `path`.
+    pub(crate) fn advance(&self, path: &Path, files: u64, bytes: u64) {
+        self.files.fetch_add(files, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        let Some(callback) = &self.monitor.callback else {
+            return;
+        };
+        // Threads finding a report under way skip theirs rather than wait
+        let Ok(mut last_report) = self.last_report.try_lock() else {
+            return;
+        };
+        if last_report.is_some_and(|last| last.elapsed() < self.monitor.interval) {
+            return;
+        }
+        *last_report = Some(Instant::now());
+        callback(&self.snapshot(Some(path.to_path_buf()), false));
+    }
+
+    /// Makes the last report of the operation.
+    pub(crate) fn finish(&self) {
+        if let Some(callback) = &self.monitor.callback {
+            let _report = self.last_report.lock().unwrap_or_else(|e| e.into_inner());
+            callback(&self.snapshot(None, true));
+        }
+    }
+
+    fn snapshot(&self, current_path: Option<PathBuf>, finished: bool) -> Progress {
+        Progress {
+            files: self.files.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            current_path,
+            total_files: self.total_files,
+            total_bytes: self.total_bytes,
+            finished,
+            cancelled: self.is_cancelled(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracker_reports() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&reports);
+        let monitor = Monitor::new()
+            .with_progress(move |progress| sink.lock().unwrap().push(progress.clone()))
+            .with_interval(Duration::from_secs(3600));
+
+        let tracker = Tracker::with_totals(&monitor, 4, 400);
+        for index in 0..4 {
+            tracker.advance(Path::new(&format!("file{}", index)), 1, 100);
+        }
+        tracker.finish();
+
+        let reports = reports.lock().unwrap();
+        // The first report is immediate, the next ones wait for the interval
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].current_path(), Some(Path::new("file0")));
+        assert_eq!(reports[0].fraction(), Some(0.25));
+        assert!(reports[1].is_finished() && !reports[1].is_cancelled());
+        assert_eq!((reports[1].files(), reports[1].bytes()), (4, 400));
+    }
+
+    #[test]
+    fn test_cancellation_token() {
+        let token = CancellationToken::new();
+        let monitor = Monitor::new().with_cancellation(token.clone());
+        assert!(!monitor.is_cancelled());
+        token.cancel();
+        assert!(monitor.is_cancelled() && monitor.cancellation().is_cancelled());
+        assert!(monitor.clone().is_cancelled() && !Monitor::new().is_cancelled());
+    }
+}
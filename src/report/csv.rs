@@ -0,0 +1,112 @@
+//! CSV rendering of reports, one file per table.
+//!
+//! Fields follow RFC 4180: they are quoted when they hold a comma, a quote
+//! or a line break, and quotes are doubled. Sizes are written in bytes and
+//! timestamps in RFC 3339 form so that the files can be processed further.
+//!
+//! File names are chosen by whoever created the files, so fields that a
+//! spreadsheet would run as a formula are prefixed with `'`. Fields that
+//! already start with `'` get one too, so that `unguard` restores every
+//! field exactly.
+
+use super::{Document, Table, Value};
+
+/// Leading characters that make spreadsheet applications read a field as a formula
+const FORMULA_PREFIXES: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+
+/// Renders the key figures of a report as `Item,Value` rows.
+pub(crate) fn render_summary(document: &Document) -> String {
+    let mut csv = String::from("Item,Value\r\n");
+    push_row(&mut csv, ["Title", &document.title]);
+    push_row(&mut csv, ["Generated", &document.generated.to_rfc3339()]);
+    for (_, label, value) in &document.summary {
+        push_row(&mut csv, [*label, &field(value)]);
+    }
+    csv
+}
+
+/// Renders a table with a heading row.
+pub(crate) fn render_table(table: &Table) -> String {
+    let mut csv = String::new();
+    push_row(&mut csv, table.columns.iter().map(|column| column.title.as_str()));
+    for row in &table.rows {
+        let fields: Vec<String> = row.iter().map(field).collect();
+        push_row(&mut csv, fields.iter().map(String::as_str));
+    }
+    csv
+}
+
+/// Formats a value as an unescaped CSV field.
+fn field(value: &Value) -> String {
+    match value {
+        Value::Empty => String::new(),
+        Value::Text(text) => text.clone(),
+        Value::Integer(number) | Value::Size(number) => number.to_string(),
+        Value::Bool(flag) => flag.to_string(),
+        Value::Time(time) => time.to_rfc3339(),
+    }
+}
+
+/// Appends a row of escaped fields terminated by CRLF.
+pub(crate) fn push_row<'a>(csv: &mut String, fields: impl IntoIterator<Item = &'a str>) {
+    for (index, field) in fields.into_iter().enumerate() {
+        if index > 0 {
+            csv.push(',');
+        }
+        let guarded;
+        let field = if field.starts_with(FORMULA_PREFIXES) || field.starts_with('\'') {
+            guarded = format!("'{}", field);
+            &guarded
+        } else {
+            field
+        };
+        if field.contains([',', '"', '\r', '\n']) {
+            csv.push('"');
+            csv.push_str(&field.replace('"', "\"\""));
+            csv.push('"');
+        } else {
+            csv.push_str(field);
+        }
+    }
+    csv.push_str("\r\n");
+}
+
+/// Removes the prefix `push_row` adds to fields that could run as formulas.
+pub(crate) fn unguard(field: &str) -> &str {
+    field.strip_prefix('\'').unwrap_or(field)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Column;
+    use super::*;
+
+    #[test]
+    fn test_render_table_escaping() {
+        let table = Table {
+            id: "files",
+            title: "Files",
+            columns: vec![Column::new("path", "Path"), Column::new("size", "Size")],
+            rows: vec![
+                vec![Value::text("say \"hi\", bye"), Value::Size(1024)],
+                vec![Value::text("two\nlines"), Value::Empty],
+            ],
+        };
+        assert_eq!(
+            render_table(&table),
+            "Path,Size\r\n\"say \"\"hi\"\", bye\",1024\r\n\"two\nlines\",\r\n"
+        );
+    }
+
+    #[test]
+    fn test_formula_guard() {
+        let fields = ["=HYPERLINK(\"http://x\")", "+1", "-2", "@SUM(A1)", "'quoted", "plain", "a=b"];
+        let mut csv = String::new();
+        push_row(&mut csv, fields);
+        assert_eq!(csv, "\"'=HYPERLINK(\"\"http://x\"\")\",'+1,'-2,'@SUM(A1),''quoted,plain,a=b\r\n");
+
+        let read = crate::hash_sets::split_csv(csv.trim_end());
+        let restored: Vec<&str> = read.iter().map(|field| unguard(field)).collect();
+        assert_eq!(restored, fields);
+    }
+}
@@ -0,0 +1,133 @@
+//! HTML rendering of reports.
+//!
+//! The page is self-contained: styles are inlined and no script or external
+//! resource is referenced, so it can be attached to a case file or ticket
+//! and opened offline. Sizes are shown in human-readable units.
+
+use std::fmt::Write;
+
+use super::{Document, Table, Value};
+use crate::format_file_size;
+
+/// Styles of the report page
+const STYLE: &str = "\
+body{font-family:Segoe UI,Helvetica,Arial,sans-serif;margin:2em;color:#222}\
+h1{margin-bottom:0}\
+.generated{color:#666;margin-top:.3em}\
+.summary{display:flex;flex-wrap:wrap;gap:1em;margin:1.5em 0}\
+.card{border:1px solid #ccc;border-radius:6px;padding:.6em 1em;min-width:9em}\
+.card .label{color:#666;font-size:.85em}\
+.card .value{font-size:1.3em;font-weight:600}\
+table{border-collapse:collapse;margin-bottom:2em;font-size:.9em}\
+th,td{border:1px solid #ddd;padding:.3em .6em;text-align:left;vertical-align:top}\
+th{background:#f2f2f2}\
+tr:nth-child(even) td{background:#fafafa}\
+td.number{text-align:right;white-space:nowrap}\
+.empty{color:#888;font-style:italic}";
+
+/// Renders a report as a complete HTML page.
+pub(crate) fn render(document: &Document) -> String {
+    let mut html = String::from("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    let _ = writeln!(html, "<title>{}</title>", escape(&document.title));
+    let _ = writeln!(html, "<style>{}</style>\n</head>\n<body>", STYLE);
+    let _ = writeln!(html, "<h1>{}</h1>", escape(&document.title));
+    let _ = writeln!(
+        html,
+        "<p class=\"generated\">Generated {}</p>",
+        document.generated.format("%Y-%m-%d %H:%M:%S %:z")
+    );
+
+    html.push_str("<div class=\"summary\">\n");
+    for (_, label, value) in &document.summary {
+        let _ = writeln!(
+            html,
+            "<div class=\"card\"><div class=\"label\">{}</div><div class=\"value\">{}</div></div>",
+            escape(label),
+            cell_text(value)
+        );
+    }
+    html.push_str("</div>\n");
+
+    for table in &document.tables {
+        render_table(&mut html, table);
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn render_table(html: &mut String, table: &Table) {
+    let _ = writeln!(html, "<h2 id=\"{}\">{} ({})</h2>", table.id, escape(table.title), table.rows.len());
+    if table.rows.is_empty() {
+        html.push_str("<p class=\"empty\">None</p>\n");
+        return;
+    }
+
+    html.push_str("<table>\n<thead><tr>");
+    for column in &table.columns {
+        let _ = write!(html, "<th>{}</th>", escape(&column.title));
+    }
+    html.push_str("</tr></thead>\n<tbody>\n");
+    for row in &table.rows {
+        html.push_str("<tr>");
+        for value in row {
+            match value {
+                Value::Integer(_) | Value::Size(_) => {
+                    let _ = write!(html, "<td class=\"number\">{}</td>", cell_text(value));
+                }
+                _ => {
+                    let _ = write!(html, "<td>{}</td>", cell_text(value));
+                }
+            }
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody>\n</table>\n");
+}
+
+/// Formats a value as escaped cell content.
+fn cell_text(value: &Value) -> String {
+    match value {
+        Value::Empty => String::new(),
+        Value::Text(text) => escape(text),
+        Value::Integer(number) => number.to_string(),
+        Value::Size(size) => format_file_size(*size),
+        Value::Bool(true) => "Yes".to_string(),
+        Value::Bool(false) => "No".to_string(),
+        Value::Time(time) => time.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+    }
+}
+
+/// Escapes the characters with a meaning in HTML text and attributes.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::sample_report;
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let dir = tempfile::tempdir().unwrap();
+        let html = render(&sample_report(dir.path()).document());
+
+        assert!(html.contains("<title>Case &lt;17&gt;</title>"));
+        assert!(html.contains("<div class=\"label\">Total capacity</div><div class=\"value\">2.00 KB</div>"));
+        assert!(html.contains("<h2 id=\"files\">Files (2)</h2>"));
+        assert!(html.contains("notes &lt;draft&gt;, v2.txt"));
+        assert!(html.contains("<td>Evidence &quot;USB&quot; drive</td>"));
+        assert!(!html.contains("<script"));
+    }
+}
@@ -0,0 +1,98 @@
+//! JSON rendering of reports.
+//!
+//! The document is an object holding the title, the generation time, a
+//! `summary` object and one array per table, each row being an object keyed
+//! by column. Empty cells are `null`, sizes are numbers of bytes and
+//! timestamps are RFC 3339 strings.
+
+use std::fmt::Write;
+
+use super::{Document, Value};
+
+/// Renders a report as an indented JSON document.
+pub(crate) fn render(document: &Document) -> String {
+    let mut json = String::from("{\n");
+    let _ = writeln!(json, "  \"title\": {},", string(&document.title));
+    let _ = writeln!(json, "  \"generated\": {},", string(&document.generated.to_rfc3339()));
+
+    json.push_str("  \"summary\": {");
+    for (index, (key, _, value)) in document.summary.iter().enumerate() {
+        let separator = if index > 0 { "," } else { "" };
+        let _ = write!(json, "{}\n    {}: {}", separator, string(key), value_json(value));
+    }
+    json.push_str("\n  }");
+
+    for table in &document.tables {
+        let _ = write!(json, ",\n  {}: [", string(table.id));
+        for (index, row) in table.rows.iter().enumerate() {
+            let separator = if index > 0 { "," } else { "" };
+            let fields: Vec<String> = table
+                .columns
+                .iter()
+                .zip(row)
+                .map(|(column, value)| format!("{}: {}", string(&column.key), value_json(value)))
+                .collect();
+            let _ = write!(json, "{}\n    {{{}}}", separator, fields.join(", "));
+        }
+        json.push_str(if table.rows.is_empty() { "]" } else { "\n  ]" });
+    }
+    json.push_str("\n}\n");
+    json
+}
+
+/// Formats a value as a JSON literal.
+fn value_json(value: &Value) -> String {
+    match value {
+        Value::Empty => "null".to_string(),
+        Value::Text(text) => string(text),
+        Value::Integer(number) | Value::Size(number) => number.to_string(),
+        Value::Bool(flag) => flag.to_string(),
+        Value::Time(time) => string(&time.to_rfc3339()),
+    }
+}
+
+/// Formats text as a quoted JSON string.
+fn string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::sample_report;
+    use super::*;
+
+    #[test]
+    fn test_string_escaping() {
+        assert_eq!(string("C:\\Users\\\"x\"\n\u{1}"), "\"C:\\\\Users\\\\\\\"x\\\"\\n\\u0001\"");
+    }
+
+    #[test]
+    fn test_render() {
+        let dir = tempfile::tempdir().unwrap();
+        let json = render(&sample_report(dir.path()).document());
+
+        assert!(json.starts_with("{\n  \"title\": \"Case <17>\",\n  \"generated\": \""));
+        assert!(json.contains("\"summary\": {\n    \"disks\": 1,"));
+        assert!(json.contains("\"model\": \"Evidence \\\"USB\\\" drive\""));
+        assert!(json.contains("\"size\": 2048"));
+        assert!(json.contains("\"unallocated\": 1048"));
+        assert!(json.contains("\"md5\": null"));
+        assert!(json.ends_with("]\n}\n"));
+    }
+}
@@ -0,0 +1,567 @@
+//! Inventory and forensic reports.
+//!
+//! A `Report` gathers a disk inventory and, optionally, file listings,
+//! identification results and extension mismatches. It is turned into a
+//! single document model made of a summary and tables, which is then
+//! rendered as a self-contained HTML page, a JSON document or one CSV file
+//! per table, so that every format carries the same data.
+
+pub(crate) mod csv;
+mod html;
+mod json;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, Utc};
+
+use crate::{Disk, FileEntry, FileSource, HashAlgorithm, KnownStatus};
+
+/// A value of a report cell, rendered according to the output format.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    /// No value
+    Empty,
+    /// Free text
+    Text(String),
+    /// A count or identifier
+    Integer(u64),
+    /// A size in bytes, shown in human-readable units in HTML
+    Size(u64),
+    /// A yes/no flag
+    Bool(bool),
+    /// A timestamp, in RFC 3339 form in JSON and CSV
+    Time(DateTime<Utc>),
+}
+
+impl Value {
+    fn text(text: impl ToString) -> Self {
+        Value::Text(text.to_string())
+    }
+
+    fn optional<T>(value: Option<T>, map: impl FnOnce(T) -> Value) -> Self {
+        value.map(map).unwrap_or(Value::Empty)
+    }
+}
+
+/// A column of a report table.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Column {
+    /// Key used in JSON objects
+    pub(crate) key: String,
+    /// Heading used in HTML and CSV
+    pub(crate) title: String,
+}
+
+impl Column {
+    fn new(key: &str, title: &str) -> Self {
+        Column {
+            key: key.to_string(),
+            title: title.to_string(),
+        }
+    }
+}
+
+/// A section of a report, rendered as an HTML table, a JSON array or a CSV file.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Table {
+    /// Identifier used as JSON key and CSV file name
+    pub(crate) id: &'static str,
+    /// Heading of the section
+    pub(crate) title: &'static str,
+    pub(crate) columns: Vec<Column>,
+    pub(crate) rows: Vec<Vec<Value>>,
+}
+
+/// The content of a report, shared by every output format.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Document {
+    pub(crate) title: String,
+    pub(crate) generated: DateTime<Local>,
+    /// Key figures, as (JSON key, label, value)
+    pub(crate) summary: Vec<(&'static str, &'static str, Value)>,
+    pub(crate) tables: Vec<Table>,
+}
+
+/// A report on a disk inventory and, optionally, the files found on it.
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::{find_mismatched_extensions, get_files, get_image_disk, identify_files, Report};
+///
+/// let disk = get_image_disk("D:/cases/2024-17/usb.E01").unwrap();
+/// let files = get_files("E:\\").unwrap();
+/// let mismatches = find_mismatched_extensions(&files);
+///
+/// let report = Report::new("Case 2024-17", vec![disk])
+///     .with_mismatches(mismatches)
+///     .with_identified(identify_files(files.clone()))
+///     .with_files(files);
+/// report.write_html("D:/cases/2024-17/report.html").unwrap();
+/// report.write_json("D:/cases/2024-17/report.json").unwrap();
+/// report.write_csv("D:/cases/2024-17/csv").unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Report {
+    title: String,
+    generated: DateTime<Local>,
+    disks: Vec<Disk>,
+    files: Option<Vec<FileEntry>>,
+    identified: Option<HashMap<String, Vec<FileEntry>>>,
+    mismatches: Option<Vec<(FileEntry, String)>>,
+}
+
+impl Report {
+    /// Creates a report on the given disks, generated now.
+    ///
+    /// # Arguments
+    /// * `title` - Title of the report, such as a case or ticket reference
+    /// * `disks` - The disk inventory, from `get_disks` or `get_image_disk`
+    pub fn new(title: &str, disks: Vec<Disk>) -> Self {
+        Report {
+            title: title.to_string(),
+            generated: Local::now(),
+            disks,
+            files: None,
+            identified: None,
+            mismatches: None,
+        }
+    }
+
+    /// Adds a file listing, such as the output of `get_files`.
+    pub fn with_files(mut self, files: Vec<FileEntry>) -> Self {
+        self.files = Some(files);
+        self
+    }
+
+    /// Adds file identification results, as returned by `identify_files`.
+    pub fn with_identified(mut self, identified: HashMap<String, Vec<FileEntry>>) -> Self {
+        self.identified = Some(identified);
+        self
+    }
+
+    /// Adds files whose extension does not match their content, as returned
+    /// by `find_mismatched_extensions`.
+    pub fn with_mismatches(mut self, mismatches: Vec<(FileEntry, String)>) -> Self {
+        self.mismatches = Some(mismatches);
+        self
+    }
+
+    /// Returns the title of the report
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Returns when the report was generated
+    pub fn generated(&self) -> DateTime<Local> {
+        self.generated
+    }
+
+    /// Renders the report as a self-contained HTML page
+    pub fn to_html(&self) -> String {
+        html::render(&self.document())
+    }
+
+    /// Renders the report as a JSON document
+    pub fn to_json(&self) -> String {
+        json::render(&self.document())
+    }
+
+    /// Renders each section of the report as CSV
+    ///
+    /// # Returns
+    /// A vector of (file name, CSV content) pairs, one per section
+    pub fn to_csv(&self) -> Vec<(String, String)> {
+        let document = self.document();
+        let mut files = vec![("summary.csv".to_string(), csv::render_summary(&document))];
+        files.extend(
+            document
+                .tables
+                .iter()
+                .map(|table| (format!("{}.csv", table.id), csv::render_table(table))),
+        );
+        files
+    }
+
+    /// Writes the HTML report to `path`
+    pub fn write_html<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_html())
+    }
+
+    /// Writes the JSON report to `path`
+    pub fn write_json<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+
+    /// Writes one CSV file per section into `directory`, creating it if needed
+    ///
+    /// # Returns
+    /// * `io::Result<Vec<PathBuf>>` - The paths of the written files
+    pub fn write_csv<P: AsRef<Path>>(&self, directory: P) -> io::Result<Vec<PathBuf>> {
+        fs::create_dir_all(&directory)?;
+        self.to_csv()
+            .into_iter()
+            .map(|(name, content)| {
+                let path = directory.as_ref().join(name);
+                fs::write(&path, content)?;
+                Ok(path)
+            })
+            .collect()
+    }
+
+    /// Builds the document model rendered by every format.
+    pub(crate) fn document(&self) -> Document {
+        let mut tables = vec![self.disk_table(), self.partition_table(), self.layout_table()];
+        if let Some(files) = &self.files {
+            tables.push(file_table(files));
+        }
+        if let Some(identified) = &self.identified {
+            tables.push(identified_table(identified));
+        }
+        if let Some(mismatches) = &self.mismatches {
+            tables.push(mismatch_table(mismatches));
+        }
+
+        Document {
+            title: self.title.clone(),
+            generated: self.generated,
+            summary: self.summary(),
+            tables,
+        }
+    }
+
+    fn summary(&self) -> Vec<(&'static str, &'static str, Value)> {
+        let mut summary = vec![
+            ("disks", "Disks", Value::Integer(self.disks.len() as u64)),
+            (
+                "total_capacity",
+                "Total capacity",
+                Value::Size(self.disks.iter().map(|disk| disk.size() as u64).sum()),
+            ),
+            (
+                "partitions",
+                "Partitions",
+                Value::Integer(self.disks.iter().map(|disk| disk.partitions().len() as u64).sum()),
+            ),
+            (
+                "unallocated",
+                "Unallocated space",
+                Value::Size(self.disks.iter().filter_map(Disk::unallocated_space).sum()),
+            ),
+        ];
+        if let Some(files) = &self.files {
+            summary.extend([
+                ("files", "Files", Value::Integer(files.len() as u64)),
+                ("files_size", "Total file size", Value::Size(files.iter().map(FileEntry::size).sum())),
+                (
+                    "deleted_files",
+                    "Deleted files",
+                    Value::Integer(files.iter().filter(|file| file.is_deleted()).count() as u64),
+                ),
+                (
+                    "known_bad_files",
+                    "Known-bad files",
+                    Value::Integer(
+                        files
+                            .iter()
+                            .filter(|file| file.known_status() == KnownStatus::KnownBad)
+                            .count() as u64,
+                    ),
+                ),
+            ]);
+        }
+        if let Some(identified) = &self.identified {
+            summary.push(("file_categories", "File categories", Value::Integer(identified.len() as u64)));
+        }
+        if let Some(mismatches) = &self.mismatches {
+            summary.push(("extension_mismatches", "Extension mismatches", Value::Integer(mismatches.len() as u64)));
+        }
+        summary
+    }
+
+    fn disk_table(&self) -> Table {
+        Table {
+            id: "disks",
+            title: "Disks",
+            columns: vec![
+                Column::new("device", "Device"),
+                Column::new("model", "Model"),
+                Column::new("serial", "Serial"),
+                Column::new("type", "Type"),
+                Column::new("removable", "Removable"),
+                Column::new("size", "Size"),
+                Column::new("partitions", "Partitions"),
+                Column::new("unallocated", "Unallocated"),
+            ],
+            rows: self
+                .disks
+                .iter()
+                .map(|disk| {
+                    vec![
+                        Value::text(disk.device_name()),
+                        Value::text(disk.model()),
+                        Value::text(disk.serial()),
+                        Value::text(disk.kind()),
+                        Value::Bool(disk.removable()),
+                        Value::Size(disk.size() as u64),
+                        Value::Integer(disk.partitions().len() as u64),
+                        Value::optional(disk.unallocated_space(), Value::Size),
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    fn partition_table(&self) -> Table {
+        Table {
+            id: "partitions",
+            title: "Partitions",
+            columns: vec![
+                Column::new("device", "Device"),
+                Column::new("id", "Id"),
+                Column::new("name", "Name"),
+                Column::new("file_system", "File system"),
+                Column::new("offset", "Offset"),
+                Column::new("total_space", "Total space"),
+                Column::new("available_space", "Available space"),
+            ],
+            rows: self
+                .disks
+                .iter()
+                .flat_map(|disk| {
+                    disk.partitions().iter().map(|partition| {
+                        vec![
+                            Value::text(disk.device_name()),
+                            Value::Integer(partition.id() as u64),
+                            Value::text(partition.name()),
+                            Value::text(partition.file_system()),
+                            Value::optional(partition.offset(), Value::Integer),
+                            Value::Size(partition.total_space()),
+                            Value::Size(partition.available_space()),
+                        ]
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    fn layout_table(&self) -> Table {
+        Table {
+            id: "layout",
+            title: "Disk layout",
+            columns: vec![
+                Column::new("device", "Device"),
+                Column::new("offset", "Offset"),
+                Column::new("length", "Length"),
+                Column::new("content", "Content"),
+            ],
+            rows: self
+                .disks
+                .iter()
+                .flat_map(|disk| {
+                    disk.extents().iter().map(|extent| {
+                        vec![
+                            Value::text(disk.device_name()),
+                            Value::Integer(extent.offset()),
+                            Value::Size(extent.length()),
+                            Value::text(extent.kind()),
+                        ]
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Lists files with their metadata, labels and computed digests.
+fn file_table(files: &[FileEntry]) -> Table {
+    // Only the digests computed for at least one file get a column
+    let algorithms: Vec<HashAlgorithm> = HashAlgorithm::ALL
+        .into_iter()
+        .filter(|algorithm| files.iter().any(|file| file.hashes().get(*algorithm).is_some()))
+        .collect();
+
+    let mut columns = vec![
+        Column::new("path", "Path"),
+        Column::new("name", "Name"),
+        Column::new("extension", "Extension"),
+        Column::new("size", "Size"),
+        Column::new("modified", "Modified"),
+        Column::new("accessed", "Accessed"),
+        Column::new("changed", "Changed"),
+        Column::new("created", "Created"),
+        Column::new("source", "Source"),
+        Column::new("deleted", "Deleted"),
+        Column::new("hash_set", "Hash set"),
+    ];
+    columns.extend(algorithms.iter().map(|algorithm| Column {
+        key: algorithm.to_string().replace('-', "").to_lowercase(),
+        title: algorithm.to_string(),
+    }));
+
+    let rows = files
+        .iter()
+        .map(|file| {
+            let mut row = vec![
+                Value::text(file.path().display()),
+                Value::text(file.name()),
+                Value::optional(file.extension(), Value::text),
+                Value::Size(file.size()),
+                Value::optional(file.times().modified(), Value::Time),
+                Value::optional(file.times().accessed(), Value::Time),
+                Value::optional(file.times().changed(), Value::Time),
+                Value::optional(file.times().born(), Value::Time),
+                match file.source() {
+                    FileSource::FileSystem => Value::text("File system"),
+                    FileSource::Image { image, .. } => Value::text(image.display()),
+                },
+                Value::optional(file.recoverability(), Value::text),
+                match file.known_status() {
+                    KnownStatus::Unknown => Value::Empty,
+                    status => Value::text(status),
+                },
+            ];
+            row.extend(
+                algorithms
+                    .iter()
+                    .map(|algorithm| Value::optional(file.hashes().get(*algorithm), Value::text)),
+            );
+            row
+        })
+        .collect();
+
+    Table {
+        id: "files",
+        title: "Files",
+        columns,
+        rows,
+    }
+}
+
+/// Lists identified files by category, categories sorted by name.
+fn identified_table(identified: &HashMap<String, Vec<FileEntry>>) -> Table {
+    let mut categories: Vec<(&String, &Vec<FileEntry>)> = identified.iter().collect();
+    categories.sort_by(|a, b| a.0.cmp(b.0));
+
+    Table {
+        id: "identified",
+        title: "Identified files",
+        columns: vec![
+            Column::new("category", "Category"),
+            Column::new("path", "Path"),
+            Column::new("size", "Size"),
+        ],
+        rows: categories
+            .into_iter()
+            .flat_map(|(category, files)| {
+                files.iter().map(move |file| {
+                    vec![Value::text(category), Value::text(file.path().display()), Value::Size(file.size())]
+                })
+            })
+            .collect(),
+    }
+}
+
+/// Lists files whose extension does not match their content.
+fn mismatch_table(mismatches: &[(FileEntry, String)]) -> Table {
+    Table {
+        id: "mismatches",
+        title: "Extension mismatches",
+        columns: vec![
+            Column::new("path", "Path"),
+            Column::new("extension", "Extension"),
+            Column::new("detected_type", "Detected type"),
+        ],
+        rows: mismatches
+            .iter()
+            .map(|(file, mime)| {
+                vec![
+                    Value::text(file.path().display()),
+                    Value::optional(file.extension(), Value::text),
+                    Value::text(mime),
+                ]
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{find_mismatched_extensions, get_files, identify_files, DiskExtent, DiskKind, ExtentKind, Partition};
+
+    /// A report with one disk, two files and one mismatch
+    pub(crate) fn sample_report(dir: &Path) -> Report {
+        fs::write(dir.join("photo.jpg"), [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, b'J', b'F', b'I', b'F']).unwrap();
+        fs::write(dir.join("notes <draft>, v2.txt"), [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]).unwrap();
+        let mut files = get_files(dir.to_str().unwrap()).unwrap();
+        files.sort_by(|a, b| a.name().cmp(b.name()));
+        files[0].hash(&[HashAlgorithm::Md5]).unwrap();
+
+        let partition = Partition::new(1, "C:".to_string(), crate::FileSystem::Unknown, 1000, 400).with_offset(512);
+        let disk = Disk::new(
+            "\\\\.\\PhysicalDrive0".to_string(),
+            "Evidence \"USB\" drive".to_string(),
+            "SN-1".to_string(),
+            DiskKind::SSD,
+            2048,
+            true,
+            vec![partition],
+        )
+        .with_extents(vec![DiskExtent::new(512, 1000, ExtentKind::Partition(Some(1)))]);
+
+        Report::new("Case <17>", vec![disk])
+            .with_mismatches(find_mismatched_extensions(&files))
+            .with_identified(identify_files(files.clone()))
+            .with_files(files)
+    }
+
+    #[test]
+    fn test_document() {
+        let dir = tempfile::tempdir().unwrap();
+        let document = sample_report(dir.path()).document();
+
+        let ids: Vec<&str> = document.tables.iter().map(|table| table.id).collect();
+        assert_eq!(ids, vec!["disks", "partitions", "layout", "files", "identified", "mismatches"]);
+        assert!(document.summary.contains(&("files", "Files", Value::Integer(2))));
+        assert!(document.summary.contains(&("extension_mismatches", "Extension mismatches", Value::Integer(1))));
+
+        let files = &document.tables[3];
+        assert_eq!(files.columns.last().unwrap().key, "md5");
+        assert_eq!(files.rows[0][1], Value::text("notes <draft>, v2.txt"));
+        assert_eq!(files.rows[1][11], Value::Empty);
+
+        let mismatches = &document.tables[5];
+        assert_eq!(mismatches.rows[0][2], Value::text("image/png"));
+    }
+
+    #[test]
+    fn test_report_without_files() {
+        let document = Report::new("Inventory", Vec::new()).document();
+        assert_eq!(document.tables.len(), 3);
+        assert!(document.tables.iter().all(|table| table.rows.is_empty()));
+        assert_eq!(document.summary.len(), 4);
+    }
+
+    #[test]
+    fn test_write_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let files_dir = dir.path().join("files");
+        fs::create_dir(&files_dir).unwrap();
+        let report = sample_report(&files_dir);
+
+        report.write_html(dir.path().join("report.html")).unwrap();
+        report.write_json(dir.path().join("report.json")).unwrap();
+        let written = report.write_csv(dir.path().join("csv")).unwrap();
+
+        assert!(fs::read_to_string(dir.path().join("report.html")).unwrap().starts_with("<!DOCTYPE html>"));
+        assert!(fs::read_to_string(dir.path().join("report.json")).unwrap().starts_with('{'));
+        assert_eq!(written.len(), 7);
+        assert_eq!(written[0], dir.path().join("csv").join("summary.csv"));
+        assert!(fs::read_to_string(&written[4]).unwrap().starts_with("Path,Name,Extension,Size,"));
+    }
+}
@@ -0,0 +1,1919 @@
+//! This module scans directory trees for files matching a set of options.
+//!
+//! `ScanOptions` gathers everything that controls a scan: how the tree is
+//! walked (depth, symbolic links, file system boundaries, skipped
+//! directories), which entries are kept (directories, hidden and system
+//! files, sizes, dates, name patterns) and how the result is ordered and
+//! truncated. The `get_files` family of functions are presets of it.
+//!
+//! Names can be matched with wildcard patterns, against the file name or
+//! the path relative to the root, or with regular expressions, against the
+//! file name or the full path. Ignore files in the `.gitignore` format can
+//! prune the walk as well.
+//!
+//! A `Monitor` set in the options reports the progress of a scan and can
+//! cancel it, in which case the entries found so far are returned.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use std::{fmt, io, thread};
+
+use chrono::{DateTime, Utc};
+use regex::{Regex, RegexBuilder};
+use walkdir::{DirEntry, WalkDir};
+
+use crate::file_extraction::file_identity;
+use crate::pattern::{Glob, IgnoreFile};
+use crate::progress::{Monitor, Tracker};
+use crate::{native_metadata, FileEntry, FileEntryError, FileIdentity, TimestampKind};
+
+/// Directories holding deleted files and restore points on Windows volumes
+const SYSTEM_DIRECTORIES: [&str; 2] = ["$Recycle.Bin", "System Volume Information"];
+
+/// Number of entries a streaming scan finds ahead of its consumer
+const STREAM_CAPACITY: usize = 1024;
+
+/// A timestamp kind with inclusive lower and upper bounds, either of which may be open
+type TimeRange = (TimestampKind, Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+/// Key used to sort the result of a scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// Full path, in byte order
+    Path,
+    /// File name, ignoring case
+    Name,
+    /// Size in bytes
+    Size,
+    /// Last modification time, unknown times first
+    Modified,
+}
+
+/// Which size of the files directory sizes add up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeKind {
+    /// Length of the content, as reported by `FileEntry::size`
+    Apparent,
+    /// Space allocated on disk, as reported by `FileEntry::allocated_size`
+    Allocated,
+}
+
+/// What a regular expression is matched against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchTarget {
+    /// The file name alone
+    Name,
+    /// The full path of the file, with the separators of the platform
+    Path,
+}
+
+/// A regular expression compiled for both case modes, so that the mode can
+/// be chosen after it is added.
+#[derive(Debug, Clone)]
+struct NameRegex {
+    target: MatchTarget,
+    sensitive: Regex,
+    insensitive: Regex,
+}
+
+impl NameRegex {
+    fn new(pattern: &str, target: MatchTarget) -> Result<Self, regex::Error> {
+        Ok(NameRegex {
+            target,
+            sensitive: Regex::new(pattern)?,
+            insensitive: RegexBuilder::new(pattern).case_insensitive(true).build()?,
+        })
+    }
+
+    fn is_match(&self, name: &str, path: &Path, case_insensitive: bool) -> bool {
+        let regex = if case_insensitive { &self.insensitive } else { &self.sensitive };
+        match self.target {
+            MatchTarget::Name => regex.is_match(name),
+            MatchTarget::Path => regex.is_match(&path.to_string_lossy()),
+        }
+    }
+}
+
+impl PartialEq for NameRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.target == other.target && self.sensitive.as_str() == other.sensitive.as_str()
+    }
+}
+
+/// Error type for scans that go on after errors
+#[derive(Debug)]
+pub enum ScanError {
+    /// Walkdir errors from listing directories and reading entries
+    WalkDir(walkdir::Error),
+    /// The metadata of a listed entry could not be read, for instance because it was deleted meanwhile
+    Metadata {
+        /// Path of the entry
+        path: PathBuf,
+        /// The error met
+        error: io::Error,
+    },
+    /// IO errors, such as failing to start the threads of the scan
+    Io(io::Error),
+}
+
+impl ScanError {
+    /// Returns the path of the directory or entry that could not be read, if known
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::WalkDir(e) => e.path(),
+            Self::Metadata { path, .. } => Some(path),
+            Self::Io(_) => None,
+        }
+    }
+
+    /// Returns the kind of the error, such as `PermissionDenied` for an access-denied folder.
+    ///
+    /// Loops of symbolic links are reported as `Other`.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            Self::WalkDir(e) => e.io_error().map_or(io::ErrorKind::Other, io::Error::kind),
+            Self::Metadata { error, .. } => error.kind(),
+            Self::Io(e) => e.kind(),
+        }
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WalkDir(e) => write!(f, "Directory traversal error: {}", e),
+            Self::Metadata { path, error } => write!(f, "Metadata error for {}: {}", path.display(), error),
+            Self::Io(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ScanError {}
+
+impl From<walkdir::Error> for ScanError {
+    fn from(err: walkdir::Error) -> Self {
+        Self::WalkDir(err)
+    }
+}
+
+impl From<io::Error> for ScanError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Options controlling which entries `scan_files` returns.
+///
+/// By default every regular file below the root is returned, hidden and
+/// system files included, in the order they are found by the threads
+/// walking the tree.
+///
+/// # Examples
+/// ```
+/// use chrono::{Duration, Utc};
+/// use win_disk_info::{ScanOptions, SortKey, TimestampKind};
+///
+/// // The 20 largest documents changed during the last month
+/// let options = ScanOptions::user_files()
+///     .with_include("**/*.{docx,pdf}")
+///     .with_time_range(TimestampKind::Modified, Some(Utc::now() - Duration::days(30)), None)
+///     .with_sort(SortKey::Size, true)
+///     .with_limit(20);
+/// assert_eq!(options.limit(), Some(20));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    min_depth: usize,
+    max_depth: Option<usize>,
+    follow_links: bool,
+    same_file_system: bool,
+    include_directories: bool,
+    include_hidden: bool,
+    include_system: bool,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    time_ranges: Vec<TimeRange>,
+    includes: Vec<String>,
+    excludes: Vec<String>,
+    include_regexes: Vec<NameRegex>,
+    exclude_regexes: Vec<NameRegex>,
+    ignore_files: Vec<String>,
+    skipped_directories: Vec<String>,
+    case_insensitive: bool,
+    sort: Option<(SortKey, bool)>,
+    limit: Option<usize>,
+    threads: usize,
+    monitor: Monitor,
+    size_kind: SizeKind,
+    native_metadata: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            min_depth: 0,
+            max_depth: None,
+            follow_links: false,
+            same_file_system: false,
+            include_directories: false,
+            include_hidden: true,
+            include_system: true,
+            min_size: None,
+            max_size: None,
+            time_ranges: Vec::new(),
+            includes: Vec::new(),
+            excludes: Vec::new(),
+            include_regexes: Vec::new(),
+            exclude_regexes: Vec::new(),
+            ignore_files: Vec::new(),
+            skipped_directories: Vec::new(),
+            case_insensitive: cfg!(windows),
+            sort: None,
+            limit: None,
+            threads: 0,
+            monitor: Monitor::new(),
+            size_kind: SizeKind::Apparent,
+            native_metadata: true,
+        }
+    }
+}
+
+impl PartialEq for ScanOptions {
+    /// Compares what the options walk and select, leaving out the monitor, which only observes a scan.
+    fn eq(&self, other: &Self) -> bool {
+        let ScanOptions {
+            min_depth,
+            max_depth,
+            follow_links,
+            same_file_system,
+            include_directories,
+            include_hidden,
+            include_system,
+            min_size,
+            max_size,
+            time_ranges,
+            includes,
+            excludes,
+            include_regexes,
+            exclude_regexes,
+            ignore_files,
+            skipped_directories,
+            case_insensitive,
+            sort,
+            limit,
+            threads,
+            monitor: _,
+            size_kind,
+            native_metadata,
+        } = self;
+        *min_depth == other.min_depth
+            && *max_depth == other.max_depth
+            && *follow_links == other.follow_links
+            && *same_file_system == other.same_file_system
+            && *include_directories == other.include_directories
+            && *include_hidden == other.include_hidden
+            && *include_system == other.include_system
+            && *min_size == other.min_size
+            && *max_size == other.max_size
+            && *time_ranges == other.time_ranges
+            && *includes == other.includes
+            && *excludes == other.excludes
+            && *include_regexes == other.include_regexes
+            && *exclude_regexes == other.exclude_regexes
+            && *ignore_files == other.ignore_files
+            && *skipped_directories == other.skipped_directories
+            && *case_insensitive == other.case_insensitive
+            && *sort == other.sort
+            && *limit == other.limit
+            && *threads == other.threads
+            && *size_kind == other.size_kind
+            && *native_metadata == other.native_metadata
+    }
+}
+
+impl ScanOptions {
+    /// Creates options returning every regular file, like `get_files`.
+    ///
+    /// Name patterns ignore case on Windows and are case-sensitive elsewhere.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Preset for the documents of users.
+    ///
+    /// Hidden and system files are left out, and so are the recycle bin and
+    /// the `System Volume Information` directory.
+    pub fn user_files() -> Self {
+        Self::default()
+            .with_hidden(false)
+            .with_system(false)
+            .with_system_directories_skipped()
+    }
+
+    /// Preset for an inventory of a whole volume.
+    ///
+    /// Hidden and system files are kept, but the scan stays on the file
+    /// system of the root and skips the recycle bin and the
+    /// `System Volume Information` directory.
+    pub fn volume() -> Self {
+        Self::default()
+            .with_same_file_system(true)
+            .with_system_directories_skipped()
+    }
+
+    fn with_system_directories_skipped(self) -> Self {
+        SYSTEM_DIRECTORIES
+            .iter()
+            .fold(self, |options, directory| options.with_skipped_directory(directory))
+    }
+
+    /// Ignores entries less than `depth` levels below the root, which is at depth 0.
+    pub fn with_min_depth(mut self, depth: usize) -> Self {
+        self.min_depth = depth;
+        self
+    }
+
+    /// Does not descend more than `depth` levels below the root.
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Follows symbolic links to directories and files.
+    pub fn with_follow_links(mut self, follow_links: bool) -> Self {
+        self.follow_links = follow_links;
+        self
+    }
+
+    /// Does not cross into other file systems mounted below the root.
+    pub fn with_same_file_system(mut self, same_file_system: bool) -> Self {
+        self.same_file_system = same_file_system;
+        self
+    }
+
+    /// Returns directories as well as files, except the root itself.
+    pub fn with_directories(mut self, include_directories: bool) -> Self {
+        self.include_directories = include_directories;
+        self
+    }
+
+    /// Keeps or leaves out hidden files; hidden directories are not entered when left out.
+    pub fn with_hidden(mut self, include_hidden: bool) -> Self {
+        self.include_hidden = include_hidden;
+        self
+    }
+
+    /// Keeps or leaves out files with the system attribute; such directories are not entered when left out.
+    pub fn with_system(mut self, include_system: bool) -> Self {
+        self.include_system = include_system;
+        self
+    }
+
+    /// Leaves out files smaller than `size` bytes.
+    pub fn with_min_size(mut self, size: u64) -> Self {
+        self.min_size = Some(size);
+        self
+    }
+
+    /// Leaves out files larger than `size` bytes.
+    pub fn with_max_size(mut self, size: u64) -> Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    /// Keeps files whose timestamp of the given kind is within a range.
+    ///
+    /// Both bounds are inclusive and either can be left open. Files whose
+    /// timestamp of that kind is unknown are left out.
+    pub fn with_time_range(
+        mut self,
+        kind: TimestampKind,
+        after: Option<DateTime<Utc>>,
+        before: Option<DateTime<Utc>>,
+    ) -> Self {
+        self.time_ranges.push((kind, after, before));
+        self
+    }
+
+    /// Keeps files matching a wildcard pattern such as `*.pst` or `**/*.{jpg,png}`.
+    ///
+    /// Patterns containing a `/` (or, on Windows, a `\\`) are matched against
+    /// the path relative to the root, with `/` separators; other patterns
+    /// against the file name. When several patterns or regular expressions
+    /// are given, a file matching any of them is kept.
+    pub fn with_include(mut self, pattern: &str) -> Self {
+        self.includes.push(pattern.to_string());
+        self
+    }
+
+    /// Leaves out files and directories matching a wildcard pattern, as `with_include` matches it.
+    pub fn with_exclude(mut self, pattern: &str) -> Self {
+        self.excludes.push(pattern.to_string());
+        self
+    }
+
+    /// Keeps files whose name or full path matches a regular expression.
+    ///
+    /// The expression is searched anywhere in the text unless anchored with
+    /// `^` and `$`.
+    ///
+    /// # Returns
+    /// * `Ok(ScanOptions)` - The options with the expression added
+    /// * `Err(regex::Error)` - If the expression is invalid
+    pub fn with_regex(mut self, pattern: &str, target: MatchTarget) -> Result<Self, regex::Error> {
+        self.include_regexes.push(NameRegex::new(pattern, target)?);
+        Ok(self)
+    }
+
+    /// Leaves out files and directories whose name or full path matches a regular expression.
+    ///
+    /// # Returns
+    /// * `Ok(ScanOptions)` - The options with the expression added
+    /// * `Err(regex::Error)` - If the expression is invalid
+    pub fn with_exclude_regex(mut self, pattern: &str, target: MatchTarget) -> Result<Self, regex::Error> {
+        self.exclude_regexes.push(NameRegex::new(pattern, target)?);
+        Ok(self)
+    }
+
+    /// Honours ignore files with this name, such as `.gitignore`, found in the scanned directories.
+    ///
+    /// Each file applies to its directory and everything below it, with the
+    /// syntax of `.gitignore`: `#` comments, `!` negations, a trailing `/`
+    /// for directories only and a leading or inner `/` to anchor a pattern
+    /// to the directory of the file. Rules of deeper files take precedence.
+    pub fn with_ignore_file(mut self, name: &str) -> Self {
+        self.ignore_files.push(name.to_string());
+        self
+    }
+
+    /// Does not enter directories with this name, compared ignoring case.
+    pub fn with_skipped_directory(mut self, name: &str) -> Self {
+        self.skipped_directories.push(name.to_string());
+        self
+    }
+
+    /// Matches wildcard patterns, regular expressions and ignore files ignoring case, as Windows does.
+    pub fn with_case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Sorts the result by `key`, largest or latest first when `descending`.
+    pub fn with_sort(mut self, key: SortKey, descending: bool) -> Self {
+        self.sort = Some((key, descending));
+        self
+    }
+
+    /// Returns at most `limit` entries, the first ones of the sort order when one is set.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Walks the tree on `threads` threads, or on as many as the available parallelism when 0.
+    ///
+    /// Several threads list directories concurrently, which pays off on
+    /// trees with many directories and on network shares, where most of the
+    /// time is spent waiting for listings. With one thread the tree is walked
+    /// on the calling thread. Either way, entries come in no particular order
+    /// unless a sort key is set, for instance `SortKey::Path`.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Reports the progress of the scan to `monitor` and stops it when the monitor is cancelled.
+    ///
+    /// Progress counts the files walked, whether they match or not, and the
+    /// bytes of the matching ones; the totals are the estimates of the
+    /// monitor. A cancelled scan returns the entries found until then.
+    pub fn with_monitor(mut self, monitor: Monitor) -> Self {
+        self.monitor = monitor;
+        self
+    }
+
+    /// Sets which size of the files directory sizes add up, the apparent size by default.
+    ///
+    /// Allocated sizes account for cluster slack and for compressed and
+    /// sparse files, so that their totals follow the free space of the
+    /// volume. Files whose allocated size is unknown count with their
+    /// apparent size. Size filters always apply to the apparent size.
+    pub fn with_size_kind(mut self, size_kind: SizeKind) -> Self {
+        self.size_kind = size_kind;
+        self
+    }
+
+    /// Sets whether the file ID, link count, change time and allocated size of each entry are read, which they are by default.
+    ///
+    /// On Windows they take opening a handle to every file, a query of its
+    /// compressed size and a lookup of its volume, which dominate scans of
+    /// large trees. Without them, entries only carry what the directory
+    /// listing gives, hard links are counted once per link and allocated
+    /// sizes fall back to apparent sizes. On Unix they come with the rest of
+    /// the metadata and are always read.
+    pub fn with_native_metadata(mut self, native_metadata: bool) -> Self {
+        self.native_metadata = native_metadata;
+        self
+    }
+
+    /// Returns the minimum depth of the returned entries
+    pub fn min_depth(&self) -> usize {
+        self.min_depth
+    }
+
+    /// Returns the maximum depth of the walk, if limited
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    /// Returns true if symbolic links are followed
+    pub fn follow_links(&self) -> bool {
+        self.follow_links
+    }
+
+    /// Returns true if the scan stays on the file system of the root
+    pub fn same_file_system(&self) -> bool {
+        self.same_file_system
+    }
+
+    /// Returns true if directories are returned
+    pub fn include_directories(&self) -> bool {
+        self.include_directories
+    }
+
+    /// Returns true if hidden files are returned
+    pub fn include_hidden(&self) -> bool {
+        self.include_hidden
+    }
+
+    /// Returns true if system files are returned
+    pub fn include_system(&self) -> bool {
+        self.include_system
+    }
+
+    /// Returns the wildcard patterns of the files to keep
+    pub fn includes(&self) -> &[String] {
+        &self.includes
+    }
+
+    /// Returns the wildcard patterns of the files and directories to leave out
+    pub fn excludes(&self) -> &[String] {
+        &self.excludes
+    }
+
+    /// Returns the names of the ignore files that are honoured
+    pub fn ignore_files(&self) -> &[String] {
+        &self.ignore_files
+    }
+
+    /// Returns the names of the directories that are not entered
+    pub fn skipped_directories(&self) -> &[String] {
+        &self.skipped_directories
+    }
+
+    /// Returns true if patterns ignore case
+    pub fn case_insensitive(&self) -> bool {
+        self.case_insensitive
+    }
+
+    /// Returns the sort key and direction, if sorted
+    pub fn sort(&self) -> Option<(SortKey, bool)> {
+        self.sort
+    }
+
+    /// Returns the maximum number of returned entries, if limited
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Returns the number of threads walking the tree, 0 for the available parallelism
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Returns which size of the files directory sizes add up
+    pub fn size_kind(&self) -> SizeKind {
+        self.size_kind
+    }
+
+    /// Returns true if the native details of each entry are read
+    pub fn native_metadata(&self) -> bool {
+        self.native_metadata
+    }
+
+    /// Returns the progress and cancellation settings of the scan
+    pub fn monitor(&self) -> &Monitor {
+        &self.monitor
+    }
+
+    /// Returns the number of threads to start, resolving 0 to the available parallelism.
+    fn resolved_threads(&self) -> usize {
+        match self.threads {
+            0 => thread::available_parallelism().map(usize::from).unwrap_or(1),
+            threads => threads,
+        }
+    }
+
+    /// Returns true if the attributes, size and times of a scanned entry are wanted.
+    fn keeps(&self, file: &FileEntry) -> bool {
+        let attributes = file.attributes();
+        if (attributes.is_hidden() && !self.include_hidden) || (attributes.is_system() && !self.include_system) {
+            return false;
+        }
+        if self.min_size.is_some_and(|min| file.size() < min) || self.max_size.is_some_and(|max| file.size() > max) {
+            return false;
+        }
+        self.time_ranges.iter().all(|(kind, after, before)| {
+            file.times().get(*kind).is_some_and(|time| {
+                after.is_none_or(|after| time >= after) && before.is_none_or(|before| time <= before)
+            })
+        })
+    }
+
+    /// Compares two entries by the sort key, if any.
+    fn compare(&self, a: &FileEntry, b: &FileEntry) -> Ordering {
+        let Some((key, descending)) = self.sort else {
+            return Ordering::Equal;
+        };
+        let ordering = match key {
+            SortKey::Path => a.path().cmp(b.path()),
+            SortKey::Name => a.name().to_lowercase().cmp(&b.name().to_lowercase()),
+            SortKey::Size => a.size().cmp(&b.size()),
+            SortKey::Modified => a.modified().cmp(&b.modified()),
+        };
+        // Ties keep a stable order by path so that results are reproducible
+        let ordering = if descending { ordering.reverse() } else { ordering };
+        ordering.then_with(|| a.path().cmp(b.path()))
+    }
+}
+
+/// Ignore files of a directory and its ancestors, with the depth of the directory holding each
+type Ignores = Arc<Vec<(usize, IgnoreFile)>>;
+
+/// The name filters of a scan, with patterns compiled for the case mode.
+struct Matcher<'a> {
+    options: &'a ScanOptions,
+    root: &'a Path,
+    includes: Vec<Glob>,
+    excludes: Vec<Glob>,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(root: &'a Path, options: &'a ScanOptions) -> Self {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| Glob::native(pattern, options.case_insensitive))
+                .collect()
+        };
+        Matcher {
+            options,
+            root,
+            includes: compile(&options.includes),
+            excludes: compile(&options.excludes),
+        }
+    }
+
+    /// Returns the path of an entry relative to the root, separated by `/`.
+    fn relative(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(self.root).unwrap_or(path);
+        let components: Vec<_> = relative.iter().map(|component| component.to_string_lossy()).collect();
+        components.join("/")
+    }
+
+    fn glob_matches(glob: &Glob, name: &str, relative: &str) -> bool {
+        glob.is_match(if glob.has_separator() { relative } else { name })
+    }
+
+    /// Returns the ignore files applying below a directory: those of its
+    /// ancestors, followed by its own.
+    fn ignores_below(&self, directory: &Path, depth: usize, inherited: &Ignores) -> Ignores {
+        let own: Vec<IgnoreFile> = self
+            .options
+            .ignore_files
+            .iter()
+            .filter_map(|name| IgnoreFile::read(directory, name, self.options.case_insensitive))
+            .collect();
+        if own.is_empty() {
+            return Arc::clone(inherited);
+        }
+        let mut ignores = inherited.as_ref().clone();
+        ignores.extend(own.into_iter().map(|ignore| (depth, ignore)));
+        Arc::new(ignores)
+    }
+
+    /// Returns true if a walked entry is neither entered nor returned.
+    fn prunes(&self, entry: &DirEntry, depth: usize, ignores: &Ignores) -> bool {
+        let options = self.options;
+        let is_dir = entry.file_type().is_dir();
+        let name = entry.file_name().to_string_lossy();
+        if is_dir
+            && options
+                .skipped_directories
+                .iter()
+                .any(|skipped| skipped.eq_ignore_ascii_case(&name))
+        {
+            return true;
+        }
+
+        let relative = self.relative(entry.path());
+        if self.excludes.iter().any(|glob| Self::glob_matches(glob, &name, &relative))
+            || options
+                .exclude_regexes
+                .iter()
+                .any(|regex| regex.is_match(&name, entry.path(), options.case_insensitive))
+        {
+            return true;
+        }
+
+        if !ignores.is_empty() {
+            let components: Vec<&str> = relative.split('/').collect();
+            // Deeper files come last, and their decisions override those of their ancestors
+            let ignored = ignores
+                .iter()
+                .rev()
+                .filter(|(directory_depth, _)| *directory_depth < depth)
+                .find_map(|(directory_depth, ignore)| {
+                    ignore.decision(&components[*directory_depth..].join("/"), is_dir)
+                })
+                .unwrap_or(false);
+            if ignored {
+                return true;
+            }
+        }
+
+        // Directories are pruned early; files are checked on their FileEntry
+        is_dir && (!options.include_hidden || !options.include_system) && {
+            let (hidden, system) = walked_attributes(entry);
+            (hidden && !options.include_hidden) || (system && !options.include_system)
+        }
+    }
+
+    /// Returns true if a file matches the include patterns, if any.
+    fn includes(&self, name: &str, path: &Path) -> bool {
+        let options = self.options;
+        if self.includes.is_empty() && options.include_regexes.is_empty() {
+            return true;
+        }
+        let relative = self.relative(path);
+        self.includes.iter().any(|glob| Self::glob_matches(glob, name, &relative))
+            || options
+                .include_regexes
+                .iter()
+                .any(|regex| regex.is_match(name, path, options.case_insensitive))
+    }
+
+    /// Returns the entry of a walked file or directory if it is part of the result.
+    ///
+    /// Entries whose metadata cannot be read are reported as errors rather
+    /// than returned with made-up values.
+    fn scanned(&self, entry: &DirEntry, depth: usize) -> Result<Option<FileEntry>, ScanError> {
+        let wanted = entry.file_type().is_file()
+            || (self.options.include_directories && entry.file_type().is_dir() && depth > 0);
+        if !wanted || !self.includes(&entry.file_name().to_string_lossy(), entry.path()) {
+            return Ok(None);
+        }
+        let file = FileEntry::from_walked(entry, self.options.native_metadata).map_err(|error| match error {
+            FileEntryError::MetadataError(error) => ScanError::WalkDir(error),
+            FileEntryError::TimeError(error) => ScanError::Metadata {
+                path: entry.path().to_path_buf(),
+                error,
+            },
+        })?;
+        Ok(self.options.keeps(&file).then_some(file))
+    }
+
+    /// Returns the entry of a walked file or directory like `scanned`, counting walked files in the progress.
+    fn tracked(&self, entry: &DirEntry, depth: usize, tracker: &Tracker) -> Result<Option<FileEntry>, ScanError> {
+        let scanned = self.scanned(entry, depth);
+        if entry.file_type().is_file() {
+            let bytes = match &scanned {
+                Ok(Some(file)) => file.size(),
+                _ => 0,
+            };
+            tracker.advance(entry.path(), 1, bytes);
+        }
+        scanned
+    }
+}
+
+/// The sizes and identity of a walked file, for adding up directory sizes.
+struct FileSize {
+    size: u64,
+    allocated_size: Option<u64>,
+    links: u64,
+    /// Volume and file ID, shared by the hard links to the file
+    identity: Option<FileIdentity>,
+}
+
+impl FileSize {
+    /// Reads the sizes of a walked file from its metadata.
+    fn read(entry: &DirEntry, metadata: &std::fs::Metadata, detailed: bool) -> Self {
+        let native = native_metadata(entry.path(), &entry.file_name().to_string_lossy(), metadata, detailed);
+        FileSize {
+            size: metadata.len(),
+            allocated_size: native.allocated_size,
+            links: native.links.unwrap_or(1),
+            identity: native.volume_id.zip(native.file_id).map(|(volume, index)| FileIdentity::new(volume, index)),
+        }
+    }
+}
+
+impl From<&FileEntry> for FileSize {
+    fn from(file: &FileEntry) -> Self {
+        FileSize {
+            size: file.size(),
+            allocated_size: file.allocated_size(),
+            links: file.hard_links().unwrap_or(1),
+            identity: file.identity(),
+        }
+    }
+}
+
+/// A directory waiting to be listed by the traversal.
+struct PendingDirectory {
+    path: PathBuf,
+    depth: usize,
+    ignores: Ignores,
+    /// Identities of the directory and its ancestors, to detect loops when links are followed
+    ancestors: Vec<FileIdentity>,
+    /// Device or volume serial number of the root, set when the walk stays on its file system
+    device: Option<u64>,
+}
+
+/// Directories shared by the traversal threads.
+struct WalkQueue {
+    pending: Vec<PendingDirectory>,
+    /// Paths of the directories being listed, which may add more
+    active: Vec<PathBuf>,
+    /// Set when a visit asks to stop the walk
+    stopped: bool,
+}
+
+/// Function saving the directories a walk has left to list
+pub(crate) type SaveDirectories<'a> = &'a (dyn Fn(Vec<PathBuf>) + Sync);
+
+/// Periodic saving of the directories a walk has left to list, so that an interrupted walk can be resumed.
+struct WalkCheckpoint<'a> {
+    interval: Duration,
+    /// Time of the last save, locked while the directories are gathered
+    saved: Mutex<Instant>,
+    save: SaveDirectories<'a>,
+}
+
+impl WalkCheckpoint<'_> {
+    /// Returns the directories to save if the interval has elapsed since the last save.
+    fn due(&self, queue: &WalkQueue) -> Option<Vec<PathBuf>> {
+        let mut saved = self.saved.try_lock().ok()?;
+        if saved.elapsed() < self.interval {
+            return None;
+        }
+        *saved = Instant::now();
+        Some(remaining_directories(queue))
+    }
+}
+
+/// Returns the directories a walk has not listed yet, or not to the end.
+fn remaining_directories(queue: &WalkQueue) -> Vec<PathBuf> {
+    queue
+        .pending
+        .iter()
+        .map(|directory| directory.path.clone())
+        .chain(queue.active.iter().cloned())
+        .collect()
+}
+
+/// An entry found by the traversal, with its depth below the root, or the error met instead.
+type Walked = Result<(DirEntry, usize), walkdir::Error>;
+
+/// What one traversal thread gathered for a scan that stops at the first error.
+#[derive(Default)]
+struct Collected<T> {
+    items: T,
+    error: Option<walkdir::Error>,
+}
+
+/// Walks a directory tree on the threads set in the options.
+///
+/// Each thread repeatedly takes a directory from a shared queue, lists it
+/// and queues its subdirectories, so that the tree is spread over the
+/// threads whatever its shape. Every entry that is not pruned and lies
+/// within the depth limits, and every error, is given to `visit`, on the
+/// thread that found it and with the state that thread created with
+/// `init`. A visit returning `ControlFlow::Break`, or the cancellation of
+/// the monitor of the options, stops all the threads.
+/// The states of the threads are returned once the walk is over.
+///
+/// Directories are listed with `walkdir`, one level at a time, so that
+/// errors are those of a sequential walk.
+fn walk<S, I, V>(path: &Path, options: &ScanOptions, matcher: &Matcher, init: I, visit: V) -> Vec<S>
+where
+    S: Send,
+    I: Fn() -> S + Sync,
+    V: Fn(&mut S, Walked) -> ControlFlow<()> + Sync,
+{
+    let mut state = init();
+    let root = match WalkDir::new(path).max_depth(0).follow_links(options.follow_links).into_iter().next() {
+        Some(Ok(root)) => root,
+        Some(Err(error)) => {
+            let _ = visit(&mut state, Err(error));
+            return vec![state];
+        }
+        None => return vec![state],
+    };
+    let root_is_dir = root.file_type().is_dir();
+    let ignores = if root_is_dir {
+        matcher.ignores_below(root.path(), 0, &Arc::new(Vec::new()))
+    } else {
+        Arc::new(Vec::new())
+    };
+    let root_path = root.path().to_path_buf();
+    if options.min_depth == 0 && visit(&mut state, Ok((root, 0))).is_break() {
+        return vec![state];
+    }
+    if !root_is_dir || options.max_depth == Some(0) {
+        return vec![state];
+    }
+
+    let ancestors = if options.follow_links { file_identity(&root_path).into_iter().collect() } else { Vec::new() };
+    let device = root_device(&root_path, options);
+    let first = PendingDirectory {
+        path: root_path,
+        depth: 0,
+        ignores,
+        ancestors,
+        device,
+    };
+    walk_directories(vec![first], options, matcher, None, state, init, visit)
+}
+
+/// Walks the tree below `directory`, a directory inside the tree of `root`, as `walk` would walk that part of the tree.
+///
+/// Depth limits, relative patterns and ignore files keep applying as if the
+/// walk had started at `root`, so that a subtree can be walked again on its
+/// own. The directory itself is not visited.
+fn walk_below<S, I, V>(root: &Path, directory: &Path, options: &ScanOptions, matcher: &Matcher, init: I, visit: V) -> Vec<S>
+where
+    S: Send,
+    I: Fn() -> S + Sync,
+    V: Fn(&mut S, Walked) -> ControlFlow<()> + Sync,
+{
+    let state = init();
+    match pending_below(root, directory, options, matcher) {
+        Some(first) => walk_directories(vec![first], options, matcher, None, state, init, visit),
+        None => vec![state],
+    }
+}
+
+/// Prepares the listing of `directory`, a directory inside the tree of `root`, or returns `None` if it lies at the depth limit.
+fn pending_below(root: &Path, directory: &Path, options: &ScanOptions, matcher: &Matcher) -> Option<PendingDirectory> {
+    let relative = directory.strip_prefix(root).unwrap_or(Path::new(""));
+    let depth = relative.components().count();
+    if options.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+        return None;
+    }
+
+    // Ignore files and identities are gathered down from the root as the walk would have
+    let mut path = root.to_path_buf();
+    let mut ignores = matcher.ignores_below(&path, 0, &Arc::new(Vec::new()));
+    let mut ancestors: Vec<FileIdentity> = Vec::new();
+    if options.follow_links {
+        ancestors.extend(file_identity(&path).ok());
+    }
+    for (index, component) in relative.components().enumerate() {
+        path.push(component);
+        ignores = matcher.ignores_below(&path, index + 1, &ignores);
+        if options.follow_links {
+            ancestors.extend(file_identity(&path).ok());
+        }
+    }
+    Some(PendingDirectory {
+        path,
+        depth,
+        ignores,
+        ancestors,
+        device: root_device(root, options),
+    })
+}
+
+/// Returns the device or volume serial number of the root if the walk stays on its file system.
+fn root_device(root: &Path, options: &ScanOptions) -> Option<u64> {
+    if options.same_file_system {
+        file_identity(root).ok().map(|identity| identity.volume())
+    } else {
+        None
+    }
+}
+
+/// Lists the directories of `first` and every directory found below them, on the threads set in the options.
+///
+/// `state` is the state of the calling thread; the other threads create theirs with `init`.
+/// With a checkpoint, the directories left to list are saved as the walk
+/// goes, and once more when it is cancelled. A directory whose listing is
+/// cut short by the cancellation counts as left to list.
+fn walk_directories<S, I, V>(
+    first: Vec<PendingDirectory>,
+    options: &ScanOptions,
+    matcher: &Matcher,
+    checkpoint: Option<&WalkCheckpoint>,
+    mut state: S,
+    init: I,
+    visit: V,
+) -> Vec<S>
+where
+    S: Send,
+    I: Fn() -> S + Sync,
+    V: Fn(&mut S, Walked) -> ControlFlow<()> + Sync,
+{
+    let queue = Mutex::new(WalkQueue {
+        pending: first.into_iter().rev().collect(),
+        active: Vec::new(),
+        stopped: false,
+    });
+    let changed = Condvar::new();
+
+    let work = |state: &mut S| loop {
+        let directory = {
+            let mut queue = queue.lock().unwrap_or_else(|e| e.into_inner());
+            loop {
+                if queue.stopped || options.monitor.is_cancelled() {
+                    return;
+                }
+                if let Some(directory) = queue.pending.pop() {
+                    queue.active.push(directory.path.clone());
+                    break directory;
+                }
+                if queue.active.is_empty() {
+                    return;
+                }
+                queue = changed.wait(queue).unwrap_or_else(|e| e.into_inner());
+            }
+        };
+
+        let depth = directory.depth + 1;
+        let mut subdirectories = Vec::new();
+        let mut flow = ControlFlow::Continue(());
+        let mut interrupted = false;
+        let entries = WalkDir::new(&directory.path)
+            .min_depth(1)
+            .max_depth(1)
+            .follow_links(options.follow_links);
+        for entry in entries {
+            if options.monitor.is_cancelled() {
+                interrupted = true;
+                break;
+            }
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(error) => {
+                    flow = visit(state, Err(error));
+                    if flow.is_break() {
+                        break;
+                    }
+                    continue;
+                }
+            };
+            if matcher.prunes(&entry, depth, &directory.ignores) {
+                continue;
+            }
+            if entry.file_type().is_dir() && options.max_depth.is_none_or(|max_depth| depth < max_depth) {
+                let identity = if options.follow_links || directory.device.is_some() {
+                    file_identity(entry.path()).ok()
+                } else {
+                    None
+                };
+                // A mount point, or a link back to an ancestor, is listed but not entered
+                let mounted = directory.device.zip(identity).is_some_and(|(device, identity)| identity.volume() != device);
+                if !mounted && !identity.is_some_and(|identity| directory.ancestors.contains(&identity)) {
+                    let identity = identity.filter(|_| options.follow_links);
+                    subdirectories.push(PendingDirectory {
+                        path: entry.path().to_path_buf(),
+                        depth,
+                        ignores: matcher.ignores_below(entry.path(), depth, &directory.ignores),
+                        ancestors: directory.ancestors.iter().copied().chain(identity).collect(),
+                        device: directory.device,
+                    });
+                }
+            }
+            if depth >= options.min_depth {
+                flow = visit(state, Ok((entry, depth)));
+                if flow.is_break() {
+                    break;
+                }
+            }
+        }
+
+        let mut queue = queue.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(position) = queue.active.iter().position(|path| *path == directory.path) {
+            queue.active.swap_remove(position);
+        }
+        queue.stopped |= flow.is_break();
+        if interrupted {
+            // Its subdirectories are found again when it is listed anew
+            queue.pending.push(directory);
+        } else {
+            // Listed in reverse so that the first subdirectory is the next one taken
+            queue.pending.extend(subdirectories.into_iter().rev());
+        }
+        changed.notify_all();
+        let remaining = checkpoint.filter(|_| !queue.stopped && !interrupted).and_then(|checkpoint| checkpoint.due(&queue));
+        drop(queue);
+        // The entries of the directories listed so far were visited before they were gathered
+        if let (Some(checkpoint), Some(remaining)) = (checkpoint, remaining) {
+            (checkpoint.save)(remaining);
+        }
+    };
+
+    let threads = options.resolved_threads();
+    let states = if threads == 1 {
+        work(&mut state);
+        vec![state]
+    } else {
+        thread::scope(|scope| {
+            let workers: Vec<_> = (1..threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut state = init();
+                        work(&mut state);
+                        state
+                    })
+                })
+                .collect();
+            work(&mut state);
+            let mut states = vec![state];
+            for worker in workers {
+                match worker.join() {
+                    Ok(state) => states.push(state),
+                    Err(panic) => std::panic::resume_unwind(panic),
+                }
+            }
+            states
+        })
+    };
+    if let Some(checkpoint) = checkpoint.filter(|_| options.monitor.is_cancelled()) {
+        let queue = queue.lock().unwrap_or_else(|e| e.into_inner());
+        (checkpoint.save)(remaining_directories(&queue));
+    }
+    states
+}
+
+/// Returns whether a walked directory is hidden and whether it has the system attribute.
+#[cfg(windows)]
+fn walked_attributes(entry: &DirEntry) -> (bool, bool) {
+    use std::os::windows::fs::MetadataExt;
+
+    // The metadata of walked entries comes with the directory listing on Windows
+    let attributes = entry.metadata().map(|metadata| metadata.file_attributes()).unwrap_or(0);
+    (attributes & 0x2 != 0, attributes & 0x4 != 0)
+}
+
+/// Returns whether a walked directory is hidden and whether it has the system attribute.
+#[cfg(not(windows))]
+fn walked_attributes(entry: &DirEntry) -> (bool, bool) {
+    (entry.file_name().to_string_lossy().starts_with('.'), false)
+}
+
+/// Scans a directory tree for the entries matching a set of options
+///
+/// The tree is walked once, on several threads unless the options say
+/// otherwise, skipping the directories the options exclude. The remaining
+/// entries are filtered, then sorted and truncated as requested; without a
+/// sort key they come in no particular order.
+///
+/// The first directory that cannot be listed stops the scan, while entries
+/// whose metadata cannot be read are left out. Use `scan_files_with_errors`
+/// to scan the rest of the tree and get every error. A scan cancelled
+/// through the monitor of the options returns the entries found so far.
+///
+/// # Arguments
+/// * `path` - A string path to the directory to scan
+/// * `options` - What to walk and which entries to return
+///
+/// # Returns
+/// * `Ok(Vec<FileEntry>)` - The matching entries
+/// * `Err(walkdir::Error)` - If there's an error during directory traversal
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::{scan_files, ScanOptions};
+///
+/// let options = ScanOptions::user_files().with_include("*.pst").with_min_size(1024 * 1024);
+/// for file in scan_files("C:\\Users", &options).unwrap() {
+///     println!("{} ({} bytes)", file.path().display(), file.size());
+/// }
+/// ```
+pub fn scan_files(path: &str, options: &ScanOptions) -> Result<Vec<FileEntry>, walkdir::Error> {
+    let root = Path::new(path);
+    let matcher = Matcher::new(root, options);
+    let tracker = Tracker::new(&options.monitor);
+    let found = walk(root, options, &matcher, Collected::default, |found: &mut Collected<Vec<FileEntry>>, walked| match walked {
+        Ok((entry, depth)) => {
+            // Entries that vanish or cannot be read are left out; `scan_files_with_errors` reports them
+            found.items.extend(matcher.tracked(&entry, depth, &tracker).ok().flatten());
+            ControlFlow::Continue(())
+        }
+        Err(error) => {
+            found.error = Some(error);
+            ControlFlow::Break(())
+        }
+    });
+    tracker.finish();
+    let mut files = Vec::new();
+    for found in found {
+        if let Some(error) = found.error {
+            return Err(error);
+        }
+        files.extend(found.items);
+    }
+
+    if options.sort.is_some() {
+        files.sort_by(|a, b| options.compare(a, b));
+    }
+    if let Some(limit) = options.limit {
+        files.truncate(limit);
+    }
+    Ok(files)
+}
+
+/// Entries found by a scan that goes on after errors, and the errors met.
+#[derive(Debug, Default)]
+pub struct ScanOutcome {
+    files: Vec<FileEntry>,
+    errors: Vec<ScanError>,
+    cancelled: bool,
+}
+
+impl ScanOutcome {
+    /// Returns the matching entries
+    pub fn files(&self) -> &[FileEntry] {
+        &self.files
+    }
+
+    /// Returns the errors met, with the path and kind of each
+    pub fn errors(&self) -> &[ScanError] {
+        &self.errors
+    }
+
+    /// Returns true if the scan was cancelled before the whole tree was walked
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+
+    /// Returns true if the whole tree could be read
+    pub fn is_complete(&self) -> bool {
+        self.errors.is_empty() && !self.cancelled
+    }
+
+    /// Splits the outcome into the entries and the errors
+    pub fn into_parts(self) -> (Vec<FileEntry>, Vec<ScanError>) {
+        (self.files, self.errors)
+    }
+}
+
+/// Scans a directory tree for the entries matching a set of options, going on after errors
+///
+/// Unlike `scan_files`, a directory that cannot be listed, such as the
+/// profile of another user, or an entry whose metadata cannot be read does
+/// not stop the scan: it is recorded with its path and the kind of the error,
+/// and the rest of the tree is scanned. Entries are only returned with the
+/// metadata actually read from the file system. A scan cancelled through
+/// the monitor of the options returns what it found so far, flagged as
+/// cancelled.
+///
+/// # Arguments
+/// * `path` - A string path to the directory to scan
+/// * `options` - What to walk and which entries to return
+///
+/// # Returns
+/// * `ScanOutcome` - The matching entries, sorted and limited as the options say, and the errors met
+///
+/// # Examples
+/// ```no_run
+/// use std::io::ErrorKind;
+/// use win_disk_info::{scan_files_with_errors, ScanOptions};
+///
+/// let outcome = scan_files_with_errors("C:\\Users", &ScanOptions::user_files());
+/// println!("{} files", outcome.files().len());
+/// for error in outcome.errors() {
+///     if error.kind() == ErrorKind::PermissionDenied {
+///         println!("Access denied: {:?}", error.path());
+///     }
+/// }
+/// ```
+pub fn scan_files_with_errors(path: &str, options: &ScanOptions) -> ScanOutcome {
+    let root = Path::new(path);
+    let matcher = Matcher::new(root, options);
+    let tracker = Tracker::new(&options.monitor);
+    let found = walk(root, options, &matcher, ScanOutcome::default, |found: &mut ScanOutcome, walked| {
+        let scanned = match walked {
+            Ok((entry, depth)) => matcher.tracked(&entry, depth, &tracker),
+            Err(error) => Err(ScanError::WalkDir(error)),
+        };
+        match scanned {
+            Ok(file) => found.files.extend(file),
+            Err(error) => found.errors.push(error),
+        }
+        ControlFlow::Continue(())
+    });
+    tracker.finish();
+
+    let mut outcome = ScanOutcome {
+        cancelled: options.monitor.is_cancelled(),
+        ..ScanOutcome::default()
+    };
+    for found in found {
+        outcome.files.extend(found.files);
+        outcome.errors.extend(found.errors);
+    }
+    if options.sort.is_some() {
+        outcome.files.sort_by(|a, b| options.compare(a, b));
+    }
+    if let Some(limit) = options.limit {
+        outcome.files.truncate(limit);
+    }
+    // Errors are listed by path whatever thread met them
+    outcome.errors.sort_by(|a, b| a.path().cmp(&b.path()));
+    outcome
+}
+
+/// Streams the entries of a directory tree matching a set of options
+///
+/// The tree is walked in the background, on the threads set in the
+/// options, while the entries are consumed. At most a fixed number of
+/// entries are found ahead of the consumer, so memory use does not grow
+/// with the size of the tree, and the first entries are available as soon
+/// as they are found. As with `scan_files_with_errors`, errors are yielded
+/// in place of the directories and entries that could not be read, and the
+/// walk goes on after them.
+///
+/// Entries come in no particular order: the sort key of the options is
+/// ignored, but the limit is honoured. Dropping the iterator, or cancelling
+/// the monitor of the options, stops the walk and ends the iteration.
+///
+/// # Arguments
+/// * `path` - A string path to the directory to scan
+/// * `options` - What to walk and which entries to return
+///
+/// # Returns
+/// * `FileScan` - An iterator over the matching entries and the errors met
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::{scan_files_iter, ScanOptions};
+///
+/// let mut total = 0;
+/// for file in scan_files_iter("D:\\", &ScanOptions::volume()) {
+///     match file {
+///         Ok(file) => total += file.size(),
+///         Err(error) => eprintln!("{}", error),
+///     }
+/// }
+/// println!("{} bytes", total);
+/// ```
+pub fn scan_files_iter(path: &str, options: &ScanOptions) -> FileScan {
+    if options.limit == Some(0) {
+        // Nothing can be returned, so the walk is not started
+        return FileScan {
+            receiver: None,
+            remaining: Some(0),
+        };
+    }
+    let (sender, receiver) = mpsc::sync_channel(STREAM_CAPACITY);
+    let root = PathBuf::from(path);
+    let options = options.clone();
+    let limit = options.limit;
+    let spawned = thread::Builder::new().name("scan".to_string()).spawn(move || {
+        let matcher = Matcher::new(&root, &options);
+        let tracker = Tracker::new(&options.monitor);
+        walk(&root, &options, &matcher, || (), |_, walked| {
+            let item = match walked {
+                Ok((entry, depth)) => match matcher.tracked(&entry, depth, &tracker) {
+                    Ok(Some(file)) => Ok(file),
+                    Ok(None) => return ControlFlow::Continue(()),
+                    Err(error) => Err(error),
+                },
+                Err(error) => Err(ScanError::from(error)),
+            };
+            // The consumer is gone once the iterator is dropped
+            match sender.send(item) {
+                Ok(()) => ControlFlow::Continue(()),
+                Err(_) => ControlFlow::Break(()),
+            }
+        });
+        tracker.finish();
+    });
+
+    let receiver = match spawned {
+        Ok(_) => receiver,
+        Err(error) => {
+            let (sender, receiver) = mpsc::sync_channel(1);
+            let _ = sender.send(Err(ScanError::Io(error)));
+            receiver
+        }
+    };
+    FileScan {
+        receiver: Some(receiver),
+        remaining: limit,
+    }
+}
+
+/// Iterator over the entries of a streaming scan, created by `scan_files_iter`.
+#[derive(Debug)]
+pub struct FileScan {
+    /// Dropped once the limit is reached, which stops the walk
+    receiver: Option<Receiver<Result<FileEntry, ScanError>>>,
+    remaining: Option<usize>,
+}
+
+impl Iterator for FileScan {
+    type Item = Result<FileEntry, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.receiver.as_ref()?.recv().ok();
+        if let (Some(Ok(_)), Some(remaining)) = (&item, self.remaining.as_mut()) {
+            *remaining -= 1;
+            if *remaining == 0 {
+                self.receiver = None;
+            }
+        }
+        if item.is_none() {
+            self.receiver = None;
+        }
+        item
+    }
+}
+
+/// Calculates the total size of the files matching a set of options
+///
+/// The sizes are added up as the tree is walked, without keeping the
+/// entries, so that whole volumes can be measured in constant memory. Sort
+/// and limit options are ignored, and directories do not count. A file with
+/// several hard links inside the tree counts once. With
+/// `SizeKind::Allocated`, the total is the space the files take on the
+/// volume. A scan cancelled through the monitor of the options returns the
+/// size counted so far.
+///
+/// # Arguments
+/// * `path` - A string path to the directory to analyze
+/// * `options` - What to walk and which files to count
+///
+/// # Returns
+/// * `Ok(u64)` - The total size in bytes
+/// * `Err(walkdir::Error)` - If there's an error during directory traversal
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::{format_file_size, scan_directory_size, ScanOptions};
+///
+/// let options = ScanOptions::volume().with_threads(16);
+/// let size = scan_directory_size("\\\\fileserver\\share", &options).unwrap();
+/// println!("{}", format_file_size(size));
+/// ```
+pub fn scan_directory_size(path: &str, options: &ScanOptions) -> Result<u64, walkdir::Error> {
+    let sizes = walk_sizes(Path::new(path), None, options, |total: &mut u64, _, size| *total += size)?;
+    Ok(sizes.into_iter().sum())
+}
+
+/// Walks a tree, or only the part of it below `below`, giving `add` every directory and the size of every matching file.
+///
+/// Directories are given with a size of 0, and files that do not match the
+/// options or whose size cannot be read are left out, as are the hard links
+/// to a file already given. Files are given with the size of the kind set
+/// in the options. Progress is reported and cancellation honoured as in
+/// `scan_directory_size`. The states of the threads are returned, or the
+/// first error met.
+pub(crate) fn walk_sizes<S, F>(root: &Path, below: Option<&Path>, options: &ScanOptions, add: F) -> Result<Vec<S>, walkdir::Error>
+where
+    S: Default + Send,
+    F: Fn(&mut S, &DirEntry, u64) + Sync,
+{
+    let matcher = Matcher::new(root, options);
+    // Attributes and times are only read when a filter needs them
+    let needs_entry = !options.include_hidden || !options.include_system || !options.time_ranges.is_empty();
+    let tracker = Tracker::new(&options.monitor);
+    // Identities of the files with several links already counted, shared by the threads
+    let linked = Mutex::new(HashSet::new());
+    let visit = |total: &mut Collected<S>, walked: Walked| {
+        let (entry, depth) = match walked {
+            Ok(walked) => walked,
+            Err(error) => {
+                total.error = Some(error);
+                return ControlFlow::Break(());
+            }
+        };
+        if entry.file_type().is_dir() {
+            add(&mut total.items, &entry, 0);
+        }
+        if !entry.file_type().is_file() {
+            return ControlFlow::Continue(());
+        }
+        let sized = if needs_entry {
+            matcher.scanned(&entry, depth).ok().flatten().map(|file| FileSize::from(&file))
+        } else if matcher.includes(&entry.file_name().to_string_lossy(), entry.path()) {
+            entry.metadata().ok().map(|metadata| FileSize::read(&entry, &metadata, options.native_metadata)).filter(|sized| {
+                options.min_size.is_none_or(|min| sized.size >= min) && options.max_size.is_none_or(|max| sized.size <= max)
+            })
+        } else {
+            None
+        };
+        let sized = sized.filter(|sized| match sized.identity {
+            Some(identity) if sized.links > 1 => linked.lock().unwrap_or_else(|e| e.into_inner()).insert(identity),
+            _ => true,
+        });
+        let size = sized.map(|sized| match options.size_kind {
+            SizeKind::Apparent => sized.size,
+            SizeKind::Allocated => sized.allocated_size.unwrap_or(sized.size),
+        });
+        tracker.advance(entry.path(), 1, size.unwrap_or(0));
+        if let Some(size) = size {
+            add(&mut total.items, &entry, size);
+        }
+        ControlFlow::Continue(())
+    };
+    let states = match below {
+        Some(directory) => walk_below(root, directory, options, &matcher, Collected::default, visit),
+        None => walk(root, options, &matcher, Collected::default, visit),
+    };
+    tracker.finish();
+    let mut items = Vec::with_capacity(states.len());
+    for state in states {
+        if let Some(error) = state.error {
+            return Err(error);
+        }
+        items.push(state.items);
+    }
+    Ok(items)
+}
+
+/// Walks the trees below `directories`, directories inside the tree of `root`, giving `found` every matching entry and every error met.
+///
+/// The directories are walked as `walk_below` would walk each of them, so
+/// that a walk can be resumed from the directories an earlier one left to
+/// list. With a checkpoint, these directories are given to its function
+/// at most once per interval while the walk goes on, and once more if it is
+/// cancelled: walking them again finds the entries not found yet. Entries
+/// are given from the threads of the walk; progress is reported and
+/// cancellation honoured as in `scan_files`.
+pub(crate) fn walk_files<F>(
+    root: &Path,
+    directories: &[PathBuf],
+    options: &ScanOptions,
+    checkpoint: Option<(Duration, SaveDirectories)>,
+    found: F,
+) where
+    F: Fn(Result<FileEntry, ScanError>) + Sync,
+{
+    let matcher = Matcher::new(root, options);
+    let tracker = Tracker::new(&options.monitor);
+    let first = directories
+        .iter()
+        .filter_map(|directory| pending_below(root, directory, options, &matcher))
+        .collect();
+    let checkpoint = checkpoint.map(|(interval, save)| WalkCheckpoint {
+        interval,
+        saved: Mutex::new(Instant::now()),
+        save,
+    });
+    walk_directories(first, options, &matcher, checkpoint.as_ref(), (), || (), |_, walked| {
+        match walked {
+            Ok((entry, depth)) => match matcher.tracked(&entry, depth, &tracker) {
+                Ok(Some(file)) => found(Ok(file)),
+                Ok(None) => {}
+                Err(error) => found(Err(error)),
+            },
+            Err(error) => found(Err(ScanError::from(error))),
+        }
+        ControlFlow::Continue(())
+    });
+    tracker.finish();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::CancellationToken;
+
+    fn scan_tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("docs").join("old")).unwrap();
+        fs::create_dir_all(root.join(".cache")).unwrap();
+        fs::create_dir_all(root.join("$Recycle.Bin").join("S-1-5-21")).unwrap();
+        fs::write(root.join("Outlook.PST"), vec![0u8; 3000]).unwrap();
+        fs::write(root.join("docs").join("2023-report.pdf"), vec![0u8; 2000]).unwrap();
+        fs::write(root.join("docs").join("old").join("2022-report.pdf"), vec![0u8; 1000]).unwrap();
+        fs::write(root.join(".cache").join("blob.bin"), vec![0u8; 500]).unwrap();
+        fs::write(root.join(".hidden"), b"x").unwrap();
+        fs::write(root.join("$Recycle.Bin").join("S-1-5-21").join("$RABC.pdf"), vec![0u8; 100]).unwrap();
+        dir
+    }
+
+    fn names(files: &[FileEntry]) -> Vec<&str> {
+        let mut names: Vec<&str> = files.iter().map(FileEntry::name).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_scan_everything() {
+        let dir = scan_tree();
+        let files = scan_files(dir.path().to_str().unwrap(), &ScanOptions::new()).unwrap();
+        assert_eq!(files.len(), 6);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_user_files_preset() {
+        let dir = scan_tree();
+        let files = scan_files(dir.path().to_str().unwrap(), &ScanOptions::user_files()).unwrap();
+        assert_eq!(names(&files), vec!["2022-report.pdf", "2023-report.pdf", "Outlook.PST"]);
+    }
+
+    #[test]
+    fn test_options_equality_ignores_monitor() {
+        assert_eq!(ScanOptions::new(), ScanOptions::new());
+        assert_eq!(ScanOptions::user_files(), ScanOptions::user_files().with_monitor(Monitor::new()));
+        assert_ne!(ScanOptions::new(), ScanOptions::new().with_limit(1));
+        assert_ne!(ScanOptions::new(), ScanOptions::new().with_native_metadata(false));
+    }
+
+    #[test]
+    fn test_scan_without_native_metadata() {
+        let dir = scan_tree();
+        let root = dir.path().to_str().unwrap();
+        let options = ScanOptions::new().with_sort(SortKey::Path, false);
+        let detailed = scan_files(root, &options).unwrap();
+        let listed = scan_files(root, &options.clone().with_native_metadata(false)).unwrap();
+
+        assert_eq!(names(&listed), names(&detailed));
+        assert_eq!(listed.iter().map(FileEntry::size).collect::<Vec<_>>(), detailed.iter().map(FileEntry::size).collect::<Vec<_>>());
+        assert!(detailed.iter().all(|file| file.identity().is_some()));
+        // Only Windows needs extra calls for the details, which Unix always reads
+        assert_eq!(listed.iter().all(|file| file.identity().is_some()), cfg!(unix));
+        assert_eq!(
+            scan_directory_size(root, &ScanOptions::new().with_native_metadata(false)).unwrap(),
+            scan_directory_size(root, &ScanOptions::new()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_depth_and_directories() {
+        let dir = scan_tree();
+        let options = ScanOptions::volume().with_max_depth(1).with_directories(true).with_exclude(".*");
+        let files = scan_files(dir.path().to_str().unwrap(), &options).unwrap();
+        assert_eq!(names(&files), vec!["Outlook.PST", "docs"]);
+        assert!(files.iter().find(|file| file.name() == "docs").unwrap().is_dir());
+
+        let options = ScanOptions::new().with_min_depth(2);
+        let files = scan_files(dir.path().to_str().unwrap(), &options).unwrap();
+        assert_eq!(names(&files), vec!["$RABC.pdf", "2022-report.pdf", "2023-report.pdf", "blob.bin"]);
+    }
+
+    #[test]
+    fn test_patterns_and_sizes() {
+        let dir = scan_tree();
+        let root = dir.path().to_str().unwrap();
+
+        let options = ScanOptions::volume().with_include("*.pst");
+        assert!(scan_files(root, &options.clone().with_case_insensitive(false)).unwrap().is_empty());
+        assert_eq!(names(&scan_files(root, &options.with_case_insensitive(true)).unwrap()), vec!["Outlook.PST"]);
+
+        let options = ScanOptions::new().with_include("20??-*").with_min_size(1500).with_max_size(2500);
+        assert_eq!(names(&scan_files(root, &options).unwrap()), vec!["2023-report.pdf"]);
+    }
+
+    #[test]
+    fn test_relative_globs_and_regexes() {
+        let dir = scan_tree();
+        let root = dir.path().to_str().unwrap();
+
+        let options = ScanOptions::volume().with_include("docs/**/*.pdf");
+        assert_eq!(names(&scan_files(root, &options).unwrap()), vec!["2022-report.pdf", "2023-report.pdf"]);
+        let options = ScanOptions::volume().with_include("docs/*.pdf");
+        assert_eq!(names(&scan_files(root, &options).unwrap()), vec!["2023-report.pdf"]);
+        let options = ScanOptions::volume().with_exclude("docs/old");
+        assert_eq!(scan_files(root, &options).unwrap().len(), 4);
+
+        let options = ScanOptions::volume()
+            .with_regex(r"^\d{4}-report\.PDF$", MatchTarget::Name)
+            .unwrap()
+            .with_case_insensitive(true);
+        assert_eq!(scan_files(root, &options).unwrap().len(), 2);
+        let options = ScanOptions::volume().with_exclude_regex(r"[/\\]old[/\\]", MatchTarget::Path).unwrap();
+        assert_eq!(scan_files(root, &options).unwrap().len(), 4);
+        assert!(ScanOptions::new().with_regex("(unclosed", MatchTarget::Name).is_err());
+    }
+
+    #[test]
+    fn test_ignore_files() {
+        let dir = scan_tree();
+        let root = dir.path();
+        fs::write(root.join(".gitignore"), "*.pdf\n.cache/\n").unwrap();
+        fs::write(root.join("docs").join(".gitignore"), "!2023-*\n").unwrap();
+
+        let options = ScanOptions::volume().with_ignore_file(".gitignore").with_exclude(".gitignore");
+        let files = scan_files(root.to_str().unwrap(), &options).unwrap();
+        assert_eq!(names(&files), vec![".hidden", "2023-report.pdf", "Outlook.PST"]);
+
+        let files = scan_files(root.to_str().unwrap(), &ScanOptions::volume().with_exclude(".gitignore")).unwrap();
+        assert_eq!(files.len(), 5);
+    }
+
+    #[test]
+    fn test_threads_find_the_same_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for a in 0..8 {
+            for b in 0..8 {
+                let leaf = dir.path().join(format!("a{}", a)).join(format!("b{}", b));
+                fs::create_dir_all(&leaf).unwrap();
+                fs::write(leaf.join("file.txt"), vec![0u8; a * 8 + b]).unwrap();
+            }
+        }
+        let root = dir.path().to_str().unwrap();
+
+        let sorted = ScanOptions::new().with_sort(SortKey::Path, false);
+        let sequential = scan_files(root, &sorted.clone().with_threads(1)).unwrap();
+        let parallel = scan_files(root, &sorted.clone().with_threads(8)).unwrap();
+        assert_eq!(sequential.len(), 64);
+        let paths = |files: &[FileEntry]| files.iter().map(|file| file.path().to_path_buf()).collect::<Vec<_>>();
+        assert_eq!(paths(&sequential), paths(&parallel));
+
+        let total: u64 = (0..64).sum();
+        assert_eq!(scan_directory_size(root, &ScanOptions::new().with_threads(4)).unwrap(), total);
+        assert_eq!(scan_directory_size(root, &ScanOptions::new().with_include("a7/**").with_min_size(60)).unwrap(), 60 + 61 + 62 + 63);
+        assert!(scan_files(&format!("{}/missing", root), &ScanOptions::new()).is_err());
+    }
+
+    #[test]
+    fn test_progress_and_cancellation() {
+        let dir = tempfile::tempdir().unwrap();
+        for index in 0..10 {
+            let subdirectory = dir.path().join(format!("d{}", index));
+            fs::create_dir(&subdirectory).unwrap();
+            fs::write(subdirectory.join("file.bin"), vec![0u8; 100]).unwrap();
+        }
+        let root = dir.path().to_str().unwrap();
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&reports);
+        let monitor = Monitor::new()
+            .with_progress(move |progress| sink.lock().unwrap().push(progress.clone()))
+            .with_estimated_total(Some(10), None);
+        let files = scan_files(root, &ScanOptions::new().with_include("d[0-4]/*").with_monitor(monitor)).unwrap();
+        assert_eq!(files.len(), 5);
+
+        // Every file walked is counted, but only the bytes of the matching ones
+        let last = reports.lock().unwrap().last().cloned().unwrap();
+        assert!(last.is_finished() && !last.is_cancelled());
+        assert_eq!((last.files(), last.bytes(), last.total_files()), (10, 500, Some(10)));
+        assert_eq!(last.fraction(), Some(1.0));
+
+        // Cancelling from the first report stops the walk after the first file
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        let monitor = Monitor::new()
+            .with_progress(move |_| canceller.cancel())
+            .with_cancellation(token.clone())
+            .with_interval(Duration::ZERO);
+        let options = ScanOptions::new().with_threads(1).with_monitor(monitor);
+        assert_eq!(scan_files(root, &options).unwrap().len(), 1);
+
+        let outcome = scan_files_with_errors(root, &options);
+        assert!(outcome.is_cancelled() && !outcome.is_complete());
+        assert!(outcome.files().is_empty());
+        assert_eq!(scan_files_iter(root, &options).count(), 0);
+        assert_eq!(scan_directory_size(root, &options).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_streaming_scan() {
+        let dir = scan_tree();
+        let root = dir.path().to_str().unwrap();
+        let options = ScanOptions::volume().with_threads(2);
+
+        let streamed: Vec<FileEntry> = scan_files_iter(root, &options).map(Result::unwrap).collect();
+        assert_eq!(names(&streamed), names(&scan_files(root, &options).unwrap()));
+        assert_eq!(scan_files_iter(root, &options.clone().with_limit(2)).count(), 2);
+        assert_eq!(scan_files_iter(root, &options.clone().with_limit(0)).count(), 0);
+
+        // Stopping early must not wait for the rest of the walk
+        let first = scan_files_iter(root, &ScanOptions::new().with_threads(1)).next();
+        assert!(first.unwrap().is_ok());
+
+        let mut missing = scan_files_iter(&format!("{}/missing", root), &options);
+        assert!(matches!(missing.next(), Some(Err(ScanError::WalkDir(_)))));
+        assert!(missing.next().is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_goes_on_after_errors() {
+        let dir = scan_tree();
+        let broken = dir.path().join("docs").join("broken");
+        std::os::unix::fs::symlink(dir.path().join("nowhere"), &broken).unwrap();
+        let root = dir.path().to_str().unwrap();
+        let options = ScanOptions::volume().with_follow_links(true);
+
+        assert!(scan_files(root, &options).is_err());
+        let outcome = scan_files_with_errors(root, &options);
+        assert_eq!(outcome.files().len(), 5);
+        assert!(!outcome.is_complete());
+        assert_eq!(outcome.errors().len(), 1);
+        assert_eq!(outcome.errors()[0].path(), Some(broken.as_path()));
+        assert_eq!(outcome.errors()[0].kind(), io::ErrorKind::NotFound);
+
+        let streamed: Vec<_> = scan_files_iter(root, &options).collect();
+        assert_eq!(streamed.iter().filter(|item| item.is_err()).count(), 1);
+        assert_eq!(streamed.len(), 6);
+    }
+
+    #[test]
+    fn test_vanished_entries_are_not_made_up() {
+        let dir = scan_tree();
+        let entries: Vec<DirEntry> = WalkDir::new(dir.path()).into_iter().map(Result::unwrap).collect();
+        let entry = entries.into_iter().find(|entry| entry.file_name() == "Outlook.PST").unwrap();
+        fs::remove_file(entry.path()).unwrap();
+
+        assert!(FileEntry::try_from(entry.clone()).is_err());
+        let options = ScanOptions::new();
+        let matcher = Matcher::new(dir.path(), &options);
+        let error = matcher.scanned(&entry, 1).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(error.path().unwrap().ends_with("Outlook.PST"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow_links_stops_at_loops() {
+        let dir = scan_tree();
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("docs").join("back")).unwrap();
+
+        let options = ScanOptions::volume().with_follow_links(true).with_directories(true);
+        let files = scan_files(dir.path().to_str().unwrap(), &options).unwrap();
+        assert!(files.iter().any(|file| file.name() == "back"));
+        assert_eq!(files.iter().filter(|file| file.name() == "Outlook.PST").count(), 1);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    #[ignore = "needs a non-empty mount point in a directory other than the root, such as /dev/pts or /dev/shm"]
+    fn test_same_file_system_skips_mount_points() {
+        use std::os::unix::fs::MetadataExt;
+
+        // A mount point inside a small directory of another device, such as /dev/shm or /dev/pts
+        let mounts = fs::read_to_string("/proc/mounts").unwrap_or_default();
+        let mounted = mounts.lines().filter_map(|line| line.split(' ').nth(1)).map(PathBuf::from).find(|mount| {
+            let parent = mount.parent().filter(|parent| *parent != Path::new("/"));
+            match (parent.and_then(|parent| fs::metadata(parent).ok()), fs::metadata(mount)) {
+                (Some(parent), Ok(metadata)) => {
+                    metadata.is_dir()
+                        && parent.dev() != metadata.dev()
+                        && fs::read_dir(mount).is_ok_and(|mut entries| entries.next().is_some())
+                }
+                _ => false,
+            }
+        });
+        let mounted = mounted.expect("no mount point to scan across");
+        let parent = mounted.parent().unwrap().to_str().unwrap();
+
+        let options = ScanOptions::new().with_directories(true).with_max_depth(3).with_same_file_system(true);
+        let outcome = scan_files_with_errors(parent, &options);
+        assert!(outcome.files().iter().any(|file| file.path() == mounted));
+        assert!(!outcome.files().iter().any(|file| file.path().starts_with(&mounted) && file.path() != mounted));
+        let crossing = scan_files_with_errors(parent, &options.with_same_file_system(false));
+        assert!(crossing.files().iter().any(|file| file.path().starts_with(&mounted) && file.path() != mounted));
+    }
+
+    #[test]
+    fn test_time_range() {
+        let dir = scan_tree();
+        let root = dir.path().to_str().unwrap();
+        let now = Utc::now();
+
+        let options = ScanOptions::new().with_time_range(TimestampKind::Modified, Some(now - chrono::Duration::hours(1)), None);
+        assert_eq!(scan_files(root, &options).unwrap().len(), 6);
+        let options = ScanOptions::new().with_time_range(TimestampKind::Modified, None, Some(now - chrono::Duration::hours(1)));
+        assert!(scan_files(root, &options).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sort_and_limit() {
+        let dir = scan_tree();
+        let options = ScanOptions::volume().with_sort(SortKey::Size, true).with_limit(2);
+        let files = scan_files(dir.path().to_str().unwrap(), &options).unwrap();
+        let sizes: Vec<u64> = files.iter().map(FileEntry::size).collect();
+        assert_eq!(sizes, vec![3000, 2000]);
+
+        let options = ScanOptions::volume().with_sort(SortKey::Name, false);
+        let files = scan_files(dir.path().to_str().unwrap(), &options).unwrap();
+        assert_eq!(files.first().unwrap().name(), ".hidden");
+    }
+}
@@ -0,0 +1,315 @@
+//! Timeline export of file timestamps.
+//!
+//! Files are exported either as a bodyfile, the intermediate format of The
+//! Sleuth Kit read by `mactime` and most timeline tools, or directly as the
+//! CSV that `mactime -d` produces: one row per distinct timestamp of a file,
+//! sorted chronologically, with the MACB letters of the times it stands for.
+
+use std::io::{self, Write};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::report::csv::push_row;
+use crate::{FileEntry, TimestampKind};
+
+/// Permissions shown, as `fls` does, for files without POSIX permissions
+const DEFAULT_PERMISSIONS: &str = "rwxrwxrwx";
+
+/// A point of a timeline: one file and the timestamps it has at that time.
+#[derive(Debug, Clone)]
+pub struct TimelineEvent<'a> {
+    time: DateTime<Utc>,
+    kinds: Vec<TimestampKind>,
+    file: &'a FileEntry,
+}
+
+impl<'a> TimelineEvent<'a> {
+    /// Returns the time of the event, in UTC
+    pub fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    /// Returns the timestamps of the file equal to the time of the event, in MACB order
+    pub fn kinds(&self) -> &[TimestampKind] {
+        &self.kinds
+    }
+
+    /// Returns the file the event belongs to
+    pub fn file(&self) -> &'a FileEntry {
+        self.file
+    }
+
+    /// Returns the MACB string of the event, such as `m.c.`
+    pub fn macb(&self) -> String {
+        TimestampKind::ALL
+            .iter()
+            .map(|kind| if self.kinds.contains(kind) { kind.letter() } else { '.' })
+            .collect()
+    }
+}
+
+/// Builds a chronological timeline of the timestamps of files
+///
+/// Each file contributes one event per distinct time; timestamps of a file
+/// sharing the same time are merged into a single event. Events at the same
+/// time are ordered by path.
+///
+/// # Arguments
+/// * `files` - The files whose timestamps make up the timeline
+///
+/// # Returns
+/// * `Vec<TimelineEvent>` - The events, oldest first
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::{get_files, timeline};
+///
+/// let files = get_files("C:\\Users\\suspect\\Downloads").unwrap();
+/// for event in timeline(&files) {
+///     println!("{} {} {}", event.time(), event.macb(), event.file().path().display());
+/// }
+/// ```
+pub fn timeline(files: &[FileEntry]) -> Vec<TimelineEvent<'_>> {
+    let mut events: Vec<TimelineEvent> = Vec::new();
+    for file in files {
+        let first = events.len();
+        for (kind, time) in file.times().iter() {
+            match events[first..].iter_mut().find(|event| event.time == time) {
+                Some(event) => event.kinds.push(kind),
+                None => events.push(TimelineEvent {
+                    time,
+                    kinds: vec![kind],
+                    file,
+                }),
+            }
+        }
+    }
+    events.sort_by(|a, b| a.time.cmp(&b.time).then_with(|| a.file.path().cmp(b.file.path())));
+    events
+}
+
+/// Returns the TSK mode string of a file, such as `r/rrw-r--r--`.
+fn mode_string(file: &FileEntry) -> String {
+    let kind = match file.mode().map(|mode| mode & 0o170000) {
+        Some(0o040000) => 'd',
+        Some(0o120000) => 'l',
+        Some(0o010000) => 'p',
+        Some(0o020000) => 'c',
+        Some(0o060000) => 'b',
+        Some(0o140000) => 's',
+        Some(_) => 'r',
+        None if file.is_dir() => 'd',
+        None if file.attributes().is_reparse_point() => 'l',
+        None => 'r',
+    };
+    let permissions = match file.mode() {
+        Some(mode) => (0..9)
+            .map(|bit| if mode & (0o400 >> bit) != 0 { ['r', 'w', 'x'][bit % 3] } else { '-' })
+            .collect(),
+        None => DEFAULT_PERMISSIONS.to_string(),
+    };
+    format!("{}/{}{}", kind, kind, permissions)
+}
+
+/// Returns an optional number as written to timelines, `0` when unknown.
+fn number(value: Option<impl ToString>) -> String {
+    value.map_or_else(|| "0".to_string(), |value| value.to_string())
+}
+
+/// Returns the name of a file as written to timelines.
+fn timeline_name(file: &FileEntry) -> String {
+    let name = file.path().to_string_lossy();
+    if file.is_deleted() {
+        format!("{} (deleted)", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Writes the timestamps of files in The Sleuth Kit bodyfile format
+///
+/// Each file becomes one `MD5|name|inode|mode|UID|GID|size|atime|mtime|ctime|crtime`
+/// line, with times in whole seconds since the Unix epoch. Unknown values,
+/// such as times a file system does not record or owners outside Unix, are
+/// written as `0`. The MD5 is written when it was computed, and deleted
+/// files are suffixed with ` (deleted)` as `fls` does. The result can be
+/// given to `mactime -b`.
+///
+/// # Arguments
+/// * `files` - The files to export
+/// * `writer` - Where the bodyfile is written
+///
+/// # Returns
+/// * `io::Result<()>` - An error if writing fails
+///
+/// # Examples
+/// ```no_run
+/// use std::fs::File;
+/// use win_disk_info::{get_files, write_bodyfile};
+///
+/// let files = get_files("C:\\Users\\suspect").unwrap();
+/// write_bodyfile(&files, File::create("E:\\case-17\\bodyfile.txt").unwrap()).unwrap();
+/// ```
+pub fn write_bodyfile<W: Write>(files: &[FileEntry], mut writer: W) -> io::Result<()> {
+    for file in files {
+        let seconds = |kind| file.times().get(kind).map_or(0, |time: DateTime<Utc>| time.timestamp());
+        // The bodyfile has no quoting, so separators inside names are replaced
+        let name = timeline_name(file).replace('|', "_");
+        writeln!(
+            writer,
+            "{}|{}|{}|{}|{}|{}|{}|{}|{}|{}|{}",
+            file.hashes().md5().unwrap_or("0"),
+            name,
+            number(file.file_id()),
+            mode_string(file),
+            number(file.uid()),
+            number(file.gid()),
+            file.size(),
+            seconds(TimestampKind::Accessed),
+            seconds(TimestampKind::Modified),
+            seconds(TimestampKind::Changed),
+            seconds(TimestampKind::Born),
+        )?;
+    }
+    writer.flush()
+}
+
+/// Writes a timeline of files as the CSV produced by `mactime -d`
+///
+/// Rows follow `timeline`: one per distinct time of each file, oldest first,
+/// with `Date,Size,Type,Mode,UID,GID,Meta,File Name` columns. Unlike
+/// `mactime`, dates are written in UTC in RFC 3339 form with the full
+/// precision recorded by the file system, so that no time zone has to be
+/// chosen and events within the same second keep their order.
+///
+/// # Arguments
+/// * `files` - The files to export
+/// * `writer` - Where the CSV is written
+///
+/// # Returns
+/// * `io::Result<()>` - An error if writing fails
+///
+/// # Examples
+/// ```no_run
+/// use std::fs::File;
+/// use win_disk_info::{get_deleted_files, write_mactime_csv};
+///
+/// let files = get_deleted_files("E:\\case-17\\disk.E01").unwrap();
+/// write_mactime_csv(&files, File::create("E:\\case-17\\timeline.csv").unwrap()).unwrap();
+/// ```
+pub fn write_mactime_csv<W: Write>(files: &[FileEntry], mut writer: W) -> io::Result<()> {
+    let mut csv = String::new();
+    push_row(&mut csv, ["Date", "Size", "Type", "Mode", "UID", "GID", "Meta", "File Name"]);
+    writer.write_all(csv.as_bytes())?;
+
+    for event in timeline(files) {
+        csv.clear();
+        let date = event.time.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        let size = event.file.size().to_string();
+        let macb = event.macb();
+        let mode = mode_string(event.file);
+        let uid = number(event.file.uid());
+        let gid = number(event.file.gid());
+        let meta = number(event.file.file_id());
+        let name = timeline_name(event.file);
+        push_row(&mut csv, [&*date, &size, &macb, &mode, &uid, &gid, &meta, &name]);
+        writer.write_all(csv.as_bytes())?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::images::SharedImage;
+    use crate::{FileTimes, Recoverability};
+
+    fn sample_files() -> Vec<FileEntry> {
+        let early = Utc.with_ymd_and_hms(2024, 3, 15, 10, 30, 0).unwrap();
+        let late = early + chrono::Duration::nanoseconds(1_500_000_100);
+        let image = SharedImage::new(PathBuf::from("/case/disk.img"));
+        let report = FileEntry::from_image(
+            PathBuf::from("/case/disk.img/Users/a|b.docx"),
+            1200,
+            FileTimes::new()
+                .with_modified(Some(late))
+                .with_accessed(Some(late))
+                .with_changed(Some(late))
+                .with_born(Some(early)),
+            &image,
+            Vec::new(),
+        )
+        .with_deleted(Recoverability::Recoverable);
+        let carved = FileEntry::from_image(
+            PathBuf::from("/case/disk.img/$Carved/f0000000001.jpg"),
+            300,
+            FileTimes::new(),
+            &image,
+            Vec::new(),
+        );
+        vec![report, carved]
+    }
+
+    #[test]
+    fn test_timeline_merges_equal_times() {
+        let files = sample_files();
+        let events = timeline(&files);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].macb(), "...b");
+        assert_eq!(events[1].macb(), "mac.");
+        assert_eq!(events[1].kinds(), [TimestampKind::Modified, TimestampKind::Accessed, TimestampKind::Changed]);
+        assert!(events[0].time() < events[1].time());
+    }
+
+    #[test]
+    fn test_write_bodyfile() {
+        let mut output = Vec::new();
+        write_bodyfile(&sample_files(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[0],
+            "0|/case/disk.img/Users/a_b.docx (deleted)|0|r/rrwxrwxrwx|0|0|1200|1710498601|1710498601|1710498601|1710498600"
+        );
+        assert_eq!(lines[1], "0|/case/disk.img/$Carved/f0000000001.jpg|0|r/rrwxrwxrwx|0|0|300|0|0|0|0");
+    }
+
+    #[test]
+    fn test_write_mactime_csv() {
+        let mut output = Vec::new();
+        write_mactime_csv(&sample_files(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Date,Size,Type,Mode,UID,GID,Meta,File Name\r\n\
+             2024-03-15T10:30:00Z,1200,...b,r/rrwxrwxrwx,0,0,0,/case/disk.img/Users/a|b.docx (deleted)\r\n\
+             2024-03-15T10:30:01.500000100Z,1200,mac.,r/rrwxrwxrwx,0,0,0,/case/disk.img/Users/a|b.docx (deleted)\r\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_bodyfile_unix_metadata() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        use crate::get_files;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.sh");
+        std::fs::write(&path, b"#!/bin/sh").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o754)).unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+
+        let mut output = Vec::new();
+        write_bodyfile(&get_files(dir.path().to_str().unwrap()).unwrap(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let fields: Vec<&str> = output.trim_end().split('|').collect();
+        assert_eq!(fields[2], metadata.ino().to_string());
+        assert_eq!(fields[3], "r/rrwxr-xr--");
+        assert_eq!(fields[4], metadata.uid().to_string());
+        assert_eq!(fields[9], metadata.ctime().to_string());
+    }
+}
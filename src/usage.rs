@@ -0,0 +1,325 @@
+//! This module summarizes the space used by files, by kind of content.
+//!
+//! Files are identified by their signature, as `identify_files` does, and
+//! their sizes are added up per category, per MIME type and per extension.
+//! Files whose content is not recognized are also broken down by extension,
+//! which usually tells what they are. Hard links to the same file count once.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[cfg(feature = "serialize")]
+use serde::Serialize;
+
+use crate::file_extraction::format_file_size;
+use crate::file_identification::detect_content_type;
+use crate::{FileEntry, Partition};
+
+/// Category and MIME type of files without a recognized signature
+const UNKNOWN: &str = "Unknown";
+
+/// Category of files whose content could not be read
+const UNREADABLE: &str = "Unreadable";
+
+/// Extension bucket of files without an extension
+const NO_EXTENSION: &str = "(none)";
+
+/// The files of one category, MIME type or extension, and the space they use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct UsageBucket {
+    name: String,
+    files: u64,
+    bytes: u64,
+    allocated_bytes: u64,
+}
+
+impl UsageBucket {
+    fn new(name: &str) -> Self {
+        UsageBucket {
+            name: name.to_string(),
+            files: 0,
+            bytes: 0,
+            allocated_bytes: 0,
+        }
+    }
+
+    fn add(&mut self, file: &FileEntry) {
+        self.files += 1;
+        self.bytes += file.size();
+        self.allocated_bytes += file.allocated_size().unwrap_or(file.size());
+    }
+
+    /// Returns the name of the category, MIME type or lowercase extension
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the number of files
+    pub fn file_count(&self) -> u64 {
+        self.files
+    }
+
+    /// Returns the total size of the files in bytes
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns the space allocated to the files on disk, in bytes, or their size when unknown
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocated_bytes
+    }
+}
+
+/// Space used by a set of files, per category, MIME type and extension.
+///
+/// Every breakdown is sorted by size, largest first. Files that cannot be
+/// read are counted in the `Unreadable` category; they and the files of the
+/// `Unknown` category are listed by extension in `unidentified`.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct UsageSummary {
+    files: u64,
+    bytes: u64,
+    allocated_bytes: u64,
+    categories: Vec<UsageBucket>,
+    mime_types: Vec<UsageBucket>,
+    extensions: Vec<UsageBucket>,
+    unidentified: Vec<UsageBucket>,
+    /// Capacity of the partition the files are on, in bytes
+    capacity: Option<u64>,
+}
+
+impl UsageSummary {
+    /// Relates the summary to the partition holding the files, for percentages of its capacity.
+    pub fn with_partition(mut self, partition: &Partition) -> Self {
+        self.capacity = Some(partition.total_space());
+        self
+    }
+
+    /// Returns the number of files summarized
+    pub fn file_count(&self) -> u64 {
+        self.files
+    }
+
+    /// Returns the total size of the files in bytes
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns the space allocated to the files on disk, in bytes
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocated_bytes
+    }
+
+    /// Returns the usage per content category, such as `Image` or `Document`
+    pub fn categories(&self) -> &[UsageBucket] {
+        &self.categories
+    }
+
+    /// Returns the usage per MIME type, `Unknown` for unidentified files
+    pub fn mime_types(&self) -> &[UsageBucket] {
+        &self.mime_types
+    }
+
+    /// Returns the usage per lowercase extension of all files, `(none)` for files without one
+    pub fn extensions(&self) -> &[UsageBucket] {
+        &self.extensions
+    }
+
+    /// Returns the usage per lowercase extension of the files that could not be identified
+    pub fn unidentified(&self) -> &[UsageBucket] {
+        &self.unidentified
+    }
+
+    /// Returns the capacity of the partition set with `with_partition`, in bytes
+    pub fn capacity(&self) -> Option<u64> {
+        self.capacity
+    }
+
+    /// Returns the share of the summarized size taken by a bucket, in percent
+    pub fn percent_of_total(&self, bucket: &UsageBucket) -> f64 {
+        percent(bucket.bytes, self.bytes)
+    }
+
+    /// Returns the share of the partition capacity allocated to a bucket, in percent, if a partition is set
+    pub fn percent_of_capacity(&self, bucket: &UsageBucket) -> Option<f64> {
+        self.capacity.map(|capacity| percent(bucket.allocated_bytes, capacity))
+    }
+}
+
+impl fmt::Display for UsageSummary {
+    /// Formats the summary as one line per category, largest first.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} files, {}", self.files, format_file_size(self.bytes))?;
+        for bucket in &self.categories {
+            write!(
+                f,
+                "\n  {}: {} in {} files ({:.1}%",
+                bucket.name,
+                format_file_size(bucket.bytes),
+                bucket.files,
+                self.percent_of_total(bucket)
+            )?;
+            if let Some(percent) = self.percent_of_capacity(bucket) {
+                write!(f, ", {:.1}% of the partition", percent)?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns `part` as a percentage of `whole`, 0 when `whole` is empty.
+fn percent(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / whole as f64
+    }
+}
+
+/// Adds a file to the bucket named `name` of a breakdown.
+fn add_to(buckets: &mut HashMap<String, UsageBucket>, name: &str, file: &FileEntry) {
+    buckets
+        .entry(name.to_string())
+        .or_insert_with(|| UsageBucket::new(name))
+        .add(file);
+}
+
+/// Returns the buckets of a breakdown, largest first.
+fn sorted(buckets: HashMap<String, UsageBucket>) -> Vec<UsageBucket> {
+    let mut buckets: Vec<UsageBucket> = buckets.into_values().collect();
+    buckets.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+    buckets
+}
+
+/// Summarizes the space used by files per content category, MIME type and extension
+///
+/// Each file is identified from its first bytes, as `identify_files` does;
+/// files that cannot be read are counted in the `Unreadable` category.
+/// Directories are skipped, and a file reached through several hard links
+/// counts once. Files are consumed one at a time, so the files streamed by
+/// `scan_files_iter` can be summarized in constant memory.
+///
+/// # Arguments
+/// * `files` - Any iterator over the files to summarize
+///
+/// # Returns
+/// * `UsageSummary` - The number of files and bytes per category, MIME type and extension
+///
+/// # Examples
+/// ```no_run
+/// use win_disk_info::{scan_files_iter, usage_summary, FileSystem, Partition, ScanOptions};
+/// use std::path::PathBuf;
+///
+/// let partition = Partition::new(0, "D:".to_string(), FileSystem::NTFS(PathBuf::from("D:\\")), 2_000_000_000_000, 500_000_000_000);
+/// let files = scan_files_iter("D:\\", &ScanOptions::volume()).filter_map(Result::ok);
+/// let summary = usage_summary(files).with_partition(&partition);
+/// println!("{}", summary);
+/// for bucket in summary.unidentified().iter().take(10) {
+///     println!("*.{}: {} files", bucket.name(), bucket.file_count());
+/// }
+/// ```
+pub fn usage_summary<I>(files: I) -> UsageSummary
+where
+    I: IntoIterator<Item = FileEntry>,
+{
+    let mut total = UsageBucket::new("Total");
+    let mut categories = HashMap::new();
+    let mut mime_types = HashMap::new();
+    let mut extensions = HashMap::new();
+    let mut unidentified = HashMap::new();
+    let mut linked = HashSet::new();
+
+    for file in files {
+        if file.is_dir() {
+            continue;
+        }
+        if file.hard_links().unwrap_or(1) > 1 {
+            if let Some(identity) = file.identity() {
+                if !linked.insert(identity) {
+                    continue;
+                }
+            }
+        }
+
+        let extension = file.extension().map(str::to_lowercase);
+        let extension = extension.as_deref().unwrap_or(NO_EXTENSION);
+        total.add(&file);
+        add_to(&mut extensions, extension, &file);
+        match detect_content_type(&file) {
+            Ok(Some((category, mime_type))) => {
+                add_to(&mut categories, &category, &file);
+                add_to(&mut mime_types, &mime_type, &file);
+            }
+            identified => {
+                let category = if identified.is_ok() { UNKNOWN } else { UNREADABLE };
+                add_to(&mut categories, category, &file);
+                add_to(&mut mime_types, UNKNOWN, &file);
+                add_to(&mut unidentified, extension, &file);
+            }
+        }
+    }
+
+    UsageSummary {
+        files: total.files,
+        bytes: total.bytes,
+        allocated_bytes: total.allocated_bytes,
+        categories: sorted(categories),
+        mime_types: sorted(mime_types),
+        extensions: sorted(extensions),
+        unidentified: sorted(unidentified),
+        capacity: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{get_files, FileSystem};
+
+    #[test]
+    fn test_usage_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0x4A, 0x46, 0x49, 0x46];
+        fs::write(dir.path().join("photo.jpg"), [&jpeg[..], &[0u8; 990]].concat()).unwrap();
+        fs::write(dir.path().join("renamed.DAT"), [&jpeg[..], &[0u8; 490]].concat()).unwrap();
+        fs::write(dir.path().join("build.log"), vec![b'x'; 300]).unwrap();
+        fs::write(dir.path().join("README"), vec![b'x'; 20]).unwrap();
+
+        let summary = usage_summary(get_files(dir.path().to_str().unwrap()).unwrap());
+        assert_eq!((summary.file_count(), summary.bytes()), (4, 1820));
+
+        let names = |buckets: &[UsageBucket]| buckets.iter().map(|bucket| bucket.name().to_string()).collect::<Vec<_>>();
+        assert_eq!(names(summary.categories()), ["Image", "Unknown"]);
+        assert_eq!(summary.categories()[0].file_count(), 2);
+        assert_eq!(summary.categories()[0].bytes(), 1500);
+        assert_eq!(names(summary.mime_types()), ["image/jpeg", "Unknown"]);
+        assert_eq!(names(summary.extensions()), ["jpg", "dat", "log", "(none)"]);
+        assert_eq!(names(summary.unidentified()), ["log", "(none)"]);
+        assert_eq!(summary.percent_of_total(&summary.categories()[1]), 320.0 * 100.0 / 1820.0);
+        assert_eq!(summary.percent_of_capacity(&summary.categories()[0]), None);
+
+        let partition = Partition::new(0, "Data".to_string(), FileSystem::EXT4(PathBuf::from("/")), 1_000_000, 0);
+        let summary = summary.with_partition(&partition);
+        let allocated = summary.categories()[0].allocated_bytes();
+        assert_eq!(summary.percent_of_capacity(&summary.categories()[0]), Some(allocated as f64 / 10_000.0));
+        assert!(summary.to_string().contains("Image: 1.46 KB in 2 files (82.4%, "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_usage_summary_counts_links_once() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), b"shared").unwrap();
+        fs::hard_link(dir.path().join("a.txt"), dir.path().join("b.txt")).unwrap();
+
+        let summary = usage_summary(get_files(dir.path().to_str().unwrap()).unwrap());
+        assert_eq!((summary.file_count(), summary.bytes()), (1, 6));
+        assert_eq!(summary.unidentified()[0].name(), "txt");
+    }
+}
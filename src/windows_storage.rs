@@ -1,10 +1,28 @@
-use crate::{Disk, DiskError, DiskKind, FileSystem, Partition};
+use crate::partition_table::{
+    read_partition_table, GptLayout, PartitionEntry, PartitionScheme, PartitionTable, PartitionType, MBR_TYPES_EXTENDED,
+};
+use crate::{Disk, DiskError, DiskExtent, DiskKind, ExtentKind, FileSystem, Partition};
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
 use wmi::{COMLibrary, Variant, WMIConnection};
 
 /// Constants for WMI queries and paths
 const WMI_STORAGE_NAMESPACE: &str = "ROOT\\Microsoft\\Windows\\Storage";
 const REMOVABLE_MEDIA_CAPABILITY: &str = "Supports Removable Media";
+/// Read size used on raw disk devices, which only accept sector-aligned reads
+const RAW_READ_SIZE: usize = 4096;
+/// Sector size assumed when the disk does not report one
+const DEFAULT_SECTOR_SIZE: u64 = 512;
+/// Size of a GPT partition entry as written by Windows
+const GPT_ENTRY_SIZE: u64 = 128;
+/// Upper bound on the partition entries requested from the disk driver
+const MAX_LAYOUT_ENTRIES: usize = 4096;
+/// Prefix of the WMI partition type of GPT partitions (e.g., "GPT: Basic Data")
+const WMI_GPT_TYPE_PREFIX: &str = "GPT:";
+
+/// Offset and size of a partition in bytes
+type PartitionRange = (u64, u64);
 
 /// Media type constants for Windows disk drives
 const MEDIA_TYPE_HDD: u16 = 3;
@@ -16,6 +34,7 @@ const MEDIA_TYPE_SCM: u16 = 5;
 /// This enum represents the file systems that are explicitly supported
 /// by this application, with a fallback for other file systems.
 #[derive(Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
 enum SupportedFileSystem {
     /// NTFS (New Technology File System)
     NTFS,
@@ -23,6 +42,10 @@ enum SupportedFileSystem {
     FAT32,
     /// exFAT (Extended File Allocation Table)
     EXFAT,
+    /// CDFS (ISO 9660 optical media)
+    CDFS,
+    /// UDF (Universal Disk Format optical media)
+    UDF,
     /// Any other file system not explicitly supported
     NotImplemented(String),
 }
@@ -33,6 +56,8 @@ impl From<&str> for SupportedFileSystem {
             "NTFS" => Self::NTFS,
             "FAT32" => Self::FAT32,
             "exFAT" => Self::EXFAT,
+            "CDFS" => Self::CDFS,
+            "UDF" => Self::UDF,
             _ => Self::NotImplemented(s.to_string()),
         }
     }
@@ -229,23 +254,251 @@ fn process_disk(
     let kind = get_disk_kind(&disk_info)?;
     let size = get_u64_value(&disk_info, "Size")? as usize;
     let removable = get_bool_value(&disk_info, "Removable")?;
+    let sector_size = get_u32_value(&disk_info, "BytesPerSector").map_or(DEFAULT_SECTOR_SIZE, u64::from);
 
     // Get partitions
     let device_id = get_string_value(&disk_info, "DeviceID")?;
-    let partitions = match get_partitions(wmi_con, &device_id, partition_count) {
+    let (partitions, ranges, scheme) = match get_partitions(wmi_con, &device_id, partition_count) {
         Ok(p) => p,
         Err(_) => return None,
     };
 
-    Some(Disk::new(
-        device_name,
-        model,
-        serial,
-        kind,
-        size,
-        removable,
-        partitions,
-    ))
+    // The disk driver reports the layout to any user, while reading the
+    // partition table from the raw device needs administrator rights
+    let extents = match query_drive_layout(&device_id).or_else(|| read_disk_layout(&device_id, size as u64)) {
+        Some(table) => label_extents(&table, size as u64, &partitions),
+        None => partition_extents(&ranges, &partitions, scheme, sector_size, size as u64),
+    };
+
+    Some(
+        Disk::new(
+            device_name,
+            model,
+            serial,
+            kind,
+            size,
+            removable,
+            partitions,
+        )
+        .with_extents(extents),
+    )
+}
+
+/// Returns the id of the listed partition starting at `offset`, if any
+fn partition_id_at(partitions: &[Partition], offset: u64) -> Option<usize> {
+    partitions
+        .iter()
+        .find(|partition| partition.offset() == Some(offset))
+        .map(Partition::id)
+}
+
+/// Returns the layout of a disk holding `table`, with the partitions labelled
+///
+/// # Arguments
+/// * `table` - Partition table of the disk
+/// * `size` - Disk size in bytes
+/// * `partitions` - Partitions listed for the disk, used to label the extents
+fn label_extents(table: &PartitionTable, size: u64, partitions: &[Partition]) -> Vec<DiskExtent> {
+    table
+        .extents(size)
+        .into_iter()
+        .map(|extent| match extent.kind() {
+            ExtentKind::Partition(_) => {
+                let id = partition_id_at(partitions, extent.offset());
+                DiskExtent::new(extent.offset(), extent.length(), ExtentKind::Partition(id))
+            }
+            _ => extent.clone(),
+        })
+        .collect()
+}
+
+/// Queries the partition table of a disk from the disk driver
+///
+/// The device is opened without access rights, which the layout query does
+/// not need, so this works without administrator rights. The driver does not
+/// report where the extended boot records of MBR disks are, so those
+/// sectors are shown as unallocated.
+///
+/// # Arguments
+/// * `device_id` - Raw device path (e.g., "\\.\PHYSICALDRIVE0")
+///
+/// # Returns
+/// * `Option<PartitionTable>` - The partition table, or None if the query fails
+///   or the disk is not partitioned
+fn query_drive_layout(device_id: &str) -> Option<PartitionTable> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+    use windows::core::HRESULT;
+    use windows::Win32::Foundation::{ERROR_INSUFFICIENT_BUFFER, HANDLE};
+    use windows::Win32::System::Ioctl::{
+        DISK_GEOMETRY_EX, DRIVE_LAYOUT_INFORMATION_EX, IOCTL_DISK_GET_DRIVE_GEOMETRY_EX, IOCTL_DISK_GET_DRIVE_LAYOUT_EX,
+        PARTITION_INFORMATION_EX, PARTITION_STYLE_GPT, PARTITION_STYLE_MBR,
+    };
+    use windows::Win32::System::IO::DeviceIoControl;
+
+    let file = std::fs::OpenOptions::new().access_mode(0).open(device_id).ok()?;
+    let handle = HANDLE(file.as_raw_handle());
+
+    let mut geometry = DISK_GEOMETRY_EX::default();
+    let mut returned = 0;
+    // SAFETY: the handle stays open for the duration of the call and `geometry` is a buffer of the given size
+    let queried = unsafe {
+        DeviceIoControl(
+            handle,
+            IOCTL_DISK_GET_DRIVE_GEOMETRY_EX,
+            None,
+            0,
+            Some(&mut geometry as *mut DISK_GEOMETRY_EX as *mut _),
+            size_of::<DISK_GEOMETRY_EX>() as u32,
+            Some(&mut returned),
+            None,
+        )
+    };
+    let sector_size = match queried {
+        Ok(()) if geometry.Geometry.BytesPerSector > 0 => geometry.Geometry.BytesPerSector as u64,
+        _ => DEFAULT_SECTOR_SIZE,
+    };
+
+    // The query fails until the buffer is large enough for every partition entry
+    let mut capacity = 128;
+    let buffer = loop {
+        let size = size_of::<DRIVE_LAYOUT_INFORMATION_EX>() + capacity * size_of::<PARTITION_INFORMATION_EX>();
+        // A u64 buffer is aligned for the 64-bit fields of the layout structures
+        let mut buffer = vec![0u64; size.div_ceil(8)];
+        // SAFETY: the handle stays open for the duration of the call and `buffer` holds `size` bytes
+        let queried = unsafe {
+            DeviceIoControl(
+                handle,
+                IOCTL_DISK_GET_DRIVE_LAYOUT_EX,
+                None,
+                0,
+                Some(buffer.as_mut_ptr() as *mut _),
+                size as u32,
+                Some(&mut returned),
+                None,
+            )
+        };
+        match queried {
+            Ok(()) => break buffer,
+            Err(error)
+                if error.code() == HRESULT::from_win32(ERROR_INSUFFICIENT_BUFFER.0) && capacity < MAX_LAYOUT_ENTRIES =>
+            {
+                capacity *= 4
+            }
+            Err(_) => return None,
+        }
+    };
+
+    // SAFETY: the buffer is aligned and was filled with a layout holding at most `capacity + 1` entries
+    let (layout, entries) = unsafe {
+        let layout = &*(buffer.as_ptr() as *const DRIVE_LAYOUT_INFORMATION_EX);
+        let first = buffer
+            .as_ptr()
+            .cast::<u8>()
+            .add(std::mem::offset_of!(DRIVE_LAYOUT_INFORMATION_EX, PartitionEntry))
+            .cast::<PARTITION_INFORMATION_EX>();
+        let count = (layout.PartitionCount as usize).min(capacity + 1);
+        (layout, std::slice::from_raw_parts(first, count))
+    };
+
+    let gpt = layout.PartitionStyle == PARTITION_STYLE_GPT.0 as u32;
+    if !gpt && layout.PartitionStyle != PARTITION_STYLE_MBR.0 as u32 {
+        return None;
+    }
+    let entries = entries
+        .iter()
+        .filter(|entry| entry.PartitionLength > 0)
+        .filter_map(|entry| {
+            let (partition_type, name) = if gpt {
+                // SAFETY: the entries of a GPT disk hold GPT information
+                let info = unsafe { entry.Anonymous.Gpt };
+                let name = String::from_utf16_lossy(&info.Name).trim_end_matches('\0').to_string();
+                (PartitionType::Gpt(format!("{:?}", info.PartitionType)), name)
+            } else {
+                // SAFETY: the entries of an MBR disk hold MBR information
+                let kind = unsafe { entry.Anonymous.Mbr }.PartitionType;
+                // Extended partitions only contain the logical partitions, which are listed too
+                if MBR_TYPES_EXTENDED.contains(&kind) {
+                    return None;
+                }
+                (PartitionType::Mbr(kind), String::new())
+            };
+            Some(PartitionEntry {
+                offset: entry.StartingOffset as u64,
+                length: entry.PartitionLength as u64,
+                partition_type,
+                name,
+            })
+        })
+        .collect();
+
+    let layout = gpt.then(|| {
+        // SAFETY: GPT disks hold GPT layout information
+        let info = unsafe { layout.Anonymous.Gpt };
+        let first_usable = info.StartingUsableOffset as u64;
+        let last_usable = first_usable + info.UsableLength as u64;
+        let entries_size = (info.MaxPartitionCount as u64 * GPT_ENTRY_SIZE).div_ceil(sector_size) * sector_size;
+        // The backup entry array follows the usable space and precedes the backup header
+        GptLayout {
+            sector_size,
+            primary_header: sector_size,
+            primary_entries: 2 * sector_size,
+            entries_size,
+            backup_header: last_usable + entries_size,
+            first_usable,
+            last_usable,
+        }
+    });
+
+    Some(PartitionTable {
+        scheme: if gpt { PartitionScheme::Gpt } else { PartitionScheme::Mbr },
+        entries,
+        gpt: layout,
+        boot_records: Vec::new(),
+    })
+}
+
+/// Reads the partition table of a disk from its raw device
+///
+/// # Arguments
+/// * `device_id` - Raw device path (e.g., "\\.\PHYSICALDRIVE0")
+/// * `size` - Disk size in bytes
+///
+/// # Returns
+/// * `Option<PartitionTable>` - The partition table, or None if the device cannot be read
+fn read_disk_layout(device_id: &str, size: u64) -> Option<PartitionTable> {
+    let file = File::open(device_id).ok()?;
+    let mut reader = BufReader::with_capacity(RAW_READ_SIZE, file);
+    read_partition_table(&mut reader, size).ok()?
+}
+
+/// Builds the layout of a disk from the partition ranges reported by WMI
+///
+/// The partitioning metadata of GPT disks is placed where partitioning tools
+/// write it. On MBR disks it cannot be told apart from unallocated space this
+/// way, so the space before the first partition is reported as unallocated.
+///
+/// # Arguments
+/// * `ranges` - Offset and size of every partition of the disk
+/// * `partitions` - Partitions listed for the disk, used to label the extents
+/// * `scheme` - Partitioning scheme reported for the partitions
+/// * `sector_size` - Logical sector size of the disk in bytes
+/// * `size` - Disk size in bytes
+fn partition_extents(
+    ranges: &[PartitionRange],
+    partitions: &[Partition],
+    scheme: PartitionScheme,
+    sector_size: u64,
+    size: u64,
+) -> Vec<DiskExtent> {
+    let mut extents = match scheme {
+        PartitionScheme::Gpt => GptLayout::standard(sector_size, size).extents(),
+        PartitionScheme::Mbr => Vec::new(),
+    };
+    extents.extend(ranges.iter().map(|&(offset, length)| {
+        DiskExtent::new(offset, length, ExtentKind::Partition(partition_id_at(partitions, offset)))
+    }));
+    extents
 }
 
 /// Retrieves all partitions for a disk
@@ -256,12 +509,14 @@ fn process_disk(
 /// * `partition_count` - Running count of partitions (modified by this function)
 ///
 /// # Returns
-/// * `Result<Vec<Partition>, DiskError>` - The partitions or an error
+/// * `Result<(Vec<Partition>, Vec<PartitionRange>, PartitionScheme), DiskError>` - The partitions
+///   with a volume, the offset and size of every partition including hidden ones, and the
+///   partitioning scheme of the disk
 fn get_partitions(
     wmi_con: &WMIConnection,
     device_id: &str,
     partition_count: &mut usize,
-) -> Result<Vec<Partition>, DiskError> {
+) -> Result<(Vec<Partition>, Vec<PartitionRange>, PartitionScheme), DiskError> {
     let query = format!(
         "ASSOCIATORS OF {{Win32_DiskDrive.DeviceID='{}'}} WHERE AssocClass=Win32_DiskDriveToDiskPartition",
         device_id
@@ -274,7 +529,17 @@ fn get_partitions(
         .filter_map(|result| process_partition(wmi_con, result, partition_count))
         .collect();
 
-    Ok(partitions)
+    let ranges = results
+        .iter()
+        .filter_map(|result| Some((get_u64_value(result, "StartingOffset")?, get_u64_value(result, "Size")?)))
+        .collect();
+
+    let gpt = results
+        .iter()
+        .any(|result| get_string_value(result, "Type").is_some_and(|kind| kind.starts_with(WMI_GPT_TYPE_PREFIX)));
+    let scheme = if gpt { PartitionScheme::Gpt } else { PartitionScheme::Mbr };
+
+    Ok((partitions, ranges, scheme))
 }
 
 /// Processes a single partition from WMI data
@@ -302,13 +567,16 @@ fn process_partition(
     let total_space = get_u64_value(&logical_disk, "Size")?;
     let available_space = get_u64_value(&logical_disk, "FreeSpace")?;
 
-    let partition = Partition::new(
+    let mut partition = Partition::new(
         *partition_count,
         name,
         file_system,
         total_space,
         available_space,
     );
+    if let Some(offset) = get_u64_value(partition_data, "StartingOffset") {
+        partition = partition.with_offset(offset);
+    }
 
     *partition_count += 1;
     Some(partition)
@@ -346,6 +614,21 @@ fn get_u64_value(map: &HashMap<String, Variant>, key: &str) -> Option<u64> {
     }
 }
 
+/// Extracts a u32 value from a WMI variant map
+///
+/// # Arguments
+/// * `map` - The WMI data map
+/// * `key` - Key to look up
+///
+/// # Returns
+/// * `Option<u32>` - The u32 value if found, or None
+fn get_u32_value(map: &HashMap<String, Variant>, key: &str) -> Option<u32> {
+    match map.get(key) {
+        Some(Variant::UI4(value)) => Some(*value),
+        _ => None,
+    }
+}
+
 /// Extracts a boolean value from a WMI variant map
 ///
 /// # Arguments
@@ -375,6 +658,8 @@ fn create_file_system(fs_type: &str, mount_path: &str) -> Option<FileSystem> {
         SupportedFileSystem::NTFS => Some(FileSystem::NTFS(mount_path)),
         SupportedFileSystem::FAT32 => Some(FileSystem::FAT32(mount_path)),
         SupportedFileSystem::EXFAT => Some(FileSystem::EXFAT(mount_path)),
+        SupportedFileSystem::CDFS => Some(FileSystem::ISO9660(mount_path)),
+        SupportedFileSystem::UDF => Some(FileSystem::UDF(mount_path)),
         SupportedFileSystem::NotImplemented(fs) => Some(FileSystem::NotImplemented(fs, mount_path)),
     }
 }
@@ -447,6 +732,8 @@ mod tests {
             SupportedFileSystem::from("exFAT"),
             SupportedFileSystem::EXFAT
         );
+        assert_eq!(SupportedFileSystem::from("CDFS"), SupportedFileSystem::CDFS);
+        assert_eq!(SupportedFileSystem::from("UDF"), SupportedFileSystem::UDF);
 
         match SupportedFileSystem::from("XFS") {
             SupportedFileSystem::NotImplemented(fs) => assert_eq!(fs, "XFS"),
@@ -471,6 +758,16 @@ mod tests {
             _ => panic!("Expected EXFAT file system"),
         }
 
+        match create_file_system("CDFS", "G:\\") {
+            Some(FileSystem::ISO9660(path)) => assert_eq!(path, PathBuf::from("G:\\")),
+            _ => panic!("Expected ISO 9660 file system"),
+        }
+
+        match create_file_system("UDF", "H:\\") {
+            Some(FileSystem::UDF(path)) => assert_eq!(path, PathBuf::from("H:\\")),
+            _ => panic!("Expected UDF file system"),
+        }
+
         match create_file_system("EXT4", "F:\\") {
             Some(FileSystem::NotImplemented(fs, path)) => {
                 assert_eq!(fs, "EXT4");